@@ -2,36 +2,74 @@
 //!
 //! This module provides client functionality for WebSocket connections.
 
-use std::net::SocketAddr;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(all(
+    feature = "metrics",
+    any(feature = "transport-tcp", feature = "transport-tls")
+))]
 use std::time::Instant;
 
-use tokio::time::timeout;
-
+#[cfg(any(feature = "transport-tcp", feature = "transport-tls"))]
+use std::future::Future;
+#[cfg(any(feature = "transport-tcp", feature = "transport-tls"))]
+use std::time::Duration;
+
+use aerosocket_core::error::ConfigError;
+#[cfg(any(feature = "transport-tcp", feature = "transport-tls"))]
+use aerosocket_core::error::TimeoutError;
+#[cfg(all(
+    feature = "compression",
+    any(feature = "transport-tcp", feature = "transport-tls")
+))]
+use aerosocket_core::DeflateContext;
+#[cfg(any(feature = "transport-tcp", feature = "transport-tls"))]
 use aerosocket_core::{
     handshake::{
         create_client_handshake, parse_server_handshake, request_to_string,
-        validate_server_handshake, HandshakeConfig,
+        validate_server_handshake, HandshakeConfig, HandshakeResponse,
+    },
+    protocol::constants::{
+        HEADER_SEC_WEBSOCKET_EXTENSIONS, HEADER_SEC_WEBSOCKET_KEY, MAX_HEADER_SIZE,
     },
-    protocol::constants::{HEADER_SEC_WEBSOCKET_KEY, MAX_HEADER_SIZE},
+    protocol::ExtensionOffer,
     transport::TransportStream,
-    Error, Result,
 };
+use aerosocket_core::{Error, Result};
 
 #[cfg(feature = "transport-tcp")]
 use aerosocket_transport_tcp::TcpStream;
 #[cfg(feature = "transport-tls")]
 use aerosocket_transport_tls::TlsStream;
 
-use crate::config::ClientConfig as ClientOptions;
+use crate::config::{ClientConfig as ClientOptions, TlsConfig};
+#[cfg(any(feature = "transport-tcp", feature = "transport-tls"))]
+use crate::resolver::{connect_happy_eyeballs, Resolver, SystemResolver};
 
 #[cfg(feature = "transport-tls")]
 use std::sync::Arc;
 
 /// WebSocket client
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Client {
     /// Server address
+    #[cfg_attr(
+        not(any(feature = "transport-tcp", feature = "transport-tls")),
+        allow(dead_code)
+    )]
     addr: SocketAddr,
+    /// Host name to resolve instead of connecting to `addr` directly
+    #[cfg_attr(
+        not(any(feature = "transport-tcp", feature = "transport-tls")),
+        allow(dead_code)
+    )]
+    host: Option<String>,
+    /// Request path and query, when connecting through a URL
+    #[cfg_attr(
+        not(any(feature = "transport-tcp", feature = "transport-tls")),
+        allow(dead_code)
+    )]
+    path: Option<String>,
     /// Client configuration
     config: ClientOptions,
 }
@@ -41,211 +79,431 @@ impl Client {
     pub fn new(addr: SocketAddr) -> Self {
         Self {
             addr,
+            host: None,
+            path: None,
+            config: ClientOptions::default(),
+        }
+    }
+
+    /// Create a client that resolves `host` when connecting
+    ///
+    /// Every resolved address is raced with Happy Eyeballs (RFC 8305). The
+    /// configured [resolver](crate::resolver::Resolver) is used if set,
+    /// otherwise the system resolver.
+    pub fn from_host(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            addr: SocketAddr::from(([0, 0, 0, 0], port)),
+            host: Some(host.into()),
+            path: None,
             config: ClientOptions::default(),
         }
     }
 
+    /// Point the client at a `ws://` or `wss://` URL
+    ///
+    /// The scheme must agree with the TLS configuration: `ws://` with TLS
+    /// configured, or `wss://` without it, is a configuration error. With
+    /// [`ClientConfig::auto_tls`](crate::config::ClientConfig::auto_tls) set,
+    /// `wss://` enables TLS with default settings instead.
+    fn for_url(mut self, url: &str) -> Result<Self> {
+        let uri: http::Uri = url
+            .parse()
+            .map_err(|e| invalid_url(url, format!("{}", e)))?;
+        let secure = match uri.scheme_str() {
+            Some("ws") => false,
+            Some("wss") => true,
+            _ => return Err(invalid_url(url, "scheme must be ws or wss")),
+        };
+
+        match (secure, self.config.tls.is_some()) {
+            (false, true) => {
+                return Err(Error::Config(ConfigError::Validation(format!(
+                    "{} uses ws:// but TLS is configured; use wss:// or remove the TLS config",
+                    url
+                ))))
+            }
+            (true, false) if self.config.auto_tls => {
+                self.config.tls = Some(TlsConfig::default());
+            }
+            (true, false) => {
+                return Err(Error::Config(ConfigError::Validation(format!(
+                    "{} uses wss:// but no TLS config is set; configure TLS or enable auto_tls",
+                    url
+                ))))
+            }
+            _ => {}
+        }
+
+        let host = uri
+            .host()
+            .ok_or_else(|| invalid_url(url, "missing host"))?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+        match host.parse::<IpAddr>() {
+            Ok(ip) => {
+                self.addr = SocketAddr::new(ip, port);
+                self.host = None;
+            }
+            Err(_) => {
+                self.addr = SocketAddr::from(([0, 0, 0, 0], port));
+                self.host = Some(host.to_string());
+            }
+        }
+        self.path = uri
+            .path_and_query()
+            .map(|path| path.as_str().to_string())
+            .filter(|path| !path.is_empty());
+        Ok(self)
+    }
+
+    /// Connect to a `ws://` or `wss://` URL
+    ///
+    /// The port defaults to 80 for `ws://` and 443 for `wss://`. A host name
+    /// is resolved when connecting and is sent as the `Host` header and, for
+    /// `wss://`, as the TLS server name unless one is configured. The path
+    /// and query become the handshake request target.
+    ///
+    /// Fails with a [`ConfigError`] before opening a socket if the scheme
+    /// does not match the TLS configuration (see
+    /// [`ClientConfig::auto_tls`](crate::config::ClientConfig::auto_tls)).
+    pub async fn connect_url(self, url: &str) -> Result<crate::connection::ClientConnection> {
+        self.for_url(url)?.connect().await
+    }
+
+    /// Connect with extra handshake headers for this connection only
+    ///
+    /// The headers are merged over [`ClientConfig::headers`](crate::config::ClientConfig::headers),
+    /// replacing any configured header of the same name regardless of case,
+    /// which suits values that change per connect such as a rotating token.
+    pub async fn connect_with_headers(
+        mut self,
+        extra: HashMap<String, String>,
+    ) -> Result<crate::connection::ClientConnection> {
+        for (name, value) in extra {
+            self.config
+                .headers
+                .retain(|(existing, _)| !existing.eq_ignore_ascii_case(&name));
+            self.config.headers.push((name, value));
+        }
+        self.connect().await
+    }
+
+    /// Open the TCP connection, resolving the host name if there is one
+    #[cfg(any(feature = "transport-tcp", feature = "transport-tls"))]
+    async fn connect_tcp(
+        addr: SocketAddr,
+        host: Option<&str>,
+        config: &ClientOptions,
+    ) -> Result<tokio::net::TcpStream> {
+        let addrs = match (host, &config.resolver) {
+            (None, _) => vec![addr],
+            (Some(host), Some(resolver)) => resolver.resolve(host, addr.port()).await?,
+            (Some(host), None) => SystemResolver.resolve(host, addr.port()).await?,
+        };
+        let stream = connect_happy_eyeballs(addrs, config.connection_attempt_delay).await?;
+        config.tcp.apply(&stream).map_err(Error::Io)?;
+        Ok(stream)
+    }
+
     /// Set client configuration
     pub fn with_config(mut self, config: ClientOptions) -> Self {
         self.config = config;
         self
     }
 
+    /// Get the client configuration
+    pub fn config(&self) -> &ClientOptions {
+        &self.config
+    }
+
     /// Connect to the WebSocket server
     #[cfg(any(feature = "transport-tcp", feature = "transport-tls"))]
     #[cfg_attr(feature = "logging", tracing::instrument(skip(self)))]
     #[allow(clippy::field_reassign_with_default)]
     pub async fn connect(self) -> Result<crate::connection::ClientConnection> {
         let addr = self.addr;
+        let host = self.host.clone();
+        let path = self.path.clone().unwrap_or_default();
         let config = self.config.clone();
-        let handshake_timeout = config.handshake_timeout;
 
-        let fut = async move {
-            #[cfg(feature = "metrics")]
-            let handshake_start = Instant::now();
-
-            // Build handshake config from client settings
-            let mut handshake_config = HandshakeConfig::default();
-            handshake_config.protocols = config.protocols.clone();
-            if let Some(origin) = &config.origin {
-                handshake_config.origin = Some(origin.clone());
-            }
-            for (name, value) in &config.headers {
-                handshake_config
-                    .extra_headers
-                    .insert(name.clone(), value.clone());
-            }
-            handshake_config.auth = config.auth.clone();
+        #[cfg(feature = "metrics")]
+        let handshake_start = Instant::now();
 
-            // Decide between TLS and TCP based on TLS configuration
-            if let Some(tls_cfg) = &config.tls {
-                #[cfg(feature = "transport-tls")]
-                {
-                    let server_name = tls_cfg.server_name.as_deref().unwrap_or("localhost");
-
-                    handshake_config.host = Some(format!("{}:{}", server_name, addr.port()));
-                    let uri = format!("wss://{}:{}", server_name, addr.port());
-                    let request = create_client_handshake(&uri, &handshake_config)?;
-
-                    let client_key = request
-                        .headers
-                        .get(HEADER_SEC_WEBSOCKET_KEY)
-                        .cloned()
-                        .ok_or_else(|| {
-                            Error::Other(
-                                "Missing sec-websocket-key in client handshake".to_string(),
-                            )
-                        })?;
-
-                    let request_string = request_to_string(&request);
-
-                    let tls_config = crate::config::build_rustls_client_config(tls_cfg)?;
-                    let mut stream =
-                        TlsStream::connect(addr, Arc::new(tls_config), server_name).await?;
-
-                    stream.write_all(request_string.as_bytes()).await?;
-                    stream.flush().await?;
-
-                    let mut buffer = Vec::new();
-                    let mut temp = [0u8; 1024];
-
-                    loop {
-                        let n = stream.read(&mut temp).await?;
-                        if n == 0 {
-                            break;
-                        }
-                        buffer.extend_from_slice(&temp[..n]);
-                        if buffer.len() > MAX_HEADER_SIZE {
-                            return Err(Error::Other("Server handshake too large".to_string()));
-                        }
-                        if buffer.windows(4).any(|w| w == b"\r\n\r\n") {
-                            break;
-                        }
-                    }
+        // Build handshake config from client settings
+        let mut handshake_config = HandshakeConfig::default();
+        handshake_config.protocols = config.protocols.clone();
+        if let Some(origin) = &config.origin {
+            handshake_config.origin = Some(origin.clone());
+        }
+        for (name, value) in &config.headers {
+            handshake_config
+                .extra_headers
+                .insert(name.clone(), value.clone());
+        }
+        handshake_config.auth = config.auth.clone();
+        handshake_config.compression = aerosocket_core::handshake::CompressionConfig {
+            enabled: config.compression.enabled,
+            client_max_window_bits: config.compression.client_max_window_bits,
+            server_max_window_bits: config.compression.server_max_window_bits,
+            compression_level: Some(config.compression.level as u32),
+            server_no_context_takeover: !config.compression.server_context_takeover,
+            client_no_context_takeover: !config.compression.client_context_takeover,
+        };
 
-                    let raw_response = String::from_utf8_lossy(&buffer).to_string();
-                    let response = parse_server_handshake(&raw_response)?;
-                    validate_server_handshake(&response, &client_key)?;
-
-                    let remote_addr = stream.remote_addr()?;
-                    let mut connection = crate::connection::ClientConnection::with_stream(
-                        remote_addr,
-                        Box::new(stream) as Box<dyn TransportStream>,
-                    );
-                    connection.set_connected();
-
-                    #[cfg(feature = "metrics")]
-                    {
-                        let elapsed = handshake_start.elapsed().as_secs_f64();
-                        metrics::histogram!("aerosocket_client_handshake_duration_seconds")
-                            .record(elapsed);
-                        metrics::counter!("aerosocket_client_connections_opened_total")
-                            .increment(1);
+        // Decide between TLS and TCP based on TLS configuration
+        if let Some(tls_cfg) = &config.tls {
+            #[cfg(feature = "transport-tls")]
+            {
+                let server_name = tls_cfg
+                    .server_name
+                    .as_deref()
+                    .or(host.as_deref())
+                    .unwrap_or("localhost");
+
+                handshake_config.host = Some(format!("{}:{}", server_name, addr.port()));
+                let uri = format!("wss://{}:{}{}", server_name, addr.port(), path);
+                let request = create_client_handshake(&uri, &handshake_config)?;
+
+                let client_key = request
+                    .headers
+                    .get(HEADER_SEC_WEBSOCKET_KEY)
+                    .cloned()
+                    .ok_or_else(|| {
+                        Error::Other("Missing sec-websocket-key in client handshake".to_string())
+                    })?;
+
+                let request_string = request_to_string(&request);
+
+                let tls_config = crate::config::build_rustls_client_config(tls_cfg)?;
+                let mut stream = within(
+                    config.connect_timeout,
+                    TimeoutError::Connect {
+                        timeout: config.connect_timeout,
+                    },
+                    async {
+                        let tcp_stream = Self::connect_tcp(addr, host.as_deref(), &config).await?;
+                        TlsStream::connect_stream(tcp_stream, Arc::new(tls_config), server_name)
+                            .await
+                    },
+                )
+                .await?;
+
+                let (response, buffer, header_end) = within(
+                    config.handshake_timeout,
+                    TimeoutError::Handshake {
+                        timeout: config.handshake_timeout,
+                    },
+                    Self::exchange_handshake(&mut stream, &request_string, &client_key),
+                )
+                .await?;
+
+                let remote_addr = stream.remote_addr()?;
+                let mut connection = crate::connection::ClientConnection::with_stream(
+                    remote_addr,
+                    Box::new(stream) as Box<dyn TransportStream>,
+                );
+                connection.set_connected();
+                connection.set_mask_frames(config.mask_frames);
+                connection.set_max_frame_size(config.max_frame_size);
+                connection.set_strict_protocol(config.strict_protocol);
+                connection.set_keepalive(config.keepalive_interval, config.keepalive_timeout);
+                connection.buffer_received(&buffer[header_end..]);
+                if let Some(ext_header) = response.header(HEADER_SEC_WEBSOCKET_EXTENSIONS) {
+                    for offer in ExtensionOffer::parse_header(ext_header)? {
+                        connection.add_extension(offer.name);
                     }
-
-                    Ok(connection)
+                }
+                #[cfg(feature = "compression")]
+                if let Some(params) = response.deflate_params()? {
+                    connection.set_deflate(Some(
+                        DeflateContext::for_client(
+                            &params,
+                            u32::from(self.config.compression.level),
+                        )
+                        .with_dictionary(self.config.compression.dictionary.clone())
+                        .with_max_message_size(config.max_message_size),
+                    ));
                 }
 
-                #[cfg(not(feature = "transport-tls"))]
+                #[cfg(feature = "metrics")]
                 {
-                    let _ = tls_cfg;
-                    Err(Error::Other(
-                        "TLS configuration provided but transport-tls feature is not enabled for aerosocket-client"
-                            .to_string(),
-                    ))
+                    let elapsed = handshake_start.elapsed().as_secs_f64();
+                    metrics::histogram!("aerosocket_client_handshake_duration_seconds")
+                        .record(elapsed);
+                    metrics::counter!("aerosocket_client_connections_opened_total").increment(1);
                 }
-            } else {
-                #[cfg(feature = "transport-tcp")]
-                {
-                    let uri = format!("ws://{}", addr);
-                    let request = create_client_handshake(&uri, &handshake_config)?;
-
-                    let client_key = request
-                        .headers
-                        .get(HEADER_SEC_WEBSOCKET_KEY)
-                        .cloned()
-                        .ok_or_else(|| {
-                            Error::Other(
-                                "Missing sec-websocket-key in client handshake".to_string(),
-                            )
-                        })?;
-
-                    let request_string = request_to_string(&request);
-                    let mut stream = TcpStream::connect(addr).await?;
-
-                    stream.write_all(request_string.as_bytes()).await?;
-                    stream.flush().await?;
-
-                    let mut buffer = Vec::new();
-                    let mut temp = [0u8; 1024];
-
-                    loop {
-                        let n = stream.read(&mut temp).await?;
-                        if n == 0 {
-                            break;
-                        }
-                        buffer.extend_from_slice(&temp[..n]);
-                        if buffer.len() > MAX_HEADER_SIZE {
-                            return Err(Error::Other("Server handshake too large".to_string()));
-                        }
-                        if buffer.windows(4).any(|w| w == b"\r\n\r\n") {
-                            break;
-                        }
-                    }
 
-                    let raw_response = String::from_utf8_lossy(&buffer).to_string();
-                    let response = parse_server_handshake(&raw_response)?;
-                    validate_server_handshake(&response, &client_key)?;
-
-                    let remote_addr = stream.remote_addr()?;
-                    let mut connection = crate::connection::ClientConnection::with_stream(
-                        remote_addr,
-                        Box::new(stream) as Box<dyn TransportStream>,
-                    );
-                    connection.set_connected();
-
-                    #[cfg(feature = "metrics")]
-                    {
-                        let elapsed = handshake_start.elapsed().as_secs_f64();
-                        metrics::histogram!("aerosocket_client_handshake_duration_seconds")
-                            .record(elapsed);
-                        metrics::counter!("aerosocket_client_connections_opened_total")
-                            .increment(1);
-                    }
+                Ok(connection)
+            }
 
-                    Ok(connection)
+            #[cfg(not(feature = "transport-tls"))]
+            {
+                let _ = tls_cfg;
+                Err(Error::MissingTransport {
+                    requested: aerosocket_core::transport::TransportType::Tls,
+                })
+            }
+        } else {
+            #[cfg(feature = "transport-tcp")]
+            {
+                let authority = match &host {
+                    Some(host) => format!("{}:{}", host, addr.port()),
+                    None => addr.to_string(),
+                };
+                handshake_config.host = Some(authority.clone());
+                let uri = format!("ws://{}{}", authority, path);
+                let request = create_client_handshake(&uri, &handshake_config)?;
+
+                let client_key = request
+                    .headers
+                    .get(HEADER_SEC_WEBSOCKET_KEY)
+                    .cloned()
+                    .ok_or_else(|| {
+                        Error::Other("Missing sec-websocket-key in client handshake".to_string())
+                    })?;
+
+                let request_string = request_to_string(&request);
+                let mut stream = TcpStream::from_tokio(
+                    within(
+                        config.connect_timeout,
+                        TimeoutError::Connect {
+                            timeout: config.connect_timeout,
+                        },
+                        Self::connect_tcp(addr, host.as_deref(), &config),
+                    )
+                    .await?,
+                );
+
+                let (response, buffer, header_end) = within(
+                    config.handshake_timeout,
+                    TimeoutError::Handshake {
+                        timeout: config.handshake_timeout,
+                    },
+                    Self::exchange_handshake(&mut stream, &request_string, &client_key),
+                )
+                .await?;
+
+                let remote_addr = stream.remote_addr()?;
+                let mut connection = crate::connection::ClientConnection::with_stream(
+                    remote_addr,
+                    Box::new(stream) as Box<dyn TransportStream>,
+                );
+                connection.set_connected();
+                connection.set_mask_frames(config.mask_frames);
+                connection.set_max_frame_size(config.max_frame_size);
+                connection.set_strict_protocol(config.strict_protocol);
+                connection.set_keepalive(config.keepalive_interval, config.keepalive_timeout);
+                connection.buffer_received(&buffer[header_end..]);
+                if let Some(ext_header) = response.header(HEADER_SEC_WEBSOCKET_EXTENSIONS) {
+                    for offer in ExtensionOffer::parse_header(ext_header)? {
+                        connection.add_extension(offer.name);
+                    }
+                }
+                #[cfg(feature = "compression")]
+                if let Some(params) = response.deflate_params()? {
+                    connection.set_deflate(Some(
+                        DeflateContext::for_client(
+                            &params,
+                            u32::from(self.config.compression.level),
+                        )
+                        .with_dictionary(self.config.compression.dictionary.clone())
+                        .with_max_message_size(config.max_message_size),
+                    ));
                 }
 
-                #[cfg(not(feature = "transport-tcp"))]
+                #[cfg(feature = "metrics")]
                 {
-                    Err(Error::Other(
-                        "No transport feature enabled (transport-tcp or transport-tls) for aerosocket-client"
-                            .to_string(),
-                    ))
+                    let elapsed = handshake_start.elapsed().as_secs_f64();
+                    metrics::histogram!("aerosocket_client_handshake_duration_seconds")
+                        .record(elapsed);
+                    metrics::counter!("aerosocket_client_connections_opened_total").increment(1);
                 }
+
+                Ok(connection)
             }
-        };
 
-        match timeout(handshake_timeout, fut).await {
-            Ok(result) => result,
-            Err(_) => Err(Error::Timeout(
-                aerosocket_core::error::TimeoutError::Handshake {
-                    timeout: handshake_timeout,
-                },
-            )),
+            #[cfg(not(feature = "transport-tcp"))]
+            {
+                Err(Error::MissingTransport {
+                    requested: aerosocket_core::transport::TransportType::Tcp,
+                })
+            }
         }
     }
 
+    /// Send the upgrade request and read the server's response
+    ///
+    /// Returns the validated response with the bytes read and where its
+    /// head ends; frames sent right after the 101 may share a read with it.
+    #[cfg(any(feature = "transport-tcp", feature = "transport-tls"))]
+    async fn exchange_handshake<S: TransportStream>(
+        stream: &mut S,
+        request: &str,
+        client_key: &str,
+    ) -> Result<(HandshakeResponse, Vec<u8>, usize)> {
+        stream.write_all(request.as_bytes()).await?;
+        stream.flush().await?;
+
+        let mut buffer = Vec::new();
+        let mut temp = [0u8; 1024];
+
+        loop {
+            let n = stream.read(&mut temp).await?;
+            if n == 0 {
+                break;
+            }
+            buffer.extend_from_slice(&temp[..n]);
+            if buffer.len() > MAX_HEADER_SIZE {
+                return Err(Error::Other("Server handshake too large".to_string()));
+            }
+            if buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+                break;
+            }
+        }
+
+        let header_end = buffer
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map_or(buffer.len(), |pos| pos + 4);
+        let raw_response = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+        let response = parse_server_handshake(&raw_response)?;
+        validate_server_handshake(&response, client_key)?;
+        Ok((response, buffer, header_end))
+    }
+
     /// Connect to the WebSocket server (requires a transport feature)
     #[cfg(not(any(feature = "transport-tcp", feature = "transport-tls")))]
     pub async fn connect(self) -> Result<crate::connection::ClientConnection> {
-        Err(Error::Other(
-            "No transport feature enabled (transport-tcp or transport-tls) for aerosocket-client"
-                .to_string(),
-        ))
+        let requested = if self.config.tls.is_some() {
+            aerosocket_core::transport::TransportType::Tls
+        } else {
+            aerosocket_core::transport::TransportType::Tcp
+        };
+        Err(Error::MissingTransport { requested })
     }
 }
 
+/// Run `fut`, failing with `error` if it takes longer than `limit`
+#[cfg(any(feature = "transport-tcp", feature = "transport-tls"))]
+async fn within<T>(
+    limit: Duration,
+    error: TimeoutError,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::time::timeout(limit, fut)
+        .await
+        .unwrap_or(Err(Error::Timeout(error)))
+}
+
+fn invalid_url(url: &str, reason: impl std::fmt::Display) -> Error {
+    Error::Config(ConfigError::InvalidValue {
+        field: "url".to_string(),
+        value: url.to_string(),
+        reason: reason.to_string(),
+    })
+}
+
 /// Client builder
 #[derive(Debug)]
 pub struct ClientBuilder {
@@ -274,12 +532,30 @@ impl ClientBuilder {
         self
     }
 
-    /// Set handshake timeout
+    /// Set how long opening the TCP connection, and TLS session, may take
+    pub fn connect_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.config.connect_timeout = timeout;
+        self
+    }
+
+    /// Set how long the HTTP upgrade exchange may take once connected
     pub fn handshake_timeout(mut self, timeout: std::time::Duration) -> Self {
         self.config.handshake_timeout = timeout;
         self
     }
 
+    /// Set the socket options applied to the TCP connection
+    pub fn tcp_config(mut self, config: crate::config::TcpTransportConfig) -> Self {
+        self.config.tcp = config;
+        self
+    }
+
+    /// Set whether Nagle's algorithm is disabled (on by default)
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.config.tcp.nodelay = nodelay;
+        self
+    }
+
     /// Enable/disable compression
     pub fn compression(mut self, enabled: bool) -> Self {
         self.config.compression.enabled = enabled;
@@ -323,4 +599,221 @@ mod tests {
         assert_eq!(client.config.max_frame_size, 2048);
         assert!(client.config.compression.enabled);
     }
+
+    #[test]
+    fn test_url_sets_address_and_path() {
+        let client = Client::new("127.0.0.1:1".parse().unwrap())
+            .for_url("ws://127.0.0.1:9001/chat?room=1")
+            .unwrap();
+        assert_eq!(client.addr, "127.0.0.1:9001".parse::<SocketAddr>().unwrap());
+        assert_eq!(client.host, None);
+        assert_eq!(client.path.as_deref(), Some("/chat?room=1"));
+
+        let client = Client::new("127.0.0.1:1".parse().unwrap())
+            .for_url("ws://example.com")
+            .unwrap();
+        assert_eq!(client.host.as_deref(), Some("example.com"));
+        assert_eq!(client.addr.port(), 80);
+
+        assert!(matches!(
+            Client::new("127.0.0.1:1".parse().unwrap()).for_url("http://example.com"),
+            Err(Error::Config(ConfigError::InvalidValue { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_url_default_and_explicit_ports() {
+        let auto_tls = || {
+            Client::new("127.0.0.1:1".parse().unwrap())
+                .with_config(ClientConfig::default().auto_tls(true))
+        };
+
+        let client = auto_tls()
+            .for_url("wss://api.example.com:8443/socket?token=x")
+            .unwrap();
+        assert_eq!(client.host.as_deref(), Some("api.example.com"));
+        assert_eq!(client.addr.port(), 8443);
+        assert_eq!(client.path.as_deref(), Some("/socket?token=x"));
+        assert!(client.config.tls.is_some());
+
+        let client = Client::new("127.0.0.1:1".parse().unwrap())
+            .for_url("ws://[::1]/socket")
+            .unwrap();
+        assert_eq!(client.addr, "[::1]:80".parse::<SocketAddr>().unwrap());
+        assert_eq!(client.host, None);
+
+        let client = auto_tls().for_url("wss://[::1]:9443").unwrap();
+        assert_eq!(client.addr, "[::1]:9443".parse::<SocketAddr>().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_ws_url_with_tls_is_rejected() {
+        let config = ClientConfig::default().tls(TlsConfig::default());
+        let result = Client::new("127.0.0.1:1".parse().unwrap())
+            .with_config(config)
+            .connect_url("ws://127.0.0.1:9001")
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::Config(ConfigError::Validation(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wss_url_without_tls_is_rejected() {
+        let result = Client::new("127.0.0.1:1".parse().unwrap())
+            .connect_url("wss://127.0.0.1:9001")
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::Config(ConfigError::Validation(_)))
+        ));
+    }
+
+    #[test]
+    fn test_wss_url_enables_tls_with_auto_tls() {
+        let client = Client::new("127.0.0.1:1".parse().unwrap())
+            .with_config(ClientConfig::default().auto_tls(true))
+            .for_url("wss://example.com/feed")
+            .unwrap();
+        assert!(client.config.tls.is_some());
+        assert_eq!(client.addr.port(), 443);
+    }
+
+    #[cfg(feature = "transport-tcp")]
+    #[tokio::test]
+    async fn test_runtime_headers_override_config_headers() {
+        use aerosocket_core::handshake::{
+            create_server_handshake, parse_client_handshake, response_to_string,
+        };
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let mut chunk = [0u8; 1024];
+                let n = stream.read(&mut chunk).await.unwrap();
+                request.extend_from_slice(&chunk[..n]);
+            }
+            let request = String::from_utf8(request).unwrap();
+            let parsed = parse_client_handshake(&request).unwrap();
+            let response = create_server_handshake(&parsed, &HandshakeConfig::default()).unwrap();
+            stream
+                .write_all(response_to_string(&response).as_bytes())
+                .await
+                .unwrap();
+            request
+        });
+
+        let config = ClientConfig::default()
+            .add_header("Authorization".to_string(), "Bearer stale".to_string())
+            .add_header("X-Trace".to_string(), "abc".to_string());
+        let extra = HashMap::from([("authorization".to_string(), "Bearer fresh".to_string())]);
+        Client::new(addr)
+            .with_config(config)
+            .connect_with_headers(extra)
+            .await
+            .unwrap();
+
+        let request = server.await.unwrap().to_ascii_lowercase();
+        assert!(request.contains("authorization: bearer fresh\r\n"));
+        assert!(!request.contains("bearer stale"));
+        assert!(request.contains("x-trace: abc\r\n"));
+    }
+
+    /// Resolver whose lookups never answer, like a blackholed address
+    #[cfg(feature = "transport-tcp")]
+    #[derive(Debug)]
+    struct BlackholeResolver;
+
+    #[cfg(feature = "transport-tcp")]
+    impl crate::resolver::Resolver for BlackholeResolver {
+        fn resolve<'a>(&'a self, _host: &'a str, _port: u16) -> crate::resolver::ResolveFuture<'a> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    #[cfg(feature = "transport-tcp")]
+    #[tokio::test]
+    async fn test_unreachable_server_trips_connect_timeout() {
+        let config = ClientConfig::default()
+            .resolver(std::sync::Arc::new(BlackholeResolver))
+            .connect_timeout(Duration::from_millis(50))
+            .handshake_timeout(Duration::from_secs(30));
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            Client::from_host("blackhole.invalid", 80)
+                .with_config(config)
+                .connect(),
+        )
+        .await
+        .expect("connect timeout was not enforced");
+        match result {
+            Err(Error::Timeout(TimeoutError::Connect { timeout })) => {
+                assert_eq!(timeout, Duration::from_millis(50));
+            }
+            other => panic!("expected a connect timeout, got {:?}", other.err()),
+        }
+    }
+
+    #[cfg(feature = "transport-tcp")]
+    #[tokio::test]
+    async fn test_silent_server_trips_handshake_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Accept the connection but never answer the upgrade request
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            drop(stream);
+        });
+
+        let config = ClientConfig::default()
+            .connect_timeout(Duration::from_secs(30))
+            .handshake_timeout(Duration::from_millis(100));
+        match Client::new(addr).with_config(config).connect().await {
+            Err(Error::Timeout(TimeoutError::Handshake { timeout })) => {
+                assert_eq!(timeout, Duration::from_millis(100));
+            }
+            other => panic!("expected a handshake timeout, got {:?}", other.err()),
+        }
+        server.abort();
+    }
+
+    #[cfg(not(any(feature = "transport-tcp", feature = "transport-tls")))]
+    #[tokio::test]
+    async fn test_connect_without_transport_feature() {
+        let addr = "127.0.0.1:8080".parse().unwrap();
+        match Client::new(addr).connect().await {
+            Err(Error::MissingTransport { requested }) => {
+                assert_eq!(requested, aerosocket_core::transport::TransportType::Tcp);
+            }
+            other => panic!("expected MissingTransport error, got {:?}", other.err()),
+        }
+    }
+
+    #[cfg(not(feature = "transport-tls"))]
+    #[tokio::test]
+    async fn test_connect_with_tls_config_without_tls_feature() {
+        let addr = "127.0.0.1:8080".parse().unwrap();
+        let config = ClientConfig::default().tls(crate::config::TlsConfig {
+            verify: true,
+            ca_file: None,
+            cert_file: None,
+            key_file: None,
+            server_name: None,
+            min_version: None,
+            max_version: None,
+        });
+        match Client::new(addr).with_config(config).connect().await {
+            Err(Error::MissingTransport { requested }) => {
+                assert_eq!(requested, aerosocket_core::transport::TransportType::Tls);
+            }
+            other => panic!("expected MissingTransport error, got {:?}", other.err()),
+        }
+    }
 }
@@ -2,7 +2,9 @@
 //!
 //! This module provides configuration options for WebSocket clients.
 
+use crate::resolver::{SharedResolver, DEFAULT_CONNECTION_ATTEMPT_DELAY};
 use aerosocket_core::error::ConfigError;
+pub use aerosocket_core::transport::TcpTransportConfig;
 use aerosocket_core::Error;
 use std::time::Duration;
 
@@ -59,10 +61,17 @@ pub struct ClientConfig {
     pub max_frame_size: usize,
     /// Maximum message size in bytes
     pub max_message_size: usize,
-    /// Handshake timeout
+    /// Time allowed to resolve the host and open the TCP connection,
+    /// including the TLS handshake for `wss://`
+    pub connect_timeout: Duration,
+    /// Time allowed for the HTTP upgrade exchange once connected
     pub handshake_timeout: Duration,
     /// Idle timeout
     pub idle_timeout: Duration,
+    /// Ping the server after this long without a frame (`None` disables keepalive)
+    pub keepalive_interval: Option<Duration>,
+    /// Close with 1001 when nothing arrives this long after a keepalive ping
+    pub keepalive_timeout: Option<Duration>,
     /// Compression configuration
     pub compression: CompressionConfig,
     /// TLS configuration
@@ -79,6 +88,27 @@ pub struct ClientConfig {
     pub auth: Option<aerosocket_core::Auth>,
     /// Reconnection configuration
     pub reconnection: ReconnectionConfig,
+    /// Host name resolver (`None` uses the system resolver)
+    pub resolver: Option<SharedResolver>,
+    /// Delay between staggered Happy Eyeballs connection attempts
+    pub connection_attempt_delay: Duration,
+    /// Socket options applied to the TCP connection, including one carrying TLS
+    pub tcp: TcpTransportConfig,
+    /// Mask outgoing frames
+    ///
+    /// RFC 6455 requires it. Turning it off saves CPU on trusted
+    /// server-to-server links, but only servers with
+    /// `allow_unmasked_clients` enabled will accept the frames.
+    pub mask_frames: bool,
+    /// Enforce every RFC 6455 MUST on server frames, closing with the
+    /// prescribed status code on any violation
+    ///
+    /// When off, masked server frames and invalid close codes are accepted
+    /// and invalid UTF-8 text is replaced rather than rejected.
+    pub strict_protocol: bool,
+    /// Enable TLS with default settings when connecting to a `wss://` URL
+    /// without a TLS configuration, instead of rejecting the URL
+    pub auto_tls: bool,
 }
 
 impl Default for ClientConfig {
@@ -86,8 +116,11 @@ impl Default for ClientConfig {
         Self {
             max_frame_size: aerosocket_core::protocol::constants::DEFAULT_MAX_FRAME_SIZE,
             max_message_size: aerosocket_core::protocol::constants::DEFAULT_MAX_MESSAGE_SIZE,
+            connect_timeout: aerosocket_core::protocol::constants::DEFAULT_CONNECT_TIMEOUT,
             handshake_timeout: aerosocket_core::protocol::constants::DEFAULT_HANDSHAKE_TIMEOUT,
             idle_timeout: aerosocket_core::protocol::constants::DEFAULT_IDLE_TIMEOUT,
+            keepalive_interval: None,
+            keepalive_timeout: None,
             compression: CompressionConfig::default(),
             tls: None,
             user_agent: format!("aerosocket-client/{}", env!("CARGO_PKG_VERSION")),
@@ -96,6 +129,12 @@ impl Default for ClientConfig {
             headers: Vec::new(),
             auth: None,
             reconnection: ReconnectionConfig::default(),
+            resolver: None,
+            connection_attempt_delay: DEFAULT_CONNECTION_ATTEMPT_DELAY,
+            tcp: TcpTransportConfig::default(),
+            mask_frames: true,
+            strict_protocol: false,
+            auto_tls: false,
         }
     }
 }
@@ -121,12 +160,41 @@ impl ClientConfig {
             )));
         }
 
+        if self.compression.enabled && !cfg!(feature = "compression") {
+            return Err(Error::Config(ConfigError::Validation(
+                "compression is enabled but the `compression` feature is not compiled in"
+                    .to_string(),
+            )));
+        }
+
+        if self.connect_timeout.is_zero() {
+            return Err(Error::Config(ConfigError::Validation(
+                "connect_timeout must be greater than 0".to_string(),
+            )));
+        }
+
         if self.handshake_timeout.is_zero() {
             return Err(Error::Config(ConfigError::Validation(
                 "handshake_timeout must be greater than 0".to_string(),
             )));
         }
 
+        for (field, timeout) in [
+            ("keepalive_interval", self.keepalive_interval),
+            ("keepalive_timeout", self.keepalive_timeout),
+        ] {
+            if timeout.is_some_and(|timeout| timeout.is_zero()) {
+                return Err(Error::Config(ConfigError::Validation(format!(
+                    "{} must be greater than 0",
+                    field
+                ))));
+            }
+        }
+
+        if let Some(tls) = &self.tls {
+            tls.validate()?;
+        }
+
         Ok(())
     }
 
@@ -142,6 +210,12 @@ impl ClientConfig {
         self
     }
 
+    /// Set connect timeout
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
     /// Set handshake timeout
     pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
         self.handshake_timeout = timeout;
@@ -154,6 +228,18 @@ impl ClientConfig {
         self
     }
 
+    /// Ping the server after `interval` without a frame
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Close the connection when nothing arrives within `timeout` of a keepalive ping
+    pub fn keepalive_timeout(mut self, timeout: Duration) -> Self {
+        self.keepalive_timeout = Some(timeout);
+        self
+    }
+
     /// Set user agent
     pub fn user_agent(mut self, agent: String) -> Self {
         self.user_agent = agent;
@@ -190,6 +276,42 @@ impl ClientConfig {
         self
     }
 
+    /// Set a custom host name resolver
+    pub fn resolver(mut self, resolver: SharedResolver) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Set the delay between staggered connection attempts
+    pub fn connection_attempt_delay(mut self, delay: Duration) -> Self {
+        self.connection_attempt_delay = delay;
+        self
+    }
+
+    /// Set the socket options applied to the TCP connection
+    pub fn tcp_config(mut self, config: TcpTransportConfig) -> Self {
+        self.tcp = config;
+        self
+    }
+
+    /// Set whether outgoing frames are masked (see [`ClientConfig::mask_frames`])
+    pub fn mask_frames(mut self, mask: bool) -> Self {
+        self.mask_frames = mask;
+        self
+    }
+
+    /// Enable strict RFC 6455 enforcement (see [`ClientConfig::strict_protocol`])
+    pub fn strict_protocol(mut self, strict: bool) -> Self {
+        self.strict_protocol = strict;
+        self
+    }
+
+    /// Set whether `wss://` URLs enable TLS when none is configured
+    pub fn auto_tls(mut self, enabled: bool) -> Self {
+        self.auto_tls = enabled;
+        self
+    }
+
     /// Enable automatic reconnection
     pub fn enable_reconnection(mut self) -> Self {
         self.reconnection.enabled = true;
@@ -236,6 +358,11 @@ pub struct CompressionConfig {
     pub server_max_window_bits: Option<u8>,
     /// Client max window bits
     pub client_max_window_bits: Option<u8>,
+    /// Preset deflate dictionary, agreed with the peer out of band
+    ///
+    /// Improves the ratio for small, repetitive payloads. Only the last 32 KiB
+    /// are used.
+    pub dictionary: Option<Vec<u8>>,
 }
 
 impl Default for CompressionConfig {
@@ -247,6 +374,7 @@ impl Default for CompressionConfig {
             client_context_takeover: true,
             server_max_window_bits: None,
             client_max_window_bits: None,
+            dictionary: None,
         }
     }
 }
@@ -255,10 +383,15 @@ impl Default for CompressionConfig {
 #[derive(Debug, Clone)]
 pub struct TlsConfig {
     /// Enable TLS verification
+    ///
+    /// Leave this on outside of local development; see
+    /// [`TlsConfig::danger_accept_invalid_certs`].
     pub verify: bool,
     /// Path to CA certificate file
     pub ca_file: Option<String>,
-    /// Path to client certificate file
+    /// Path to client certificate file, presented for mutual TLS
+    ///
+    /// Must be set together with `key_file`.
     pub cert_file: Option<String>,
     /// Path to client private key file
     pub key_file: Option<String>,
@@ -270,6 +403,65 @@ pub struct TlsConfig {
     pub max_version: Option<TlsVersion>,
 }
 
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            verify: true,
+            ca_file: None,
+            cert_file: None,
+            key_file: None,
+            server_name: None,
+            min_version: None,
+            max_version: None,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Accept any server certificate without verification
+    ///
+    /// **DANGER: development only.** This installs a certificate verifier that
+    /// accepts every certificate, including expired, self-signed and
+    /// wrong-host ones, so anyone on the network path can impersonate the
+    /// server and read or alter all traffic. It exists for talking to a local
+    /// server with a self-signed certificate; never enable it in production.
+    ///
+    /// Cannot be combined with [`TlsConfig::ca_file`]: trusting a custom CA
+    /// is the safe way to accept a private certificate.
+    pub fn danger_accept_invalid_certs(mut self) -> Self {
+        self.verify = false;
+        self
+    }
+
+    /// Present a client certificate to servers requiring mutual TLS
+    pub fn client_cert(
+        mut self,
+        cert_file: impl Into<String>,
+        key_file: impl Into<String>,
+    ) -> Self {
+        self.cert_file = Some(cert_file.into());
+        self.key_file = Some(key_file.into());
+        self
+    }
+
+    /// Validate the TLS configuration
+    pub fn validate(&self) -> aerosocket_core::Result<()> {
+        if self.cert_file.is_some() != self.key_file.is_some() {
+            return Err(Error::Config(ConfigError::Validation(
+                "cert_file and key_file must be set together for client authentication".to_string(),
+            )));
+        }
+
+        if !self.verify && self.ca_file.is_some() {
+            return Err(Error::Config(ConfigError::Validation(
+                "danger_accept_invalid_certs cannot be combined with a custom ca_file".to_string(),
+            )));
+        }
+
+        Ok(())
+    }
+}
+
 /// TLS version
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TlsVersion {
@@ -374,8 +566,9 @@ impl rustls::client::ServerCertVerifier for NoCertificateVerification {
 
 /// Build a rustls ClientConfig from this TlsConfig
 #[cfg(feature = "transport-tls")]
-#[allow(deprecated)]
 pub fn build_rustls_client_config(tls: &TlsConfig) -> aerosocket_core::Result<RustlsClientConfig> {
+    tls.validate()?;
+
     // Root store: either custom CA file or system/webpki roots
     let mut root_store = RootCertStore::empty();
 
@@ -406,7 +599,7 @@ pub fn build_rustls_client_config(tls: &TlsConfig) -> aerosocket_core::Result<Ru
     let mut config = if let (Some(cert_path), Some(key_path)) = (&tls.cert_file, &tls.key_file) {
         let certs = load_certs(cert_path)?;
         let key = load_private_key(key_path)?;
-        builder.with_single_cert(certs, key).map_err(|e| {
+        builder.with_client_auth_cert(certs, key).map_err(|e| {
             Error::Config(ConfigError::Validation(format!(
                 "Invalid client certificate/key: {}",
                 e
@@ -416,7 +609,8 @@ pub fn build_rustls_client_config(tls: &TlsConfig) -> aerosocket_core::Result<Ru
         builder.with_no_client_auth()
     };
 
-    // Apply verify flag: when false, disable certificate verification
+    // Apply verify flag: when false (danger_accept_invalid_certs), disable
+    // certificate verification entirely
     if !tls.verify {
         config
             .dangerous()
@@ -454,6 +648,17 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn test_compression_requires_feature() {
+        let mut config = ClientConfig::default();
+        config.compression.enabled = true;
+        assert!(matches!(
+            config.validate(),
+            Err(Error::Config(ConfigError::Validation(_)))
+        ));
+    }
+
     #[test]
     fn test_client_config_builder() {
         let config = ClientConfig::default()
@@ -472,4 +677,137 @@ mod tests {
             ("X-Custom".to_string(), "value".to_string())
         );
     }
+
+    #[test]
+    fn test_accept_invalid_certs_excludes_custom_ca() {
+        let tls = TlsConfig::default().danger_accept_invalid_certs();
+        assert!(!tls.verify);
+        assert!(tls.validate().is_ok());
+
+        let tls = TlsConfig {
+            ca_file: Some("ca.pem".to_string()),
+            ..tls
+        };
+        assert!(matches!(
+            tls.validate(),
+            Err(Error::Config(ConfigError::Validation(_)))
+        ));
+        assert!(ClientConfig::default().tls(tls).validate().is_err());
+    }
+
+    #[test]
+    fn test_client_cert_needs_both_files() {
+        let tls = TlsConfig::default().client_cert("client.pem", "client.key");
+        assert!(tls.validate().is_ok());
+
+        let tls = TlsConfig {
+            key_file: None,
+            ..tls
+        };
+        assert!(matches!(
+            tls.validate(),
+            Err(Error::Config(ConfigError::Validation(_)))
+        ));
+    }
+
+    /// Start a TLS server on localhost with a fresh self-signed certificate
+    #[cfg(feature = "transport-tls")]
+    async fn self_signed_server() -> std::net::SocketAddr {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![Certificate(cert.serialize_der().unwrap())],
+                PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let _ = acceptor.accept(tcp).await;
+            }
+        });
+        addr
+    }
+
+    #[cfg(feature = "transport-tls")]
+    #[tokio::test]
+    async fn test_self_signed_server_requires_accept_invalid_certs() {
+        use aerosocket_transport_tls::TlsStream;
+
+        let addr = self_signed_server().await;
+
+        let verifying = build_rustls_client_config(&TlsConfig::default()).unwrap();
+        assert!(TlsStream::connect(addr, Arc::new(verifying), "localhost")
+            .await
+            .is_err());
+
+        let accepting =
+            build_rustls_client_config(&TlsConfig::default().danger_accept_invalid_certs())
+                .unwrap();
+        assert!(TlsStream::connect(addr, Arc::new(accepting), "localhost")
+            .await
+            .is_ok());
+    }
+
+    #[cfg(feature = "transport-tls")]
+    #[tokio::test]
+    async fn test_client_certificate_is_presented() {
+        use aerosocket_transport_tls::TlsStream;
+
+        let mut ca_params = rcgen::CertificateParams::new(Vec::new());
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+        let client = rcgen::generate_simple_self_signed(vec!["client".to_string()]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("client.pem");
+        let key_path = dir.path().join("client.key");
+        std::fs::write(&cert_path, client.serialize_pem_with_signer(&ca).unwrap()).unwrap();
+        std::fs::write(&key_path, client.serialize_private_key_pem()).unwrap();
+
+        // Server requiring a client certificate signed by `ca`
+        let server_cert =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(&Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(
+                rustls::server::AllowAnyAuthenticatedClient::new(roots).boxed(),
+            )
+            .with_single_cert(
+                vec![Certificate(server_cert.serialize_der().unwrap())],
+                PrivateKey(server_cert.serialize_private_key_der()),
+            )
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (results_tx, mut results) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let _ = results_tx.send(acceptor.accept(tcp).await.is_ok());
+            }
+        });
+
+        let with_cert = TlsConfig::default()
+            .danger_accept_invalid_certs()
+            .client_cert(cert_path.to_str().unwrap(), key_path.to_str().unwrap());
+        let config = build_rustls_client_config(&with_cert).unwrap();
+        let _stream = TlsStream::connect(addr, Arc::new(config), "localhost")
+            .await
+            .unwrap();
+        assert_eq!(results.recv().await, Some(true));
+
+        let without_cert = TlsConfig::default().danger_accept_invalid_certs();
+        let config = build_rustls_client_config(&without_cert).unwrap();
+        let _ = TlsStream::connect(addr, Arc::new(config), "localhost").await;
+        assert_eq!(results.recv().await, Some(false));
+    }
 }
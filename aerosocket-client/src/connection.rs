@@ -2,15 +2,38 @@
 //!
 //! This module provides connection management for WebSocket clients.
 
+use aerosocket_core::error::{FrameError, TimeoutError};
 use aerosocket_core::frame::Frame;
-use aerosocket_core::protocol::Opcode;
+use aerosocket_core::protocol::{constants, Opcode};
 use aerosocket_core::transport::TransportStream;
+#[cfg(feature = "compression")]
+use aerosocket_core::DeflateContext;
 use aerosocket_core::{Message, Result};
 use bytes::{Bytes, BytesMut};
 use std::fmt;
 use std::net::SocketAddr;
+use std::time::Duration;
 
 /// Represents a WebSocket client connection
+///
+/// This is the only connection type; the crate root and the prelude both
+/// re-export it. A connection created with [`ClientConnection::new`] has no
+/// stream yet, so sending and receiving fail instead of being dropped:
+///
+/// ```rust
+/// use aerosocket_client::{connection, ClientConnection};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let addr = "127.0.0.1:8080".parse().unwrap();
+/// let mut conn: connection::ClientConnection = ClientConnection::new(addr);
+///
+/// assert!(!conn.is_connected());
+/// assert!(conn.send_text("hello").await.is_err());
+/// assert!(conn.next().await.is_err());
+/// assert!(conn.close(Some(1000), None).await.is_err());
+/// # }
+/// ```
 pub struct ClientConnection {
     /// Server address
     remote_addr: SocketAddr,
@@ -19,6 +42,19 @@ pub struct ClientConnection {
     /// Connection metadata
     pub metadata: ConnectionMetadata,
     stream: Option<Box<dyn TransportStream>>,
+    /// Negotiated permessage-deflate state, kept across messages
+    #[cfg(feature = "compression")]
+    deflate: Option<DeflateContext>,
+    /// Bytes read from the stream but not yet parsed into a frame
+    read_buffer: BytesMut,
+    /// Whether outgoing frames are masked
+    mask_frames: bool,
+    /// Largest payload length accepted in a frame header
+    max_frame_size: usize,
+    /// Enforce every RFC 6455 MUST, failing the connection on any violation
+    strict_protocol: bool,
+    /// Ping schedule while `next()` waits for frames
+    keepalive: Option<Keepalive>,
 }
 
 /// Connection state
@@ -53,8 +89,13 @@ pub struct ConnectionMetadata {
     pub bytes_sent: u64,
     /// Bytes received count
     pub bytes_received: u64,
-    /// Whether compression was negotiated
+    /// Whether the server accepted `permessage-deflate`
+    ///
+    /// Frames with RSV1 set are rejected unless it did, whatever the client
+    /// offered.
     pub compression_negotiated: bool,
+    /// When the server last answered a ping
+    pub last_pong_at: Option<std::time::Instant>,
 }
 
 impl ClientConnection {
@@ -73,8 +114,16 @@ impl ClientConnection {
                 bytes_sent: 0,
                 bytes_received: 0,
                 compression_negotiated: false,
+                last_pong_at: None,
             },
             stream: None,
+            #[cfg(feature = "compression")]
+            deflate: None,
+            read_buffer: BytesMut::new(),
+            mask_frames: true,
+            max_frame_size: constants::DEFAULT_MAX_FRAME_SIZE,
+            strict_protocol: false,
+            keepalive: None,
         }
     }
 
@@ -94,8 +143,16 @@ impl ClientConnection {
                 bytes_sent: 0,
                 bytes_received: 0,
                 compression_negotiated: false,
+                last_pong_at: None,
             },
             stream: Some(stream),
+            #[cfg(feature = "compression")]
+            deflate: None,
+            read_buffer: BytesMut::new(),
+            mask_frames: true,
+            max_frame_size: constants::DEFAULT_MAX_FRAME_SIZE,
+            strict_protocol: false,
+            keepalive: None,
         }
     }
 
@@ -114,28 +171,77 @@ impl ClientConnection {
         &self.metadata
     }
 
+    /// Compress and decompress data messages through a per-connection context
+    ///
+    /// Set once `permessage-deflate` is negotiated. Messages sent with
+    /// [`send`](Self::send) are compressed, and incoming compressed messages
+    /// are inflated whole, so the window can carry over between messages
+    /// when context takeover is in effect.
+    #[cfg(feature = "compression")]
+    pub fn set_deflate(&mut self, deflate: Option<DeflateContext>) {
+        self.deflate = deflate;
+    }
+
     fn update_activity(&mut self) {
         let now = std::time::Instant::now();
         self.metadata.last_activity_at = now;
     }
 
     /// Send a message
+    ///
+    /// A Ping or Pong over 125 bytes, or a Close RFC 6455 does not allow on
+    /// the wire, is refused before anything is written.
     #[cfg_attr(feature = "logging", tracing::instrument(skip(self, message)))]
     pub async fn send(&mut self, message: Message) -> Result<()> {
+        message.validate()?;
+        let frame = message.into_frame();
+        #[cfg(feature = "compression")]
+        let frame = match &mut self.deflate {
+            Some(deflate) => frame.compress_with(deflate),
+            None => frame,
+        };
+        self.send_frames(vec![frame]).await
+    }
+
+    /// Send a data message split into frames carrying at most `fragment_size`
+    /// payload bytes each
+    ///
+    /// Control messages cannot be fragmented and are sent as a single frame.
+    pub async fn send_fragmented(&mut self, message: Message, fragment_size: usize) -> Result<()> {
+        let (opcode, payload) = match message {
+            Message::Text(text) => (Opcode::Text, text.into_frame().payload),
+            Message::Binary(data) => (Opcode::Binary, data.into_frame().payload),
+            control => return self.send(control).await,
+        };
+
+        let fragment_size = fragment_size.max(1);
+        let chunk_count = payload.len().div_ceil(fragment_size).max(1);
+        let frames = (0..chunk_count)
+            .map(|i| {
+                let start = i * fragment_size;
+                let end = (start + fragment_size).min(payload.len());
+                let opcode = if i == 0 { opcode } else { Opcode::Continuation };
+                Frame::new(opcode, payload.slice(start..end)).fin(i + 1 == chunk_count)
+            })
+            .collect();
+
+        self.send_frames(frames).await
+    }
+
+    /// Mask and write the frames of one message
+    ///
+    /// Every frame gets its own freshly generated masking key, as RFC 6455
+    /// requires of all client-to-server frames, continuations included,
+    /// unless masking has been turned off with
+    /// [`set_mask_frames`](Self::set_mask_frames).
+    async fn send_frames(&mut self, frames: Vec<Frame>) -> Result<()> {
         self.update_activity();
 
         if let Some(stream) = &mut self.stream {
-            let frame = match message {
-                Message::Text(text) => Frame::text(text.as_bytes().to_vec()),
-                Message::Binary(data) => Frame::binary(data.as_bytes().to_vec()),
-                Message::Ping(data) => Frame::ping(data.as_bytes().to_vec()),
-                Message::Pong(data) => Frame::pong(data.as_bytes().to_vec()),
-                Message::Close(close_msg) => {
-                    Frame::close(close_msg.code(), Some(close_msg.reason()))
-                }
-            };
-
-            let frame_bytes = frame.to_bytes();
+            let mut frame_bytes = BytesMut::new();
+            for frame in frames {
+                frame.mask(self.mask_frames).write_to(&mut frame_bytes);
+            }
 
             #[cfg(feature = "metrics")]
             {
@@ -189,48 +295,133 @@ impl ClientConnection {
     #[cfg_attr(feature = "logging", tracing::instrument(skip(self)))]
     pub async fn next(&mut self) -> Result<Option<Message>> {
         self.update_activity();
+        #[cfg(feature = "compression")]
+        let inflates_messages = self.deflate.is_some();
+        #[cfg(not(feature = "compression"))]
+        let inflates_messages = false;
 
         if let Some(stream) = &mut self.stream {
+            // An unfragmented message keeps the frame's payload as is; only
+            // fragmented ones are copied into a contiguous buffer
+            let mut single_payload = None;
             let mut message_buffer = Vec::new();
             let mut final_frame = false;
             let mut opcode = None;
+            #[cfg(feature = "compression")]
+            let mut compressed = false;
 
             while !final_frame {
-                let mut frame_buffer = BytesMut::new();
-
-                loop {
-                    let mut temp_buf = [0u8; 2];
-                    let n = stream.read(&mut temp_buf).await?;
-                    if n == 0 {
-                        self.state = ConnectionState::Closed;
-                        return Ok(None);
-                    }
-                    frame_buffer.extend_from_slice(&temp_buf[..n]);
-
-                    if frame_buffer.len() >= 2 {
-                        break;
+                // Frames may arrive split across reads or several per read, so
+                // leftover bytes stay buffered for the next frame
+                let parsed = loop {
+                    let parsed = if inflates_messages {
+                        Frame::parse_compressed(&mut self.read_buffer, self.max_frame_size)
+                    } else {
+                        Frame::parse(
+                            &mut self.read_buffer,
+                            self.metadata.compression_negotiated,
+                            self.max_frame_size,
+                        )
+                    };
+                    match parsed {
+                        Err(aerosocket_core::Error::Frame(FrameError::InsufficientData {
+                            ..
+                        })) => {
+                            // Read straight onto the end of the buffer, so one
+                            // read can carry many frames
+                            let start = self.read_buffer.len();
+                            let read = stream.read_buf(
+                                &mut self.read_buffer,
+                                constants::DEFAULT_READ_BUFFER_SIZE,
+                            );
+                            let read = match &mut self.keepalive {
+                                Some(keepalive) => {
+                                    let remaining =
+                                        keepalive.remaining(self.metadata.last_activity_at);
+                                    match tokio::time::timeout(remaining, read).await {
+                                        Ok(read) => read,
+                                        Err(_) => {
+                                            // Drop the space reserved by the abandoned read
+                                            self.read_buffer.truncate(start);
+                                            if let Some(timeout) = keepalive.expire() {
+                                                fail_connection(stream, 1001, self.mask_frames)
+                                                    .await;
+                                                self.state = ConnectionState::Closed;
+                                                return Err(aerosocket_core::Error::Timeout(
+                                                    TimeoutError::Read { timeout },
+                                                ));
+                                            }
+                                            stream
+                                                .write_all(
+                                                    &Frame::ping(Bytes::new())
+                                                        .mask(self.mask_frames)
+                                                        .to_bytes(),
+                                                )
+                                                .await?;
+                                            stream.flush().await?;
+                                            continue;
+                                        }
+                                    }
+                                }
+                                None => read.await,
+                            };
+                            if read? == 0 {
+                                self.state = ConnectionState::Closed;
+                                return Ok(None);
+                            }
+                        }
+                        result => break result,
                     }
-                }
+                };
 
-                match Frame::parse(&mut frame_buffer, self.metadata.compression_negotiated) {
+                match parsed {
                     Ok(frame) => {
+                        // Any frame, control frames included, counts as activity
+                        self.metadata.last_activity_at = std::time::Instant::now();
+                        if let Some(keepalive) = &mut self.keepalive {
+                            keepalive.ping_sent_at = None;
+                        }
+
+                        if self.strict_protocol {
+                            // RFC 6455 section 5.1: servers must not mask frames
+                            let checked = if frame.masked {
+                                Err(aerosocket_core::Error::Protocol(
+                                    aerosocket_core::error::ProtocolError::InvalidFrame(
+                                        "Masked server frame".to_string(),
+                                    ),
+                                ))
+                            } else {
+                                frame.validate_strict()
+                            };
+                            if let Err(e) = checked {
+                                let code = e.close_code().map_or(1002, |code| code.code());
+                                fail_connection(stream, code, self.mask_frames).await;
+                                self.state = ConnectionState::Closed;
+                                return Err(e);
+                            }
+                        }
+
                         match frame.opcode {
                             Opcode::Ping => {
-                                let ping_data = frame.payload.to_vec();
-                                stream.write_all(&Frame::pong(ping_data).to_bytes()).await?;
+                                stream
+                                    .write_all(
+                                        &Frame::pong(frame.payload)
+                                            .mask(self.mask_frames)
+                                            .to_bytes(),
+                                    )
+                                    .await?;
                                 stream.flush().await?;
                                 continue;
                             }
                             Opcode::Pong => {
+                                self.metadata.last_pong_at = Some(std::time::Instant::now());
                                 continue;
                             }
                             Opcode::Close => {
-                                let close_code = if frame.payload.len() >= 2 {
-                                    let code_bytes = &frame.payload[..2];
-                                    u16::from_be_bytes([code_bytes[0], code_bytes[1]])
-                                } else {
-                                    1000
-                                };
+                                // An empty payload carries no status code
+                                let close_code = (frame.payload.len() >= 2).then(|| {
+                                    u16::from_be_bytes([frame.payload[0], frame.payload[1]])
+                                });
 
                                 let close_reason = if frame.payload.len() > 2 {
                                     String::from_utf8_lossy(&frame.payload[2..]).to_string()
@@ -239,24 +430,27 @@ impl ClientConnection {
                                 };
 
                                 self.state = ConnectionState::Closing;
-                                return Ok(Some(Message::close(
-                                    Some(close_code),
-                                    Some(close_reason),
-                                )));
+                                return Ok(Some(Message::close(close_code, Some(close_reason))));
                             }
                             Opcode::Continuation | Opcode::Text | Opcode::Binary => {
-                                // If this is the first frame, record its opcode
-                                if opcode.is_none() {
-                                    opcode = Some(frame.opcode);
-                                } else if frame.opcode != Opcode::Continuation {
+                                // The first frame carries the message opcode and
+                                // every later one must be a continuation
+                                if opcode.is_some() != (frame.opcode == Opcode::Continuation) {
                                     return Err(aerosocket_core::Error::Protocol(
-                                        aerosocket_core::error::ProtocolError::InvalidFrame(
-                                            "Expected continuation frame".to_string(),
-                                        ),
+                                        aerosocket_core::error::ProtocolError::InvalidContinuation,
                                     ));
                                 }
+                                #[cfg(feature = "compression")]
+                                if opcode.is_none() {
+                                    compressed = frame.rsv[0];
+                                }
+                                opcode.get_or_insert(frame.opcode);
 
-                                message_buffer.extend_from_slice(&frame.payload);
+                                if frame.fin && frame.opcode != Opcode::Continuation {
+                                    single_payload = Some(frame.payload);
+                                } else {
+                                    message_buffer.extend_from_slice(&frame.payload);
+                                }
                                 final_frame = frame.fin;
                             }
                             _ => {
@@ -267,32 +461,45 @@ impl ClientConnection {
                         }
                     }
                     Err(e) => {
-                        let mut temp_buf = [0u8; 1024];
-                        match stream.read(&mut temp_buf).await {
-                            Ok(0) => {
-                                self.state = ConnectionState::Closed;
-                                return Ok(None);
-                            }
-                            Ok(n) => {
-                                let _ = e;
-                                frame_buffer.extend_from_slice(&temp_buf[..n]);
-                            }
-                            Err(err) => return Err(err),
+                        if let (true, Some(code)) = (self.strict_protocol, e.close_code()) {
+                            fail_connection(stream, code.code(), self.mask_frames).await;
+                            self.state = ConnectionState::Closed;
                         }
-                        continue;
+                        return Err(e);
                     }
                 }
             }
 
+            let payload = single_payload.unwrap_or_else(|| Bytes::from(message_buffer));
+
+            // A compressed message is inflated whole, as its deflate stream
+            // runs across the frame boundaries
+            #[cfg(feature = "compression")]
+            let payload = match &mut self.deflate {
+                Some(deflate) if compressed => match deflate.decompress(&payload) {
+                    Ok(inflated) => Bytes::from(inflated),
+                    Err(e) => {
+                        let code = e.close_code().map_or(1007, |code| code.code());
+                        fail_connection(stream, code, self.mask_frames).await;
+                        self.state = ConnectionState::Closed;
+                        return Err(e);
+                    }
+                },
+                _ => payload,
+            };
+            let message_len = payload.len();
             let message = match opcode.unwrap_or(Opcode::Text) {
-                Opcode::Text => {
-                    let text = String::from_utf8_lossy(&message_buffer).to_string();
-                    Message::text(text)
-                }
-                Opcode::Binary => {
-                    let data = Bytes::from(message_buffer.clone());
-                    Message::binary(data)
-                }
+                // Takes over the payload's allocation when nothing else shares it
+                Opcode::Text => match String::from_utf8(Vec::from(payload)) {
+                    Ok(text) => Message::text(text),
+                    Err(_) if self.strict_protocol => {
+                        fail_connection(stream, 1007, self.mask_frames).await;
+                        self.state = ConnectionState::Closed;
+                        return Err(aerosocket_core::Error::InvalidUtf8);
+                    }
+                    Err(e) => Message::text(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+                },
+                Opcode::Binary => Message::binary(payload),
                 _ => {
                     return Err(aerosocket_core::Error::Other(
                         "Invalid message opcode".to_string(),
@@ -301,15 +508,15 @@ impl ClientConnection {
             };
 
             self.metadata.messages_received += 1;
-            self.metadata.bytes_received += message_buffer.len() as u64;
+            self.metadata.bytes_received += message_len as u64;
 
             #[cfg(feature = "metrics")]
             {
                 metrics::counter!("aerosocket_client_messages_received_total").increment(1);
                 metrics::counter!("aerosocket_client_bytes_received_total")
-                    .increment(message_buffer.len() as u64);
+                    .increment(message_len as u64);
                 metrics::histogram!("aerosocket_client_message_size_bytes")
-                    .record(message_buffer.len() as f64);
+                    .record(message_len as f64);
             }
 
             Ok(Some(message))
@@ -362,13 +569,67 @@ impl ClientConnection {
         self.metadata.last_activity_at = std::time::Instant::now();
     }
 
+    /// Set whether outgoing frames are masked
+    ///
+    /// Masking is required by RFC 6455; only servers that allow unmasked
+    /// clients accept unmasked frames.
+    pub fn set_mask_frames(&mut self, mask: bool) {
+        self.mask_frames = mask;
+    }
+
+    /// Set the largest payload a single frame may declare
+    ///
+    /// The length field is checked before any payload is buffered, so
+    /// `next()` fails with [`FrameError::TooLarge`] without reading the body.
+    pub fn set_max_frame_size(&mut self, size: usize) {
+        self.max_frame_size = size;
+    }
+
+    /// Enforce every RFC 6455 MUST on received frames
+    ///
+    /// Masked server frames, oversized control frames, malformed Close
+    /// payloads and invalid close codes fail the connection with 1002, and
+    /// text that is not valid UTF-8 with 1007. A Close frame carrying the
+    /// code is sent before the error is returned.
+    pub fn set_strict_protocol(&mut self, strict: bool) {
+        self.strict_protocol = strict;
+    }
+
+    /// Ping a quiet server every `interval` while `next()` waits for frames
+    ///
+    /// With a `timeout`, a server that sends nothing at all, pong or
+    /// otherwise, within that long of a ping is closed with 1001 and `next()`
+    /// fails with [`TimeoutError::Read`]. A `None` interval turns keepalive off.
+    pub fn set_keepalive(&mut self, interval: Option<Duration>, timeout: Option<Duration>) {
+        self.keepalive = interval.map(|interval| Keepalive {
+            interval,
+            timeout,
+            ping_sent_at: None,
+        });
+    }
+
+    /// When the server last answered a ping
+    pub fn last_pong_at(&self) -> Option<std::time::Instant> {
+        self.metadata.last_pong_at
+    }
+
+    /// Queue bytes already read from the stream ahead of any further reads
+    #[cfg(any(feature = "transport-tcp", feature = "transport-tls"))]
+    pub(crate) fn buffer_received(&mut self, data: &[u8]) {
+        self.read_buffer.extend_from_slice(data);
+    }
+
     /// Set the subprotocol
     pub fn set_subprotocol(&mut self, subprotocol: String) {
         self.metadata.subprotocol = Some(subprotocol);
     }
 
-    /// Add an extension
+    /// Add an extension accepted by the server
     pub fn add_extension(&mut self, extension: String) {
+        if extension.eq_ignore_ascii_case(aerosocket_core::protocol::extensions::PERMESSAGE_DEFLATE)
+        {
+            self.metadata.compression_negotiated = true;
+        }
         self.metadata.extensions.push(extension);
     }
 }
@@ -435,9 +696,57 @@ impl fmt::Debug for ClientConnectionHandle {
     }
 }
 
+/// Ping schedule for a connection with keepalive enabled
+#[derive(Debug, Clone, Copy)]
+struct Keepalive {
+    /// Quiet time before a ping is sent
+    interval: Duration,
+    /// How long a ping may go unanswered before the connection is closed
+    timeout: Option<Duration>,
+    /// When the outstanding ping was sent
+    ping_sent_at: Option<std::time::Instant>,
+}
+
+impl Keepalive {
+    /// Time left before the next ping is due or the outstanding one expires
+    fn remaining(&self, last_activity: std::time::Instant) -> Duration {
+        match (self.ping_sent_at, self.timeout) {
+            (Some(sent), Some(timeout)) => timeout.saturating_sub(sent.elapsed()),
+            (Some(sent), None) => self.interval.saturating_sub(sent.elapsed()),
+            (None, _) => self.interval.saturating_sub(last_activity.elapsed()),
+        }
+    }
+
+    /// Called once `remaining` has run out: returns the timeout if the
+    /// outstanding ping went unanswered, otherwise records a new ping as sent
+    fn expire(&mut self) -> Option<Duration> {
+        match (self.ping_sent_at, self.timeout) {
+            (Some(_), Some(timeout)) => Some(timeout),
+            _ => {
+                self.ping_sent_at = Some(std::time::Instant::now());
+                None
+            }
+        }
+    }
+}
+
+/// Best-effort write of a Close frame when strict mode or keepalive fails the connection
+async fn fail_connection(stream: &mut Box<dyn TransportStream>, code: u16, mask: bool) {
+    let reason = match code {
+        1001 => "Keepalive timeout",
+        1007 => "Invalid payload data",
+        _ => "Protocol error",
+    };
+    let frame = Frame::close(Some(code), Some(reason)).mask(mask).to_bytes();
+    if stream.write_all(&frame).await.is_ok() {
+        let _ = stream.flush().await;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aerosocket_core::transport::mock::ScriptedStream;
 
     #[test]
     fn test_client_connection_creation() {
@@ -483,4 +792,185 @@ mod tests {
         let _ = conn.ping(None).await;
         let _ = conn.pong(None).await;
     }
+
+    #[tokio::test]
+    async fn test_send_fragmented_masks_every_frame() {
+        let stream = ScriptedStream::new(vec![]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = ClientConnection::with_stream(remote, Box::new(stream));
+
+        conn.send_fragmented(Message::text("hello world!"), 5)
+            .await
+            .unwrap();
+
+        let mut buf = BytesMut::from(&written.lock().unwrap()[..]);
+        let mut frames = Vec::new();
+        while !buf.is_empty() {
+            frames.push(Frame::parse(&mut buf, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap());
+        }
+
+        let opcodes: Vec<_> = frames.iter().map(|f| f.opcode).collect();
+        assert_eq!(
+            opcodes,
+            [Opcode::Text, Opcode::Continuation, Opcode::Continuation]
+        );
+        let fins: Vec<_> = frames.iter().map(|f| f.fin).collect();
+        assert_eq!(fins, [false, false, true]);
+        assert!(frames.iter().all(|f| f.masked && f.mask.is_some()));
+
+        let payload: Vec<u8> = frames.iter().flat_map(|f| f.payload.to_vec()).collect();
+        assert_eq!(payload, b"hello world!");
+        assert_eq!(conn.metadata().messages_sent, 1);
+    }
+
+    #[tokio::test]
+    async fn test_leftover_bytes_survive_between_reads() {
+        // One read carries two whole frames and the start of a third
+        let third = Frame::text("three").to_bytes();
+        let mut first_read = Frame::text("one").to_bytes().to_vec();
+        first_read.extend_from_slice(&Frame::binary(&b"two"[..]).to_bytes());
+        first_read.extend_from_slice(&third[..3]);
+        let stream = ScriptedStream::new(vec![first_read, third[3..].to_vec()]);
+        let read_calls = stream.read_calls.clone();
+        let remote = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = ClientConnection::with_stream(remote, Box::new(stream));
+
+        let first = conn.next().await.unwrap().unwrap();
+        assert_eq!(first.as_text(), Some("one"));
+        let second = conn.next().await.unwrap().unwrap();
+        assert_eq!(second.as_bytes(), b"two");
+        assert_eq!(read_calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let third = conn.next().await.unwrap().unwrap();
+        assert_eq!(third.as_text(), Some("three"));
+        assert_eq!(read_calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_single_frame_payload_is_not_copied() {
+        let stream = ScriptedStream::new(vec![]);
+        let remote = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = ClientConnection::with_stream(remote, Box::new(stream));
+
+        let data = vec![0x5a; 1024];
+        conn.read_buffer
+            .extend_from_slice(&Frame::binary(data.clone()).to_bytes());
+        // 2 byte header and 2 byte extended length
+        let payload_ptr = conn.read_buffer[4..].as_ptr();
+
+        let Message::Binary(binary) = conn.next().await.unwrap().unwrap() else {
+            panic!("expected a Binary message");
+        };
+        assert_eq!(binary.as_bytes().as_ptr(), payload_ptr);
+        assert_eq!(binary.as_bytes(), &data[..]);
+    }
+
+    #[tokio::test]
+    async fn test_ping_between_fragments_is_answered() {
+        let stream = ScriptedStream::new(vec![
+            Frame::new(Opcode::Text, "Hel")
+                .fin(false)
+                .to_bytes()
+                .to_vec(),
+            Frame::ping("are you there").to_bytes().to_vec(),
+            Frame::new(Opcode::Continuation, "lo").to_bytes().to_vec(),
+        ]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = ClientConnection::with_stream(remote, Box::new(stream));
+
+        let message = conn.next().await.unwrap().unwrap();
+        assert_eq!(message.as_text(), Some("Hello"));
+
+        let mut sent = BytesMut::from(&written.lock().unwrap()[..]);
+        let pong = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(pong.opcode, Opcode::Pong);
+        assert_eq!(&pong.payload[..], b"are you there");
+        assert!(sent.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_pings_quiet_server_then_gives_up() {
+        let mut stream = ScriptedStream::new(vec![Frame::pong("hi").to_bytes().to_vec()]);
+        stream.stall_when_empty = true;
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = ClientConnection::with_stream(remote, Box::new(stream));
+        conn.set_keepalive(
+            Some(Duration::from_millis(50)),
+            Some(Duration::from_millis(100)),
+        );
+
+        let result = tokio::time::timeout(Duration::from_secs(5), conn.next())
+            .await
+            .expect("next() ignored the keepalive timeout");
+        assert!(matches!(
+            result,
+            Err(aerosocket_core::Error::Timeout(TimeoutError::Read { timeout }))
+                if timeout == Duration::from_millis(100)
+        ));
+        assert!(conn.last_pong_at().is_some());
+        assert!(conn.is_closed());
+
+        // A masked ping, then a masked Close with 1001
+        let mut sent = BytesMut::from(&written.lock().unwrap()[..]);
+        let ping = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(ping.opcode, Opcode::Ping);
+        assert!(ping.masked);
+        let close = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(close.opcode, Opcode::Close);
+        assert_eq!(&close.payload[..2], &1001u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_mask_frames_can_be_disabled() {
+        let stream = ScriptedStream::new(vec![]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = ClientConnection::with_stream(remote, Box::new(stream));
+        conn.set_mask_frames(false);
+
+        conn.send_text("plain").await.unwrap();
+
+        assert_eq!(
+            written.lock().unwrap()[..],
+            Frame::text("plain").to_bytes()[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_strict_mode_rejects_masked_server_frames() {
+        let stream = ScriptedStream::new(vec![]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = ClientConnection::with_stream(remote, Box::new(stream));
+        conn.set_strict_protocol(true);
+        conn.read_buffer
+            .extend_from_slice(&Frame::text("hello").mask(true).to_bytes());
+
+        assert!(conn.next().await.is_err());
+        assert!(conn.is_closed());
+
+        let mut sent = BytesMut::from(&written.lock().unwrap()[..]);
+        let close = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(close.opcode, Opcode::Close);
+        assert_eq!(&close.payload[..2], &1002u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_bare_close_received_has_no_status() {
+        let stream = ScriptedStream::new(vec![]);
+        let remote = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = ClientConnection::with_stream(remote, Box::new(stream));
+        conn.set_connected();
+        conn.read_buffer
+            .extend_from_slice(&Frame::close(None, None).to_bytes());
+
+        let Some(Message::Close(close)) = conn.next().await.unwrap() else {
+            panic!("expected a Close message");
+        };
+        assert_eq!(close.code(), None);
+        assert_eq!(close.status(), aerosocket_core::error::CloseCode::NoStatus);
+    }
 }
@@ -35,11 +35,15 @@
 pub mod client;
 pub mod config;
 pub mod connection;
+pub mod reconnect;
+pub mod resolver;
 
 // Prelude module
 pub mod prelude;
 
 // Re-export key types for convenience
 pub use client::{Client, ClientBuilder};
-pub use config::{ClientConfig, CompressionConfig, TlsConfig};
+pub use config::{ClientConfig, CompressionConfig, TcpTransportConfig, TlsConfig};
 pub use connection::ClientConnection;
+pub use reconnect::{ClientEvent, ReconnectingClient};
+pub use resolver::{Resolver, SystemResolver};
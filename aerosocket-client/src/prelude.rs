@@ -8,6 +8,8 @@ pub use crate::config::{ClientConfig, CompressionConfig, TlsConfig};
 pub use crate::connection::{
     ClientConnection, ClientConnectionHandle, ConnectionMetadata, ConnectionState,
 };
+pub use crate::reconnect::{ClientEvent, ReconnectingClient};
+pub use crate::resolver::{Resolver, SystemResolver};
 
 // Re-export core types for convenience
 pub use aerosocket_core::prelude::*;
@@ -0,0 +1,444 @@
+//! Automatically reconnecting client
+//!
+//! [`ReconnectingClient`] wraps a [`Client`] and replaces its connection when
+//! it drops, backing off between attempts as configured by
+//! [`ReconnectionConfig`]. Applications can follow the connection lifecycle
+//! through [`ReconnectingClient::on_event`], e.g. to drive UI state.
+
+use crate::client::Client;
+use crate::config::ReconnectionConfig;
+use crate::connection::ClientConnection;
+use aerosocket_core::{Error, Message, Result};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Connection lifecycle transition reported by a [`ReconnectingClient`]
+///
+/// `attempt` is 0 for the initial connection and counts reconnection
+/// attempts from 1 after that, restarting whenever a connection succeeds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// A connection attempt is starting
+    Connecting {
+        /// Attempt number
+        attempt: usize,
+    },
+    /// The connection has been established
+    Connected {
+        /// Attempt number that succeeded
+        attempt: usize,
+    },
+    /// An established connection was lost
+    Disconnected {
+        /// Error that ended the connection, if it did not close cleanly
+        error: Option<String>,
+    },
+    /// Waiting before the next reconnection attempt
+    Reconnecting {
+        /// Attempt number about to be made
+        attempt: usize,
+        /// Backoff delay before the attempt
+        delay: Duration,
+        /// Error from the previous attempt or lost connection
+        last_error: Option<String>,
+    },
+    /// `max_attempts` was reached; the client stays disconnected
+    GaveUp {
+        /// Number of reconnection attempts made
+        attempts: usize,
+        /// Error from the last attempt
+        last_error: Option<String>,
+    },
+}
+
+type EventCallback = Arc<dyn Fn(ClientEvent) + Send + Sync>;
+
+/// Client that reconnects with exponential backoff when its connection drops
+///
+/// Reconnection follows the wrapped client's
+/// [`ClientConfig::reconnection`](crate::config::ClientConfig::reconnection)
+/// settings; wrapping a client opts in regardless of the `enabled` flag.
+/// Messages are not replayed: a message whose send fails is lost, and the
+/// next call reconnects.
+pub struct ReconnectingClient {
+    client: Client,
+    reconnection: ReconnectionConfig,
+    connection: Option<ClientConnection>,
+    on_event: Option<EventCallback>,
+}
+
+impl std::fmt::Debug for ReconnectingClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReconnectingClient")
+            .field("client", &self.client)
+            .field("reconnection", &self.reconnection)
+            .field("connected", &self.connection.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ReconnectingClient {
+    /// Wrap a client
+    pub fn new(client: Client) -> Self {
+        let reconnection = client.config().reconnection.clone();
+        Self {
+            client,
+            reconnection,
+            connection: None,
+            on_event: None,
+        }
+    }
+
+    /// Call `f` on every lifecycle transition
+    ///
+    /// The callback runs inline on the task driving the client, so it should
+    /// return quickly.
+    pub fn on_event<F>(mut self, f: F) -> Self
+    where
+        F: Fn(ClientEvent) + Send + Sync + 'static,
+    {
+        self.on_event = Some(Arc::new(f));
+        self
+    }
+
+    /// Current connection, if one is established
+    pub fn connection(&mut self) -> Option<&mut ClientConnection> {
+        self.connection.as_mut()
+    }
+
+    /// Whether a connection is currently established
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Establish the initial connection, retrying with backoff if it fails
+    pub async fn connect(&mut self) -> Result<()> {
+        if self.connection.is_some() {
+            return Ok(());
+        }
+
+        self.emit(ClientEvent::Connecting { attempt: 0 });
+        match self.client.clone().connect().await {
+            Ok(connection) => {
+                self.connection = Some(connection);
+                self.emit(ClientEvent::Connected { attempt: 0 });
+                Ok(())
+            }
+            Err(e) => self.reconnect(e.to_string(), e).await,
+        }
+    }
+
+    /// Receive the next message, reconnecting if the connection is lost
+    ///
+    /// A Close from the server, end of stream and read errors all count as a
+    /// lost connection. Returns an error only once reconnecting gives up.
+    pub async fn next(&mut self) -> Result<Option<Message>> {
+        loop {
+            self.connect().await?;
+            let Some(connection) = self.connection.as_mut() else {
+                continue;
+            };
+
+            let error = match connection.next().await {
+                Ok(Some(Message::Close(_))) | Ok(None) => None,
+                Ok(Some(message)) => return Ok(Some(message)),
+                Err(e) => Some(e),
+            };
+            self.connection = None;
+            let last_error = error.as_ref().map(ToString::to_string);
+            self.emit(ClientEvent::Disconnected {
+                error: last_error.clone(),
+            });
+
+            let cause = error.unwrap_or_else(|| Error::Connection("Connection closed".to_string()));
+            self.reconnect(last_error.unwrap_or_else(|| cause.to_string()), cause)
+                .await?;
+        }
+    }
+
+    /// Send a message, connecting first if needed
+    ///
+    /// If the send fails the connection is dropped and the error returned;
+    /// the message is not retried.
+    pub async fn send(&mut self, message: Message) -> Result<()> {
+        self.connect().await?;
+        let Some(connection) = self.connection.as_mut() else {
+            return Err(Error::Connection("Not connected".to_string()));
+        };
+
+        let result = connection.send(message).await;
+        if let Err(e) = &result {
+            self.connection = None;
+            self.emit(ClientEvent::Disconnected {
+                error: Some(e.to_string()),
+            });
+        }
+        result
+    }
+
+    /// Close the current connection without reconnecting
+    pub async fn close(&mut self, code: Option<u16>, reason: Option<&str>) -> Result<()> {
+        match self.connection.take() {
+            Some(mut connection) => connection.close(code, reason).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Retry with backoff until a connection succeeds or `max_attempts` is reached
+    async fn reconnect(&mut self, mut last_error: String, mut cause: Error) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            if self
+                .reconnection
+                .max_attempts
+                .is_some_and(|max| attempt > max)
+            {
+                self.emit(ClientEvent::GaveUp {
+                    attempts: attempt - 1,
+                    last_error: Some(last_error),
+                });
+                return Err(cause);
+            }
+
+            let delay = backoff_delay(&self.reconnection, attempt);
+            self.emit(ClientEvent::Reconnecting {
+                attempt,
+                delay,
+                last_error: Some(last_error),
+            });
+            tokio::time::sleep(delay).await;
+
+            self.emit(ClientEvent::Connecting { attempt });
+            match self.client.clone().connect().await {
+                Ok(connection) => {
+                    self.connection = Some(connection);
+                    self.emit(ClientEvent::Connected { attempt });
+                    return Ok(());
+                }
+                Err(e) => {
+                    last_error = e.to_string();
+                    cause = e;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    fn emit(&self, event: ClientEvent) {
+        if let Some(on_event) = &self.on_event {
+            on_event(event);
+        }
+    }
+}
+
+/// Delay before reconnection `attempt` (1-based), with jitter applied
+fn backoff_delay(config: &ReconnectionConfig, attempt: usize) -> Duration {
+    let exponent = i32::try_from(attempt.saturating_sub(1)).unwrap_or(i32::MAX);
+    let base = (config.initial_delay.as_secs_f64() * config.backoff_multiplier.powi(exponent))
+        .min(config.max_delay.as_secs_f64());
+    let jitter = config.jitter.clamp(0.0, 1.0) * (rand::random::<f64>() * 2.0 - 1.0);
+    Duration::from_secs_f64((base * (1.0 + jitter)).max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "transport-tcp")]
+    use crate::config::ClientConfig;
+
+    #[test]
+    fn test_backoff_delay_grows_and_caps() {
+        let config = ReconnectionConfig {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+            backoff_multiplier: 2.0,
+            jitter: 0.0,
+            ..Default::default()
+        };
+        let delays: Vec<_> = (1..=5).map(|n| backoff_delay(&config, n)).collect();
+        assert_eq!(delays, [100, 200, 400, 500, 500].map(Duration::from_millis));
+
+        let config = ReconnectionConfig {
+            jitter: 0.5,
+            ..config
+        };
+        for _ in 0..100 {
+            let delay = backoff_delay(&config, 1);
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(150));
+        }
+    }
+
+    /// Accept one connection and answer its WebSocket handshake
+    #[cfg(feature = "transport-tcp")]
+    async fn accept_handshake(listener: &tokio::net::TcpListener) -> tokio::net::TcpStream {
+        use aerosocket_core::handshake::{
+            create_server_handshake, parse_client_handshake, response_to_string, HandshakeConfig,
+        };
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let mut chunk = [0u8; 1024];
+            let n = stream.read(&mut chunk).await.unwrap();
+            request.extend_from_slice(&chunk[..n]);
+        }
+        let request = parse_client_handshake(&String::from_utf8_lossy(&request)).unwrap();
+        let response = create_server_handshake(&request, &HandshakeConfig::default());
+        let response = response_to_string(&response.unwrap());
+        stream.write_all(response.as_bytes()).await.unwrap();
+        stream
+    }
+
+    /// Send a text message, then keep the connection open for the rest of the test
+    #[cfg(feature = "transport-tcp")]
+    async fn greet_and_hold(mut stream: tokio::net::TcpStream, text: &'static str) {
+        use aerosocket_core::frame::Frame;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        stream
+            .write_all(&Frame::text(text).to_bytes())
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            let mut sink = [0u8; 1024];
+            while matches!(stream.read(&mut sink).await, Ok(n) if n > 0) {}
+        });
+    }
+
+    /// Server that drops its first connection right after the handshake and
+    /// greets the next one with a text message
+    #[cfg(feature = "transport-tcp")]
+    async fn flaky_server() -> std::net::SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for connection in 0.. {
+                let stream = accept_handshake(&listener).await;
+                if connection > 0 {
+                    greet_and_hold(stream, "hello").await;
+                }
+            }
+        });
+        addr
+    }
+
+    #[cfg(feature = "transport-tcp")]
+    #[tokio::test]
+    async fn test_on_event_follows_reconnection() {
+        let addr = flaky_server().await;
+        let config = ClientConfig::default().reconnection_config(ReconnectionConfig {
+            initial_delay: Duration::from_millis(10),
+            jitter: 0.0,
+            ..Default::default()
+        });
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let mut client = ReconnectingClient::new(Client::new(addr).with_config(config))
+            .on_event(move |event| sink.lock().unwrap().push(event));
+
+        client.connect().await.unwrap();
+        let message = client.next().await.unwrap().unwrap();
+        assert_eq!(message.as_text(), Some("hello"));
+
+        let events = events.lock().unwrap().clone();
+        assert_eq!(events[0], ClientEvent::Connecting { attempt: 0 });
+        assert_eq!(events[1], ClientEvent::Connected { attempt: 0 });
+        assert!(matches!(events[2], ClientEvent::Disconnected { .. }));
+        assert!(matches!(
+            events[3],
+            ClientEvent::Reconnecting { attempt: 1, delay, .. } if delay == Duration::from_millis(10)
+        ));
+        assert_eq!(events[4], ClientEvent::Connecting { attempt: 1 });
+        assert_eq!(events[5], ClientEvent::Connected { attempt: 1 });
+        assert_eq!(events.len(), 6);
+    }
+
+    #[cfg(feature = "transport-tcp")]
+    #[tokio::test]
+    async fn test_reconnects_after_server_restart() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = ClientConfig::default().reconnection_config(ReconnectionConfig {
+            initial_delay: Duration::from_millis(20),
+            max_delay: Duration::from_millis(20),
+            jitter: 0.0,
+            ..Default::default()
+        });
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let mut client = ReconnectingClient::new(Client::new(addr).with_config(config))
+            .on_event(move |event| sink.lock().unwrap().push(event));
+
+        let (_, mut stream) = tokio::join!(client.connect(), accept_handshake(&listener));
+        stream
+            .write_all(&aerosocket_core::frame::Frame::text("before").to_bytes())
+            .await
+            .unwrap();
+        let message = client.next().await.unwrap().unwrap();
+        assert_eq!(message.as_text(), Some("before"));
+
+        // Kill the server mid-stream and bring it back on the same port later
+        drop(stream);
+        drop(listener);
+        let restart = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+            greet_and_hold(accept_handshake(&listener).await, "after").await;
+            listener
+        });
+
+        let message = client.next().await.unwrap().unwrap();
+        assert_eq!(message.as_text(), Some("after"));
+        assert!(client.is_connected());
+        let _listener = restart.await.unwrap();
+
+        let events = events.lock().unwrap();
+        let failed_attempts = events
+            .iter()
+            .filter(
+                |event| matches!(event, ClientEvent::Reconnecting { attempt, .. } if *attempt > 1),
+            )
+            .count();
+        assert!(failed_attempts > 0, "server was down for several attempts");
+        assert!(matches!(
+            events.last(),
+            Some(ClientEvent::Connected { attempt }) if *attempt == failed_attempts + 1
+        ));
+    }
+
+    #[cfg(feature = "transport-tcp")]
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        // Nothing listens on a freshly released port
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let config = ClientConfig::default().reconnection_config(ReconnectionConfig {
+            max_attempts: Some(2),
+            initial_delay: Duration::from_millis(1),
+            jitter: 0.0,
+            ..Default::default()
+        });
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let mut client = ReconnectingClient::new(Client::new(addr).with_config(config))
+            .on_event(move |event| sink.lock().unwrap().push(event));
+
+        assert!(client.connect().await.is_err());
+        let events = events.lock().unwrap();
+        assert!(matches!(
+            events.last(),
+            Some(ClientEvent::GaveUp {
+                attempts: 2,
+                last_error: Some(_)
+            })
+        ));
+    }
+}
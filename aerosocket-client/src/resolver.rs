@@ -0,0 +1,162 @@
+//! Host name resolution and connection racing
+//!
+//! Clients created from a host name resolve it through a [`Resolver`] (the
+//! system resolver by default) and then race the resulting addresses with
+//! Happy Eyeballs (RFC 8305), so an unreachable IPv6 route does not stall a
+//! dual-stack connection.
+
+use aerosocket_core::{Error, Result};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// Default delay between staggered connection attempts (RFC 8305 section 5)
+pub const DEFAULT_CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Future returned by [`Resolver::resolve`]
+pub type ResolveFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<SocketAddr>>> + Send + 'a>>;
+
+/// Custom host name resolution
+pub trait Resolver: Send + Sync + std::fmt::Debug {
+    /// Resolve `host` to the socket addresses to try for `port`, in order of preference
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a>;
+}
+
+/// Shared resolver handle
+pub type SharedResolver = Arc<dyn Resolver>;
+
+/// Resolver backed by the operating system (`getaddrinfo`)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host, port))
+                .await
+                .map_err(Error::Io)?;
+            Ok(addrs.collect())
+        })
+    }
+}
+
+/// Order addresses for racing, alternating between address families
+///
+/// The first family in the resolver's answer goes first, as RFC 8305
+/// section 4 recommends.
+fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+    let prefer_v6 = first.is_ipv6();
+    let (preferred, other): (Vec<_>, Vec<_>) =
+        addrs.into_iter().partition(|a| a.is_ipv6() == prefer_v6);
+
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+    ordered
+}
+
+/// Connect to the first reachable address using Happy Eyeballs
+///
+/// Attempts start `attempt_delay` apart, or as soon as the previous attempt
+/// fails, and run concurrently; the first to complete wins and the rest are
+/// dropped. Returns the last error if every attempt fails.
+pub async fn connect_happy_eyeballs(
+    addrs: Vec<SocketAddr>,
+    attempt_delay: Duration,
+) -> Result<TcpStream> {
+    let mut pending = interleave_families(addrs).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    loop {
+        if attempts.is_empty() {
+            match pending.next() {
+                Some(addr) => attempts.push(TcpStream::connect(addr)),
+                None => break,
+            }
+        }
+
+        let stagger = tokio::time::sleep(attempt_delay);
+        tokio::select! {
+            Some(result) = attempts.next() => match result {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    last_error = Some(e);
+                    if let Some(addr) = pending.next() {
+                        attempts.push(TcpStream::connect(addr));
+                    }
+                }
+            },
+            _ = stagger => {
+                if let Some(addr) = pending.next() {
+                    attempts.push(TcpStream::connect(addr));
+                }
+            }
+        }
+    }
+
+    Err(match last_error {
+        Some(e) => Error::Io(e),
+        None => Error::Other("Host name resolved to no addresses".to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct StaticResolver(Vec<SocketAddr>);
+
+    impl Resolver for StaticResolver {
+        fn resolve<'a>(&'a self, _host: &'a str, _port: u16) -> ResolveFuture<'a> {
+            Box::pin(async move { Ok(self.0.clone()) })
+        }
+    }
+
+    #[test]
+    fn test_interleave_families() {
+        let addrs: Vec<SocketAddr> = ["[::1]:1", "[::2]:1", "10.0.0.1:1", "10.0.0.2:1"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let ordered: Vec<String> = interleave_families(addrs)
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(ordered, ["[::1]:1", "10.0.0.1:1", "[::2]:1", "10.0.0.2:1"]);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_ipv6_falls_back_to_ipv4_quickly() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // 100::/64 is the IPv6 discard prefix (RFC 6666): it never answers
+        let resolver = StaticResolver(vec![
+            SocketAddr::new("100::1".parse().unwrap(), port),
+            SocketAddr::new("127.0.0.1".parse().unwrap(), port),
+        ]);
+        let addrs = resolver.resolve("dual-stack.test", port).await.unwrap();
+
+        let start = std::time::Instant::now();
+        let stream = connect_happy_eyeballs(addrs, DEFAULT_CONNECTION_ATTEMPT_DELAY)
+            .await
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(stream.peer_addr().unwrap().is_ipv4());
+    }
+}
@@ -0,0 +1,95 @@
+//! Frame benchmarks
+//!
+//! Measures frame serialization, parsing (masked and unmasked) and raw
+//! masking throughput across a range of payload sizes.
+//!
+//! Run with `cargo bench -p aerosocket-core --bench frame`.
+
+use aerosocket_core::frame::{apply_mask, Frame};
+use aerosocket_core::protocol::constants::DEFAULT_MAX_FRAME_SIZE;
+use bytes::BytesMut;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const PAYLOAD_SIZES: &[usize] = &[16, 1024, 64 * 1024, 1024 * 1024];
+
+fn payload(size: usize) -> Vec<u8> {
+    (0..size).map(|i| i as u8).collect()
+}
+
+fn bench_serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_serialize");
+    for &size in PAYLOAD_SIZES {
+        let frame = Frame::binary(payload(size));
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("unmasked", size), &frame, |b, frame| {
+            let mut buf = BytesMut::with_capacity(size + 14);
+            b.iter(|| {
+                buf.clear();
+                black_box(frame).write_to(&mut buf);
+                black_box(&buf);
+            });
+        });
+
+        let masked = Frame::binary(payload(size)).mask(true);
+        group.bench_with_input(BenchmarkId::new("masked", size), &masked, |b, frame| {
+            let mut buf = BytesMut::with_capacity(size + 14);
+            b.iter(|| {
+                buf.clear();
+                black_box(frame).write_to(&mut buf);
+                black_box(&buf);
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_parse");
+    for &size in PAYLOAD_SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+
+        let unmasked = Frame::binary(payload(size)).to_bytes();
+        group.bench_with_input(BenchmarkId::new("unmasked", size), &unmasked, |b, bytes| {
+            b.iter(|| {
+                let mut buf = BytesMut::from(&bytes[..]);
+                black_box(Frame::parse(&mut buf, false, DEFAULT_MAX_FRAME_SIZE).unwrap());
+            });
+        });
+
+        let masked = Frame::binary(payload(size)).mask(true).to_bytes();
+        group.bench_with_input(BenchmarkId::new("masked", size), &masked, |b, bytes| {
+            b.iter(|| {
+                let mut buf = BytesMut::from(&bytes[..]);
+                black_box(Frame::parse(&mut buf, false, DEFAULT_MAX_FRAME_SIZE).unwrap());
+            });
+        });
+    }
+    group.finish();
+}
+
+/// One byte at a time, as a baseline for `apply_mask`
+fn apply_mask_bytewise(data: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+fn bench_mask(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mask");
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    for &size in PAYLOAD_SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("word", size), &size, |b, &size| {
+            let mut data = payload(size);
+            b.iter(|| apply_mask(black_box(&mut data), mask));
+        });
+        group.bench_with_input(BenchmarkId::new("bytewise", size), &size, |b, &size| {
+            let mut data = payload(size);
+            b.iter(|| apply_mask_bytewise(black_box(&mut data), mask));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_serialize, bench_parse, bench_mask);
+criterion_main!(benches);
@@ -0,0 +1,400 @@
+//! Per-connection `permessage-deflate` state (RFC 7692)
+//!
+//! A [`DeflateContext`] keeps one deflate and one inflate stream for the
+//! life of a connection. With context takeover a message may refer back to
+//! earlier ones, which is where repetitive payloads gain most; without it
+//! the stream is reset after every message, re-primed with the preset
+//! dictionary if one is configured.
+
+use crate::error::{Error, FrameError, MessageError, Result};
+use crate::handshake::DeflateParams;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+/// Size of the deflate sliding window; older dictionary bytes are unreachable
+const DEFLATE_WINDOW: usize = 32 * 1024;
+
+/// Window bits of the full 32 KiB window, used when none were negotiated
+const MAX_WINDOW_BITS: u8 = 15;
+
+/// Empty stored block ending every sync flush, left off on the wire
+/// (RFC 7692 section 7.2.1)
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// The part of `dictionary` the sliding window can still reach
+fn dictionary_window(dictionary: &[u8]) -> &[u8] {
+    &dictionary[dictionary.len().saturating_sub(DEFLATE_WINDOW)..]
+}
+
+/// Compression state for one side of a `permessage-deflate` connection
+///
+/// Outgoing messages go through [`compress`](Self::compress) and incoming
+/// ones through [`decompress`](Self::decompress), one whole message at a
+/// time, since the sliding window spans frame boundaries.
+pub struct DeflateContext {
+    compressor: Compress,
+    decompressor: Decompress,
+    /// Keep the compression window across outgoing messages
+    compress_takeover: bool,
+    /// Keep the decompression window across incoming messages
+    decompress_takeover: bool,
+    /// Window the peer compresses with, restored when the inflater is reset
+    decompress_window_bits: u8,
+    /// Preset dictionary both streams start from
+    dictionary: Option<Vec<u8>>,
+    /// Largest decompressed message accepted
+    max_message_size: usize,
+}
+
+impl std::fmt::Debug for DeflateContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeflateContext")
+            .field("compress_takeover", &self.compress_takeover)
+            .field("decompress_takeover", &self.decompress_takeover)
+            .field("decompress_window_bits", &self.decompress_window_bits)
+            .field("dictionary", &self.dictionary.as_ref().map(Vec::len))
+            .field("max_message_size", &self.max_message_size)
+            .finish()
+    }
+}
+
+impl DeflateContext {
+    /// Create a context compressing at `level` (0-9)
+    ///
+    /// `compress_takeover` keeps the window of outgoing messages and
+    /// `decompress_takeover` that of incoming ones.
+    pub fn new(level: u32, compress_takeover: bool, decompress_takeover: bool) -> Self {
+        Self::with_window_bits(
+            level,
+            (compress_takeover, MAX_WINDOW_BITS),
+            (decompress_takeover, MAX_WINDOW_BITS),
+        )
+    }
+
+    /// Create the server's context for the negotiated parameters
+    ///
+    /// Outgoing messages use no more than `server_max_window_bits`, and the
+    /// inflater is sized for `client_max_window_bits`.
+    pub fn for_server(params: &DeflateParams, level: u32) -> Self {
+        Self::with_window_bits(
+            level,
+            (
+                !params.server_no_context_takeover,
+                negotiated_window_bits(params.server_max_window_bits),
+            ),
+            (
+                !params.client_no_context_takeover,
+                negotiated_window_bits(params.client_max_window_bits),
+            ),
+        )
+    }
+
+    /// Create the client's context for the negotiated parameters
+    ///
+    /// The mirror of [`for_server`](Self::for_server).
+    pub fn for_client(params: &DeflateParams, level: u32) -> Self {
+        Self::with_window_bits(
+            level,
+            (
+                !params.client_no_context_takeover,
+                negotiated_window_bits(params.client_max_window_bits),
+            ),
+            (
+                !params.server_no_context_takeover,
+                negotiated_window_bits(params.server_max_window_bits),
+            ),
+        )
+    }
+
+    /// Create a context from the takeover flag and window bits of each direction
+    fn with_window_bits(level: u32, compress: (bool, u8), decompress: (bool, u8)) -> Self {
+        let (compress_takeover, compress_window_bits) = compress;
+        let (decompress_takeover, decompress_window_bits) = decompress;
+        Self {
+            compressor: Compress::new_with_window_bits(
+                Compression::new(level.min(9)),
+                false,
+                compress_window_bits,
+            ),
+            decompressor: Decompress::new_with_window_bits(false, decompress_window_bits),
+            compress_takeover,
+            decompress_takeover,
+            decompress_window_bits,
+            dictionary: None,
+            max_message_size: usize::MAX,
+        }
+    }
+
+    /// Prime both streams with a preset dictionary agreed with the peer
+    ///
+    /// Only the last 32 KiB are used. The dictionary is restored whenever a
+    /// stream is reset between messages.
+    pub fn with_dictionary(mut self, dictionary: Option<Vec<u8>>) -> Self {
+        self.dictionary = dictionary.map(|dictionary| dictionary_window(&dictionary).to_vec());
+        self.reset_compressor();
+        self.reset_decompressor();
+        self
+    }
+
+    /// Set the largest message [`decompress`](Self::decompress) may produce
+    ///
+    /// Unlimited by default.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Compress one whole message payload
+    ///
+    /// The output is sync-flushed with the trailing `00 00 ff ff` removed,
+    /// ready to be sent with RSV1 set.
+    pub fn compress(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(payload.len() / 2 + 64);
+        let result = deflate_into(&mut self.compressor, payload, &mut output);
+        // After a failure the peer could not follow our window any more
+        if result.is_err() || !self.compress_takeover {
+            self.reset_compressor();
+        }
+        result?;
+
+        if output.ends_with(&DEFLATE_TAIL) {
+            output.truncate(output.len() - DEFLATE_TAIL.len());
+        }
+        Ok(output)
+    }
+
+    /// Decompress one whole message payload received with RSV1 set
+    ///
+    /// Fails with [`MessageError::TooLarge`] once the output passes the
+    /// maximum message size, so a small payload cannot inflate without bound.
+    pub fn decompress(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        let max_size = self.max_message_size;
+        let mut output = Vec::with_capacity(payload.len().saturating_mul(2).min(max_size));
+        let result = inflate_into(&mut self.decompressor, payload, &mut output, max_size).and_then(
+            |ended| {
+                if ended {
+                    Ok(true)
+                } else {
+                    inflate_into(&mut self.decompressor, &DEFLATE_TAIL, &mut output, max_size)
+                }
+            },
+        );
+
+        // A final block ends the peer's stream, so its next message starts afresh
+        if !matches!(result, Ok(false)) || !self.decompress_takeover {
+            self.reset_decompressor();
+        }
+        result?;
+        Ok(output)
+    }
+
+    fn reset_compressor(&mut self) {
+        self.compressor.reset();
+        if let Some(dictionary) = &self.dictionary {
+            if self.compressor.set_dictionary(dictionary).is_err() {
+                self.compressor.reset();
+            }
+        }
+    }
+
+    fn reset_decompressor(&mut self) {
+        // `reset` goes back to the full window, so a smaller one is rebuilt
+        if self.decompress_window_bits < MAX_WINDOW_BITS {
+            self.decompressor =
+                Decompress::new_with_window_bits(false, self.decompress_window_bits);
+        } else {
+            self.decompressor.reset(false);
+        }
+        if let Some(dictionary) = &self.dictionary {
+            if self.decompressor.set_dictionary(dictionary).is_err() {
+                self.decompressor.reset(false);
+            }
+        }
+    }
+}
+
+/// Window bits to use for a negotiated limit
+///
+/// zlib has no 8-bit window. The handshake never lets this side compress
+/// with one, and a 9-bit inflater reads what a peer compressed with 8 bits.
+fn negotiated_window_bits(bits: Option<u8>) -> u8 {
+    bits.map_or(MAX_WINDOW_BITS, |bits| bits.clamp(9, MAX_WINDOW_BITS))
+}
+
+/// Run `input` through `compressor` and sync-flush it into `output`
+fn deflate_into(compressor: &mut Compress, mut input: &[u8], output: &mut Vec<u8>) -> Result<()> {
+    loop {
+        if output.capacity() - output.len() < 64 {
+            output.reserve(input.len().max(1024));
+        }
+        let consumed = compressor.total_in();
+        compressor
+            .compress_vec(input, output, FlushCompress::Sync)
+            .map_err(std::io::Error::other)?;
+        input = &input[(compressor.total_in() - consumed) as usize..];
+
+        // The flush is complete once all input is in and output space is left over
+        if input.is_empty() && output.len() < output.capacity() {
+            return Ok(());
+        }
+    }
+}
+
+/// Inflate `input` into `output`, returning whether the stream ended
+fn inflate_into(
+    decompressor: &mut Decompress,
+    mut input: &[u8],
+    output: &mut Vec<u8>,
+    max_size: usize,
+) -> Result<bool> {
+    loop {
+        if output.capacity() - output.len() < 64 {
+            output.reserve(output.len().max(1024));
+        }
+        let (consumed, produced) = (decompressor.total_in(), output.len());
+        let status = decompressor
+            .decompress_vec(input, output, FlushDecompress::Sync)
+            .map_err(|_| Error::Frame(FrameError::DecompressionFailed))?;
+        input = &input[(decompressor.total_in() - consumed) as usize..];
+
+        if output.len() > max_size {
+            return Err(MessageError::TooLarge {
+                size: output.len(),
+                max: max_size,
+            }
+            .into());
+        }
+        match status {
+            Status::StreamEnd => return Ok(true),
+            _ if input.is_empty() && output.len() < output.capacity() => return Ok(false),
+            // Neither side moved, so more input would be needed to continue
+            _ if decompressor.total_in() == consumed && output.len() == produced => {
+                return Err(FrameError::DecompressionFailed.into());
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Vec<u8> {
+        (0..200)
+            .map(|i| {
+                format!(
+                    r#"{{"id":{},"status":"ok","tags":["alpha","beta"]}}"#,
+                    i % 7
+                )
+            })
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    #[test]
+    fn test_context_takeover_shrinks_repeated_messages() {
+        let message = payload();
+        let mut sender = DeflateContext::new(6, true, true);
+        let mut receiver = DeflateContext::new(6, true, true);
+
+        let first = sender.compress(&message).unwrap();
+        let second = sender.compress(&message).unwrap();
+        assert!(second.len() < first.len());
+
+        assert_eq!(receiver.decompress(&first).unwrap(), message);
+        assert_eq!(receiver.decompress(&second).unwrap(), message);
+    }
+
+    #[test]
+    fn test_no_context_takeover_resets_between_messages() {
+        let message = payload();
+        let mut sender = DeflateContext::new(6, false, false);
+        let mut receiver = DeflateContext::new(6, false, false);
+
+        let first = sender.compress(&message).unwrap();
+        let second = sender.compress(&message).unwrap();
+        assert_eq!(first, second);
+
+        // Each message must inflate on its own
+        assert_eq!(receiver.decompress(&second).unwrap(), message);
+        assert_eq!(receiver.decompress(&first).unwrap(), message);
+    }
+
+    #[test]
+    fn test_params_pick_the_side_that_takes_over() {
+        let params = DeflateParams {
+            server_no_context_takeover: true,
+            ..DeflateParams::default()
+        };
+        let server = DeflateContext::for_server(&params, 6);
+        let client = DeflateContext::for_client(&params, 6);
+        assert!(!server.compress_takeover && server.decompress_takeover);
+        assert!(client.compress_takeover && !client.decompress_takeover);
+    }
+
+    #[test]
+    fn test_negotiated_window_bits_limit_back_references() {
+        // A block of noise repeated 4 KiB later, out of reach of a 9-bit window
+        let mut seed = 1u32;
+        let block: Vec<u8> = (0..4096)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let message = [block.clone(), block].concat();
+
+        let narrow = DeflateParams {
+            server_max_window_bits: Some(9),
+            ..DeflateParams::default()
+        };
+        let compressed = DeflateContext::for_server(&narrow, 6)
+            .compress(&message)
+            .unwrap();
+        let full = DeflateContext::for_server(&DeflateParams::default(), 6)
+            .compress(&message)
+            .unwrap();
+        assert!(compressed.len() > full.len() + 2048);
+
+        let mut client = DeflateContext::for_client(&narrow, 6);
+        assert_eq!(client.decompress(&compressed).unwrap(), message);
+    }
+
+    #[test]
+    fn test_dictionary_survives_resets() {
+        let dictionary = br#"{"type":"update","channel":"ticker"}"#.to_vec();
+        let message = br#"{"type":"update","channel":"ticker","price":1}"#;
+        let mut sender =
+            DeflateContext::new(6, false, false).with_dictionary(Some(dictionary.clone()));
+        let mut receiver = DeflateContext::new(6, false, false).with_dictionary(Some(dictionary));
+        let mut plain = DeflateContext::new(6, false, false);
+
+        for _ in 0..2 {
+            let compressed = sender.compress(message).unwrap();
+            assert!(compressed.len() < plain.compress(message).unwrap().len());
+            assert_eq!(receiver.decompress(&compressed).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_decompress_enforces_max_size() {
+        let message = vec![0u8; 64 * 1024];
+        let compressed = DeflateContext::new(6, true, true)
+            .compress(&message)
+            .unwrap();
+
+        let err = DeflateContext::new(6, true, true)
+            .with_max_message_size(1024)
+            .decompress(&compressed)
+            .unwrap_err();
+        assert!(matches!(err, Error::Message(MessageError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn test_decompress_rejects_garbage() {
+        let err = DeflateContext::new(6, true, true)
+            .decompress(&[0xff, 0xff, 0xff])
+            .unwrap_err();
+        assert!(matches!(err, Error::Frame(FrameError::DecompressionFailed)));
+    }
+}
@@ -63,6 +63,17 @@ pub enum Error {
     #[error("Invalid UTF-8 in text frame")]
     InvalidUtf8,
 
+    /// The requested transport was not compiled in
+    #[error(
+        "Transport not available: {requested} (enable the `{}` feature of aerosocket-server or `{}` of aerosocket-client)",
+        .requested.server_feature(),
+        .requested.client_feature()
+    )]
+    MissingTransport {
+        /// Transport that was requested by the configuration
+        requested: crate::transport::TransportType,
+    },
+
     /// Connection closed
     #[error("Connection closed: {code} - {reason}")]
     Closed {
@@ -73,6 +84,30 @@ pub enum Error {
     },
 }
 
+impl Error {
+    /// Close code to send to the peer when this error ends a connection
+    ///
+    /// Returns `None` for errors that are not caused by the peer's data, such as
+    /// local I/O or configuration failures.
+    pub fn close_code(&self) -> Option<CloseCode> {
+        match self {
+            Error::Protocol(_) => Some(CloseCode::ProtocolError),
+            Error::Frame(FrameError::TooLarge { .. })
+            | Error::Frame(FrameError::DecompressedTooLarge { .. })
+            | Error::Message(MessageError::TooLarge { .. })
+            | Error::Message(MessageError::TooManyFragments { .. })
+            | Error::CapacityExceeded { .. } => Some(CloseCode::TooBig),
+            Error::Frame(FrameError::DecompressionFailed) | Error::InvalidUtf8 => {
+                Some(CloseCode::InvalidPayload)
+            }
+            Error::Frame(FrameError::InsufficientData { .. }) => None,
+            Error::Frame(_) | Error::Message(_) => Some(CloseCode::ProtocolError),
+            Error::Security(SecurityError::PolicyViolation(_)) => Some(CloseCode::PolicyViolation),
+            _ => None,
+        }
+    }
+}
+
 /// WebSocket protocol specific errors
 #[derive(Error, Debug, Clone)]
 pub enum ProtocolError {
@@ -116,6 +151,19 @@ pub enum ProtocolError {
     #[error("Reserved bits set in frame")]
     ReservedBitsSet,
 
+    /// Data frame inside a fragmented message that is not a continuation,
+    /// or a continuation with no message to continue
+    #[error("Invalid continuation frame")]
+    InvalidContinuation,
+
+    /// Frame uses an opcode reserved by RFC 6455 (0x3-0x7, 0xB-0xF)
+    #[error("Reserved opcode: {0:#x}")]
+    ReservedOpcode(u8),
+
+    /// Client frame without the MASK bit (RFC 6455 section 5.1)
+    #[error("Client frames must be masked")]
+    MaskingRequired,
+
     /// Invalid HTTP method
     #[error("Invalid HTTP method: {0}")]
     InvalidMethod(String),
@@ -176,17 +224,29 @@ pub enum FrameError {
     #[error("Decompression failed")]
     DecompressionFailed,
 
+    /// Decompressed payload larger than allowed
+    #[error("Decompressed payload too large (max: {max})")]
+    DecompressedTooLarge { max: usize },
+
     /// Control frames cannot be fragmented
     #[error("Control frames cannot be fragmented")]
     FragmentedControlFrame,
+
+    /// Control frame payload over 125 bytes
+    #[error("Control frame payload too large: {size} bytes (max: 125)")]
+    ControlFrameTooLarge { size: usize },
 }
 
 /// Configuration errors
 #[derive(Error, Debug, Clone)]
 pub enum ConfigError {
     /// Invalid configuration value
-    #[error("Invalid configuration value for {field}: {value}")]
-    InvalidValue { field: String, value: String },
+    #[error("Invalid configuration value for {field}: {value} ({reason})")]
+    InvalidValue {
+        field: String,
+        value: String,
+        reason: String,
+    },
 
     /// Missing required configuration
     #[error("Missing required configuration: {field}")]
@@ -204,6 +264,10 @@ pub enum MessageError {
     #[error("Message too large: {size} bytes (max: {max})")]
     TooLarge { size: usize, max: usize },
 
+    /// Message split across too many frames
+    #[error("Message split across too many fragments: {count} (max: {max})")]
+    TooManyFragments { count: usize, max: usize },
+
     /// Invalid message format
     #[error("Invalid message format: {0}")]
     InvalidFormat(String),
@@ -244,6 +308,10 @@ pub enum SecurityError {
 /// Timeout errors
 #[derive(Error, Debug, Clone)]
 pub enum TimeoutError {
+    /// Connect timeout, covering address resolution, TCP and TLS
+    #[error("Connect timeout: {timeout:?}")]
+    Connect { timeout: std::time::Duration },
+
     /// Handshake timeout
     #[error("Handshake timeout: {timeout:?}")]
     Handshake { timeout: std::time::Duration },
@@ -342,6 +410,15 @@ impl CloseCode {
         }
     }
 
+    /// Create an application close code, failing outside 3000-4999
+    ///
+    /// Unlike [`from`](Self::from), which turns out-of-range values into
+    /// [`CloseCode::ProtocolError`], this reports them as
+    /// [`CloseError::InvalidCode`], like [`AppCloseCode::new`].
+    pub fn application(code: u16) -> std::result::Result<Self, CloseError> {
+        AppCloseCode::checked(code).map(Into::into)
+    }
+
     /// Get the numeric value of the close code
     pub fn code(&self) -> u16 {
         match self {
@@ -381,6 +458,97 @@ impl fmt::Display for CloseCode {
     }
 }
 
+/// Application-defined close code, guaranteed to lie in 3000-4999
+///
+/// RFC 6455 section 7.4.2 leaves this range to libraries and applications.
+/// Declaring codes as `AppCloseCode` constants keeps them in range and lets
+/// them be matched against a received [`CloseCode`]:
+///
+/// ```
+/// use aerosocket_core::error::{AppCloseCode, CloseCode};
+///
+/// const KICKED: AppCloseCode = AppCloseCode::new_const(4001);
+///
+/// let received = CloseCode::from(4001);
+/// assert_eq!(AppCloseCode::try_from(received).ok(), Some(KICKED));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AppCloseCode(u16);
+
+impl AppCloseCode {
+    /// Lowest application close code
+    pub const MIN: u16 = 3000;
+
+    /// Highest application close code
+    pub const MAX: u16 = 4999;
+
+    /// Create an application close code, failing outside 3000-4999
+    ///
+    /// Out-of-range values are reported as [`CloseError::InvalidCode`].
+    pub fn new(code: u16) -> Result<Self> {
+        Ok(Self::checked(code)?)
+    }
+
+    fn checked(code: u16) -> std::result::Result<Self, CloseError> {
+        if (Self::MIN..=Self::MAX).contains(&code) {
+            Ok(Self(code))
+        } else {
+            Err(CloseError::InvalidCode { code })
+        }
+    }
+
+    /// Create an application close code in a const context
+    ///
+    /// Panics outside 3000-4999, which fails compilation when used to
+    /// initialize a `const`.
+    pub const fn new_const(code: u16) -> Self {
+        assert!(
+            code >= Self::MIN && code <= Self::MAX,
+            "application close codes must be in 3000-4999"
+        );
+        Self(code)
+    }
+
+    /// Get the numeric value of the close code
+    pub const fn code(self) -> u16 {
+        self.0
+    }
+}
+
+impl From<AppCloseCode> for CloseCode {
+    fn from(code: AppCloseCode) -> Self {
+        CloseCode::Application(code.0)
+    }
+}
+
+impl From<AppCloseCode> for u16 {
+    fn from(code: AppCloseCode) -> Self {
+        code.0
+    }
+}
+
+impl TryFrom<CloseCode> for AppCloseCode {
+    type Error = Error;
+
+    fn try_from(code: CloseCode) -> Result<Self> {
+        Self::new(code.code())
+    }
+}
+
+impl TryFrom<u16> for AppCloseCode {
+    type Error = Error;
+
+    fn try_from(code: u16) -> Result<Self> {
+        Self::new(code)
+    }
+}
+
+impl fmt::Display for AppCloseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -392,6 +560,40 @@ mod tests {
         assert_eq!(CloseCode::from(999), CloseCode::ProtocolError);
     }
 
+    #[test]
+    fn test_application_close_code_range() {
+        for code in [3000, 4999] {
+            assert_eq!(
+                CloseCode::application(code).unwrap(),
+                CloseCode::Application(code)
+            );
+        }
+        for code in [2999, 5000] {
+            assert!(matches!(
+                CloseCode::application(code),
+                Err(CloseError::InvalidCode { code: c }) if c == code
+            ));
+        }
+    }
+
+    #[test]
+    fn test_app_close_code() {
+        let code = AppCloseCode::new(3001).unwrap();
+        assert_eq!(code.code(), 3001);
+        let close: CloseCode = code.into();
+        assert_eq!(close, CloseCode::Application(3001));
+        assert_eq!(AppCloseCode::try_from(close).unwrap(), code);
+        assert_eq!(u16::from(AppCloseCode::new_const(4999)), 4999);
+
+        for invalid in [1000, 2999, 5000] {
+            assert!(matches!(
+                AppCloseCode::new(invalid),
+                Err(Error::Close(CloseError::InvalidCode { code: c })) if c == invalid
+            ));
+        }
+        assert!(AppCloseCode::try_from(CloseCode::Normal).is_err());
+    }
+
     #[test]
     fn test_error_display() {
         let err = Error::Protocol(ProtocolError::UnsupportedVersion);
@@ -399,4 +601,22 @@ mod tests {
         println!("Error message: {}", msg); // Debug output
         assert!(msg.contains("protocol") || msg.contains("version") || msg.contains("WebSocket"));
     }
+
+    #[test]
+    fn test_reserved_opcode_maps_to_protocol_error_close() {
+        let err = Error::Protocol(ProtocolError::ReservedOpcode(0xB));
+        assert_eq!(err.close_code(), Some(CloseCode::ProtocolError));
+        assert_eq!(err.close_code().unwrap().code(), 1002);
+        assert!(err.to_string().contains("0xb"));
+    }
+
+    #[test]
+    fn test_missing_transport_display() {
+        let err = Error::MissingTransport {
+            requested: crate::transport::TransportType::Tls,
+        };
+        let msg = err.to_string();
+        assert!(msg.contains("`tls-transport`"), "{}", msg);
+        assert!(msg.contains("`transport-tls`"), "{}", msg);
+    }
 }
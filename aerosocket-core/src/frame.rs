@@ -4,11 +4,18 @@
 //! following the RFC 6455 WebSocket protocol specification.
 
 use crate::{
-    error::{Error, FrameError, Result},
-    protocol::{frame::*, Opcode},
+    error::{Error, FrameError, ProtocolError, Result},
+    protocol::{
+        constants::{DEFAULT_MAX_FRAME_SIZE, MAX_CONTROL_PAYLOAD_SIZE},
+        frame::*,
+        utils, Opcode,
+    },
 };
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
+#[cfg(feature = "compression")]
+use crate::compression::DeflateContext;
+
 /// Represents a WebSocket frame according to RFC 6455
 #[derive(Debug, Clone)]
 pub struct Frame {
@@ -55,30 +62,64 @@ impl Frame {
     }
 
     /// Create a close frame with optional code and reason
+    ///
+    /// Without a code the payload is empty: RFC 6455 section 5.5.1 only
+    /// allows a reason after a status code, so `reason` is dropped then.
+    /// A reason over 123 bytes makes a frame peers refuse; see
+    /// [`try_close`](Self::try_close).
     pub fn close(code: Option<u16>, reason: Option<&str>) -> Self {
         let mut payload = BytesMut::new();
 
         if let Some(code) = code {
             payload.put_u16(code);
-        }
-
-        if let Some(reason) = reason {
-            payload.put_slice(reason.as_bytes());
+            if let Some(reason) = reason {
+                payload.put_slice(reason.as_bytes());
+            }
         }
 
         Self::new(Opcode::Close, payload.freeze())
     }
 
+    /// Create a close frame, refusing a payload over 125 bytes
+    pub fn try_close(code: Option<u16>, reason: Option<&str>) -> Result<Self> {
+        Self::close(code, reason).checked_control()
+    }
+
     /// Create a ping frame
+    ///
+    /// The payload is not checked; see [`try_ping`](Self::try_ping).
     pub fn ping(payload: impl Into<Bytes>) -> Self {
         Self::new(Opcode::Ping, payload)
     }
 
+    /// Create a ping frame, refusing a payload over 125 bytes
+    pub fn try_ping(payload: impl Into<Bytes>) -> Result<Self> {
+        Self::ping(payload).checked_control()
+    }
+
     /// Create a pong frame
+    ///
+    /// The payload is not checked; see [`try_pong`](Self::try_pong).
     pub fn pong(payload: impl Into<Bytes>) -> Self {
         Self::new(Opcode::Pong, payload)
     }
 
+    /// Create a pong frame, refusing a payload over 125 bytes
+    pub fn try_pong(payload: impl Into<Bytes>) -> Result<Self> {
+        Self::pong(payload).checked_control()
+    }
+
+    /// Refuse a control frame whose payload RFC 6455 section 5.5 disallows
+    fn checked_control(self) -> Result<Self> {
+        if self.payload.len() > MAX_CONTROL_PAYLOAD_SIZE {
+            return Err(FrameError::ControlFrameTooLarge {
+                size: self.payload.len(),
+            }
+            .into());
+        }
+        Ok(self)
+    }
+
     /// Set the FIN bit
     pub fn fin(mut self, fin: bool) -> Self {
         self.fin = fin;
@@ -110,19 +151,31 @@ impl Frame {
     }
 
     /// Apply compression to the frame (for data frames)
+    ///
+    /// The frame is compressed on its own, as without context takeover, and
+    /// sync-flushed with the trailing `00 00 ff ff` removed.
     #[cfg(feature = "compression")]
     pub fn compress(mut self, enabled: bool) -> Self {
         if enabled && self.opcode.is_data() && !self.rsv[0] {
-            use flate2::write::DeflateEncoder;
-            use flate2::Compression;
-            use std::io::Write;
-
-            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::new(6));
-            if encoder.write_all(&self.payload).is_ok() && encoder.flush().is_ok() {
-                if let Ok(compressed) = encoder.finish() {
-                    self.payload = Bytes::from(compressed);
-                    self.rsv[0] = true;
-                }
+            if let Ok(compressed) = DeflateContext::new(6, false, false).compress(&self.payload) {
+                self.payload = Bytes::from(compressed);
+                self.rsv[0] = true;
+            }
+        }
+        self
+    }
+
+    /// Compress a whole-message data frame through a connection's context
+    ///
+    /// Unlike [`compress`](Self::compress), the context's window carries over
+    /// to later messages when context takeover is negotiated. On failure the
+    /// frame is left uncompressed.
+    #[cfg(feature = "compression")]
+    pub fn compress_with(mut self, context: &mut DeflateContext) -> Self {
+        if self.fin && matches!(self.opcode, Opcode::Text | Opcode::Binary) && !self.rsv[0] {
+            if let Ok(compressed) = context.compress(&self.payload) {
+                self.payload = Bytes::from(compressed);
+                self.rsv[0] = true;
             }
         }
         self
@@ -135,8 +188,23 @@ impl Frame {
         buf.freeze()
     }
 
+    /// Number of bytes the frame takes on the wire
+    pub fn encoded_len(&self) -> usize {
+        let len = self.payload.len();
+        let extended = if len < 126 {
+            0
+        } else if len <= u16::MAX as usize {
+            2
+        } else {
+            8
+        };
+        2 + extended + self.mask.map_or(0, |_| 4) + len
+    }
+
     /// Write the frame to a buffer
     pub fn write_to(&self, buf: &mut BytesMut) {
+        buf.reserve(14 + self.payload.len());
+
         // Write first byte
         let first_byte = ((self.fin as u8) << 7)
             | ((self.rsv[0] as u8) << 6)
@@ -169,7 +237,63 @@ impl Frame {
     }
 
     /// Parse a frame from bytes
-    pub fn parse(buf: &mut BytesMut, compression_enabled: bool) -> Result<Self> {
+    ///
+    /// Fails with [`FrameError::TooLarge`] as soon as the header declares a
+    /// payload longer than `max_frame_size`, before any payload is buffered.
+    /// A compressed payload may inflate to at most `max_frame_size` bytes;
+    /// past that, decompression stops and the frame is refused with
+    /// [`FrameError::DecompressedTooLarge`].
+    pub fn parse(
+        buf: &mut BytesMut,
+        compression_enabled: bool,
+        max_frame_size: usize,
+    ) -> Result<Self> {
+        let frame = Self::parse_frame(buf, compression_enabled, max_frame_size)?;
+
+        // Decompress payload if needed
+        #[cfg(feature = "compression")]
+        if frame.rsv[0] {
+            let decompressed = DeflateContext::new(6, false, false)
+                .with_max_message_size(max_frame_size)
+                .decompress(&frame.payload)
+                .map_err(|err| match err {
+                    Error::Message(crate::error::MessageError::TooLarge { .. }) => {
+                        FrameError::DecompressedTooLarge {
+                            max: max_frame_size,
+                        }
+                        .into()
+                    }
+                    err => err,
+                })?;
+            return Ok(Frame {
+                payload: Bytes::from(decompressed),
+                ..frame
+            });
+        }
+
+        Ok(frame)
+    }
+
+    /// Parse a frame of a `permessage-deflate` connection, leaving it compressed
+    ///
+    /// RSV1 is accepted on the first frame of a data message and kept set; the
+    /// caller reassembles the message and inflates it as a whole with the
+    /// connection's `DeflateContext`.
+    pub fn parse_compressed(buf: &mut BytesMut, max_frame_size: usize) -> Result<Self> {
+        let frame = Self::parse_frame(buf, true, max_frame_size)?;
+        // RFC 7692 section 6.1: only the first frame of a message may set RSV1
+        if frame.rsv[0] && frame.opcode == Opcode::Continuation {
+            return Err(FrameError::ReservedBitsSet.into());
+        }
+        Ok(frame)
+    }
+
+    /// Parse and validate a frame without decompressing it
+    fn parse_frame(
+        buf: &mut BytesMut,
+        compression_enabled: bool,
+        max_frame_size: usize,
+    ) -> Result<Self> {
         if buf.len() < 2 {
             return Err(FrameError::InsufficientData {
                 needed: 2,
@@ -186,8 +310,8 @@ impl Frame {
         let rsv1 = (first_byte & RSV1_BIT) != 0;
         let rsv2 = (first_byte & RSV2_BIT) != 0;
         let rsv3 = (first_byte & RSV3_BIT) != 0;
-        let opcode = Opcode::from(first_byte & OPCODE_MASK)
-            .ok_or(FrameError::InvalidOpcode(first_byte & OPCODE_MASK))?;
+        let opcode_bits = first_byte & OPCODE_MASK;
+        let opcode = Opcode::from(opcode_bits).ok_or(ProtocolError::ReservedOpcode(opcode_bits))?;
 
         // Read second byte
         let second_byte = cursor.get_u8();
@@ -212,7 +336,26 @@ impl Frame {
                 }
                 .into());
             }
-            payload_len = cursor.get_u64() as usize;
+            // A length the platform cannot address must not be truncated,
+            // or the rest of the frame would be read as the next one
+            payload_len = usize::try_from(cursor.get_u64()).unwrap_or(usize::MAX);
+        }
+
+        // RFC 6455 section 5.5: control frames carry at most 125 bytes
+        if opcode.is_control() && payload_len > MAX_CONTROL_PAYLOAD_SIZE {
+            return Err(FrameError::ControlFrameTooLarge { size: payload_len }.into());
+        }
+
+        // Check the declared length itself, so an oversized frame is refused
+        // before its payload is buffered; the cap keeps the frame length
+        // arithmetic below from overflowing
+        let max = max_frame_size.min(usize::MAX - (cursor.position() as usize + 4));
+        if payload_len > max {
+            return Err(FrameError::TooLarge {
+                size: payload_len,
+                max,
+            }
+            .into());
         }
 
         // Read masking key if present
@@ -240,32 +383,15 @@ impl Frame {
             .into());
         }
 
-        let mut payload = Bytes::copy_from_slice(
-            &buf[cursor.position() as usize..cursor.position() as usize + payload_len],
-        );
-
-        // Unmask payload if needed
+        // Take the frame out of the buffer without copying; the payload
+        // shares its allocation, unmasked in place
+        let header_len = cursor.position() as usize;
+        let mut frame_bytes = buf.split_to(header_len + payload_len);
+        frame_bytes.advance(header_len);
         if let Some(mask) = mask {
-            payload = mask_bytes(&payload, &mask);
-        }
-
-        // Decompress payload if needed
-        #[cfg(feature = "compression")]
-        if rsv1 && compression_enabled {
-            use flate2::read::DeflateDecoder;
-            use std::io::Read;
-
-            let mut decoder = DeflateDecoder::new(&payload[..]);
-            let mut decompressed = Vec::new();
-            if decoder.read_to_end(&mut decompressed).is_err() {
-                return Err(FrameError::DecompressionFailed.into());
-            }
-            payload = Bytes::from(decompressed);
+            apply_mask(&mut frame_bytes, mask);
         }
-
-        // Advance the buffer
-        let frame_len = cursor.position() as usize + payload_len;
-        buf.advance(frame_len);
+        let payload = frame_bytes.freeze();
 
         // Validate frame
         if opcode.is_control() && !fin {
@@ -299,6 +425,56 @@ impl Frame {
         }
     }
 
+    /// Check the frame against the RFC 6455 MUSTs that parsing tolerates
+    ///
+    /// Control frames may carry at most 125 payload bytes, and a Close payload
+    /// must be empty or hold a status code valid on the wire followed by a
+    /// UTF-8 reason. Reserved bits, opcodes, control fragmentation and control
+    /// payload size are already enforced by [`Frame::parse`], the size check
+    /// here covering frames built by hand; masking depends on the direction
+    /// of travel and is left to the caller.
+    pub fn validate_strict(&self) -> Result<()> {
+        if !self.opcode.is_control() {
+            return Ok(());
+        }
+        if self.payload.len() > MAX_CONTROL_PAYLOAD_SIZE {
+            return Err(ProtocolError::InvalidFrame(format!(
+                "control frame payload of {} bytes",
+                self.payload.len()
+            ))
+            .into());
+        }
+        if self.opcode == Opcode::Close {
+            match self.payload.len() {
+                0 => {}
+                1 => {
+                    return Err(
+                        ProtocolError::InvalidFrame("1-byte close payload".to_string()).into(),
+                    )
+                }
+                _ => {
+                    let code = u16::from_be_bytes([self.payload[0], self.payload[1]]);
+                    if !utils::is_valid_close_code(code) {
+                        return Err(ProtocolError::InvalidCloseCode(code).into());
+                    }
+                    if std::str::from_utf8(&self.payload[2..]).is_err() {
+                        return Err(Error::InvalidUtf8);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check whether this frame's payload is the same memory as `other`'s
+    ///
+    /// True when both payloads view the same bytes of one allocation, as
+    /// after a clone or a move through [`Message::into_frame`](crate::Message::into_frame),
+    /// and false once either has been copied.
+    pub fn payload_shared_with(&self, other: &Frame) -> bool {
+        self.payload.as_ptr() == other.payload.as_ptr() && self.payload.len() == other.payload.len()
+    }
+
     /// Get the payload length
     pub fn payload_len(&self) -> usize {
         self.payload.len()
@@ -339,12 +515,54 @@ pub enum FrameKind {
     Reserved,
 }
 
+/// Payloads shorter than this are masked a byte at a time
+const WORD_MASK_THRESHOLD: usize = 32;
+
+/// XOR `data` in place with the 4-byte masking key (RFC 6455 section 5.3)
+///
+/// Masking is symmetric, so the same call both masks and unmasks a payload.
+/// Larger payloads are processed eight bytes at a time.
+pub fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    apply_mask_from(data, mask, 0);
+}
+
+/// Mask `data` as if it started `offset` bytes into the payload
+fn apply_mask_from(data: &mut [u8], mask: [u8; 4], offset: usize) {
+    if data.len() < WORD_MASK_THRESHOLD {
+        apply_mask_bytewise(data, mask, offset);
+        return;
+    }
+
+    // Rotate the key so its first byte lines up with data[0]; every word
+    // then starts at a multiple of 4 from there and uses the same key
+    let mut rotated = mask;
+    rotated.rotate_left(offset % 4);
+    let mut key = [0u8; 8];
+    key[..4].copy_from_slice(&rotated);
+    key[4..].copy_from_slice(&rotated);
+    let key = u64::from_ne_bytes(key);
+
+    let mut words = data.chunks_exact_mut(8);
+    for word in &mut words {
+        let masked = u64::from_ne_bytes([
+            word[0], word[1], word[2], word[3], word[4], word[5], word[6], word[7],
+        ]) ^ key;
+        word.copy_from_slice(&masked.to_ne_bytes());
+    }
+    apply_mask_bytewise(words.into_remainder(), rotated, 0);
+}
+
+/// The reference one-byte-at-a-time masking loop
+fn apply_mask_bytewise(data: &mut [u8], mask: [u8; 4], offset: usize) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[(offset + i) % 4];
+    }
+}
+
 /// Apply masking to bytes
 fn mask_bytes(data: &[u8], mask: &[u8; 4]) -> Bytes {
-    let mut masked = BytesMut::with_capacity(data.len());
-    for (i, &byte) in data.iter().enumerate() {
-        masked.put_u8(byte ^ mask[i % 4]);
-    }
+    let mut masked = BytesMut::from(data);
+    apply_mask(&mut masked, *mask);
     masked.freeze()
 }
 
@@ -357,6 +575,8 @@ pub struct FrameParser {
     expected_size: Option<usize>,
     /// Whether compression is enabled for this connection
     compression_enabled: bool,
+    /// Largest payload length accepted in a frame header
+    max_frame_size: usize,
 }
 
 impl Default for FrameParser {
@@ -365,6 +585,7 @@ impl Default for FrameParser {
             buffer: BytesMut::new(),
             expected_size: None,
             compression_enabled: false,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
         }
     }
 }
@@ -378,12 +599,17 @@ impl FrameParser {
     /// Create a new frame parser with compression enabled
     pub fn with_compression(compression_enabled: bool) -> Self {
         Self {
-            buffer: BytesMut::new(),
-            expected_size: None,
             compression_enabled,
+            ..Self::default()
         }
     }
 
+    /// Set the largest payload length accepted in a frame header
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
     /// Feed data to the parser and try to extract frames
     pub fn feed(&mut self, data: &[u8]) -> Vec<Result<Frame>> {
         self.buffer.extend_from_slice(data);
@@ -411,7 +637,7 @@ impl FrameParser {
     fn try_parse_frame(&mut self) -> Option<Result<Frame>> {
         let mut buf = self.buffer.clone();
 
-        match Frame::parse(&mut buf, self.compression_enabled) {
+        match Frame::parse(&mut buf, self.compression_enabled, self.max_frame_size) {
             Ok(frame) => {
                 // Remove the parsed data from the buffer
                 let parsed_len = self.buffer.len() - buf.len();
@@ -465,18 +691,82 @@ mod tests {
         assert_eq!(bytes.len(), 2 + 4 + 5); // header + mask + payload
     }
 
+    #[test]
+    fn test_apply_mask_roundtrip() {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut data = b"Hello".to_vec();
+
+        apply_mask(&mut data, mask);
+        assert_eq!(data, [0x7f, 0x9f, 0x4d, 0x51, 0x58]); // RFC 6455 section 5.7
+
+        apply_mask(&mut data, mask);
+        assert_eq!(data, b"Hello");
+    }
+
+    #[test]
+    fn test_word_masking_matches_bytewise() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0x5eed);
+        let buffer: Vec<u8> = (0..1100).map(|_| rng.gen()).collect();
+        for _ in 0..500 {
+            let len = rng.gen_range(0..1024);
+            // Start at an arbitrary alignment and payload position
+            let start = rng.gen_range(0..8);
+            let offset = rng.gen_range(0..8);
+            let mask: [u8; 4] = rng.gen();
+
+            let mut expected = buffer[start..start + len].to_vec();
+            apply_mask_bytewise(&mut expected, mask, offset);
+            let mut actual = buffer.clone();
+            apply_mask_from(&mut actual[start..start + len], mask, offset);
+
+            assert_eq!(
+                &actual[start..start + len],
+                &expected[..],
+                "len {len}, start {start}, offset {offset}"
+            );
+            // Bytes around the slice are left alone
+            assert_eq!(&actual[..start], &buffer[..start]);
+            assert_eq!(&actual[start + len..], &buffer[start + len..]);
+        }
+
+        // Around the threshold and the word size
+        for len in WORD_MASK_THRESHOLD - 2..WORD_MASK_THRESHOLD + 10 {
+            let mask = [0xde, 0xad, 0xbe, 0xef];
+            let mut expected = buffer[..len].to_vec();
+            apply_mask_bytewise(&mut expected, mask, 0);
+            let mut actual = buffer[..len].to_vec();
+            apply_mask(&mut actual, mask);
+            assert_eq!(actual, expected, "len {len}");
+        }
+    }
+
     #[test]
     fn test_frame_parsing() {
         let original = Frame::text("hello");
         let bytes = original.to_bytes();
         let mut buf = BytesMut::from(&bytes[..]);
 
-        let parsed = Frame::parse(&mut buf, false).unwrap();
+        let parsed = Frame::parse(&mut buf, false, DEFAULT_MAX_FRAME_SIZE).unwrap();
         assert_eq!(parsed.kind(), FrameKind::Text);
         assert_eq!(parsed.payload, "hello");
         assert!(buf.is_empty());
     }
 
+    #[test]
+    fn test_parsed_payload_shares_buffer() {
+        let bytes = Frame::binary(vec![7u8; 300]).mask(true).to_bytes();
+        let mut buf = BytesMut::from(&bytes[..]);
+        // 2 byte header, 2 byte extended length and the masking key
+        let payload_ptr = buf[8..].as_ptr();
+
+        let parsed = Frame::parse(&mut buf, false, DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(parsed.payload.as_ptr(), payload_ptr);
+        assert_eq!(parsed.payload, vec![7u8; 300]);
+        assert!(buf.is_empty());
+    }
+
     #[test]
     fn test_large_frame() {
         let payload = vec![0u8; 65536]; // 64KB
@@ -487,6 +777,59 @@ mod tests {
         assert_eq!(bytes[2..10], (65536u64).to_be_bytes());
     }
 
+    #[test]
+    fn test_unaddressable_length_is_rejected_not_truncated() {
+        // u64::MAX never fits in usize; on 32-bit targets anything above
+        // u32::MAX would otherwise be truncated to a small length
+        let mut lengths = vec![u64::MAX];
+        if cfg!(target_pointer_width = "32") {
+            lengths.push(u32::MAX as u64 + 1);
+        }
+
+        for length in lengths {
+            let mut bytes = vec![0x82, 127];
+            bytes.extend_from_slice(&length.to_be_bytes());
+            bytes.extend_from_slice(b"next frame");
+            let mut buf = BytesMut::from(&bytes[..]);
+
+            let err = Frame::parse(&mut buf, false, DEFAULT_MAX_FRAME_SIZE).unwrap_err();
+            assert!(matches!(err, Error::Frame(FrameError::TooLarge { .. })));
+            assert_eq!(err.close_code().map(|c| c.code()), Some(1009));
+        }
+    }
+
+    #[test]
+    fn test_oversized_length_rejected_before_payload() {
+        // A masked binary header declaring 10GB, followed by a few bytes
+        let mut bytes = vec![0x82, 0x80 | 127];
+        bytes.extend_from_slice(&(10u64 << 30).to_be_bytes());
+        bytes.extend_from_slice(&[1, 2, 3, 4, 0xAA, 0xBB]);
+
+        let mut buf = BytesMut::from(&bytes[..]);
+        let err = Frame::parse(&mut buf, false, 1024).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Frame(FrameError::TooLarge { max: 1024, .. })
+        ));
+        assert_eq!(err.close_code().map(|c| c.code()), Some(1009));
+
+        let mut parser = FrameParser::new().with_max_frame_size(1024);
+        let frames = parser.feed(&bytes);
+        assert_eq!(frames.len(), 1);
+        assert!(matches!(
+            frames[0],
+            Err(Error::Frame(FrameError::TooLarge { max: 1024, .. }))
+        ));
+        assert_eq!(parser.buffered_bytes(), 0);
+
+        // Lengths at the limit still wait for the payload
+        let mut buf = BytesMut::from(&[0x82, 126, 0x04, 0x00][..]);
+        assert!(matches!(
+            Frame::parse(&mut buf, false, 1024),
+            Err(Error::Frame(FrameError::InsufficientData { .. }))
+        ));
+    }
+
     #[test]
     fn test_close_frame() {
         let frame = Frame::close(Some(1000), Some("Goodbye"));
@@ -499,6 +842,59 @@ mod tests {
         assert_eq!(bytes.len(), 11); // Total frame length
     }
 
+    #[test]
+    fn test_bare_close_frame_has_empty_payload() {
+        assert_eq!(&Frame::close(None, None).to_bytes()[..], &[0x88, 0x00]);
+        assert!(Frame::close(None, Some("ignored")).payload.is_empty());
+    }
+
+    #[test]
+    fn test_reserved_opcode_rejected_at_parse() {
+        for opcode in [0x3u8, 0x7, 0xB, 0xF] {
+            let mut buf = BytesMut::from(&[0x80 | opcode, 0x00][..]);
+            let err = Frame::parse(&mut buf, false, DEFAULT_MAX_FRAME_SIZE).unwrap_err();
+            assert!(matches!(
+                err,
+                Error::Protocol(ProtocolError::ReservedOpcode(op)) if op == opcode
+            ));
+            assert_eq!(err.close_code().map(|c| c.code()), Some(1002));
+        }
+    }
+
+    #[test]
+    fn test_control_payload_limit_at_parse() {
+        let mut buf = BytesMut::from(&Frame::ping(vec![0; 125]).to_bytes()[..]);
+        let frame = Frame::parse(&mut buf, false, DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(frame.payload.len(), 125);
+
+        // Refused from the header alone, before the payload arrives
+        let mut buf = BytesMut::from(&Frame::ping(vec![0; 126]).to_bytes()[..4]);
+        let err = Frame::parse(&mut buf, false, DEFAULT_MAX_FRAME_SIZE).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Frame(FrameError::ControlFrameTooLarge { size: 126 })
+        ));
+        assert_eq!(err.close_code().map(|c| c.code()), Some(1002));
+    }
+
+    #[test]
+    fn test_control_constructors_check_payload_size() {
+        assert!(Frame::try_ping(vec![0; 125]).is_ok());
+        assert!(Frame::try_pong(vec![0; 125]).is_ok());
+        assert!(Frame::try_close(Some(1000), Some(&"x".repeat(123))).is_ok());
+
+        for result in [
+            Frame::try_ping(vec![0; 126]),
+            Frame::try_pong(vec![0; 126]),
+            Frame::try_close(Some(1000), Some(&"x".repeat(124))),
+        ] {
+            assert!(matches!(
+                result,
+                Err(Error::Frame(FrameError::ControlFrameTooLarge { size: 126 }))
+            ));
+        }
+    }
+
     #[test]
     fn test_frame_parser() {
         let mut parser = FrameParser::new();
@@ -523,4 +919,42 @@ mod tests {
         assert_eq!(frames.len(), 1);
         assert!(frames[0].as_ref().unwrap().is_control());
     }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compressed_frame_drops_the_sync_flush_tail() {
+        let message = r#"{"type":"update","channel":"ticker","data":{"symbol":"BTC"}}"#;
+        let frame = Frame::text(message).compress(true);
+        assert!(frame.rsv[0]);
+        assert!(!frame.payload.ends_with(&[0x00, 0x00, 0xff, 0xff]));
+
+        let mut buf = BytesMut::from(&frame.to_bytes()[..]);
+        let parsed = Frame::parse(&mut buf, true, DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(&parsed.payload[..], message.as_bytes());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_decompression_is_capped_at_max_frame_size() {
+        let max = 64 * 1024;
+        // 16 MiB of zeros deflates to a few KiB, well under the frame limit
+        let bomb = Frame::binary(vec![0u8; 16 * 1024 * 1024]).compress(true);
+        assert!(bomb.rsv[0] && bomb.payload.len() < max);
+
+        let mut buf = BytesMut::from(&bomb.to_bytes()[..]);
+        let err = Frame::parse(&mut buf, true, max).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Frame(FrameError::DecompressedTooLarge { max: 65536 })
+        ));
+        assert_eq!(err.close_code().map(|c| c.code()), Some(1009));
+
+        // Exactly at the limit still inflates
+        let full = Frame::binary(vec![0u8; max]).compress(true);
+        let mut buf = BytesMut::from(&full.to_bytes()[..]);
+        assert_eq!(
+            Frame::parse(&mut buf, true, max).unwrap().payload.len(),
+            max
+        );
+    }
 }
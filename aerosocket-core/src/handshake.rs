@@ -9,9 +9,11 @@ use crate::protocol::http_header::*;
 use crate::protocol::http_method;
 use crate::protocol::http_status::*;
 use crate::protocol::http_value;
+use crate::protocol::{extensions, ExtensionOffer};
 use base64::{engine::general_purpose, Engine as _};
 use sha1::{Digest, Sha1};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 /// Authentication methods for WebSocket handshake
 #[derive(Debug, Clone)]
@@ -45,10 +47,84 @@ pub struct HandshakeRequest {
     pub body: Vec<u8>,
 }
 
+impl HandshakeRequest {
+    /// Path of the request target, without its query string or fragment
+    ///
+    /// Returned as sent, still percent-encoded.
+    pub fn path(&self) -> &str {
+        self.uri.split(['?', '#']).next().unwrap_or(&self.uri)
+    }
+
+    /// Query string of the request target, without the leading `?`
+    pub fn query(&self) -> Option<&str> {
+        let (_, rest) = self.uri.split_once('?')?;
+        Some(rest.split('#').next().unwrap_or(rest))
+    }
+
+    /// Percent-decoded query parameters
+    ///
+    /// Browsers cannot set headers on a WebSocket upgrade, so credentials
+    /// often arrive here instead. When a key is repeated the first value
+    /// wins; use [`parse_query`] on [`query`](Self::query) to see them all.
+    pub fn query_params(&self) -> HashMap<String, String> {
+        let mut params = HashMap::new();
+        for (key, value) in self.query().map(parse_query).unwrap_or_default() {
+            params.entry(key).or_insert(value);
+        }
+        params
+    }
+}
+
+/// Split a query string into percent-decoded key/value pairs, in order
+///
+/// `+` decodes to a space, as in HTML form encoding, a key without `=` gets
+/// an empty value, and empty segments are skipped. Malformed escapes are
+/// kept as they are, and invalid UTF-8 is replaced.
+pub fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+/// Decode `%XX` escapes and `+` in one query component
+fn percent_decode(input: &str) -> String {
+    let hex = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match (
+                bytes.get(i + 1).copied().and_then(hex),
+                bytes.get(i + 2).copied().and_then(hex),
+            ) {
+                (Some(high), Some(low)) => {
+                    decoded.push(high << 4 | low);
+                    i += 2;
+                }
+                _ => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
 /// Compression configuration for WebSocket connections
 #[derive(Debug, Clone)]
 pub struct CompressionConfig {
     /// Whether compression is enabled
+    ///
+    /// When disabled, a server declines a `permessage-deflate` offer by
+    /// leaving it out of the response (RFC 6455 section 9.1), and the
+    /// connection continues with uncompressed frames.
     pub enabled: bool,
     /// Maximum window size for decompression (client to server)
     pub client_max_window_bits: Option<u8>,
@@ -56,6 +132,10 @@ pub struct CompressionConfig {
     pub server_max_window_bits: Option<u8>,
     /// Compression level (0-9, where 9 is maximum compression)
     pub compression_level: Option<u32>,
+    /// Ask that the server reset its compression context after every message
+    pub server_no_context_takeover: bool,
+    /// Ask that the client reset its compression context after every message
+    pub client_no_context_takeover: bool,
 }
 
 impl Default for CompressionConfig {
@@ -65,6 +145,8 @@ impl Default for CompressionConfig {
             client_max_window_bits: Some(15),
             server_max_window_bits: Some(15),
             compression_level: Some(6),
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
         }
     }
 }
@@ -76,12 +158,189 @@ pub struct HandshakeResponse {
     pub status: u16,
     /// HTTP status message
     pub status_message: String,
-    /// HTTP headers
-    pub headers: HashMap<String, String>,
+    /// HTTP headers in the order they are sent; a name may appear more than
+    /// once, as `Set-Cookie` often does
+    pub headers: Vec<(String, String)>,
     /// Response body (should be empty for WebSocket handshake)
     pub body: Vec<u8>,
 }
 
+impl HandshakeResponse {
+    /// Value of the first header named `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// `permessage-deflate` parameters accepted in this response, if any
+    pub fn deflate_params(&self) -> Result<Option<DeflateParams>, Error> {
+        let Some(header) = self.header(HEADER_SEC_WEBSOCKET_EXTENSIONS) else {
+            return Ok(None);
+        };
+        ExtensionOffer::parse_header(header)?
+            .iter()
+            .find(|accepted| {
+                accepted
+                    .name
+                    .eq_ignore_ascii_case(extensions::PERMESSAGE_DEFLATE)
+            })
+            .map(|accepted| {
+                DeflateParams::from_accepted(accepted).ok_or_else(|| {
+                    Error::Protocol(ProtocolError::ExtensionNegotiation(header.to_string()))
+                })
+            })
+            .transpose()
+    }
+}
+
+/// `permessage-deflate` parameters agreed during the handshake (RFC 7692)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeflateParams {
+    /// The server resets its compression context after every message
+    pub server_no_context_takeover: bool,
+    /// The client resets its compression context after every message
+    pub client_no_context_takeover: bool,
+    /// Largest LZ77 window the server compresses with
+    pub server_max_window_bits: Option<u8>,
+    /// Largest LZ77 window the client compresses with
+    pub client_max_window_bits: Option<u8>,
+}
+
+impl DeflateParams {
+    /// Parameters a client offers for `config`
+    pub fn offer(config: &CompressionConfig) -> Self {
+        Self {
+            server_no_context_takeover: config.server_no_context_takeover,
+            client_no_context_takeover: config.client_no_context_takeover,
+            server_max_window_bits: config.server_max_window_bits,
+            client_max_window_bits: config.client_max_window_bits,
+        }
+    }
+
+    /// Accept the first `permessage-deflate` offer the server can honor
+    ///
+    /// Window sizes never exceed what the client offered, and a client asking
+    /// for `server_no_context_takeover` always gets it. Offers with unknown
+    /// parameters or window bits outside 8-15 are declined, as are offers
+    /// limiting the server to an 8-bit window, which zlib cannot compress with.
+    pub fn negotiate(offers: &[ExtensionOffer], config: &CompressionConfig) -> Option<Self> {
+        offers
+            .iter()
+            .filter(|offer| {
+                offer
+                    .name
+                    .eq_ignore_ascii_case(extensions::PERMESSAGE_DEFLATE)
+            })
+            .find_map(|offer| Self::accept(offer, config))
+    }
+
+    /// Answer a single offer, or `None` to decline it
+    fn accept(offer: &ExtensionOffer, config: &CompressionConfig) -> Option<Self> {
+        let mut params = Self {
+            server_no_context_takeover: config.server_no_context_takeover,
+            client_no_context_takeover: config.client_no_context_takeover,
+            server_max_window_bits: config.server_max_window_bits,
+            client_max_window_bits: None,
+        };
+        for (key, value) in &offer.params {
+            match (key.to_ascii_lowercase().as_str(), value.as_deref()) {
+                (extensions::SERVER_NO_CONTEXT_TAKEOVER, None) => {
+                    params.server_no_context_takeover = true;
+                }
+                (extensions::CLIENT_NO_CONTEXT_TAKEOVER, None) => {}
+                (extensions::SERVER_MAX_WINDOW_BITS, Some(value)) => {
+                    let offered = deflate_window_bits(value)?;
+                    params.server_max_window_bits = Some(
+                        params
+                            .server_max_window_bits
+                            .map_or(offered, |bits| bits.min(offered)),
+                    );
+                }
+                // Without a value the client only signals support for the parameter
+                (extensions::CLIENT_MAX_WINDOW_BITS, value) => {
+                    let offered = value.map_or(Some(15), window_bits)?;
+                    params.client_max_window_bits =
+                        config.client_max_window_bits.map(|bits| bits.min(offered));
+                }
+                _ => return None,
+            }
+        }
+        Some(params)
+    }
+
+    /// Read the parameters of an accepted extension, or `None` if malformed
+    ///
+    /// A client window of 8 bits is refused like a malformed value, since
+    /// zlib cannot compress with it.
+    fn from_accepted(accepted: &ExtensionOffer) -> Option<Self> {
+        let mut params = Self::default();
+        for (key, value) in &accepted.params {
+            match (key.to_ascii_lowercase().as_str(), value.as_deref()) {
+                (extensions::SERVER_NO_CONTEXT_TAKEOVER, None) => {
+                    params.server_no_context_takeover = true;
+                }
+                (extensions::CLIENT_NO_CONTEXT_TAKEOVER, None) => {
+                    params.client_no_context_takeover = true;
+                }
+                (extensions::SERVER_MAX_WINDOW_BITS, Some(value)) => {
+                    params.server_max_window_bits = Some(window_bits(value)?);
+                }
+                (extensions::CLIENT_MAX_WINDOW_BITS, Some(value)) => {
+                    params.client_max_window_bits = Some(deflate_window_bits(value)?);
+                }
+                _ => return None,
+            }
+        }
+        Some(params)
+    }
+
+    /// Render as a `Sec-WebSocket-Extensions` entry
+    pub fn to_extension(&self) -> ExtensionOffer {
+        let mut extension = ExtensionOffer::new(extensions::PERMESSAGE_DEFLATE);
+        if self.server_no_context_takeover {
+            extension = extension.with_param(extensions::SERVER_NO_CONTEXT_TAKEOVER, None);
+        }
+        if self.client_no_context_takeover {
+            extension = extension.with_param(extensions::CLIENT_NO_CONTEXT_TAKEOVER, None);
+        }
+        if let Some(bits) = self.server_max_window_bits {
+            extension =
+                extension.with_param(extensions::SERVER_MAX_WINDOW_BITS, Some(bits.to_string()));
+        }
+        if let Some(bits) = self.client_max_window_bits {
+            extension =
+                extension.with_param(extensions::CLIENT_MAX_WINDOW_BITS, Some(bits.to_string()));
+        }
+        extension
+    }
+}
+
+/// Parse a window bits value, which RFC 7692 limits to 8-15
+fn window_bits(value: &str) -> Option<u8> {
+    value.parse().ok().filter(|bits| (8..=15).contains(bits))
+}
+
+/// Parse the window bits this side has to compress with, which zlib needs
+/// to be 9-15
+fn deflate_window_bits(value: &str) -> Option<u8> {
+    window_bits(value).filter(|bits| *bits > 8)
+}
+
+/// How the server treats the `Origin` header during the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OriginPolicy {
+    /// Accept requests without an `Origin` header; a present origin must be allowed
+    #[default]
+    AllowMissing,
+    /// Reject requests without an `Origin` header; a present origin must be allowed
+    DenyMissing,
+    /// Require an `Origin` header listed in the allowlist (an empty allowlist
+    /// rejects every request)
+    RequireMatch,
+}
+
 /// WebSocket handshake configuration
 #[derive(Debug, Clone, Default)]
 pub struct HandshakeConfig {
@@ -92,7 +351,13 @@ pub struct HandshakeConfig {
     /// Origin to send (client only)
     pub origin: Option<String>,
     /// Allowed origins for CORS (server only, empty means allow all)
+    ///
+    /// Entries are exact origins such as `https://app.example.com`,
+    /// subdomain wildcards such as `https://*.example.com`, or `*` for any
+    /// origin; see [`origin_allowed`].
     pub allowed_origins: Vec<String>,
+    /// Policy for requests with a missing or unlisted origin (server only)
+    pub origin_policy: OriginPolicy,
     /// Host header value (client only)
     pub host: Option<String>,
     /// Authentication
@@ -101,6 +366,43 @@ pub struct HandshakeConfig {
     pub compression: CompressionConfig,
     /// Additional headers
     pub extra_headers: HashMap<String, String>,
+    /// Server-side subprotocol choice, replacing the match against `protocols`
+    pub protocol_selector: Option<ProtocolSelector>,
+}
+
+/// Callback choosing the subprotocol for a handshake (server only)
+///
+/// Receives the subprotocols the client offered, in the order it sent them,
+/// and returns the one to accept. `None`, or a subprotocol the client did not
+/// offer, accepts the connection without a `Sec-WebSocket-Protocol` header.
+/// Only called when the client offered at least one subprotocol.
+#[derive(Clone)]
+#[allow(clippy::type_complexity)]
+pub struct ProtocolSelector(Arc<Mutex<dyn FnMut(&[String]) -> Option<String> + Send>>);
+
+impl ProtocolSelector {
+    /// Wrap a selection callback
+    pub fn new<F>(f: F) -> Self
+    where
+        F: FnMut(&[String]) -> Option<String> + Send + 'static,
+    {
+        Self(Arc::new(Mutex::new(f)))
+    }
+
+    /// Choose among the offered subprotocols
+    pub fn select(&self, offered: &[String]) -> Option<String> {
+        let mut select = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        select(offered)
+    }
+}
+
+impl std::fmt::Debug for ProtocolSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProtocolSelector(..)")
+    }
 }
 
 /// Generate a random WebSocket key
@@ -118,9 +420,40 @@ pub fn compute_accept_key(client_key: &str) -> Result<String, Error> {
     Ok(general_purpose::STANDARD.encode(hash))
 }
 
+/// Check whether a comma-separated header value lists `token`
+///
+/// Tokens are compared case-insensitively with surrounding whitespace
+/// ignored, so `keep-alive, Upgrade` lists `upgrade`.
+pub fn header_has_token(value: &str, token: &str) -> bool {
+    value
+        .split(',')
+        .any(|candidate| candidate.trim().eq_ignore_ascii_case(token))
+}
+
+/// Add a parsed header, joining a repeated one onto the earlier value
+///
+/// Proxies may split a list such as `Connection` across several lines;
+/// joining them with a comma keeps every token (RFC 9110 section 5.3).
+fn insert_header(headers: &mut HashMap<String, String>, name: &str, value: &str) {
+    let value = value.trim();
+    headers
+        .entry(name.trim().to_lowercase())
+        .and_modify(|existing| {
+            existing.push_str(", ");
+            existing.push_str(value);
+        })
+        .or_insert_with(|| value.to_string());
+}
+
 /// Validate WebSocket key format
+///
+/// RFC 6455 section 4.1 requires the key to be a base64-encoded 16-byte
+/// nonce, so the decoded length is checked as well as the encoded one.
 pub fn validate_key(key: &str) -> bool {
-    key.len() == 24 && general_purpose::STANDARD.decode(key).is_ok()
+    key.len() == 24
+        && general_purpose::STANDARD
+            .decode(key)
+            .is_ok_and(|nonce| nonce.len() == 16)
 }
 
 /// Validate WebSocket version
@@ -176,14 +509,9 @@ pub fn create_client_handshake(
     // Add compression extension if enabled
     #[cfg(feature = "compression")]
     if config.compression.enabled {
-        let mut ext_parts: Vec<String> = vec!["permessage-deflate".to_string()];
-        if let Some(bits) = config.compression.client_max_window_bits {
-            ext_parts.push(format!("client_max_window_bits={}", bits));
-        }
-        if let Some(bits) = config.compression.server_max_window_bits {
-            ext_parts.push(format!("server_max_window_bits={}", bits));
-        }
-        let compression_ext = ext_parts.join("; ");
+        let compression_ext = DeflateParams::offer(&config.compression)
+            .to_extension()
+            .to_string();
         let existing = headers
             .get(HEADER_SEC_WEBSOCKET_EXTENSIONS)
             .cloned()
@@ -266,7 +594,7 @@ pub fn parse_client_handshake(request: &str) -> Result<HandshakeRequest, Error>
         }
 
         if let Some((key, value)) = line.split_once(':') {
-            headers.insert(key.trim().to_lowercase(), value.trim().to_string());
+            insert_header(&mut headers, key, value);
         } else {
             return Err(Error::Protocol(ProtocolError::InvalidHeader {
                 header: "unknown".to_string(),
@@ -295,7 +623,7 @@ pub fn validate_client_handshake(
         .get(HEADER_UPGRADE)
         .ok_or_else(|| Error::Protocol(ProtocolError::MissingHeader(HEADER_UPGRADE.to_string())))?;
 
-    if upgrade.to_lowercase() != http_value::WEBSOCKET {
+    if !header_has_token(upgrade, http_value::WEBSOCKET) {
         return Err(Error::Protocol(ProtocolError::InvalidHeaderValue {
             header: HEADER_UPGRADE.to_string(),
             value: upgrade.clone(),
@@ -306,7 +634,7 @@ pub fn validate_client_handshake(
         Error::Protocol(ProtocolError::MissingHeader(HEADER_CONNECTION.to_string()))
     })?;
 
-    if !connection.to_lowercase().contains("upgrade") {
+    if !header_has_token(connection, http_value::UPGRADE) {
         return Err(Error::Protocol(ProtocolError::InvalidHeaderValue {
             header: HEADER_CONNECTION.to_string(),
             value: connection.clone(),
@@ -346,18 +674,10 @@ pub fn validate_client_handshake(
     }
 
     // Check optional headers
-    if !config.allowed_origins.is_empty() {
-        if let Some(client_origin) = request.headers.get(ORIGIN) {
-            if !config.allowed_origins.contains(client_origin) {
-                return Err(Error::Protocol(ProtocolError::InvalidOrigin {
-                    expected: config.allowed_origins.join(", "),
-                    received: client_origin.clone(),
-                }));
-            }
-        }
-    }
+    validate_origin(request.headers.get(ORIGIN), config)?;
 
-    if !config.protocols.is_empty() {
+    // A protocol selector makes its own choice when the response is created
+    if !config.protocols.is_empty() && config.protocol_selector.is_none() {
         if let Some(protocol_header) = request.headers.get(HEADER_SEC_WEBSOCKET_PROTOCOL) {
             let client_protocols: Vec<&str> =
                 protocol_header.split(',').map(|s| s.trim()).collect();
@@ -379,27 +699,165 @@ pub fn validate_client_handshake(
     Ok(())
 }
 
+/// Check the client's `Origin` header against the allowlist and origin policy
+fn validate_origin(origin: Option<&String>, config: &HandshakeConfig) -> Result<(), Error> {
+    let Some(client_origin) = origin else {
+        return match config.origin_policy {
+            OriginPolicy::AllowMissing => Ok(()),
+            OriginPolicy::DenyMissing | OriginPolicy::RequireMatch => Err(Error::Protocol(
+                ProtocolError::MissingHeader(ORIGIN.to_string()),
+            )),
+        };
+    };
+
+    let must_match =
+        config.origin_policy == OriginPolicy::RequireMatch || !config.allowed_origins.is_empty();
+    if must_match && !origin_allowed(&config.allowed_origins, client_origin) {
+        return Err(Error::Protocol(ProtocolError::InvalidOrigin {
+            expected: config.allowed_origins.join(", "),
+            received: client_origin.clone(),
+        }));
+    }
+
+    Ok(())
+}
+
+/// Check an `Origin` header value against an allowlist
+///
+/// Each entry is one of:
+/// - an exact origin, `scheme://host[:port]`, compared case-insensitively;
+/// - a subdomain wildcard such as `https://*.example.com`, matching any
+///   subdomain of `example.com` (at any depth) with the same scheme and port,
+///   but not `example.com` itself;
+/// - `*`, matching any origin.
+///
+/// The opaque origin `null`, sent by sandboxed frames and `file:` pages, is
+/// only matched by an explicit `null` entry, never by a wildcard.
+pub fn origin_allowed(allowed: &[String], origin: &str) -> bool {
+    let origin = origin.trim();
+    allowed.iter().any(|pattern| {
+        let pattern = pattern.trim();
+        if pattern.eq_ignore_ascii_case(origin) {
+            return true;
+        }
+        if origin.eq_ignore_ascii_case("null") {
+            return false;
+        }
+        if pattern == "*" {
+            return true;
+        }
+
+        let (Some((pattern_scheme, pattern_host)), Some((scheme, host))) =
+            (pattern.split_once("://"), origin.split_once("://"))
+        else {
+            return false;
+        };
+        let Some(suffix) = pattern_host.strip_prefix('*') else {
+            return false;
+        };
+        if !suffix.starts_with('.') || !pattern_scheme.eq_ignore_ascii_case(scheme) {
+            return false;
+        }
+        host.len() > suffix.len()
+            && host.is_char_boundary(host.len() - suffix.len())
+            && host[host.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+            && host[..host.len() - suffix.len()].split('.').all(|label| {
+                !label.is_empty()
+                    && label
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            })
+    })
+}
+
+/// Application decision on an incoming handshake request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeDecision {
+    /// Accept the upgrade, adding these headers to the 101 response
+    Accept(Vec<(String, String)>),
+    /// Accept the upgrade even if the client's address is over its rate limit
+    AcceptExempt(Vec<(String, String)>),
+    /// Refuse the upgrade with the given HTTP status
+    Reject {
+        /// HTTP status code
+        status: u16,
+        /// Reason phrase
+        reason: String,
+    },
+}
+
+impl HandshakeDecision {
+    /// Accept the upgrade without extra headers
+    pub fn accept() -> Self {
+        Self::Accept(Vec::new())
+    }
+
+    /// Refuse the upgrade with the given HTTP status and reason phrase
+    pub fn reject(status: u16, reason: impl Into<String>) -> Self {
+        Self::Reject {
+            status,
+            reason: reason.into(),
+        }
+    }
+
+    /// Exempt an accepting decision from per-IP rate limits
+    ///
+    /// For clients the hook has authenticated, for example with a bearer
+    /// token; a refusal is left unchanged.
+    pub fn exempt_from_rate_limit(self) -> Self {
+        match self {
+            Self::Accept(headers) => Self::AcceptExempt(headers),
+            other => other,
+        }
+    }
+
+    /// Add a response header to an accepting decision
+    ///
+    /// Calling this again with the same name sends the header once per call.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        if let Self::Accept(headers) | Self::AcceptExempt(headers) = &mut self {
+            headers.push((name.into(), value.into()));
+        }
+        self
+    }
+}
+
 /// Create a server handshake response
 pub fn create_server_handshake(
     request: &HandshakeRequest,
     config: &HandshakeConfig,
 ) -> Result<HandshakeResponse, Error> {
-    let mut headers = HashMap::new();
+    create_server_handshake_with_headers(request, config, &[])
+}
+
+/// Create a server handshake response with per-connection headers
+///
+/// `extra_headers` are sent in order, repeated names included, and replace
+/// any configured extra header of the same name; names are compared
+/// case-insensitively. Neither kind may set `Upgrade`, `Connection` or a
+/// `Sec-WebSocket-*` header: those are the handshake's own, and such
+/// entries are left out.
+pub fn create_server_handshake_with_headers(
+    request: &HandshakeRequest,
+    config: &HandshakeConfig,
+    extra_headers: &[(String, String)],
+) -> Result<HandshakeResponse, Error> {
+    let mut headers = Vec::new();
 
     // Required headers
-    headers.insert(
+    headers.push((
         HEADER_UPGRADE.to_string(),
         http_value::WEBSOCKET.to_string(),
-    );
-    headers.insert(
+    ));
+    headers.push((
         HEADER_CONNECTION.to_string(),
         http_value::UPGRADE.to_string(),
-    );
+    ));
 
     // Compute accept key
     if let Some(client_key) = request.headers.get(HEADER_SEC_WEBSOCKET_KEY) {
         let accept_key = compute_accept_key(client_key)?;
-        headers.insert(HEADER_SEC_WEBSOCKET_ACCEPT.to_string(), accept_key);
+        headers.push((HEADER_SEC_WEBSOCKET_ACCEPT.to_string(), accept_key));
     } else {
         return Err(Error::Protocol(ProtocolError::MissingHeader(
             HEADER_SEC_WEBSOCKET_KEY.to_string(),
@@ -407,13 +865,33 @@ pub fn create_server_handshake(
     }
 
     // Protocol negotiation
-    if !config.protocols.is_empty() {
+    if let Some(selector) = &config.protocol_selector {
+        let offered: Vec<String> = request
+            .headers
+            .get(HEADER_SEC_WEBSOCKET_PROTOCOL)
+            .into_iter()
+            .flat_map(|header| header.split(','))
+            .map(str::trim)
+            .filter(|protocol| !protocol.is_empty())
+            .map(str::to_string)
+            .collect();
+        if !offered.is_empty() {
+            // RFC 6455 section 4.1: the client fails a connection whose
+            // subprotocol it did not offer, so never echo one
+            if let Some(protocol) = selector
+                .select(&offered)
+                .filter(|protocol| offered.contains(protocol))
+            {
+                headers.push((HEADER_SEC_WEBSOCKET_PROTOCOL.to_string(), protocol));
+            }
+        }
+    } else if !config.protocols.is_empty() {
         if let Some(protocol_header) = request.headers.get(HEADER_SEC_WEBSOCKET_PROTOCOL) {
             let client_protocols: Vec<&str> =
                 protocol_header.split(',').map(|s| s.trim()).collect();
             for protocol in &config.protocols {
                 if client_protocols.contains(&protocol.as_str()) {
-                    headers.insert(HEADER_SEC_WEBSOCKET_PROTOCOL.to_string(), protocol.clone());
+                    headers.push((HEADER_SEC_WEBSOCKET_PROTOCOL.to_string(), protocol.clone()));
                     break;
                 }
             }
@@ -424,25 +902,31 @@ pub fn create_server_handshake(
     #[cfg(feature = "compression")]
     if config.compression.enabled {
         if let Some(ext_header) = request.headers.get(HEADER_SEC_WEBSOCKET_EXTENSIONS) {
-            if ext_header.contains("permessage-deflate") {
-                let mut ext_parts: Vec<String> = vec!["permessage-deflate".to_string()];
-                if let Some(bits) = config.compression.server_max_window_bits {
-                    ext_parts.push(format!("server_max_window_bits={}", bits));
-                }
-                if let Some(bits) = config.compression.client_max_window_bits {
-                    ext_parts.push(format!("client_max_window_bits={}", bits));
-                }
-                headers.insert(
+            let offers = ExtensionOffer::parse_header(ext_header)?;
+            if let Some(accepted) = DeflateParams::negotiate(&offers, &config.compression) {
+                headers.push((
                     HEADER_SEC_WEBSOCKET_EXTENSIONS.to_string(),
-                    ext_parts.join("; "),
-                );
+                    accepted.to_extension().to_string(),
+                ));
             }
         }
     }
 
     // Add extra headers
+    let overridden = |key: &str| {
+        extra_headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(key))
+    };
     for (key, value) in &config.extra_headers {
-        headers.insert(key.clone(), value.clone());
+        if !overridden(key) && !is_handshake_header(key) {
+            headers.push((key.clone(), value.clone()));
+        }
+    }
+    for (key, value) in extra_headers {
+        if !is_handshake_header(key) {
+            headers.push((key.clone(), value.clone()));
+        }
     }
 
     Ok(HandshakeResponse {
@@ -453,6 +937,12 @@ pub fn create_server_handshake(
     })
 }
 
+/// Whether `name` is a header the handshake itself sets
+fn is_handshake_header(name: &str) -> bool {
+    let name = name.trim().to_ascii_lowercase();
+    name == HEADER_UPGRADE || name == HEADER_CONNECTION || name.starts_with("sec-websocket-")
+}
+
 /// Parse a server handshake response
 pub fn parse_server_handshake(response: &str) -> Result<HandshakeResponse, Error> {
     let mut lines = response.lines();
@@ -489,14 +979,22 @@ pub fn parse_server_handshake(response: &str) -> Result<HandshakeResponse, Error
     let status_message = parts.collect::<Vec<&str>>().join(" ");
 
     // Parse headers
-    let mut headers = HashMap::new();
+    let mut headers: Vec<(String, String)> = Vec::new();
     for line in lines {
         if line.is_empty() {
             break; // End of headers
         }
 
         if let Some((key, value)) = line.split_once(':') {
-            headers.insert(key.trim().to_lowercase(), value.trim().to_string());
+            // Repeated headers are combined as a comma-separated list
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            match headers.iter_mut().find(|(name, _)| *name == key) {
+                Some((_, existing)) => {
+                    existing.push_str(", ");
+                    existing.push_str(value);
+                }
+                None => headers.push((key, value.to_string())),
+            }
         } else {
             return Err(Error::Protocol(ProtocolError::InvalidHeader {
                 header: "unknown".to_string(),
@@ -527,31 +1025,29 @@ pub fn validate_server_handshake(
 
     // Check required headers
     let upgrade = response
-        .headers
-        .get(HEADER_UPGRADE)
+        .header(HEADER_UPGRADE)
         .ok_or_else(|| Error::Protocol(ProtocolError::MissingHeader(HEADER_UPGRADE.to_string())))?;
 
-    if upgrade.to_lowercase() != http_value::WEBSOCKET {
+    if !header_has_token(upgrade, http_value::WEBSOCKET) {
         return Err(Error::Protocol(ProtocolError::InvalidHeaderValue {
             header: HEADER_UPGRADE.to_string(),
-            value: upgrade.clone(),
+            value: upgrade.to_string(),
         }));
     }
 
-    let connection = response.headers.get(HEADER_CONNECTION).ok_or_else(|| {
+    let connection = response.header(HEADER_CONNECTION).ok_or_else(|| {
         Error::Protocol(ProtocolError::MissingHeader(HEADER_CONNECTION.to_string()))
     })?;
 
-    if !connection.to_lowercase().contains("upgrade") {
+    if !header_has_token(connection, http_value::UPGRADE) {
         return Err(Error::Protocol(ProtocolError::InvalidHeaderValue {
             header: HEADER_CONNECTION.to_string(),
-            value: connection.clone(),
+            value: connection.to_string(),
         }));
     }
 
     let accept = response
-        .headers
-        .get(HEADER_SEC_WEBSOCKET_ACCEPT)
+        .header(HEADER_SEC_WEBSOCKET_ACCEPT)
         .ok_or_else(|| {
             Error::Protocol(ProtocolError::MissingHeader(
                 HEADER_SEC_WEBSOCKET_ACCEPT.to_string(),
@@ -559,16 +1055,53 @@ pub fn validate_server_handshake(
         })?;
 
     let expected_accept = compute_accept_key(client_key)?;
-    if accept.as_str() != expected_accept {
+    if accept != expected_accept {
         return Err(Error::Protocol(ProtocolError::InvalidAcceptKey {
             expected: expected_accept,
-            received: accept.clone(),
+            received: accept.to_string(),
         }));
     }
 
     Ok(())
 }
 
+/// Compute the 101 response headers for an upgrade request parsed elsewhere
+///
+/// For embedding in an external HTTP server (hyper, axum, ...) that has
+/// already parsed the request: pass its headers as name/value pairs and write
+/// the returned headers back with status 101. Header names are matched
+/// case-insensitively and repeated headers are combined as a comma-separated
+/// list. The request is validated as by [`validate_client_handshake`], and
+/// the result carries the `Sec-WebSocket-Accept` key plus any negotiated
+/// subprotocol and extensions, with lowercase names sorted for a stable order.
+pub fn accept_response<I, K, V>(
+    headers: I,
+    config: &HandshakeConfig,
+) -> Result<Vec<(String, String)>, Error>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut request_headers = HashMap::new();
+    for (name, value) in headers {
+        insert_header(&mut request_headers, name.as_ref(), value.as_ref());
+    }
+
+    let request = HandshakeRequest {
+        method: http_method::GET.to_string(),
+        uri: "/".to_string(),
+        version: "HTTP/1.1".to_string(),
+        headers: request_headers,
+        body: Vec::new(),
+    };
+    validate_client_handshake(&request, config)?;
+
+    let mut response_headers = create_server_handshake(&request, config)?.headers;
+    response_headers.sort();
+    Ok(response_headers)
+}
+
 /// Convert handshake request to HTTP string
 pub fn request_to_string(request: &HandshakeRequest) -> String {
     let mut lines = vec![format!(
@@ -580,8 +1113,8 @@ pub fn request_to_string(request: &HandshakeRequest) -> String {
         lines.push(format!("{}: {}", key, value));
     }
 
-    lines.push(String::new()); // Empty line after headers
-    lines.join("\r\n")
+    // Each header line ends with CRLF and an empty line terminates the headers
+    format!("{}\r\n\r\n", lines.join("\r\n"))
 }
 
 /// Convert handshake response to HTTP string
@@ -595,8 +1128,8 @@ pub fn response_to_string(response: &HandshakeResponse) -> String {
         lines.push(format!("{}: {}", key, value));
     }
 
-    lines.push(String::new()); // Empty line after headers
-    lines.join("\r\n")
+    // Each header line ends with CRLF and an empty line terminates the headers
+    format!("{}\r\n\r\n", lines.join("\r\n"))
 }
 
 #[cfg(test)]
@@ -610,6 +1143,14 @@ mod tests {
         assert!(validate_key(&key));
     }
 
+    #[test]
+    fn test_key_must_decode_to_16_bytes() {
+        assert!(validate_key("AAAAAAAAAAAAAAAAAAAAAA=="));
+        // 24 base64 characters without padding decode to 18 bytes
+        assert!(!validate_key("AAAAAAAAAAAAAAAAAAAAAAAA"));
+        assert!(!validate_key("AAAAAAAAAAAAAAAAAAAAAA="));
+    }
+
     #[test]
     fn test_accept_key_calculation() {
         let key = "dGhlIHNhbXBsZSBub25jZQ=="; // "the sample nonce"
@@ -636,6 +1177,384 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_response_ends_with_blank_line() {
+        let response = HandshakeResponse {
+            status: 101,
+            status_message: "Switching Protocols".to_string(),
+            headers: vec![("upgrade".to_string(), "websocket".to_string())],
+            body: Vec::new(),
+        };
+        assert_eq!(
+            response_to_string(&response),
+            "HTTP/1.1 101 Switching Protocols\r\nupgrade: websocket\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_per_connection_headers_override_extra_headers() {
+        let mut headers = HashMap::new();
+        headers.insert(
+            HEADER_SEC_WEBSOCKET_KEY.to_string(),
+            "dGhlIHNhbXBsZSBub25jZQ==".to_string(),
+        );
+        let request = HandshakeRequest {
+            method: "GET".to_string(),
+            uri: "/".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers,
+            body: Vec::new(),
+        };
+        let mut config = HandshakeConfig::default();
+        config
+            .extra_headers
+            .insert("x-node".to_string(), "static".to_string());
+
+        let HandshakeDecision::Accept(extra) = HandshakeDecision::accept()
+            .with_header("x-node", "per-connection")
+            .with_header("set-cookie", "session=abc")
+        else {
+            unreachable!()
+        };
+        let response = create_server_handshake_with_headers(&request, &config, &extra).unwrap();
+        assert_eq!(response.header("x-node"), Some("per-connection"));
+        assert_eq!(response.header("set-cookie"), Some("session=abc"));
+    }
+
+    #[test]
+    fn test_per_connection_headers_keep_repeats_and_protocol_headers() {
+        let mut headers = HashMap::new();
+        headers.insert(
+            HEADER_SEC_WEBSOCKET_KEY.to_string(),
+            "dGhlIHNhbXBsZSBub25jZQ==".to_string(),
+        );
+        let request = HandshakeRequest {
+            method: "GET".to_string(),
+            uri: "/".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers,
+            body: Vec::new(),
+        };
+        let mut config = HandshakeConfig::default();
+        config
+            .extra_headers
+            .insert("X-Node".to_string(), "static".to_string());
+        config
+            .extra_headers
+            .insert("Connection".to_string(), "close".to_string());
+
+        let HandshakeDecision::Accept(extra) = HandshakeDecision::accept()
+            .with_header("x-node", "per-connection")
+            .with_header("Set-Cookie", "session=abc")
+            .with_header("Set-Cookie", "theme=dark")
+            .with_header("Upgrade", "h2c")
+            .with_header("Sec-WebSocket-Accept", "forged")
+        else {
+            unreachable!()
+        };
+        let response = create_server_handshake_with_headers(&request, &config, &extra).unwrap();
+
+        let values = |name: &str| {
+            response
+                .headers
+                .iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values("x-node"), ["per-connection"]);
+        assert_eq!(values("set-cookie"), ["session=abc", "theme=dark"]);
+        assert_eq!(values(HEADER_UPGRADE), [http_value::WEBSOCKET]);
+        assert_eq!(values(HEADER_CONNECTION), [http_value::UPGRADE]);
+        assert_eq!(
+            values(HEADER_SEC_WEBSOCKET_ACCEPT),
+            ["s3pPLMBiTxaQ9kYGzzhZRbK+xOo="]
+        );
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_disabled_compression_declines_deflate_offer() {
+        let mut client_config = HandshakeConfig::default();
+        client_config.compression.enabled = true;
+        let request = create_client_handshake("ws://localhost/", &client_config).unwrap();
+        assert!(request
+            .headers
+            .get(HEADER_SEC_WEBSOCKET_EXTENSIONS)
+            .is_some_and(|offer| offer.contains(extensions::PERMESSAGE_DEFLATE)));
+
+        let server_config = HandshakeConfig::default();
+        assert!(!server_config.compression.enabled);
+        let response = create_server_handshake(&request, &server_config).unwrap();
+        assert_eq!(response.header(HEADER_SEC_WEBSOCKET_EXTENSIONS), None);
+
+        let key = &request.headers[HEADER_SEC_WEBSOCKET_KEY];
+        assert!(validate_server_handshake(&response, key).is_ok());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_deflate_negotiation_follows_client_offer() {
+        let mut config = HandshakeConfig::default();
+        config.compression.enabled = true;
+        let request_with = |extensions: &str| {
+            let mut headers = HashMap::new();
+            headers.insert(
+                HEADER_SEC_WEBSOCKET_KEY.to_string(),
+                "dGhlIHNhbXBsZSBub25jZQ==".to_string(),
+            );
+            headers.insert(
+                HEADER_SEC_WEBSOCKET_EXTENSIONS.to_string(),
+                extensions.to_string(),
+            );
+            HandshakeRequest {
+                method: "GET".to_string(),
+                uri: "/".to_string(),
+                version: "HTTP/1.1".to_string(),
+                headers,
+                body: Vec::new(),
+            }
+        };
+
+        let response = create_server_handshake(
+            &request_with("x-webkit-deflate-frame, permessage-deflate; client_max_window_bits"),
+            &config,
+        )
+        .unwrap();
+        let accepted =
+            ExtensionOffer::parse_header(response.header(HEADER_SEC_WEBSOCKET_EXTENSIONS).unwrap())
+                .unwrap();
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].name, extensions::PERMESSAGE_DEFLATE);
+        assert!(accepted[0].has_param(extensions::CLIENT_MAX_WINDOW_BITS));
+
+        // client_max_window_bits is not echoed unless the client offered it
+        let response =
+            create_server_handshake(&request_with("permessage-deflate"), &config).unwrap();
+        let accepted =
+            ExtensionOffer::parse_header(response.header(HEADER_SEC_WEBSOCKET_EXTENSIONS).unwrap())
+                .unwrap();
+        assert!(!accepted[0].has_param(extensions::CLIENT_MAX_WINDOW_BITS));
+
+        // A name that merely contains the extension is not an offer of it
+        let response =
+            create_server_handshake(&request_with("x-permessage-deflate-ish"), &config).unwrap();
+        assert_eq!(response.header(HEADER_SEC_WEBSOCKET_EXTENSIONS), None);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_deflate_params_honor_offer_and_config() {
+        let mut config = HandshakeConfig::default();
+        config.compression.enabled = true;
+        config.compression.server_max_window_bits = Some(12);
+        config.compression.client_max_window_bits = Some(15);
+        config.compression.client_no_context_takeover = true;
+        let negotiate = |extensions: &str| {
+            let mut headers = HashMap::new();
+            headers.insert(
+                HEADER_SEC_WEBSOCKET_KEY.to_string(),
+                "dGhlIHNhbXBsZSBub25jZQ==".to_string(),
+            );
+            headers.insert(
+                HEADER_SEC_WEBSOCKET_EXTENSIONS.to_string(),
+                extensions.to_string(),
+            );
+            let request = HandshakeRequest {
+                method: "GET".to_string(),
+                uri: "/".to_string(),
+                version: "HTTP/1.1".to_string(),
+                headers,
+                body: Vec::new(),
+            };
+            create_server_handshake(&request, &config)
+                .unwrap()
+                .deflate_params()
+                .unwrap()
+        };
+
+        // With client_max_window_bits the window is capped by both sides
+        assert_eq!(
+            negotiate(
+                "permessage-deflate; server_max_window_bits=10; \
+                 client_max_window_bits=9; server_no_context_takeover"
+            ),
+            Some(DeflateParams {
+                server_no_context_takeover: true,
+                client_no_context_takeover: true,
+                server_max_window_bits: Some(10),
+                client_max_window_bits: Some(9),
+            })
+        );
+
+        // Without it the server may not constrain the client window
+        assert_eq!(
+            negotiate("permessage-deflate"),
+            Some(DeflateParams {
+                server_no_context_takeover: false,
+                client_no_context_takeover: true,
+                server_max_window_bits: Some(12),
+                client_max_window_bits: None,
+            })
+        );
+
+        // An offer the server cannot honor falls through to the next one
+        let params = negotiate(
+            "permessage-deflate; server_max_window_bits=8, \
+             permessage-deflate; client_max_window_bits",
+        )
+        .unwrap();
+        assert_eq!(params.server_max_window_bits, Some(12));
+        assert_eq!(params.client_max_window_bits, Some(15));
+
+        assert_eq!(negotiate("permessage-deflate; x-unknown"), None);
+    }
+
+    #[test]
+    fn test_protocol_selector_picks_or_omits_subprotocol() {
+        let request = parse_client_handshake(
+            "GET /chat HTTP/1.1\r\nHost: example.com\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+             Sec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: chat.v1, chat.v2\r\n\r\n",
+        )
+        .unwrap();
+
+        // Prefer the newest version the client speaks
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let config = HandshakeConfig {
+            protocols: vec!["other".to_string()],
+            protocol_selector: Some(ProtocolSelector::new(move |offered: &[String]| {
+                recorded.lock().unwrap().extend_from_slice(offered);
+                offered.iter().max().cloned()
+            })),
+            ..Default::default()
+        };
+        validate_client_handshake(&request, &config).unwrap();
+        let response = create_server_handshake(&request, &config).unwrap();
+        assert_eq!(
+            response.header(HEADER_SEC_WEBSOCKET_PROTOCOL),
+            Some("chat.v2")
+        );
+        assert_eq!(*seen.lock().unwrap(), ["chat.v1", "chat.v2"]);
+
+        let config = HandshakeConfig {
+            protocol_selector: Some(ProtocolSelector::new(|_: &[String]| None)),
+            ..Default::default()
+        };
+        let response = create_server_handshake(&request, &config).unwrap();
+        assert_eq!(response.header(HEADER_SEC_WEBSOCKET_PROTOCOL), None);
+        assert!(!response_to_string(&response)
+            .to_lowercase()
+            .contains("sec-websocket-protocol"));
+    }
+
+    #[test]
+    fn test_protocol_selector_choice_outside_the_offer_is_ignored() {
+        let request = parse_client_handshake(
+            "GET /chat HTTP/1.1\r\nHost: example.com\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+             Sec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: chat.v1, chat.v2\r\n\r\n",
+        )
+        .unwrap();
+
+        for choice in ["chat.v3", "chat.v1, chat.v2", "CHAT.V1", ""] {
+            let config = HandshakeConfig {
+                protocol_selector: Some(ProtocolSelector::new(move |_: &[String]| {
+                    Some(choice.to_string())
+                })),
+                ..Default::default()
+            };
+            let response = create_server_handshake(&request, &config).unwrap();
+            assert_eq!(
+                response.header(HEADER_SEC_WEBSOCKET_PROTOCOL),
+                None,
+                "{:?}",
+                choice
+            );
+        }
+    }
+
+    #[test]
+    fn test_accept_response_from_external_headers() {
+        let config = HandshakeConfig {
+            protocols: vec!["chat".to_string()],
+            ..Default::default()
+        };
+        // Mixed-case names, as an external HTTP layer might hand them over
+        let headers = [
+            ("Host", "example.com"),
+            ("Upgrade", "websocket"),
+            ("Connection", "keep-alive, Upgrade"),
+            ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+            ("Sec-WebSocket-Version", "13"),
+            ("Sec-WebSocket-Protocol", "superchat"),
+            ("sec-websocket-protocol", "chat"),
+        ];
+
+        let response = accept_response(headers, &config).unwrap();
+        assert!(response.contains(&(
+            HEADER_SEC_WEBSOCKET_ACCEPT.to_string(),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_string()
+        )));
+        assert!(response.contains(&(
+            HEADER_SEC_WEBSOCKET_PROTOCOL.to_string(),
+            "chat".to_string()
+        )));
+
+        let missing_key = [("Upgrade", "websocket"), ("Connection", "Upgrade")];
+        assert!(accept_response(missing_key, &config).is_err());
+    }
+
+    #[test]
+    fn test_proxy_mangled_upgrade_headers() {
+        let cases = [
+            ("Upgrade: websocket\r\nConnection: Upgrade\r\n", true),
+            (
+                "Upgrade: WebSocket\r\nConnection: keep-alive, Upgrade\r\n",
+                true,
+            ),
+            (
+                "upgrade: WEBSOCKET\r\nconnection: Keep-Alive,upgrade\r\n",
+                true,
+            ),
+            (
+                "Upgrade: h2c, websocket\r\nConnection: Upgrade, HTTP2-Settings\r\n",
+                true,
+            ),
+            // A proxy splitting the list across repeated header lines
+            (
+                "Upgrade: websocket\r\nConnection: keep-alive\r\nConnection: Upgrade\r\n",
+                true,
+            ),
+            ("Upgrade: websocket\r\nConnection: keep-alive\r\n", false),
+            ("Upgrade: websocket\r\nConnection: no-upgrade\r\n", false),
+            ("Upgrade: websockets\r\nConnection: Upgrade\r\n", false),
+            ("Upgrade: h2c\r\nConnection: Upgrade\r\n", false),
+        ];
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let accept = compute_accept_key(key).unwrap();
+
+        for (headers, valid) in cases {
+            let request = parse_client_handshake(&format!(
+                "GET /chat HTTP/1.1\r\nHost: example.com\r\n{}\
+                 Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+                headers, key
+            ))
+            .unwrap();
+            let result = validate_client_handshake(&request, &HandshakeConfig::default());
+            assert_eq!(result.is_ok(), valid, "request {:?}", headers);
+
+            let response = parse_server_handshake(&format!(
+                "HTTP/1.1 101 Switching Protocols\r\n{}Sec-WebSocket-Accept: {}\r\n\r\n",
+                headers, accept
+            ))
+            .unwrap();
+            let result = validate_server_handshake(&response, key);
+            assert_eq!(result.is_ok(), valid, "response {:?}", headers);
+        }
+    }
+
     #[test]
     fn test_client_handshake_parsing() {
         let raw_request = r#"GET /chat HTTP/1.1
@@ -652,4 +1571,197 @@ Sec-WebSocket-Version: 13
         assert_eq!(request.uri, "/chat");
         assert_eq!(request.headers.get("upgrade").unwrap(), "websocket");
     }
+
+    fn upgrade_request(origin: Option<&str>) -> HandshakeRequest {
+        let mut raw = String::from(
+            "GET /chat HTTP/1.1\r\nHost: example.com\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+             Sec-WebSocket-Version: 13\r\n",
+        );
+        if let Some(origin) = origin {
+            raw.push_str(&format!("Origin: {}\r\n", origin));
+        }
+        raw.push_str("\r\n");
+        parse_client_handshake(&raw).unwrap()
+    }
+
+    #[test]
+    fn test_origin_policy() {
+        let allowed = "https://app.example.com";
+        let other = "https://evil.example.net";
+        let cases = [
+            // (policy, origin header, accepted)
+            (OriginPolicy::AllowMissing, Some(allowed), true),
+            (OriginPolicy::AllowMissing, None, true),
+            (OriginPolicy::AllowMissing, Some(other), false),
+            (OriginPolicy::DenyMissing, Some(allowed), true),
+            (OriginPolicy::DenyMissing, None, false),
+            (OriginPolicy::DenyMissing, Some(other), false),
+            (OriginPolicy::RequireMatch, Some(allowed), true),
+            (OriginPolicy::RequireMatch, None, false),
+            (OriginPolicy::RequireMatch, Some(other), false),
+        ];
+
+        for (policy, origin, accepted) in cases {
+            let config = HandshakeConfig {
+                allowed_origins: vec![allowed.to_string()],
+                origin_policy: policy,
+                ..Default::default()
+            };
+            let result = validate_client_handshake(&upgrade_request(origin), &config);
+            assert_eq!(
+                result.is_ok(),
+                accepted,
+                "{:?} with origin {:?}",
+                policy,
+                origin
+            );
+        }
+    }
+
+    fn request_to(uri: &str) -> HandshakeRequest {
+        HandshakeRequest {
+            method: "GET".to_string(),
+            uri: uri.to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_query_params_are_percent_decoded() {
+        let request = request_to("/socket?token=a%20b%2Bc&room=42&name=J+Doe&flag#top");
+        assert_eq!(request.path(), "/socket");
+        assert_eq!(
+            request.query(),
+            Some("token=a%20b%2Bc&room=42&name=J+Doe&flag")
+        );
+
+        let params = request.query_params();
+        assert_eq!(params.len(), 4);
+        assert_eq!(params["token"], "a b+c");
+        assert_eq!(params["room"], "42");
+        assert_eq!(params["name"], "J Doe");
+        assert_eq!(params["flag"], "");
+
+        // Malformed escapes pass through, multi-byte UTF-8 is reassembled
+        assert_eq!(
+            parse_query("bad=%zz%4&city=Z%C3%BCrich"),
+            vec![
+                ("bad".to_string(), "%zz%4".to_string()),
+                ("city".to_string(), "Zürich".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_params_repeated_keys_keep_first() {
+        let request = request_to("/socket?room=1&room=2&&room=3");
+        assert_eq!(request.query_params()["room"], "1");
+
+        let all: Vec<_> = parse_query(request.query().unwrap())
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(all, vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn test_query_params_empty() {
+        for uri in ["/socket", "/socket?", "/socket?#frag"] {
+            let request = request_to(uri);
+            assert_eq!(request.path(), "/socket");
+            assert!(request.query_params().is_empty(), "{uri}");
+        }
+        assert_eq!(request_to("/socket").query(), None);
+        assert_eq!(request_to("/socket?").query(), Some(""));
+    }
+
+    #[test]
+    fn test_origin_wildcards() {
+        let allowed = vec![
+            "https://*.example.com".to_string(),
+            "http://localhost:3000".to_string(),
+        ];
+        let cases = [
+            ("https://app.example.com", true),
+            ("https://a.b.example.com", true),
+            ("HTTPS://App.Example.COM", true),
+            ("http://LOCALHOST:3000", true),
+            ("https://example.com", false),
+            ("http://app.example.com", false),
+            ("https://app.example.com:8443", false),
+            ("https://evilexample.com", false),
+            ("https://app.example.com.evil.net", false),
+            ("https://.example.com", false),
+            ("https://a_b.example.com", false),
+            ("http://localhost:3001", false),
+            ("null", false),
+        ];
+        for (origin, expected) in cases {
+            assert_eq!(origin_allowed(&allowed, origin), expected, "{origin}");
+        }
+
+        let config = HandshakeConfig {
+            allowed_origins: allowed,
+            ..Default::default()
+        };
+        assert!(validate_client_handshake(
+            &upgrade_request(Some("https://app.example.com")),
+            &config
+        )
+        .is_ok());
+        assert!(matches!(
+            validate_client_handshake(&upgrade_request(Some("https://example.com")), &config),
+            Err(Error::Protocol(ProtocolError::InvalidOrigin { .. }))
+        ));
+        assert!(matches!(
+            validate_client_handshake(&upgrade_request(Some("null")), &config),
+            Err(Error::Protocol(ProtocolError::InvalidOrigin { .. }))
+        ));
+        // Without an Origin header the policy decides, as for exact entries
+        assert!(validate_client_handshake(&upgrade_request(None), &config).is_ok());
+    }
+
+    #[test]
+    fn test_origin_allow_all_and_null() {
+        let any = vec!["*".to_string()];
+        assert!(origin_allowed(&any, "https://anything.test"));
+        assert!(!origin_allowed(&any, "null"));
+
+        let config = HandshakeConfig {
+            allowed_origins: any,
+            origin_policy: OriginPolicy::RequireMatch,
+            ..Default::default()
+        };
+        assert!(
+            validate_client_handshake(&upgrade_request(Some("https://a.test")), &config).is_ok()
+        );
+        assert!(validate_client_handshake(&upgrade_request(None), &config).is_err());
+
+        let null = vec!["null".to_string()];
+        assert!(origin_allowed(&null, "null"));
+        assert!(!origin_allowed(&null, "https://a.test"));
+    }
+
+    #[test]
+    fn test_origin_policy_without_allowlist() {
+        let config = HandshakeConfig {
+            origin_policy: OriginPolicy::DenyMissing,
+            ..Default::default()
+        };
+        assert!(
+            validate_client_handshake(&upgrade_request(Some("https://a.test")), &config).is_ok()
+        );
+        assert!(validate_client_handshake(&upgrade_request(None), &config).is_err());
+
+        let config = HandshakeConfig {
+            origin_policy: OriginPolicy::RequireMatch,
+            ..Default::default()
+        };
+        assert!(
+            validate_client_handshake(&upgrade_request(Some("https://a.test")), &config).is_err()
+        );
+    }
 }
@@ -16,6 +16,8 @@
 #![doc(html_root_url = "https://docs.rs/aerosocket-core/")]
 
 // Core modules
+#[cfg(feature = "compression")]
+pub mod compression;
 pub mod error;
 pub mod frame;
 pub mod handshake;
@@ -27,9 +29,14 @@ pub mod transport;
 pub mod prelude;
 
 // Re-export key types for convenience
+#[cfg(feature = "compression")]
+pub use compression::DeflateContext;
 pub use error::{Error, Result};
 pub use frame::{Frame, FrameKind};
-pub use handshake::{Auth, HandshakeConfig, HandshakeRequest, HandshakeResponse};
+pub use handshake::{
+    Auth, DeflateParams, HandshakeConfig, HandshakeDecision, HandshakeRequest, HandshakeResponse,
+    ProtocolSelector,
+};
 pub use message::{Message, MessageKind};
 pub use protocol::Opcode;
 pub use transport::Transport;
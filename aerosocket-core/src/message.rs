@@ -3,9 +3,9 @@
 //! This module provides high-level message types and handling for WebSocket messages,
 //! including support for text, binary, ping, pong, and close messages.
 
-use crate::error::{CloseCode, Error, ProtocolError, Result};
+use crate::error::{CloseCode, CloseError, Error, FrameError, MessageError, ProtocolError, Result};
 use crate::frame::{Frame, FrameKind};
-use crate::protocol::Opcode;
+use crate::protocol::{constants, utils, Opcode};
 use bytes::{Bytes, BytesMut};
 use std::fmt;
 
@@ -50,6 +50,13 @@ impl Message {
         Self::Close(CloseMessage::new(code, reason))
     }
 
+    /// Create a close message that may be sent on the wire
+    ///
+    /// See [`CloseMessage::try_new`].
+    pub fn try_close(code: Option<u16>, reason: Option<String>) -> Result<Self> {
+        CloseMessage::try_new(code, reason).map(Self::Close)
+    }
+
     /// Get the message kind
     pub fn kind(&self) -> MessageKind {
         match self {
@@ -74,6 +81,25 @@ impl Message {
         matches!(self, Message::Text(_) | Message::Binary(_))
     }
 
+    /// Check that the message may be sent as a single frame
+    ///
+    /// A Close is checked by [`CloseMessage::validate`]; a Ping or Pong
+    /// carrying more than 125 bytes fails with
+    /// [`FrameError::ControlFrameTooLarge`], as RFC 6455 section 5.5 caps
+    /// control payloads.
+    pub fn validate(&self) -> Result<()> {
+        let size = match self {
+            Message::Close(close) => return close.validate(),
+            Message::Ping(ping) => ping.len(),
+            Message::Pong(pong) => pong.len(),
+            Message::Text(_) | Message::Binary(_) => return Ok(()),
+        };
+        if size > constants::MAX_CONTROL_PAYLOAD_SIZE {
+            return Err(FrameError::ControlFrameTooLarge { size }.into());
+        }
+        Ok(())
+    }
+
     /// Get the message payload as text
     pub fn as_text(&self) -> Option<&str> {
         match self {
@@ -94,6 +120,9 @@ impl Message {
     }
 
     /// Convert message to frames
+    ///
+    /// Always a single frame; use [`into_frames`](Self::into_frames) to
+    /// fragment large messages.
     pub fn to_frames(&self) -> Vec<Frame> {
         match self {
             Message::Text(msg) => vec![msg.to_frame()],
@@ -114,6 +143,52 @@ impl Message {
             Message::Close(msg) => msg.to_frame(),
         }
     }
+
+    /// Split message into frames carrying at most `max_fragment_size` payload
+    /// bytes each
+    ///
+    /// A text or binary message larger than that becomes a first frame with
+    /// its own opcode and `fin` cleared, continuation frames, and a final
+    /// frame with `fin` set. The fragments share the message's payload
+    /// rather than copying it. Text may be split inside a UTF-8 sequence,
+    /// since only the reassembled message must be valid. Control messages,
+    /// which may not be fragmented, and data messages that fit stay a single
+    /// frame. A `max_fragment_size` of 0 is treated as 1.
+    pub fn into_frames(self, max_fragment_size: usize) -> Vec<Frame> {
+        if self.is_control() {
+            return vec![self.into_frame()];
+        }
+
+        let Frame {
+            opcode,
+            mut payload,
+            ..
+        } = self.into_frame();
+        let max_fragment_size = max_fragment_size.max(1);
+        if payload.len() <= max_fragment_size {
+            return vec![Frame::new(opcode, payload)];
+        }
+
+        let mut frames = Vec::with_capacity(payload.len().div_ceil(max_fragment_size));
+        let mut opcode = opcode;
+        while !payload.is_empty() {
+            let fragment = payload.split_to(payload.len().min(max_fragment_size));
+            frames.push(Frame::new(opcode, fragment).fin(payload.is_empty()));
+            opcode = Opcode::Continuation;
+        }
+        frames
+    }
+
+    /// Convert message into a single frame, moving the payload without copying
+    pub fn into_frame(self) -> Frame {
+        match self {
+            Message::Text(msg) => msg.into_frame(),
+            Message::Binary(msg) => msg.into_frame(),
+            Message::Ping(msg) => msg.into_frame(),
+            Message::Pong(msg) => msg.into_frame(),
+            Message::Close(msg) => msg.to_frame(),
+        }
+    }
 }
 
 impl fmt::Display for Message {
@@ -179,6 +254,11 @@ impl TextMessage {
     pub fn to_frame(&self) -> Frame {
         Frame::text(self.text.clone())
     }
+
+    /// Convert into a frame without copying the text
+    pub fn into_frame(self) -> Frame {
+        Frame::text(self.text)
+    }
 }
 
 /// Binary message
@@ -212,6 +292,11 @@ impl BinaryMessage {
     pub fn to_frame(&self) -> Frame {
         Frame::binary(self.data.clone())
     }
+
+    /// Convert into a frame sharing the payload allocation
+    pub fn into_frame(self) -> Frame {
+        Frame::binary(self.data)
+    }
 }
 
 /// Ping message
@@ -247,6 +332,11 @@ impl PingMessage {
     pub fn to_frame(&self) -> Frame {
         Frame::ping(self.data.clone())
     }
+
+    /// Convert into a frame sharing the payload allocation
+    pub fn into_frame(self) -> Frame {
+        Frame::ping(self.data)
+    }
 }
 
 /// Pong message
@@ -282,6 +372,11 @@ impl PongMessage {
     pub fn to_frame(&self) -> Frame {
         Frame::pong(self.data.clone())
     }
+
+    /// Convert into a frame sharing the payload allocation
+    pub fn into_frame(self) -> Frame {
+        Frame::pong(self.data)
+    }
 }
 
 /// Close message
@@ -293,13 +388,41 @@ pub struct CloseMessage {
 
 impl CloseMessage {
     /// Create a new close message
+    ///
+    /// A reason is only carried alongside a code, so it is dropped when
+    /// `code` is `None` and the message encodes to an empty Close payload.
     pub fn new(code: Option<u16>, reason: Option<String>) -> Self {
         Self {
             code,
-            reason: reason.unwrap_or_default(),
+            reason: code.and(reason).unwrap_or_default(),
         }
     }
 
+    /// Create a close message, rejecting what RFC 6455 forbids in a Close frame
+    ///
+    /// Fails with [`CloseError::InvalidCode`] for codes that may not be sent,
+    /// such as 1005, 1006 and 1015, and with [`CloseError::ReasonTooLong`]
+    /// when the reason exceeds 123 bytes.
+    pub fn try_new(code: Option<u16>, reason: Option<String>) -> Result<Self> {
+        let message = Self::new(code, reason);
+        message.validate()?;
+        Ok(message)
+    }
+
+    /// Check that the message may be sent in a Close frame
+    pub fn validate(&self) -> Result<()> {
+        if let Some(code) = self.code.filter(|&code| !utils::is_valid_close_code(code)) {
+            return Err(Error::Close(CloseError::InvalidCode { code }));
+        }
+        if self.reason.len() > constants::MAX_CLOSE_REASON_SIZE {
+            return Err(Error::Close(CloseError::ReasonTooLong {
+                len: self.reason.len(),
+                max: constants::MAX_CLOSE_REASON_SIZE,
+            }));
+        }
+        Ok(())
+    }
+
     /// Get the close code
     pub fn code(&self) -> Option<u16> {
         self.code
@@ -315,6 +438,11 @@ impl CloseMessage {
         self.code.map(CloseCode::from)
     }
 
+    /// Get the close status, [`CloseCode::NoStatus`] when no code was sent
+    pub fn status(&self) -> CloseCode {
+        self.close_code().unwrap_or(CloseCode::NoStatus)
+    }
+
     /// Get the message as bytes
     pub fn as_bytes(&self) -> &[u8] {
         self.reason.as_bytes()
@@ -346,7 +474,7 @@ impl CloseMessage {
 }
 
 /// Message assembler for fragmented messages
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct MessageAssembler {
     /// Buffer for assembling fragmented messages
     buffer: BytesMut,
@@ -354,6 +482,25 @@ pub struct MessageAssembler {
     opcode: Option<Opcode>,
     /// Whether we're currently assembling a message
     assembling: bool,
+    /// Largest total payload accepted across the fragments of a message
+    max_message_size: usize,
+    /// Frames received so far for the message being assembled
+    fragments: usize,
+    /// Largest number of frames a message may span
+    max_fragments: usize,
+}
+
+impl Default for MessageAssembler {
+    fn default() -> Self {
+        Self {
+            buffer: BytesMut::new(),
+            opcode: None,
+            assembling: false,
+            max_message_size: constants::DEFAULT_MAX_MESSAGE_SIZE,
+            fragments: 0,
+            max_fragments: constants::DEFAULT_MAX_FRAGMENTS_PER_MESSAGE,
+        }
+    }
 }
 
 impl MessageAssembler {
@@ -362,13 +509,63 @@ impl MessageAssembler {
         Self::default()
     }
 
+    /// Set the largest total payload accepted across the fragments of a message
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Set the largest number of frames a message may span
+    ///
+    /// Bounds the per-frame overhead a peer can cause with a run of tiny
+    /// continuation frames, which the size limit alone allows.
+    pub fn with_max_fragments(mut self, max_fragments: usize) -> Self {
+        self.max_fragments = max_fragments;
+        self
+    }
+
     /// Feed a frame and try to assemble a complete message
     pub fn feed_frame(&mut self, frame: Frame) -> Result<Option<Message>> {
+        if frame.opcode.is_reserved() {
+            self.reset();
+            return Err(Error::Protocol(ProtocolError::ReservedOpcode(
+                frame.opcode.value(),
+            )));
+        }
+
         if frame.is_control() {
             // Control frames are never fragmented
             return Ok(Some(self.control_frame_to_message(frame)?));
         }
 
+        // Until a fragmented message's final frame, every data frame must be
+        // a continuation, and a continuation needs a message to continue
+        if self.assembling != (frame.opcode == Opcode::Continuation) {
+            self.reset();
+            return Err(Error::Protocol(ProtocolError::InvalidContinuation));
+        }
+
+        // Checked on every fragment so a run of small continuations cannot
+        // grow the buffer past the limit
+        let size = self.buffer.len() + frame.payload.len();
+        if size > self.max_message_size {
+            self.reset();
+            return Err(Error::Message(MessageError::TooLarge {
+                size,
+                max: self.max_message_size,
+            }));
+        }
+
+        self.fragments += 1;
+        if self.fragments > self.max_fragments {
+            let count = self.fragments;
+            self.reset();
+            return Err(Error::Message(MessageError::TooManyFragments {
+                count,
+                max: self.max_fragments,
+            }));
+        }
+
         if !frame.fin {
             // Fragmented frame
             if !self.assembling {
@@ -379,11 +576,6 @@ impl MessageAssembler {
                 Ok(None)
             } else {
                 // Continuation of fragmented message
-                if frame.opcode != Opcode::Continuation {
-                    return Err(Error::Protocol(ProtocolError::InvalidFrame(
-                        "Expected continuation frame in fragmented message".to_string(),
-                    )));
-                }
                 self.buffer.extend_from_slice(&frame.payload);
                 Ok(None)
             }
@@ -461,6 +653,7 @@ impl MessageAssembler {
         self.buffer.clear();
         self.opcode = None;
         self.assembling = false;
+        self.fragments = 0;
     }
 
     /// Check if currently assembling a message
@@ -484,6 +677,19 @@ mod tests {
     use super::*;
     use crate::protocol::Opcode;
 
+    #[test]
+    fn test_binary_payload_is_shared_into_frame() {
+        let data = Bytes::from(vec![0xAB; 4096]);
+        let original = Frame::binary(data.clone());
+
+        let frame = Message::binary(data.clone()).into_frame();
+        assert!(frame.payload_shared_with(&original));
+        assert!(frame.clone().payload_shared_with(&original));
+
+        let copied = Frame::binary(data.to_vec());
+        assert!(!copied.payload_shared_with(&original));
+    }
+
     #[test]
     fn test_text_message() {
         let msg = Message::text("hello");
@@ -525,6 +731,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_try_close_validates_code_and_reason() {
+        assert!(matches!(
+            Message::try_close(Some(1005), None),
+            Err(Error::Close(CloseError::InvalidCode { code: 1005 }))
+        ));
+        assert!(matches!(
+            Message::try_close(Some(1000), Some("x".repeat(200))),
+            Err(Error::Close(CloseError::ReasonTooLong {
+                len: 200,
+                max: 123
+            }))
+        ));
+
+        let close = Message::try_close(Some(3000), Some("x".repeat(123))).unwrap();
+        assert_eq!(close.to_frame().payload.len(), 125);
+        assert!(Message::try_close(None, None).is_ok());
+    }
+
+    #[test]
+    fn test_validate_caps_control_payloads() {
+        assert!(Message::ping(Some(vec![0; 125])).validate().is_ok());
+        assert!(matches!(
+            Message::pong(Some(vec![0; 126])).validate(),
+            Err(Error::Frame(FrameError::ControlFrameTooLarge { size: 126 }))
+        ));
+        assert!(Message::binary(vec![0; 1000]).validate().is_ok());
+    }
+
+    #[test]
+    fn test_bare_close_message() {
+        let msg = Message::close(None, Some("no code".to_string()));
+        assert!(msg.to_frame().payload.is_empty());
+        let Message::Close(close_msg) = msg else {
+            panic!("Expected close message");
+        };
+        assert_eq!(close_msg.code(), None);
+        assert_eq!(close_msg.reason(), "");
+        assert_eq!(close_msg.status(), CloseCode::NoStatus);
+
+        let mut assembler = MessageAssembler::new();
+        let received = assembler
+            .feed_frame(Frame::close(None, None))
+            .unwrap()
+            .unwrap();
+        let Message::Close(close_msg) = received else {
+            panic!("Expected close message");
+        };
+        assert_eq!(close_msg.code(), None);
+        assert_eq!(close_msg.status(), CloseCode::NoStatus);
+    }
+
     #[test]
     fn test_message_assembler() {
         let mut assembler = MessageAssembler::new();
@@ -548,6 +806,181 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_into_frames_fragments_data_messages() {
+        let text = "Hello, fragmented world!";
+        let frames = Message::text(text).into_frames(10);
+        let lengths: Vec<_> = frames.iter().map(|frame| frame.payload.len()).collect();
+        assert_eq!(lengths, vec![10, 10, 4]);
+        assert_eq!(frames[0].opcode, Opcode::Text);
+        assert!(frames[1..]
+            .iter()
+            .all(|frame| frame.opcode == Opcode::Continuation));
+        let fins: Vec<_> = frames.iter().map(|frame| frame.fin).collect();
+        assert_eq!(fins, vec![false, false, true]);
+
+        let mut assembler = MessageAssembler::new();
+        let mut assembled = None;
+        for frame in frames {
+            assembled = assembler.feed_frame(frame).unwrap();
+        }
+        assert_eq!(assembled.unwrap().as_text(), Some(text));
+
+        let data = Bytes::from((0..=255u8).collect::<Vec<_>>());
+        let frames = Message::binary(data.clone()).into_frames(100);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].opcode, Opcode::Binary);
+        // Fragments are views into the original payload
+        assert_eq!(frames[1].payload.as_ptr(), data[100..].as_ptr());
+
+        let mut assembler = MessageAssembler::new();
+        let mut assembled = None;
+        for frame in frames {
+            assembled = assembler.feed_frame(frame).unwrap();
+        }
+        assert_eq!(assembled.unwrap().as_bytes(), &data[..]);
+    }
+
+    #[test]
+    fn test_into_frames_keeps_small_and_control_messages_whole() {
+        let frames = Message::binary(vec![1, 2, 3]).into_frames(3);
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].fin);
+        assert_eq!(frames[0].opcode, Opcode::Binary);
+
+        let frames = Message::text("").into_frames(1);
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].fin);
+
+        let frames = Message::ping(Some(vec![0; 100])).into_frames(10);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].opcode, Opcode::Ping);
+        assert!(frames[0].fin);
+
+        let frames = Message::close(Some(1000), Some("bye".to_string())).into_frames(1);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].opcode, Opcode::Close);
+    }
+
+    #[test]
+    fn test_message_assembler_rejects_reserved_opcode() {
+        let mut assembler = MessageAssembler::new();
+        let err = assembler
+            .feed_frame(Frame::new(Opcode::Reserved3, "data"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ReservedOpcode(0x3))
+        ));
+        assert_eq!(err.close_code(), Some(CloseCode::ProtocolError));
+
+        // Also rejected in the middle of a fragmented message
+        assembler
+            .feed_frame(Frame::new(Opcode::Text, "partial").fin(false))
+            .unwrap();
+        let err = assembler
+            .feed_frame(Frame::new(Opcode::ReservedB, "x"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ReservedOpcode(0xB))
+        ));
+        assert!(!assembler.is_assembling());
+    }
+
+    #[test]
+    fn test_message_assembler_rejects_mixed_fragments() {
+        let mut assembler = MessageAssembler::new();
+        assembler
+            .feed_frame(Frame::new(Opcode::Text, "partial").fin(false))
+            .unwrap();
+        let err = assembler
+            .feed_frame(Frame::new(Opcode::Binary, "final"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::InvalidContinuation)
+        ));
+        assert_eq!(err.close_code(), Some(CloseCode::ProtocolError));
+        assert!(!assembler.is_assembling());
+
+        let err = assembler
+            .feed_frame(Frame::new(Opcode::Continuation, "orphan"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::InvalidContinuation)
+        ));
+    }
+
+    #[test]
+    fn test_message_assembler_limits_total_fragment_size() {
+        let mut assembler = MessageAssembler::new().with_max_message_size(1000);
+        assembler
+            .feed_frame(Frame::new(Opcode::Binary, vec![0u8; 10]).fin(false))
+            .unwrap();
+
+        // 99 more 10-byte fragments fill the limit exactly
+        for _ in 0..99 {
+            let frame = Frame::new(Opcode::Continuation, vec![0u8; 10]).fin(false);
+            assert!(assembler.feed_frame(frame).unwrap().is_none());
+        }
+        let err = assembler
+            .feed_frame(Frame::new(Opcode::Continuation, vec![0u8; 10]).fin(false))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Message(MessageError::TooLarge {
+                size: 1010,
+                max: 1000
+            })
+        ));
+        assert_eq!(err.close_code(), Some(CloseCode::TooBig));
+        assert!(!assembler.is_assembling());
+
+        // A message of exactly the limit is still accepted
+        let message = assembler
+            .feed_frame(Frame::new(Opcode::Binary, vec![0u8; 1000]))
+            .unwrap();
+        assert!(matches!(message, Some(Message::Binary(_))));
+    }
+
+    #[test]
+    fn test_message_assembler_limits_fragment_count() {
+        let mut assembler = MessageAssembler::new().with_max_fragments(4);
+
+        // A message of exactly the limit is accepted
+        assembler
+            .feed_frame(Frame::new(Opcode::Text, "a").fin(false))
+            .unwrap();
+        for _ in 0..2 {
+            let frame = Frame::new(Opcode::Continuation, "a").fin(false);
+            assert!(assembler.feed_frame(frame).unwrap().is_none());
+        }
+        let message = assembler
+            .feed_frame(Frame::new(Opcode::Continuation, "a"))
+            .unwrap();
+        assert_eq!(message.unwrap().as_text(), Some("aaaa"));
+
+        // One more fragment than allowed is refused
+        assembler
+            .feed_frame(Frame::new(Opcode::Binary, vec![0u8]).fin(false))
+            .unwrap();
+        for _ in 0..3 {
+            let frame = Frame::new(Opcode::Continuation, vec![0u8]).fin(false);
+            assert!(assembler.feed_frame(frame).unwrap().is_none());
+        }
+        let err = assembler
+            .feed_frame(Frame::new(Opcode::Continuation, vec![0u8]).fin(false))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Message(MessageError::TooManyFragments { count: 5, max: 4 })
+        ));
+        assert_eq!(err.close_code(), Some(CloseCode::TooBig));
+        assert!(!assembler.is_assembling());
+    }
+
     #[test]
     fn test_message_display() {
         let text_msg = Message::text("hello");
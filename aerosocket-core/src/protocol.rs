@@ -122,12 +122,39 @@ pub mod constants {
     /// Maximum message size (default)
     pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024; // 64MB
 
+    /// Default connect timeout
+    pub const DEFAULT_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    /// Default largest number of frames a fragmented message may span
+    pub const DEFAULT_MAX_FRAGMENTS_PER_MESSAGE: usize = 1024;
+
     /// Default handshake timeout
     pub const DEFAULT_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
     /// Default idle timeout
     pub const DEFAULT_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300); // 5 minutes
 
+    /// Default close timeout
+    pub const DEFAULT_CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    /// Default time a shutting-down server waits for connections to close
+    pub const DEFAULT_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    /// Default number of frames read while waiting for the peer's Close
+    pub const DEFAULT_CLOSE_DRAIN_FRAMES: usize = 64;
+
+    /// Default number of bytes read while waiting for the peer's Close
+    pub const DEFAULT_CLOSE_DRAIN_BYTES: usize = 64 * 1024; // 64KB
+
+    /// Default number of bytes requested from the transport per read
+    pub const DEFAULT_READ_BUFFER_SIZE: usize = 1024;
+
+    /// Default largest payload per frame when streaming a message out in fragments
+    pub const DEFAULT_MAX_FRAGMENT_SIZE: usize = 64 * 1024; // 64KB
+
+    /// Default number of queued bytes at which buffered sends flush on their own
+    pub const DEFAULT_FLUSH_THRESHOLD: usize = 64 * 1024; // 64KB
+
     /// WebSocket key length in bytes
     pub const WEBSOCKET_KEY_LEN: usize = 16;
 
@@ -142,6 +169,9 @@ pub mod constants {
 
     /// Maximum close reason size
     pub const MAX_CLOSE_REASON_SIZE: usize = 123;
+
+    /// Maximum control frame payload size
+    pub const MAX_CONTROL_PAYLOAD_SIZE: usize = 125;
 }
 
 /// Frame header bit positions and masks
@@ -191,12 +221,18 @@ pub mod http_status {
     /// HTTP Forbidden status
     pub const FORBIDDEN: u16 = 403;
 
+    /// HTTP Not Found status
+    pub const NOT_FOUND: u16 = 404;
+
     /// HTTP Method Not Allowed status
     pub const METHOD_NOT_ALLOWED: u16 = 405;
 
     /// HTTP Upgrade Required status
     pub const UPGRADE_REQUIRED: u16 = 426;
 
+    /// HTTP Too Many Requests status
+    pub const TOO_MANY_REQUESTS: u16 = 429;
+
     /// HTTP Internal Server Error status
     pub const INTERNAL_SERVER_ERROR: u16 = 500;
 
@@ -339,6 +375,173 @@ pub mod extensions {
     pub const SERVER_NO_CONTEXT_TAKEOVER: &str = "server_no_context_takeover";
 }
 
+/// One offer (or accepted extension) from a `Sec-WebSocket-Extensions` header
+///
+/// Follows the grammar of RFC 6455 section 9.1: an extension token followed by
+/// `;`-separated parameters, each a token with an optional `=` value that may
+/// be a token or a quoted string. Multiple offers are separated by commas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionOffer {
+    /// Extension name
+    pub name: String,
+    /// Parameters in header order, with their unquoted values
+    pub params: Vec<(String, Option<String>)>,
+}
+
+impl ExtensionOffer {
+    /// Create an offer without parameters
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Add a parameter
+    pub fn with_param(mut self, key: impl Into<String>, value: Option<String>) -> Self {
+        self.params.push((key.into(), value));
+        self
+    }
+
+    /// Look up a parameter: `None` if absent, `Some(None)` if present without a value
+    pub fn param(&self, key: &str) -> Option<Option<&str>> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_deref())
+    }
+
+    /// Whether the parameter is present
+    pub fn has_param(&self, key: &str) -> bool {
+        self.param(key).is_some()
+    }
+
+    /// Parse a full header value into its offers
+    pub fn parse_header(header: &str) -> crate::Result<Vec<Self>> {
+        split_unquoted(header, ',')
+            .into_iter()
+            .map(str::trim)
+            .filter(|offer| !offer.is_empty())
+            .map(|offer| Self::parse(offer).ok_or_else(|| invalid_extensions(header)))
+            .collect()
+    }
+
+    /// Parse a single offer
+    fn parse(offer: &str) -> Option<Self> {
+        let mut parts = split_unquoted(offer, ';').into_iter().map(str::trim);
+        let name = parts.next().filter(|name| is_token(name))?;
+
+        let mut params = Vec::new();
+        for param in parts {
+            let (key, value) = match param.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(unquote(value.trim())?)),
+                None => (param, None),
+            };
+            if !is_token(key) {
+                return None;
+            }
+            params.push((key.to_string(), value));
+        }
+
+        Some(Self {
+            name: name.to_string(),
+            params,
+        })
+    }
+
+    /// Serialize a list of offers as a header value
+    pub fn to_header(offers: &[Self]) -> String {
+        offers
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl std::fmt::Display for ExtensionOffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use std::fmt::Write;
+
+        f.write_str(&self.name)?;
+        for (key, value) in &self.params {
+            match value {
+                Some(value) if is_token(value) => write!(f, "; {}={}", key, value)?,
+                Some(value) => {
+                    write!(f, "; {}=\"", key)?;
+                    for c in value.chars() {
+                        if matches!(c, '\\' | '"') {
+                            f.write_char('\\')?;
+                        }
+                        f.write_char(c)?;
+                    }
+                    f.write_char('"')?;
+                }
+                None => write!(f, "; {}", key)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid_extensions(header: &str) -> crate::Error {
+    crate::error::ProtocolError::InvalidHeaderValue {
+        header: http_header::SEC_WEBSOCKET_EXTENSIONS.to_string(),
+        value: header.to_string(),
+    }
+    .into()
+}
+
+/// Split on `separator` outside of quoted strings
+fn split_unquoted(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            c if c == separator && !in_quotes => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Unquote a token or quoted-string parameter value
+fn unquote(value: &str) -> Option<String> {
+    let Some(inner) = value
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+    else {
+        return is_token(value).then(|| value.to_string());
+    };
+
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.push(chars.next()?),
+            '"' => return None,
+            c => unquoted.push(c),
+        }
+    }
+    Some(unquoted)
+}
+
+/// Whether `s` is a non-empty HTTP token (RFC 7230 section 3.2.6)
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
 /// Utility functions for WebSocket protocol operations
 pub mod utils {
     use base64::{engine::general_purpose, Engine as _};
@@ -359,9 +562,12 @@ pub mod utils {
         general_purpose::STANDARD.encode(hash)
     }
 
-    /// Validate WebSocket key format
+    /// Validate WebSocket key format (base64 of exactly 16 bytes)
     pub fn validate_key(key: &str) -> bool {
-        key.len() == 24 && general_purpose::STANDARD.decode(key).is_ok()
+        key.len() == 24
+            && general_purpose::STANDARD
+                .decode(key)
+                .is_ok_and(|nonce| nonce.len() == 16)
     }
 
     /// Validate WebSocket version
@@ -369,22 +575,16 @@ pub mod utils {
         version == super::constants::WEBSOCKET_VERSION
     }
 
-    /// Check if a close code is valid
+    /// Check whether a close code may be sent in a Close frame
+    ///
+    /// Accepts 1000-1003, 1007-1011, the 1012-1014 registered with IANA after
+    /// RFC 6455, and the application range 3000-4999. The reserved 1004 and
+    /// the local-only 1005, 1006 and 1015 are rejected, as is anything
+    /// unassigned.
     pub fn is_valid_close_code(code: u16) -> bool {
-        use crate::error::CloseCode;
-        matches!(
-            CloseCode::from(code),
-            CloseCode::Normal
-                | CloseCode::Away
-                | CloseCode::ProtocolError
-                | CloseCode::Unsupported
-                | CloseCode::InvalidPayload
-                | CloseCode::PolicyViolation
-                | CloseCode::TooBig
-                | CloseCode::MandatoryExtension
-                | CloseCode::Internal
-                | CloseCode::Application(_)
-        )
+        use crate::error::AppCloseCode;
+
+        matches!(code, 1000..=1003 | 1007..=1014 | AppCloseCode::MIN..=AppCloseCode::MAX)
     }
 }
 
@@ -406,6 +606,7 @@ mod tests {
     fn test_websocket_key_generation() {
         let key = utils::generate_key();
         assert!(utils::validate_key(&key));
+        assert!(!utils::validate_key("AAAAAAAAAAAAAAAAAAAAAAAA"));
     }
 
     #[test]
@@ -415,12 +616,67 @@ mod tests {
         assert_eq!(utils::calculate_accept(key), expected);
     }
 
+    #[test]
+    fn test_extension_offer_parsing() {
+        let offers = ExtensionOffer::parse_header(
+            "permessage-deflate; client_max_window_bits=10; server_no_context_takeover",
+        )
+        .unwrap();
+        assert_eq!(
+            offers,
+            [ExtensionOffer {
+                name: "permessage-deflate".to_string(),
+                params: vec![
+                    ("client_max_window_bits".to_string(), Some("10".to_string())),
+                    ("server_no_context_takeover".to_string(), None),
+                ],
+            }]
+        );
+        assert_eq!(offers[0].param("client_max_window_bits"), Some(Some("10")));
+        assert!(offers[0].has_param("server_no_context_takeover"));
+        assert_eq!(offers[0].param("server_max_window_bits"), None);
+    }
+
+    #[test]
+    fn test_extension_offer_list_and_quoting() {
+        let header = r#"permessage-deflate; client_max_window_bits, x-custom; name="a;b,c", mux"#;
+        let offers = ExtensionOffer::parse_header(header).unwrap();
+        let names: Vec<_> = offers.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, ["permessage-deflate", "x-custom", "mux"]);
+        assert_eq!(offers[0].param("client_max_window_bits"), Some(None));
+        assert_eq!(offers[1].param("name"), Some(Some("a;b,c")));
+
+        // Serializing and parsing again round-trips
+        let reparsed = ExtensionOffer::parse_header(&ExtensionOffer::to_header(&offers)).unwrap();
+        assert_eq!(reparsed, offers);
+    }
+
+    #[test]
+    fn test_extension_offer_escapes_quoted_values() {
+        let offer = ExtensionOffer {
+            name: "x-custom".to_string(),
+            params: vec![("path".to_string(), Some(r#"C:\dir "quoted""#.to_string()))],
+        };
+        let header = offer.to_string();
+        assert_eq!(header, r#"x-custom; path="C:\\dir \"quoted\"""#);
+        assert_eq!(ExtensionOffer::parse_header(&header).unwrap(), [offer]);
+    }
+
+    #[test]
+    fn test_extension_offer_rejects_malformed() {
+        assert!(ExtensionOffer::parse_header("; foo").is_err());
+        assert!(ExtensionOffer::parse_header("bad name").is_err());
+        assert!(ExtensionOffer::parse_header("ext; a=\"unterminated").is_err());
+        assert!(ExtensionOffer::parse_header("").unwrap().is_empty());
+    }
+
     #[test]
     fn test_close_code_validation() {
-        assert!(utils::is_valid_close_code(1000));
-        assert!(utils::is_valid_close_code(3000));
-        assert!(utils::is_valid_close_code(999)); // 999 maps to ProtocolError which is valid
-        assert!(utils::is_valid_close_code(500)); // 500 maps to ProtocolError which is valid
-                                                  // All codes seem to map to valid CloseCode variants in the current implementation
+        for code in [1000, 1003, 1007, 1011, 1014, 3000, 4999] {
+            assert!(utils::is_valid_close_code(code), "{}", code);
+        }
+        for code in [0, 500, 999, 1004, 1005, 1006, 1015, 2999, 5000] {
+            assert!(!utils::is_valid_close_code(code), "{}", code);
+        }
     }
 }
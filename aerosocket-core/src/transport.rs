@@ -4,6 +4,7 @@
 //! with different underlying transports (TCP, TLS, QUIC, etc.).
 
 use crate::error::Result;
+use bytes::BytesMut;
 
 /// Transport trait for abstracting different transport types
 #[async_trait::async_trait]
@@ -27,6 +28,18 @@ pub trait TransportStream: Send + Sync {
     /// Read data from the stream
     async fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
 
+    /// Read up to `size` more bytes straight onto the end of `buf`
+    ///
+    /// Returns the number of bytes read, 0 at end of stream, leaving `buf`
+    /// holding exactly what it held before plus those bytes.
+    async fn read_buf(&mut self, buf: &mut BytesMut, size: usize) -> Result<usize> {
+        let start = buf.len();
+        buf.resize(start + size, 0);
+        let result = self.read(&mut buf[start..]).await;
+        buf.truncate(start + *result.as_ref().unwrap_or(&0));
+        result
+    }
+
     /// Write data to the stream
     async fn write(&mut self, buf: &[u8]) -> Result<usize>;
 
@@ -44,6 +57,73 @@ pub trait TransportStream: Send + Sync {
 
     /// Get the local address
     fn local_addr(&self) -> Result<std::net::SocketAddr>;
+
+    /// Move the stream out as independently owned read and write halves
+    ///
+    /// Returns `None` and leaves the stream untouched when the transport cannot
+    /// be split. After a successful split the stream itself is no longer usable.
+    fn take_split(&mut self) -> Option<SplitHalves> {
+        None
+    }
+}
+
+/// Read and write halves produced by [`TransportStream::take_split`]
+pub type SplitHalves = (Box<dyn TransportRead>, Box<dyn TransportWrite>);
+
+/// Read half of a split transport stream
+#[async_trait::async_trait]
+pub trait TransportRead: Send + Sync {
+    /// Read data from the stream
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+}
+
+/// Write half of a split transport stream
+#[async_trait::async_trait]
+pub trait TransportWrite: Send + Sync {
+    /// Write all data to the stream
+    async fn write_all(&mut self, buf: &[u8]) -> Result<()>;
+
+    /// Flush the stream
+    async fn flush(&mut self) -> Result<()>;
+
+    /// Shut down the write side of the stream
+    async fn close(&mut self) -> Result<()>;
+}
+
+/// Kind of transport a client or server runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransportType {
+    /// Plain TCP transport
+    Tcp,
+    /// TLS transport
+    Tls,
+}
+
+impl TransportType {
+    /// Name of the cargo feature that provides this transport on the server
+    pub fn server_feature(&self) -> &'static str {
+        match self {
+            TransportType::Tcp => "tcp-transport",
+            TransportType::Tls => "tls-transport",
+        }
+    }
+
+    /// Name of the cargo feature that provides this transport on the client
+    pub fn client_feature(&self) -> &'static str {
+        match self {
+            TransportType::Tcp => "transport-tcp",
+            TransportType::Tls => "transport-tls",
+        }
+    }
+}
+
+impl std::fmt::Display for TransportType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportType::Tcp => write!(f, "tcp"),
+            TransportType::Tls => write!(f, "tls"),
+        }
+    }
 }
 
 /// Configuration for transport options
@@ -79,6 +159,54 @@ impl Default for TransportConfig {
     }
 }
 
+/// Socket options applied to every TCP connection a transport opens or accepts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpTransportConfig {
+    /// Disable Nagle's algorithm (`TCP_NODELAY`)
+    ///
+    /// On by default, since WebSocket messages are usually small and
+    /// latency-sensitive.
+    pub nodelay: bool,
+    /// Idle time before TCP keepalive probes start (`None` leaves
+    /// `SO_KEEPALIVE` off)
+    pub keepalive: Option<std::time::Duration>,
+    /// Size of the socket receive buffer (`None` keeps the OS default)
+    pub recv_buffer: Option<usize>,
+    /// Size of the socket send buffer (`None` keeps the OS default)
+    pub send_buffer: Option<usize>,
+}
+
+impl Default for TcpTransportConfig {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            keepalive: None,
+            recv_buffer: None,
+            send_buffer: None,
+        }
+    }
+}
+
+impl TcpTransportConfig {
+    /// Apply the options to a connected stream
+    #[cfg(feature = "tokio-runtime")]
+    pub fn apply(&self, stream: &tokio::net::TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+
+        let socket = socket2::SockRef::from(stream);
+        if let Some(time) = self.keepalive {
+            socket.set_tcp_keepalive(&socket2::TcpKeepalive::new().with_time(time))?;
+        }
+        if let Some(size) = self.recv_buffer {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer {
+            socket.set_send_buffer_size(size)?;
+        }
+        Ok(())
+    }
+}
+
 /// TCP transport implementation
 #[cfg(feature = "tokio-runtime")]
 pub mod tcp {
@@ -156,10 +284,14 @@ pub mod tls {
 }
 
 /// Mock transport for testing
-#[cfg(test)]
+///
+/// Available to other crates' tests through the `test-util` feature.
+#[cfg(any(test, feature = "test-util"))]
 pub mod mock {
     use super::*;
-    use std::sync::mpsc;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
 
     /// Mock transport for testing
     #[derive(Debug)]
@@ -237,6 +369,167 @@ pub mod mock {
             Ok(self.local_addr)
         }
     }
+
+    /// Transport stream that replays scripted reads and records writes
+    ///
+    /// Each read returns the next scripted chunk, or as much of it as fits,
+    /// and reports end of stream once the script runs out. The public fields
+    /// tune how it misbehaves: stalling, flooding, trickling or wedging.
+    #[derive(Debug, Clone)]
+    pub struct ScriptedStream {
+        /// Chunks still to be read, in order
+        pub reads: VecDeque<Vec<u8>>,
+        /// Number of `read` calls so far
+        pub read_calls: Arc<AtomicUsize>,
+        /// Every byte written so far
+        pub written: Arc<Mutex<Vec<u8>>>,
+        /// Bytes the stream still takes; writes block once it reaches zero
+        pub write_budget: Arc<AtomicUsize>,
+        /// Block instead of reporting end of stream once the script runs out
+        pub stall_when_empty: bool,
+        /// Chunk returned by every read once the script runs out
+        pub repeat: Option<Vec<u8>>,
+        /// Write one byte at a time, yielding to the scheduler in between
+        pub trickle_writes: bool,
+        /// Block forever in `flush` and `close`
+        pub wedged: bool,
+        /// Support [`TransportStream::take_split`]; both halves share the
+        /// recorded writes
+        pub splittable: bool,
+    }
+
+    impl ScriptedStream {
+        /// Create a stream replaying `reads` and taking every write
+        pub fn new(reads: Vec<Vec<u8>>) -> Self {
+            Self {
+                reads: reads.into(),
+                read_calls: Default::default(),
+                written: Default::default(),
+                write_budget: Arc::new(usize::MAX.into()),
+                stall_when_empty: false,
+                repeat: None,
+                trickle_writes: false,
+                wedged: false,
+                splittable: false,
+            }
+        }
+
+        async fn wedge(&self) -> Result<()> {
+            if self.wedged {
+                std::future::pending().await
+            }
+            Ok(())
+        }
+    }
+
+    /// Return `Pending` once so other tasks get to run
+    async fn yield_once() {
+        let mut yielded = false;
+        std::future::poll_fn(|cx| {
+            if yielded {
+                std::task::Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        })
+        .await
+    }
+
+    #[async_trait::async_trait]
+    impl TransportStream for ScriptedStream {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            self.read_calls.fetch_add(1, Ordering::SeqCst);
+            if self.reads.is_empty() {
+                self.reads.extend(self.repeat.clone());
+            }
+            match self.reads.pop_front() {
+                Some(mut chunk) => {
+                    let n = chunk.len().min(buf.len());
+                    buf[..n].copy_from_slice(&chunk[..n]);
+                    if n < chunk.len() {
+                        self.reads.push_front(chunk.split_off(n));
+                    }
+                    Ok(n)
+                }
+                None if self.stall_when_empty => std::future::pending().await,
+                None => Ok(0),
+            }
+        }
+
+        async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let budget = self.write_budget.load(Ordering::SeqCst);
+            if budget == 0 {
+                return std::future::pending().await;
+            }
+            let mut n = buf.len().min(budget);
+            if self.trickle_writes && n > 0 {
+                yield_once().await;
+                n = 1;
+            }
+            self.write_budget.fetch_sub(n, Ordering::SeqCst);
+            self.written.lock().unwrap().extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        async fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                let n = self.write(buf).await?;
+                buf = &buf[n..];
+            }
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<()> {
+            self.wedge().await
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            self.wedge().await
+        }
+
+        fn remote_addr(&self) -> Result<std::net::SocketAddr> {
+            Ok("127.0.0.1:12345".parse().unwrap())
+        }
+
+        fn local_addr(&self) -> Result<std::net::SocketAddr> {
+            Ok("127.0.0.1:8080".parse().unwrap())
+        }
+
+        fn take_split(&mut self) -> Option<SplitHalves> {
+            if !self.splittable {
+                return None;
+            }
+            let read = Self {
+                reads: std::mem::take(&mut self.reads),
+                ..self.clone()
+            };
+            Some((Box::new(read), Box::new(self.clone())))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl TransportRead for ScriptedStream {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            TransportStream::read(self, buf).await
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl TransportWrite for ScriptedStream {
+        async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+            TransportStream::write_all(self, buf).await
+        }
+
+        async fn flush(&mut self) -> Result<()> {
+            TransportStream::flush(self).await
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            TransportStream::close(self).await
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,125 @@
+//! Echo benchmarks over loopback TCP
+//!
+//! A connection sends bursts of messages to a peer that echoes every byte
+//! back, then reads all the echoes, comparing a flush per frame (`send`)
+//! with coalesced writes (`send_buffered` and a final `flush`). A second
+//! group measures the round trip of a single message.
+//!
+//! Run with `cargo bench -p aerosocket-server --bench echo`.
+
+use aerosocket_core::Message;
+use aerosocket_server::Connection;
+use aerosocket_transport_tcp::TcpStream;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+
+const PAYLOAD_SIZES: &[usize] = &[16, 1024, 4 * 1024];
+
+/// Messages sent per iteration of the burst benchmarks
+///
+/// A whole burst is written before any echo is read, so it has to fit in
+/// the loopback socket buffers.
+const BURST: usize = 100;
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+/// Connect to a loopback peer that writes back everything it reads
+async fn echo_connection() -> Connection {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        stream.set_nodelay(true).unwrap();
+        let (mut read, mut write) = stream.into_split();
+        let _ = tokio::io::copy(&mut read, &mut write).await;
+        let _ = write.shutdown().await;
+    });
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.set_nodelay(true).unwrap();
+    let local = stream.local_addr().unwrap();
+    let mut conn = Connection::with_stream(addr, local, Box::new(TcpStream::from_tokio(stream)));
+    // The echoed frames are our own, which a server sends unmasked
+    conn.set_allow_unmasked(true);
+    conn
+}
+
+async fn read_echoes(conn: &mut Connection, count: usize) {
+    for _ in 0..count {
+        black_box(conn.next().await.unwrap().unwrap());
+    }
+}
+
+fn bench_burst(c: &mut Criterion) {
+    let rt = runtime();
+    let mut group = c.benchmark_group("echo_burst");
+    for &size in PAYLOAD_SIZES {
+        let payload = vec![0x5a; size];
+        group.throughput(Throughput::Bytes((size * BURST) as u64));
+
+        let mut conn = rt.block_on(echo_connection());
+        group.bench_with_input(
+            BenchmarkId::new("flush_per_frame", size),
+            &payload,
+            |b, payload| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        for _ in 0..BURST {
+                            conn.send(Message::binary(payload.clone())).await.unwrap();
+                        }
+                        read_echoes(&mut conn, BURST).await;
+                    })
+                });
+            },
+        );
+
+        let mut conn = rt.block_on(echo_connection());
+        group.bench_with_input(
+            BenchmarkId::new("coalesced", size),
+            &payload,
+            |b, payload| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        for _ in 0..BURST {
+                            conn.send_buffered(Message::binary(payload.clone()))
+                                .await
+                                .unwrap();
+                        }
+                        conn.flush().await.unwrap();
+                        read_echoes(&mut conn, BURST).await;
+                    })
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_round_trip(c: &mut Criterion) {
+    let rt = runtime();
+    let mut group = c.benchmark_group("echo_round_trip");
+    for &size in PAYLOAD_SIZES {
+        let payload = vec![0x5a; size];
+        let mut conn = rt.block_on(echo_connection());
+        group.bench_with_input(BenchmarkId::from_parameter(size), &payload, |b, payload| {
+            b.iter(|| {
+                rt.block_on(async {
+                    conn.send(Message::binary(payload.clone())).await.unwrap();
+                    read_echoes(&mut conn, 1).await;
+                })
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_burst, bench_round_trip);
+criterion_main!(benches);
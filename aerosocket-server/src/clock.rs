@@ -0,0 +1,127 @@
+//! Time source abstraction
+//!
+//! Idle tracking, close timeouts and the manager's health checks read time
+//! through a [`Clock`] instead of calling `Instant::now()` and `tokio::time`
+//! directly. Production code uses [`TokioClock`]; tests can swap in a
+//! [`MockClock`] and advance it manually to exercise timeouts without sleeping.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Source of the current time and of sleeps
+pub trait Clock: Send + Sync + std::fmt::Debug + 'static {
+    /// Current instant
+    fn now(&self) -> Instant;
+
+    /// Future that completes once `duration` has passed on this clock
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+}
+
+/// Shared clock handle
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Clock backed by tokio's timer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Default clock used by connections and the connection manager
+pub fn default_clock() -> SharedClock {
+    Arc::new(TokioClock)
+}
+
+/// Manually driven clock for tests
+///
+/// Time only moves when [`MockClock::advance`] is called; pending sleeps
+/// complete as soon as the clock has been advanced past their deadline.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    start: Instant,
+    elapsed: Arc<watch::Sender<Duration>>,
+}
+
+impl MockClock {
+    /// Create a mock clock starting at the current real instant
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::new(watch::channel(Duration::ZERO).0),
+        }
+    }
+
+    /// Move the clock forward, waking any sleeps that are now due
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.send_modify(|elapsed| *elapsed += duration);
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.borrow()
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut elapsed = self.elapsed.subscribe();
+        let deadline = *elapsed.borrow() + duration;
+        Box::pin(async move {
+            while *elapsed.borrow_and_update() < deadline {
+                if elapsed.changed().await.is_err() {
+                    // Clock dropped; time can never reach the deadline
+                    std::future::pending::<()>().await;
+                }
+            }
+        })
+    }
+}
+
+/// Run `future` for at most `limit` on `clock`, returning `None` on expiry
+pub(crate) async fn timeout<F: Future>(
+    clock: &dyn Clock,
+    limit: Duration,
+    future: F,
+) -> Option<F::Output> {
+    let sleep = clock.sleep(limit);
+    tokio::select! {
+        biased;
+        output = future => Some(output),
+        _ = sleep => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_clock_sleep_wakes_on_advance() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let sleep = tokio::spawn(clock.sleep(Duration::from_secs(10)));
+
+        clock.advance(Duration::from_secs(5));
+        tokio::task::yield_now().await;
+        assert!(!sleep.is_finished());
+
+        clock.advance(Duration::from_secs(5));
+        sleep.await.unwrap();
+        assert_eq!(clock.now() - start, Duration::from_secs(10));
+    }
+}
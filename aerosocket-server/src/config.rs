@@ -2,12 +2,20 @@
 //!
 //! This module provides configuration options for the WebSocket server.
 
+use crate::context::ConnectionContext;
+use crate::error::HandshakeError;
+use crate::rate_limit::RateLimitAlgorithm;
 use aerosocket_core::error::{ConfigError, Error};
+use aerosocket_core::handshake::{HandshakeDecision, HandshakeRequest};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[cfg(feature = "tls-transport")]
 use rustls::{
-    Certificate as RustlsCert, PrivateKey as RustlsKey, ServerConfig as RustlsServerConfig,
+    server::AllowAnyAuthenticatedClient, Certificate as RustlsCert, PrivateKey as RustlsKey,
+    RootCertStore, ServerConfig as RustlsServerConfig,
 };
 #[cfg(feature = "tls-transport")]
 use rustls_pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
@@ -23,14 +31,43 @@ pub struct ServerConfig {
     pub bind_address: std::net::SocketAddr,
     /// Maximum concurrent connections
     pub max_connections: usize,
+    /// Maximum concurrent connections from one client IP
+    ///
+    /// `None` allows a tenth of `max_connections` while backpressure is
+    /// enabled and leaves connections per IP unlimited otherwise.
+    pub max_connections_per_ip: Option<usize>,
     /// Maximum frame size in bytes
     pub max_frame_size: usize,
     /// Maximum message size in bytes
     pub max_message_size: usize,
+    /// Maximum number of frames a message may be fragmented into
+    pub max_fragments_per_message: usize,
     /// Handshake timeout
     pub handshake_timeout: Duration,
     /// Idle timeout
     pub idle_timeout: Duration,
+    /// Maximum time a connection may spend sending its Close frame
+    pub close_timeout: Duration,
+    /// Maximum time a graceful shutdown waits for connections to close with 1001
+    ///
+    /// Handlers still running once it elapses are cancelled and their
+    /// connections dropped.
+    pub shutdown_timeout: Duration,
+    /// Ping connections that have been quiet this long (`None` disables keepalive)
+    pub keepalive_interval: Option<Duration>,
+    /// Close a connection with 1001 when nothing arrives this long after a keepalive ping
+    pub keepalive_timeout: Option<Duration>,
+    /// Maximum frames read after sending Close while waiting for the peer's Close
+    pub close_drain_frames: usize,
+    /// Maximum bytes read after sending Close while waiting for the peer's Close
+    pub close_drain_bytes: usize,
+    /// Bytes each connection requests from the transport per read
+    ///
+    /// Raise it for connections carrying large messages to cut syscalls;
+    /// keep it small when holding many mostly idle connections.
+    pub read_buffer_size: usize,
+    /// Maximum number of handlers running at once (`None` means unlimited)
+    pub max_concurrent_handlers: Option<usize>,
     /// Compression configuration
     pub compression: CompressionConfig,
     /// Backpressure configuration
@@ -39,77 +76,422 @@ pub struct ServerConfig {
     pub tls: Option<TlsConfig>,
     /// Transport type
     pub transport_type: TransportType,
+    /// Socket options applied to accepted TCP connections, including those
+    /// carrying TLS
+    pub tcp: TcpTransportConfig,
     /// Supported WebSocket subprotocols
     pub supported_protocols: Vec<String>,
+    /// Custom subprotocol choice, used instead of matching `supported_protocols`
+    pub protocol_selector: Option<ProtocolSelector>,
     /// Supported WebSocket extensions
     pub supported_extensions: Vec<String>,
     /// Allowed origins for CORS (empty means allow all)
+    ///
+    /// Exact origins, subdomain wildcards such as `https://*.example.com`,
+    /// or `*`; see [`origin_allowed`](aerosocket_core::handshake::origin_allowed).
     pub allowed_origins: Vec<String>,
+    /// Policy for requests with a missing or unlisted origin
+    pub origin_policy: OriginPolicy,
+    /// Reject client frames masked with an all-zero key (close 1002)
+    pub reject_zero_mask: bool,
+    /// Accept unmasked client frames
+    ///
+    /// RFC 6455 requires clients to mask every frame; only enable this for
+    /// trusted server-to-server links whose clients set
+    /// `ClientConfig::mask_frames` to `false`.
+    pub allow_unmasked_clients: bool,
+    /// Enforce every RFC 6455 MUST on client frames, closing with the
+    /// prescribed status code on any violation
+    ///
+    /// Overrides `allow_unmasked_clients`. When off, invalid close codes are
+    /// accepted and invalid UTF-8 text is replaced rather than rejected.
+    pub strict_protocol: bool,
+    /// Total bytes a single connection may send and receive before it is closed with 1008
+    pub max_connection_bytes: Option<u64>,
     /// Extra headers to send in handshake response
     pub extra_headers: std::collections::HashMap<String, String>,
+    /// Per-request accept decision, run after the handshake has been validated
+    pub on_handshake: Option<HandshakeHook>,
+    /// Asynchronous authorization of each request, run after `on_handshake`
+    pub authorize: Option<AuthHook>,
+    /// Observer of the raw handshake request and response bytes
+    pub on_raw_handshake: Option<RawHandshakeHook>,
+    /// Overload signal checked for every accepted connection
+    pub load_shed: Option<LoadShedHook>,
+    /// Pool recycling the buffers of received binary messages reassembled from fragments
+    pub buffer_pool: Option<crate::pool::BufferPool>,
 }
 
-/// Transport type
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum TransportType {
-    /// TCP transport
-    Tcp,
-    /// TLS transport
-    Tls,
-}
+pub use aerosocket_core::handshake::{OriginPolicy, ProtocolSelector};
+pub use aerosocket_core::transport::{TcpTransportConfig, TransportType};
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             bind_address: "0.0.0.0:8080".parse().unwrap(),
             max_connections: 10_000,
+            max_connections_per_ip: None,
             max_frame_size: aerosocket_core::protocol::constants::DEFAULT_MAX_FRAME_SIZE,
             max_message_size: aerosocket_core::protocol::constants::DEFAULT_MAX_MESSAGE_SIZE,
+            max_fragments_per_message:
+                aerosocket_core::protocol::constants::DEFAULT_MAX_FRAGMENTS_PER_MESSAGE,
             handshake_timeout: aerosocket_core::protocol::constants::DEFAULT_HANDSHAKE_TIMEOUT,
             idle_timeout: aerosocket_core::protocol::constants::DEFAULT_IDLE_TIMEOUT,
+            close_timeout: aerosocket_core::protocol::constants::DEFAULT_CLOSE_TIMEOUT,
+            shutdown_timeout: aerosocket_core::protocol::constants::DEFAULT_SHUTDOWN_TIMEOUT,
+            keepalive_interval: None,
+            keepalive_timeout: None,
+            close_drain_frames: aerosocket_core::protocol::constants::DEFAULT_CLOSE_DRAIN_FRAMES,
+            close_drain_bytes: aerosocket_core::protocol::constants::DEFAULT_CLOSE_DRAIN_BYTES,
+            read_buffer_size: aerosocket_core::protocol::constants::DEFAULT_READ_BUFFER_SIZE,
+            max_concurrent_handlers: None,
             compression: CompressionConfig::default(),
             backpressure: BackpressureConfig::default(),
             tls: None,
             transport_type: TransportType::Tcp,
+            tcp: TcpTransportConfig::default(),
             supported_protocols: vec![],
+            protocol_selector: None,
             supported_extensions: vec![],
             allowed_origins: vec![],
+            origin_policy: OriginPolicy::default(),
+            reject_zero_mask: false,
+            allow_unmasked_clients: false,
+            strict_protocol: false,
+            max_connection_bytes: None,
             extra_headers: std::collections::HashMap::new(),
+            on_handshake: None,
+            authorize: None,
+            on_raw_handshake: None,
+            load_shed: None,
+            buffer_pool: None,
         }
     }
 }
 
 impl ServerConfig {
     /// Validate the configuration
+    ///
+    /// An out-of-range setting is reported as [`ConfigError::InvalidValue`]
+    /// naming the offending field, with nested fields written as a path such
+    /// as `compression.level`.
     pub fn validate(&self) -> aerosocket_core::Result<()> {
         if self.max_connections == 0 {
-            return Err(Error::Config(ConfigError::Validation(
-                "max_connections must be greater than 0".to_string(),
-            )));
+            return Err(invalid_value(
+                "max_connections",
+                self.max_connections,
+                "must be greater than 0",
+            ));
         }
 
         if self.max_frame_size == 0 {
-            return Err(Error::Config(ConfigError::Validation(
-                "max_frame_size must be greater than 0".to_string(),
-            )));
+            return Err(invalid_value(
+                "max_frame_size",
+                self.max_frame_size,
+                "must be greater than 0",
+            ));
         }
 
         if self.max_message_size == 0 {
-            return Err(Error::Config(ConfigError::Validation(
-                "max_message_size must be greater than 0".to_string(),
-            )));
+            return Err(invalid_value(
+                "max_message_size",
+                self.max_message_size,
+                "must be greater than 0",
+            ));
+        }
+
+        if self.max_fragments_per_message == 0 {
+            return Err(invalid_value(
+                "max_fragments_per_message",
+                self.max_fragments_per_message,
+                "must be greater than 0",
+            ));
+        }
+
+        if self.read_buffer_size == 0 {
+            return Err(invalid_value(
+                "read_buffer_size",
+                self.read_buffer_size,
+                "must be greater than 0",
+            ));
+        }
+
+        if self.max_connections_per_ip == Some(0) {
+            return Err(invalid_value(
+                "max_connections_per_ip",
+                0,
+                "must be greater than 0",
+            ));
+        }
+
+        if self.max_concurrent_handlers == Some(0) {
+            return Err(invalid_value(
+                "max_concurrent_handlers",
+                0,
+                "must be greater than 0",
+            ));
+        }
+
+        for (field, timeout) in [
+            ("handshake_timeout", Some(self.handshake_timeout)),
+            ("idle_timeout", Some(self.idle_timeout)),
+            ("close_timeout", Some(self.close_timeout)),
+            ("shutdown_timeout", Some(self.shutdown_timeout)),
+            ("keepalive_interval", self.keepalive_interval),
+            ("keepalive_timeout", self.keepalive_timeout),
+        ] {
+            if let Some(timeout) = timeout.filter(Duration::is_zero) {
+                return Err(invalid_value(
+                    field,
+                    format!("{:?}", timeout),
+                    "must be greater than 0",
+                ));
+            }
+        }
+
+        if self.backpressure.enabled {
+            let backpressure = &self.backpressure;
+            if backpressure.buffer_size == 0 {
+                return Err(invalid_value(
+                    "backpressure.buffer_size",
+                    backpressure.buffer_size,
+                    "must be greater than 0",
+                ));
+            }
+            if backpressure.high_water_mark > backpressure.buffer_size {
+                return Err(invalid_value(
+                    "backpressure.high_water_mark",
+                    backpressure.high_water_mark,
+                    format!("must not exceed buffer_size {}", backpressure.buffer_size),
+                ));
+            }
+            if backpressure.low_water_mark > backpressure.high_water_mark {
+                return Err(invalid_value(
+                    "backpressure.low_water_mark",
+                    backpressure.low_water_mark,
+                    format!(
+                        "must not exceed high_water_mark {}",
+                        backpressure.high_water_mark
+                    ),
+                ));
+            }
+            if backpressure.rate_limit_algorithm == RateLimitAlgorithm::TokenBucket {
+                // Also refuses NaN
+                if !(backpressure.refill_rate > 0.0 && backpressure.refill_rate.is_finite()) {
+                    return Err(invalid_value(
+                        "backpressure.refill_rate",
+                        backpressure.refill_rate,
+                        "must be a finite number greater than 0",
+                    ));
+                }
+                if backpressure.burst_capacity == 0 {
+                    return Err(invalid_value(
+                        "backpressure.burst_capacity",
+                        backpressure.burst_capacity,
+                        "must be greater than 0",
+                    ));
+                }
+            }
+        }
+
+        if self.compression.level > 9 {
+            return Err(invalid_value(
+                "compression.level",
+                self.compression.level,
+                "must be between 0 and 9",
+            ));
+        }
+
+        for (field, bits) in [
+            (
+                "compression.server_max_window_bits",
+                self.compression.server_max_window_bits,
+            ),
+            (
+                "compression.client_max_window_bits",
+                self.compression.client_max_window_bits,
+            ),
+        ] {
+            // zlib has no 8-bit window to compress with
+            if let Some(bits) = bits.filter(|bits| !(9..=15).contains(bits)) {
+                return Err(invalid_value(field, bits, "must be between 9 and 15"));
+            }
+        }
+
+        if self.compression.enabled && !cfg!(feature = "compression") {
+            return Err(invalid_value(
+                "compression.enabled",
+                self.compression.enabled,
+                "the `compression` feature is not compiled in",
+            ));
         }
 
         if self.max_message_size < self.max_frame_size {
-            return Err(Error::Config(ConfigError::Validation(
-                "max_message_size must be greater than or equal to max_frame_size".to_string(),
-            )));
+            return Err(invalid_value(
+                "max_message_size",
+                self.max_message_size,
+                format!(
+                    "must be greater than or equal to max_frame_size {}",
+                    self.max_frame_size
+                ),
+            ));
         }
 
         Ok(())
     }
 }
 
+fn invalid_value(
+    field: &str,
+    value: impl std::fmt::Display,
+    reason: impl std::fmt::Display,
+) -> Error {
+    Error::Config(ConfigError::InvalidValue {
+        field: field.to_string(),
+        value: value.to_string(),
+        reason: reason.to_string(),
+    })
+}
+
+/// Callback deciding whether to accept a handshake request
+///
+/// An accepting decision may carry response headers computed from the
+/// request (for example a `Set-Cookie` session token); they are added to
+/// the 101 response after [`ServerConfig::extra_headers`], replacing any of
+/// those with the same name. Protocol headers such as `Upgrade` or
+/// `Sec-WebSocket-Accept` cannot be overridden.
+#[derive(Clone)]
+#[allow(clippy::type_complexity)]
+pub struct HandshakeHook(Arc<dyn Fn(&HandshakeRequest) -> HandshakeDecision + Send + Sync>);
+
+impl HandshakeHook {
+    /// Wrap a decision callback
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&HandshakeRequest) -> HandshakeDecision + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    /// Decide on a handshake request
+    pub fn decide(&self, request: &HandshakeRequest) -> HandshakeDecision {
+        (self.0)(request)
+    }
+}
+
+impl std::fmt::Debug for HandshakeHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HandshakeHook(..)")
+    }
+}
+
+/// Future returned by an [`AuthHook`]
+pub type AuthFuture<'a> =
+    Pin<Box<dyn Future<Output = std::result::Result<(), HandshakeError>> + Send + 'a>>;
+
+/// Asynchronous check authorizing a handshake request
+///
+/// Runs once the request has been validated and accepted by
+/// [`ServerConfig::on_handshake`], before the 101 response is built. Values
+/// the callback stores in the context become the connection's context. An
+/// error refuses the upgrade: [`HandshakeError::AuthenticationFailed`] with
+/// `401 Unauthorized`, any other error with `403 Forbidden`.
+#[derive(Clone)]
+#[allow(clippy::type_complexity)]
+pub struct AuthHook(
+    Arc<
+        dyn for<'a> Fn(&'a HandshakeRequest, &'a mut ConnectionContext) -> AuthFuture<'a>
+            + Send
+            + Sync,
+    >,
+);
+
+impl AuthHook {
+    /// Wrap an authorization callback
+    pub fn new<F>(f: F) -> Self
+    where
+        F: for<'a> Fn(&'a HandshakeRequest, &'a mut ConnectionContext) -> AuthFuture<'a>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    /// Authorize a handshake request, stashing per-connection state in `context`
+    pub fn authorize<'a>(
+        &self,
+        request: &'a HandshakeRequest,
+        context: &'a mut ConnectionContext,
+    ) -> AuthFuture<'a> {
+        (self.0)(request, context)
+    }
+}
+
+impl std::fmt::Debug for AuthHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AuthHook(..)")
+    }
+}
+
+/// Callback observing the exact bytes of a completed handshake
+///
+/// Called with the raw HTTP request as read from the client and the raw 101
+/// response as written back, for audit logging. Runs only after the response
+/// has been sent; refused or failed handshakes are not reported.
+#[derive(Clone)]
+#[allow(clippy::type_complexity)]
+pub struct RawHandshakeHook(Arc<dyn Fn(&[u8], &[u8]) + Send + Sync>);
+
+impl RawHandshakeHook {
+    /// Wrap an observer callback
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&[u8], &[u8]) + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    /// Report a handshake's raw request and response
+    pub fn observe(&self, request: &[u8], response: &[u8]) {
+        (self.0)(request, response)
+    }
+}
+
+impl std::fmt::Debug for RawHandshakeHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RawHandshakeHook(..)")
+    }
+}
+
+/// Callback reporting whether the server is too loaded to take a connection
+///
+/// Checked as each connection is accepted; while it returns `true`, new
+/// connections are answered with `503 Service Unavailable` and closed.
+#[derive(Clone)]
+pub struct LoadShedHook(Arc<dyn Fn() -> bool + Send + Sync>);
+
+impl LoadShedHook {
+    /// Wrap an overload predicate
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    /// Check whether the next connection should be shed
+    pub fn should_shed(&self) -> bool {
+        (self.0)()
+    }
+}
+
+impl std::fmt::Debug for LoadShedHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("LoadShedHook(..)")
+    }
+}
+
 /// Compression configuration
 #[derive(Debug, Clone)]
 pub struct CompressionConfig {
@@ -125,6 +507,11 @@ pub struct CompressionConfig {
     pub server_max_window_bits: Option<u8>,
     /// Client max window bits
     pub client_max_window_bits: Option<u8>,
+    /// Preset deflate dictionary, agreed with the peer out of band
+    ///
+    /// Improves the ratio for small, repetitive payloads. Only the last 32 KiB
+    /// are used.
+    pub dictionary: Option<Vec<u8>>,
 }
 
 impl Default for CompressionConfig {
@@ -136,6 +523,7 @@ impl Default for CompressionConfig {
             client_context_takeover: true,
             server_max_window_bits: None,
             client_max_window_bits: None,
+            dictionary: None,
         }
     }
 }
@@ -147,13 +535,25 @@ pub struct BackpressureConfig {
     pub enabled: bool,
     /// Maximum requests per minute per IP
     pub max_requests_per_minute: usize,
+    /// How request (and message) budgets are counted
+    pub rate_limit_algorithm: RateLimitAlgorithm,
+    /// Tokens added to a client's bucket per second (token bucket only)
+    pub refill_rate: f64,
+    /// Tokens a full bucket holds (token bucket only)
+    pub burst_capacity: usize,
+    /// Also limit the data messages each client sends, with a budget of
+    /// their own under the same algorithm and limits as requests
+    pub limit_messages: bool,
     /// Backpressure strategy
     pub strategy: BackpressureStrategy,
-    /// Buffer size in bytes
+    /// Most encoded bytes a connection's outbound queue holds that the
+    /// transport has not taken yet
     pub buffer_size: usize,
-    /// High water mark in bytes
+    /// Queued bytes at which [`BackpressureStrategy::Buffer`] waits for the
+    /// transport and [`BackpressureStrategy::FlowControl`] stops reading
     pub high_water_mark: usize,
-    /// Low water mark in bytes
+    /// Queued bytes the queue is drained down to once the high water mark
+    /// is reached
     pub low_water_mark: usize,
 }
 
@@ -162,6 +562,10 @@ impl Default for BackpressureConfig {
         Self {
             enabled: true,
             max_requests_per_minute: 60,
+            rate_limit_algorithm: RateLimitAlgorithm::FixedWindow,
+            refill_rate: 1.0,
+            burst_capacity: 60,
+            limit_messages: false,
             strategy: BackpressureStrategy::Buffer,
             buffer_size: 64 * 1024,     // 64KB
             high_water_mark: 48 * 1024, // 48KB
@@ -171,15 +575,38 @@ impl Default for BackpressureConfig {
 }
 
 /// Backpressure strategy
+///
+/// Governs a connection's outbound queue: the frames
+/// [`Connection::feed`](crate::Connection::feed) holds until they are
+/// flushed to the transport.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BackpressureStrategy {
     /// Buffer messages (default)
+    ///
+    /// Once the queue reaches the high water mark, queuing waits for the
+    /// transport until the queue is back at the low water mark; a message
+    /// that would overflow the buffer waits for the whole queue to drain.
     Buffer,
     /// Drop oldest messages when buffer is full
+    ///
+    /// Queuing never waits, and neither does `send`: what the transport does
+    /// not take right away stays queued. A frame the transport has started on
+    /// is never dropped, and neither are compressed messages, since the
+    /// peer's decompressor depends on them; when they fill the buffer,
+    /// queuing waits as with `Buffer`.
     DropOldest,
     /// Reject new messages when buffer is full
+    ///
+    /// Queuing never waits, and neither does `send`: what the transport does
+    /// not take right away stays queued, and a message that would overflow
+    /// the buffer fails with [`Error::CapacityExceeded`].
     Reject,
     /// Apply flow control to sender
+    ///
+    /// While the queue is at the high water mark, `next()` stops reading from
+    /// the peer until the queue is drained to the low water mark, so a peer
+    /// that does not read its replies is not read from either. A message that
+    /// would overflow the buffer waits as with `Buffer`.
     FlowControl,
 }
 
@@ -192,9 +619,9 @@ pub struct TlsConfig {
     pub key_file: String,
     /// Certificate chain file (optional)
     pub cert_chain_file: Option<String>,
-    /// Enable client authentication
+    /// Require clients to present a certificate signed by a CA in `ca_file`
     pub client_auth: bool,
-    /// CA file for client authentication
+    /// CA certificates client certificates are verified against
     pub ca_file: Option<String>,
 }
 
@@ -216,7 +643,10 @@ impl TlsConfig {
         self
     }
 
-    /// Enable client authentication
+    /// Require client certificates (mutual TLS)
+    ///
+    /// Clients without a certificate signed by a CA in
+    /// [`ca_file`](Self::ca_file) fail the TLS handshake.
     pub fn client_auth(mut self, enabled: bool) -> Self {
         self.client_auth = enabled;
         self
@@ -287,21 +717,55 @@ fn load_private_key(path: &str) -> aerosocket_core::Result<RustlsKey> {
     }
 }
 
+/// Load the CAs client certificates must chain to
+#[cfg(feature = "tls-transport")]
+fn load_client_roots(tls: &TlsConfig) -> aerosocket_core::Result<RootCertStore> {
+    let ca_path = tls.ca_file.as_deref().ok_or_else(|| {
+        Error::Config(ConfigError::Validation(
+            "client_auth requires a ca_file to verify client certificates".to_string(),
+        ))
+    })?;
+
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca_path)? {
+        roots.add(&cert).map_err(|e| {
+            Error::Config(ConfigError::Validation(format!(
+                "Failed to add CA certificate from {}: {:?}",
+                ca_path, e
+            )))
+        })?;
+    }
+    if roots.is_empty() {
+        return Err(Error::Config(ConfigError::Validation(format!(
+            "No CA certificates found in {}",
+            ca_path
+        ))));
+    }
+    Ok(roots)
+}
+
+/// Build a rustls ServerConfig from this TlsConfig
+///
+/// With [`client_auth`](TlsConfig::client_auth) set, clients must present a
+/// certificate chaining to a CA in [`ca_file`](TlsConfig::ca_file).
 #[cfg(feature = "tls-transport")]
 pub fn build_rustls_server_config(tls: &TlsConfig) -> aerosocket_core::Result<RustlsServerConfig> {
     let certs = load_certs(&tls.cert_file)?;
     let key = load_private_key(&tls.key_file)?;
 
-    RustlsServerConfig::builder()
-        .with_safe_defaults()
-        .with_no_client_auth()
-        .with_single_cert(certs, key)
-        .map_err(|e| {
-            Error::Config(ConfigError::Validation(format!(
-                "Invalid TLS certificate/key: {}",
-                e
-            )))
-        })
+    let builder = RustlsServerConfig::builder().with_safe_defaults();
+    let builder = if tls.client_auth {
+        let verifier = AllowAnyAuthenticatedClient::new(load_client_roots(tls)?).boxed();
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+    builder.with_single_cert(certs, key).map_err(|e| {
+        Error::Config(ConfigError::Validation(format!(
+            "Invalid TLS certificate/key: {}",
+            e
+        )))
+    })
 }
 
 #[cfg(test)]
@@ -331,6 +795,119 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    fn invalid_field(config: &ServerConfig) -> Option<String> {
+        match config.validate() {
+            Err(Error::Config(ConfigError::InvalidValue { field, .. })) => Some(field),
+            _ => None,
+        }
+    }
+
+    type Breakage = fn(&mut ServerConfig);
+
+    #[test]
+    fn test_validation_errors_name_the_field() {
+        let cases: Vec<(&str, Breakage)> = vec![
+            ("max_connections", |c| c.max_connections = 0),
+            ("max_frame_size", |c| c.max_frame_size = 0),
+            ("max_message_size", |c| c.max_message_size = 0),
+            ("max_message_size", |c| {
+                c.max_frame_size = 1024;
+                c.max_message_size = 512;
+            }),
+            ("max_fragments_per_message", |c| {
+                c.max_fragments_per_message = 0
+            }),
+            ("read_buffer_size", |c| c.read_buffer_size = 0),
+            ("max_connections_per_ip", |c| {
+                c.max_connections_per_ip = Some(0)
+            }),
+            ("max_concurrent_handlers", |c| {
+                c.max_concurrent_handlers = Some(0)
+            }),
+            ("handshake_timeout", |c| {
+                c.handshake_timeout = Duration::ZERO
+            }),
+            ("idle_timeout", |c| c.idle_timeout = Duration::ZERO),
+            ("close_timeout", |c| c.close_timeout = Duration::ZERO),
+            ("shutdown_timeout", |c| c.shutdown_timeout = Duration::ZERO),
+            ("keepalive_interval", |c| {
+                c.keepalive_interval = Some(Duration::ZERO)
+            }),
+            ("keepalive_timeout", |c| {
+                c.keepalive_timeout = Some(Duration::ZERO)
+            }),
+            ("backpressure.buffer_size", |c| {
+                c.backpressure.buffer_size = 0
+            }),
+            ("backpressure.high_water_mark", |c| {
+                c.backpressure.high_water_mark = c.backpressure.buffer_size + 1
+            }),
+            ("backpressure.low_water_mark", |c| {
+                c.backpressure.low_water_mark = c.backpressure.high_water_mark + 1
+            }),
+            ("backpressure.refill_rate", |c| {
+                c.backpressure.rate_limit_algorithm = RateLimitAlgorithm::TokenBucket;
+                c.backpressure.refill_rate = -1.0;
+            }),
+            ("backpressure.refill_rate", |c| {
+                c.backpressure.rate_limit_algorithm = RateLimitAlgorithm::TokenBucket;
+                c.backpressure.refill_rate = 0.0;
+            }),
+            ("backpressure.burst_capacity", |c| {
+                c.backpressure.rate_limit_algorithm = RateLimitAlgorithm::TokenBucket;
+                c.backpressure.burst_capacity = 0;
+            }),
+            ("compression.level", |c| c.compression.level = 10),
+            ("compression.server_max_window_bits", |c| {
+                c.compression.server_max_window_bits = Some(8)
+            }),
+            ("compression.client_max_window_bits", |c| {
+                c.compression.client_max_window_bits = Some(16)
+            }),
+        ];
+
+        for (field, break_config) in cases {
+            let mut config = ServerConfig::default();
+            break_config(&mut config);
+            assert_eq!(invalid_field(&config).as_deref(), Some(field));
+        }
+
+        let mut config = ServerConfig::default();
+        config.compression.level = 9;
+        config.compression.server_max_window_bits = Some(9);
+        config.compression.client_max_window_bits = Some(15);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validation_error_keeps_value_and_reason_apart() {
+        let mut config = ServerConfig::default();
+        config.compression.level = 12;
+        match config.validate() {
+            Err(Error::Config(ConfigError::InvalidValue {
+                field,
+                value,
+                reason,
+            })) => {
+                assert_eq!(field, "compression.level");
+                assert_eq!(value, "12");
+                assert_eq!(reason, "must be between 0 and 9");
+            }
+            other => panic!("expected an invalid compression level, got {:?}", other),
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn test_compression_requires_feature() {
+        let mut config = ServerConfig::default();
+        config.compression.enabled = true;
+        assert_eq!(
+            invalid_field(&config).as_deref(),
+            Some("compression.enabled")
+        );
+    }
+
     #[test]
     fn test_tls_config() {
         let config = TlsConfig::new("cert.pem".to_string(), "key.pem".to_string())
@@ -2,10 +2,24 @@
 //!
 //! This module provides connection management for WebSocket clients.
 
+use crate::clock::{self, SharedClock};
+use crate::config::{BackpressureConfig, BackpressureStrategy};
+use crate::context::ConnectionContext;
+use crate::pool::BufferPool;
+use crate::rate_limit::RateLimitMiddleware;
+use crate::stats::StatsHandle;
+use aerosocket_core::error::{
+    CloseCode, FrameError, MessageError, ProtocolError, SecurityError, TimeoutError,
+};
 use aerosocket_core::frame::Frame;
-use aerosocket_core::protocol::Opcode;
-use aerosocket_core::{transport::TransportStream, Message, Result};
-use bytes::{Bytes, BytesMut};
+use aerosocket_core::protocol::{constants, Opcode};
+use aerosocket_core::transport::{TransportRead, TransportStream, TransportWrite};
+#[cfg(feature = "compression")]
+use aerosocket_core::DeflateContext;
+use aerosocket_core::{Error, Message, Result};
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::{FutureExt, Stream, StreamExt};
+use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::time::Duration;
 
@@ -19,12 +33,72 @@ pub struct Connection {
     state: ConnectionState,
     /// Connection metadata
     pub metadata: ConnectionMetadata,
+    /// Handler state keyed by type
+    context: ConnectionContext,
     /// Transport stream
     stream: Option<Box<dyn TransportStream>>,
+    /// Bytes read from the stream but not yet parsed into frames
+    read_buffer: BytesMut,
+    /// Payload of a fragmented message received so far
+    fragment_buffer: Vec<u8>,
+    /// Opcode of the fragmented message being reassembled
+    fragment_opcode: Option<Opcode>,
+    /// Whether the message being reassembled was sent compressed
+    fragment_compressed: bool,
+    /// Encoded frames queued by `feed` that the transport has not taken yet
+    outbound: OutboundQueue,
+    /// Limits and strategy for the outbound queue
+    backpressure: Option<BackpressureConfig>,
+    /// Queued bytes at which `send_buffered` flushes on its own
+    flush_threshold: usize,
+    /// Shared write half, once the connection has been split
+    writer: Option<ConnectionWriter>,
+    /// Closing handshake and counters shared with the writer and handles
+    shared: std::sync::Arc<Shared>,
+    /// Limiter that spends the peer's message budget on each data message
+    rate_limiter: Option<std::sync::Arc<RateLimitMiddleware>>,
     /// Idle timeout duration
     idle_timeout: Option<Duration>,
+    /// Ping schedule while `next()` waits for frames
+    keepalive: Option<Keepalive>,
+    /// Upper bound on how long `close` may spend sending the Close frame
+    close_timeout: Option<Duration>,
+    /// Maximum frames read while draining for the peer's Close
+    close_drain_frames: usize,
+    /// Maximum bytes read while draining for the peer's Close
+    close_drain_bytes: usize,
+    /// Bytes requested from the transport per read
+    read_buffer_size: usize,
+    /// Largest payload length accepted in a frame header (closes with 1009 beyond it)
+    max_frame_size: usize,
+    /// Largest total payload accepted across a message's fragments (closes with 1009 beyond it)
+    max_message_size: usize,
+    /// Largest payload per frame written by `send_stream`
+    max_fragment_size: usize,
+    /// Largest number of frames a received message may span (closes with 1009 beyond it)
+    max_fragments_per_message: usize,
+    /// Frames received so far for the message being reassembled
+    fragment_count: usize,
+    /// Treat an all-zero masking key as a protocol violation
+    reject_zero_mask: bool,
+    /// Accept frames the client did not mask
+    allow_unmasked: bool,
+    /// Enforce every RFC 6455 MUST, failing the connection on any violation
+    strict_protocol: bool,
+    /// Negotiated permessage-deflate state, kept across messages
+    #[cfg(feature = "compression")]
+    deflate: Option<DeflateContext>,
+    /// Pool for received binary payloads
+    buffer_pool: Option<BufferPool>,
+    /// Server-wide counters that mirror this connection's metadata
+    stats: Option<StatsHandle>,
+    /// Time source for activity tracking and timeouts
+    clock: SharedClock,
     /// Last activity timestamp
     last_activity: std::time::Instant,
+    /// When `next()` last returned a message the handler has not finished with
+    #[cfg(feature = "metrics")]
+    message_handed_out_at: Option<std::time::Instant>,
 }
 
 impl std::fmt::Debug for Connection {
@@ -34,6 +108,7 @@ impl std::fmt::Debug for Connection {
             .field("local_addr", &self.local_addr)
             .field("state", &self.state)
             .field("metadata", &self.metadata)
+            .field("context", &self.context)
             .field("stream", &"<stream>")
             .finish()
     }
@@ -48,10 +123,66 @@ pub enum ConnectionState {
     Connected,
     /// Connection is closing
     Closing,
+    /// Our Close frame was sent with [`Connection::send_close_only`]; nothing
+    /// more may be sent, but reading continues until the peer's Close
+    HalfClosed,
     /// Connection is closed
     Closed,
 }
 
+/// Which side started the closing handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseInitiator {
+    /// This endpoint sent the first Close frame
+    Local,
+    /// The peer sent the first Close frame
+    Peer,
+}
+
+/// Snapshot of a connection's internal framing state, for debugging
+#[cfg(feature = "diagnostics")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionDump {
+    /// Connection state
+    pub state: ConnectionState,
+    /// Bytes read from the stream but not yet parsed into a frame
+    pub buffered: Bytes,
+    /// Opcode of the message being reassembled, if any
+    pub assembler_opcode: Option<Opcode>,
+    /// Whether a fragmented message has started but not yet finished
+    pub mid_fragmentation: bool,
+    /// Payload bytes of the unfinished message collected so far
+    pub pending_payload_len: usize,
+}
+
+#[cfg(feature = "diagnostics")]
+impl ConnectionDump {
+    /// Buffered bytes as lowercase, space-separated hex
+    pub fn buffered_hex(&self) -> String {
+        self.buffered
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(feature = "diagnostics")]
+impl std::fmt::Display for ConnectionDump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "state={:?} opcode={:?} fragmented={} pending={}B buffered={}B [{}]",
+            self.state,
+            self.assembler_opcode,
+            self.mid_fragmentation,
+            self.pending_payload_len,
+            self.buffered.len(),
+            self.buffered_hex()
+        )
+    }
+}
+
 /// Connection metadata
 #[derive(Debug, Clone)]
 pub struct ConnectionMetadata {
@@ -63,6 +194,8 @@ pub struct ConnectionMetadata {
     pub established_at: std::time::Instant,
     /// Last activity time
     pub last_activity_at: std::time::Instant,
+    /// When the last pong was received from the peer
+    pub last_pong_at: Option<std::time::Instant>,
     /// Messages sent count
     pub messages_sent: u64,
     /// Messages received count
@@ -71,14 +204,30 @@ pub struct ConnectionMetadata {
     pub bytes_sent: u64,
     /// Bytes received count
     pub bytes_received: u64,
-    /// Whether compression was negotiated
+    /// Whether `permessage-deflate` was negotiated in the handshake
+    ///
+    /// Set from the extensions actually accepted, not from the server's
+    /// compression settings; frames with RSV1 set are rejected with
+    /// [`FrameError::ReservedBitsSet`] unless it is.
     pub compression_negotiated: bool,
+    /// Server name the client requested through TLS SNI
+    pub sni: Option<String>,
+    /// Request path the client upgraded on, without the query string
+    pub path: String,
+}
+
+impl ConnectionMetadata {
+    /// Total bytes sent and received on this connection
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes_sent + self.bytes_received
+    }
 }
 
 impl Connection {
     /// Create a new connection
     pub fn new(remote_addr: SocketAddr, local_addr: SocketAddr) -> Self {
-        let now = std::time::Instant::now();
+        let clock = clock::default_clock();
+        let now = clock.now();
         Self {
             remote_addr,
             local_addr,
@@ -88,15 +237,49 @@ impl Connection {
                 extensions: Vec::new(),
                 established_at: now,
                 last_activity_at: now,
+                last_pong_at: None,
                 messages_sent: 0,
                 messages_received: 0,
                 bytes_sent: 0,
                 bytes_received: 0,
                 compression_negotiated: false,
+                sni: None,
+                path: String::new(),
             },
+            context: ConnectionContext::new(),
             stream: None,
+            read_buffer: BytesMut::new(),
+            fragment_buffer: Vec::new(),
+            fragment_opcode: None,
+            fragment_compressed: false,
+            outbound: OutboundQueue::default(),
+            backpressure: None,
+            flush_threshold: constants::DEFAULT_FLUSH_THRESHOLD,
+            writer: None,
+            shared: Default::default(),
+            rate_limiter: None,
             idle_timeout: None,
+            keepalive: None,
+            close_timeout: Some(constants::DEFAULT_CLOSE_TIMEOUT),
+            close_drain_frames: constants::DEFAULT_CLOSE_DRAIN_FRAMES,
+            close_drain_bytes: constants::DEFAULT_CLOSE_DRAIN_BYTES,
+            read_buffer_size: constants::DEFAULT_READ_BUFFER_SIZE,
+            max_frame_size: constants::DEFAULT_MAX_FRAME_SIZE,
+            max_message_size: constants::DEFAULT_MAX_MESSAGE_SIZE,
+            max_fragment_size: constants::DEFAULT_MAX_FRAGMENT_SIZE,
+            max_fragments_per_message: constants::DEFAULT_MAX_FRAGMENTS_PER_MESSAGE,
+            fragment_count: 0,
+            reject_zero_mask: false,
+            allow_unmasked: false,
+            strict_protocol: false,
+            #[cfg(feature = "compression")]
+            deflate: None,
+            buffer_pool: None,
+            stats: None,
+            clock,
             last_activity: now,
+            #[cfg(feature = "metrics")]
+            message_handed_out_at: None,
         }
     }
 
@@ -106,26 +289,9 @@ impl Connection {
         local_addr: SocketAddr,
         stream: Box<dyn TransportStream>,
     ) -> Self {
-        let now = std::time::Instant::now();
-        Self {
-            remote_addr,
-            local_addr,
-            state: ConnectionState::Connected,
-            metadata: ConnectionMetadata {
-                subprotocol: None,
-                extensions: Vec::new(),
-                established_at: now,
-                last_activity_at: now,
-                messages_sent: 0,
-                messages_received: 0,
-                bytes_sent: 0,
-                bytes_received: 0,
-                compression_negotiated: false,
-            },
-            stream: Some(stream),
-            idle_timeout: None,
-            last_activity: now,
-        }
+        let mut connection = Self::new(remote_addr, local_addr);
+        connection.set_stream(stream);
+        connection
     }
 
     /// Create a new connection with timeout settings
@@ -135,26 +301,9 @@ impl Connection {
         stream: Box<dyn TransportStream>,
         idle_timeout: Option<Duration>,
     ) -> Self {
-        let now = std::time::Instant::now();
-        Self {
-            remote_addr,
-            local_addr,
-            state: ConnectionState::Connected,
-            metadata: ConnectionMetadata {
-                subprotocol: None,
-                extensions: Vec::new(),
-                established_at: now,
-                last_activity_at: now,
-                messages_sent: 0,
-                messages_received: 0,
-                bytes_sent: 0,
-                bytes_received: 0,
-                compression_negotiated: false,
-            },
-            stream: Some(stream),
-            idle_timeout,
-            last_activity: now,
-        }
+        let mut connection = Self::with_stream(remote_addr, local_addr, stream);
+        connection.idle_timeout = idle_timeout;
+        connection
     }
 
     /// Set the transport stream
@@ -183,10 +332,33 @@ impl Connection {
         &self.metadata
     }
 
+    /// Get the per-connection handler state
+    pub fn context(&self) -> &ConnectionContext {
+        &self.context
+    }
+
+    /// Get the per-connection handler state mutably
+    pub fn context_mut(&mut self) -> &mut ConnectionContext {
+        &mut self.context
+    }
+
+    /// Store a value in the connection context, replacing one of the same type
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.context.insert(value)
+    }
+
+    /// Get a value of type `T` from the connection context
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.context.get()
+    }
+
     /// Check if the connection has timed out
     pub fn is_timed_out(&self) -> bool {
         if let Some(timeout) = self.idle_timeout {
-            self.last_activity.elapsed() > timeout
+            self.clock
+                .now()
+                .saturating_duration_since(self.last_activity)
+                > timeout
         } else {
             false
         }
@@ -195,7 +367,10 @@ impl Connection {
     /// Get the time until the connection times out
     pub fn time_until_timeout(&self) -> Option<Duration> {
         self.idle_timeout.map(|timeout| {
-            let elapsed = self.last_activity.elapsed();
+            let elapsed = self
+                .clock
+                .now()
+                .saturating_duration_since(self.last_activity);
             if elapsed >= timeout {
                 Duration::ZERO
             } else {
@@ -206,51 +381,357 @@ impl Connection {
 
     /// Update the last activity timestamp
     fn update_activity(&mut self) {
-        self.last_activity = std::time::Instant::now();
+        self.last_activity = self.clock.now();
         self.metadata.last_activity_at = self.last_activity;
     }
 
+    /// Add what the writer sent since the last call to the metadata
+    fn collect_writer_counts(&mut self) {
+        use std::sync::atomic::Ordering;
+
+        self.metadata.messages_sent += self.shared.writer_messages.swap(0, Ordering::Relaxed);
+        self.metadata.bytes_sent += self.shared.writer_bytes.swap(0, Ordering::Relaxed);
+    }
+
+    /// Replace the time source
+    ///
+    /// The connection's establishment and last activity timestamps are reset to
+    /// the new clock's current time.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+        self.metadata.established_at = self.clock.now();
+        self.update_activity();
+    }
+
     /// Set the idle timeout
+    ///
+    /// `next()` gives up waiting once no frame has arrived for this long,
+    /// closing with 1001 and failing with [`TimeoutError::Idle`].
     pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
         self.idle_timeout = timeout;
     }
 
+    /// Ping a quiet peer every `interval` while `next()` waits for frames
+    ///
+    /// With a `timeout`, a peer that sends nothing at all, pong or otherwise,
+    /// within that long of a ping is closed with 1001 and `next()` fails with
+    /// [`TimeoutError::Read`]. A `None` interval turns keepalive off.
+    pub fn set_keepalive(&mut self, interval: Option<Duration>, timeout: Option<Duration>) {
+        self.keepalive = interval.map(|interval| Keepalive {
+            interval,
+            timeout,
+            ping_sent_at: None,
+        });
+    }
+
+    /// When the peer last answered a ping
+    pub fn last_pong_at(&self) -> Option<std::time::Instant> {
+        self.metadata.last_pong_at
+    }
+
+    /// Set the close timeout (`None` lets `close` wait indefinitely)
+    pub fn set_close_timeout(&mut self, timeout: Option<Duration>) {
+        self.close_timeout = timeout;
+    }
+
+    /// Limit how much the peer may send after our Close before the transport is
+    /// torn down by [`Connection::close_gracefully`]
+    pub fn set_close_drain_limits(&mut self, max_frames: usize, max_bytes: usize) {
+        self.close_drain_frames = max_frames;
+        self.close_drain_bytes = max_bytes;
+    }
+
+    /// Set how many bytes are requested from the transport per read
+    ///
+    /// Larger reads take fewer syscalls for big messages; smaller ones keep
+    /// idle connections cheap. Values below 1 are treated as 1.
+    pub fn set_read_buffer_size(&mut self, size: usize) {
+        self.read_buffer_size = size.max(1);
+    }
+
+    /// Set the largest payload a single frame may declare
+    ///
+    /// The length field is checked before any payload is buffered; a larger
+    /// frame closes the connection with 1009.
+    pub fn set_max_frame_size(&mut self, size: usize) {
+        self.max_frame_size = size;
+    }
+
+    /// Set the largest payload a message may carry across all its fragments
+    ///
+    /// The running total is checked as each frame arrives, so a stream of
+    /// small continuation frames closes the connection with 1009 once it
+    /// passes the limit.
+    pub fn set_max_message_size(&mut self, size: usize) {
+        self.max_message_size = size;
+    }
+
+    /// Set the largest number of frames a received message may span
+    ///
+    /// Complements [`set_max_message_size`](Self::set_max_message_size) by
+    /// bounding per-frame overhead: a message split into more frames than
+    /// this closes the connection with 1009, however small they are.
+    pub fn set_max_fragments_per_message(&mut self, max: usize) {
+        self.max_fragments_per_message = max;
+    }
+
+    /// Set the largest payload per frame written by [`send_stream`](Self::send_stream)
+    pub fn set_max_fragment_size(&mut self, size: usize) {
+        self.max_fragment_size = size.max(1);
+    }
+
+    /// Reject frames masked with an all-zero key (closes with 1002)
+    pub fn set_reject_zero_mask(&mut self, reject: bool) {
+        self.reject_zero_mask = reject;
+    }
+
+    /// Accept unmasked client frames (non-compliant; trusted peers only)
+    pub fn set_allow_unmasked(&mut self, allow: bool) {
+        self.allow_unmasked = allow;
+    }
+
+    /// Enforce every RFC 6455 MUST on received frames
+    ///
+    /// Any violation closes the connection with the status code the RFC
+    /// prescribes: unmasked frames (overriding
+    /// [`set_allow_unmasked`](Self::set_allow_unmasked)), oversized control
+    /// frames, malformed Close payloads and invalid close codes fail with
+    /// 1002, and text that is not valid UTF-8 with 1007. Frame-level errors
+    /// such as reserved bits also send the matching Close frame. When off,
+    /// close codes are taken as sent and invalid UTF-8 is replaced.
+    pub fn set_strict_protocol(&mut self, strict: bool) {
+        self.strict_protocol = strict;
+    }
+
+    /// Set the total byte budget for this connection (closes with 1008 once exceeded)
+    ///
+    /// Received messages count along with everything sent, whether through
+    /// [`send`](Self::send), [`send_raw`](Self::send_raw),
+    /// [`send_stream`](Self::send_stream) or the [`ConnectionWriter`].
+    pub fn set_max_connection_bytes(&mut self, max: Option<u64>) {
+        self.shared.max_bytes.store(
+            max.unwrap_or(u64::MAX),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+
+    /// Compress and decompress data messages through a per-connection context
+    ///
+    /// Set once `permessage-deflate` is negotiated. Outgoing messages sent
+    /// with [`feed`](Self::feed) are compressed, and incoming compressed
+    /// messages are inflated whole, so the window can carry over between
+    /// messages when context takeover is in effect. Give the context the same
+    /// maximum message size as the connection.
+    #[cfg(feature = "compression")]
+    pub fn set_deflate(&mut self, deflate: Option<DeflateContext>) {
+        self.deflate = deflate;
+    }
+
+    /// Whether data messages are inflated whole through a deflate context
+    #[cfg(feature = "compression")]
+    fn inflates_messages(&self) -> bool {
+        self.deflate.is_some()
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn inflates_messages(&self) -> bool {
+        false
+    }
+
+    /// Set the pool used for received binary messages reassembled from fragments
+    pub fn set_buffer_pool(&mut self, pool: Option<BufferPool>) {
+        self.buffer_pool = pool;
+    }
+
+    /// Estimated memory held by a connection reading `read_buffer_size`
+    /// bytes at a time
+    pub(crate) fn footprint(read_buffer_size: usize) -> u64 {
+        (std::mem::size_of::<Self>() + read_buffer_size) as u64
+    }
+
+    /// Bound the outbound queue filled by [`feed`](Self::feed)
+    ///
+    /// See [`BackpressureStrategy`] for how each strategy behaves once the
+    /// queue fills up. With `None` the queue grows until flushed.
+    pub fn set_backpressure(&mut self, backpressure: Option<BackpressureConfig>) {
+        self.backpressure = backpressure;
+    }
+
+    /// Limit the data messages the peer may send
+    ///
+    /// Each text or binary message returned by [`next`](Self::next) spends
+    /// one unit of the peer address's message budget; once it is spent, the
+    /// connection is closed with 1008 and `next` fails with
+    /// [`SecurityError::RateLimit`].
+    pub fn set_rate_limiter(&mut self, rate_limiter: Option<std::sync::Arc<RateLimitMiddleware>>) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    /// Set how many queued bytes make [`send_buffered`](Self::send_buffered)
+    /// flush on its own
+    pub fn set_flush_threshold(&mut self, threshold: usize) {
+        self.flush_threshold = threshold;
+    }
+
+    /// Report this connection's traffic into shared server counters
+    pub fn set_stats(&mut self, stats: Option<StatsHandle>) {
+        self.stats = stats;
+    }
+
+    /// Refuse to write once the closing handshake has started
+    ///
+    /// Only a Close frame may still be sent while closing, to complete the
+    /// handshake; nothing at all may be sent once the connection is closed.
+    /// A Close sent or received through the writer counts as well.
+    fn ensure_sendable(&self, is_close: bool) -> Result<()> {
+        if !is_close {
+            self.shared.ensure_open()?;
+        }
+        match self.state {
+            ConnectionState::Closing if is_close => Ok(()),
+            ConnectionState::HalfClosed => Err(Error::Closed {
+                code: CloseCode::from(self.close_code().unwrap_or(1005)),
+                reason: "close frame already sent".to_string(),
+            }),
+            ConnectionState::Closing | ConnectionState::Closed => Err(Error::Closed {
+                code: CloseCode::from(self.close_code().unwrap_or(1005)),
+                reason: "connection is closing".to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
     /// Send a message
+    ///
+    /// Equivalent to [`feed`](Self::feed) followed by [`flush`](Self::flush),
+    /// except under the [`Reject`](BackpressureStrategy::Reject) and
+    /// [`DropOldest`](BackpressureStrategy::DropOldest) strategies: there,
+    /// `send` only writes what the transport takes without waiting and leaves
+    /// the rest queued, so a slow peer fills the queue instead of stalling the
+    /// sender. The leftover goes out with the next send or flush, or before
+    /// [`next`](Self::next) reads. A split connection writes whole frames
+    /// through its shared writer and always waits.
+    ///
+    /// Once a Close frame has been sent or received, only a Close frame may
+    /// still be sent; anything else fails with [`Error::Closed`]. Once this
+    /// side has sent Close, here or through the [`ConnectionWriter`], another
+    /// Close is not written. A Close with a code
+    /// or reason RFC 6455 does not allow on the wire fails with
+    /// [`CloseError`](aerosocket_core::error::CloseError), and a Ping or Pong
+    /// over 125 bytes with [`FrameError::ControlFrameTooLarge`]; either way
+    /// nothing is written.
     pub async fn send(&mut self, message: Message) -> Result<()> {
+        let is_close = matches!(message, Message::Close(_));
+        self.feed(message).await?;
+        if is_close || !self.queues_without_waiting() {
+            return self.flush().await;
+        }
+        match &mut self.stream {
+            Some(stream) => self.outbound.write_ready(stream),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether `send` leaves what the transport does not take right away queued
+    fn queues_without_waiting(&self) -> bool {
+        self.writer.is_none()
+            && self.backpressure.as_ref().is_some_and(|backpressure| {
+                matches!(
+                    backpressure.strategy,
+                    BackpressureStrategy::Reject | BackpressureStrategy::DropOldest
+                )
+            })
+    }
+
+    /// Send a message, coalescing it with others into fewer writes
+    ///
+    /// The frame is queued like with [`feed`](Self::feed) and only written
+    /// once the queue reaches the flush threshold (64 KiB by default, see
+    /// [`set_flush_threshold`](Self::set_flush_threshold)), so a burst of
+    /// small messages costs a handful of writes rather than one per message.
+    /// Whatever is still queued goes out on the next [`flush`](Self::flush)
+    /// or `send`; callers must flush once the burst is over, or the last
+    /// messages may sit in the queue indefinitely.
+    pub async fn send_buffered(&mut self, message: Message) -> Result<()> {
+        self.feed(message).await?;
+        if self.outbound.len() >= self.flush_threshold {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Queue a message without flushing it
+    ///
+    /// The encoded frame is held in the outbound buffer until the next
+    /// [`flush`](Self::flush) or `send`, so several messages can be queued and
+    /// pushed to the transport with a single flush.
+    ///
+    /// With backpressure set, the queue is bounded by the encoded bytes the
+    /// transport has not taken yet: depending on the [`BackpressureStrategy`],
+    /// feeding may write older frames out first, drop them, or fail with
+    /// [`Error::CapacityExceeded`]. Room is made for the frame's uncompressed
+    /// size, since a frame refused after compression would desync the peer's
+    /// decompressor.
+    pub async fn feed(&mut self, message: Message) -> Result<()> {
+        let is_close = matches!(message, Message::Close(_));
+        if is_close && self.shared.close_sent() && self.shared.close_received() {
+            return Ok(());
+        }
+        self.ensure_sendable(is_close)?;
+        message.validate()?;
+
         // Update activity timestamp before borrowing stream
         self.update_activity();
+        self.collect_writer_counts();
+
+        let frame = message.into_frame();
+        if !frame.opcode.is_control() {
+            self.make_room(frame.encoded_len()).await?;
+        }
 
         if let Some(stream) = &mut self.stream {
-            // Convert message to WebSocket frame
-            let frame = match message {
-                Message::Text(text) => Frame::text(text.as_bytes().to_vec()),
-                Message::Binary(data) => Frame::binary(data.as_bytes().to_vec()),
-                Message::Ping(data) => Frame::ping(data.as_bytes().to_vec()),
-                Message::Pong(data) => Frame::pong(data.as_bytes().to_vec()),
-                Message::Close(code_and_reason) => {
-                    Frame::close(code_and_reason.code(), Some(code_and_reason.reason()))
-                }
+            // Only the first Close this side sends goes out
+            if is_close && !self.shared.mark_close_sent() {
+                return Ok(());
+            }
+            // Serialize the frame straight into the outbound buffer, so the
+            // payload is copied exactly once
+            #[cfg(feature = "compression")]
+            let frame = match &mut self.deflate {
+                Some(deflate) => frame.compress_with(deflate),
+                None => frame,
             };
-
-            // Serialize frame to bytes
-            let frame_bytes = frame.to_bytes();
+            // Dropping a compressed frame would desync the peer's inflater
+            let droppable = !frame.opcode.is_control() && !frame.rsv[0];
+            let frame_len = self.outbound.push(&frame, droppable);
 
             #[cfg(feature = "metrics")]
             {
                 metrics::counter!("aerosocket_server_messages_sent_total").increment(1);
-                metrics::counter!("aerosocket_server_bytes_sent_total")
-                    .increment(frame_bytes.len() as u64);
-                metrics::histogram!("aerosocket_server_frame_size_bytes")
-                    .record(frame_bytes.len() as f64);
+                metrics::counter!("aerosocket_server_bytes_sent_total").increment(frame_len as u64);
+                metrics::histogram!("aerosocket_server_frame_size_bytes").record(frame_len as f64);
             }
 
-            // Send frame
-            stream.write_all(&frame_bytes).await?;
-            stream.flush().await?;
-
             // Update metadata
             self.metadata.messages_sent += 1;
-            self.metadata.bytes_sent += frame_bytes.len() as u64;
+            self.metadata.bytes_sent += frame_len as u64;
+            if let Some(stats) = &self.stats {
+                stats.record_sent(1, frame_len as u64);
+            }
+
+            if self.shared.spend(frame_len as u64) && !is_close {
+                return Err(self.close_over_budget().await);
+            }
+
+            // Wait for the transport once the queue reaches the high water mark
+            if let Some(backpressure) = &self.backpressure {
+                if backpressure.strategy == BackpressureStrategy::Buffer
+                    && self.outbound.len() >= backpressure.high_water_mark
+                {
+                    let low_water_mark = backpressure.low_water_mark;
+                    self.drain_queue(low_water_mark).await?;
+                }
+            }
 
             Ok(())
         } else {
@@ -260,6 +741,206 @@ impl Connection {
         }
     }
 
+    /// Make room in the outbound queue for a frame of `incoming` encoded bytes
+    async fn make_room(&mut self, incoming: usize) -> Result<()> {
+        let Some(backpressure) = &self.backpressure else {
+            return Ok(());
+        };
+        let (strategy, buffer_size) = (backpressure.strategy, backpressure.buffer_size);
+        if self.outbound.is_empty() || self.outbound.len() + incoming <= buffer_size {
+            return Ok(());
+        }
+
+        match strategy {
+            BackpressureStrategy::Reject => Err(Error::CapacityExceeded {
+                size: self.outbound.len() + incoming,
+            }),
+            BackpressureStrategy::DropOldest => {
+                while self.outbound.len() + incoming > buffer_size {
+                    if !self.outbound.drop_oldest() {
+                        // Only frames that must go out are left
+                        return self.drain_queue(0).await;
+                    }
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("aerosocket_server_messages_dropped_total").increment(1);
+                }
+                Ok(())
+            }
+            BackpressureStrategy::Buffer | BackpressureStrategy::FlowControl => {
+                self.drain_queue(0).await
+            }
+        }
+    }
+
+    /// Write queued bytes until at most `target` remain
+    async fn drain_queue(&mut self, target: usize) -> Result<()> {
+        let Some(stream) = &mut self.stream else {
+            return Ok(());
+        };
+        if self.outbound.len() <= target {
+            return Ok(());
+        }
+        self.outbound.write_down_to(stream, target).await?;
+        stream.flush().await
+    }
+
+    /// Send a pre-built frame as a single write
+    ///
+    /// Any frames queued with [`feed`](Self::feed) are written first.
+    pub async fn send_raw(&mut self, frame: Frame) -> Result<()> {
+        let is_close = frame.opcode == Opcode::Close;
+        self.ensure_sendable(is_close)?;
+        if self.stream.is_none() {
+            return Err(aerosocket_core::Error::Other(
+                "Connection not established".to_string(),
+            ));
+        }
+        if is_close {
+            if !self.shared.mark_close_sent() {
+                return Ok(());
+            }
+            self.shared
+                .record_close(CloseInitiator::Local, close_code_of(&frame.payload));
+        }
+        self.update_activity();
+        self.collect_writer_counts();
+        let frame_len = self.outbound.push(&frame, false) as u64;
+        self.metadata.bytes_sent += frame_len;
+        if let Some(stats) = &self.stats {
+            stats.record_sent(0, frame_len);
+        }
+        if self.shared.spend(frame_len) && !is_close {
+            return Err(self.close_over_budget().await);
+        }
+        self.flush().await
+    }
+
+    /// Close with 1008 once the byte budget is spent
+    ///
+    /// Queued frames are dropped, except for one the transport has started
+    /// on, which must still be finished.
+    async fn close_over_budget(&mut self) -> Error {
+        if let Some(stream) = &mut self.stream {
+            let unfinished = self.outbound.clear();
+            self.shared.record_close(CloseInitiator::Local, Some(1008));
+            if stream.write_all(&unfinished).await.is_ok() {
+                send_close_frame(stream, &self.shared, 1008, "Byte budget exceeded").await;
+            }
+        }
+        self.state = ConnectionState::Closed;
+        byte_budget_error()
+    }
+
+    /// Send a data message whose payload arrives as a stream of chunks
+    ///
+    /// The message goes out fragmented as the chunks arrive, so a large
+    /// payload such as a file never has to be held in memory at once. Each
+    /// fragment carries at most the configured maximum fragment size
+    /// (see [`set_max_fragment_size`](Self::set_max_fragment_size)) and is
+    /// written separately, so once the connection is [split](Self::split),
+    /// pings and pongs sent through the [`ConnectionWriter`] slot in between
+    /// fragments. For [`Opcode::Text`] the chunks together must be valid UTF-8.
+    ///
+    /// If the chunk stream fails after the first fragment went out, the
+    /// unfinished message cannot be recovered and the connection is closed
+    /// with 1011.
+    pub async fn send_stream<S>(&mut self, opcode: Opcode, chunks: S) -> Result<()>
+    where
+        S: Stream<Item = Result<Bytes>>,
+    {
+        if !matches!(opcode, Opcode::Text | Opcode::Binary) {
+            return Err(Error::Protocol(ProtocolError::InvalidFrame(format!(
+                "cannot stream a {:?} message",
+                opcode
+            ))));
+        }
+
+        let mut chunks = std::pin::pin!(chunks);
+        // The last piece is held back until we know whether it ends the message
+        let mut pending: Option<Bytes> = None;
+        let mut first = true;
+        while let Some(chunk) = chunks.next().await {
+            let mut chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(err) if first => return Err(err),
+                Err(err) => {
+                    if let Some(stream) = &mut self.stream {
+                        self.shared.record_close(CloseInitiator::Local, Some(1011));
+                        send_close_frame(stream, &self.shared, 1011, "Message stream failed").await;
+                    }
+                    self.state = ConnectionState::Closed;
+                    return Err(err);
+                }
+            };
+            while !chunk.is_empty() {
+                let piece = chunk.split_to(chunk.len().min(self.max_fragment_size));
+                if let Some(fragment) = pending.replace(piece) {
+                    let opcode = if first { opcode } else { Opcode::Continuation };
+                    self.send_raw(Frame::new(opcode, fragment).fin(false))
+                        .await?;
+                    first = false;
+                }
+            }
+        }
+
+        let opcode = if first { opcode } else { Opcode::Continuation };
+        self.send_raw(Frame::new(opcode, pending.unwrap_or_default()))
+            .await?;
+        self.metadata.messages_sent += 1;
+        if let Some(stats) = &self.stats {
+            stats.record_sent(1, 0);
+        }
+        Ok(())
+    }
+
+    /// Split off a cloneable write half
+    ///
+    /// The connection keeps reading through its own read half, while the
+    /// returned [`ConnectionWriter`] (and any clones) can send from other tasks
+    /// without waiting for a pending `next()`. All writes, including those made
+    /// by the connection itself, go through the writer's mutex one frame at a
+    /// time. Calling `split` again returns another handle to the same writer.
+    ///
+    /// Fails if the connection has no stream or its transport cannot be split.
+    pub fn split(&mut self) -> Result<ConnectionWriter> {
+        if let Some(writer) = &self.writer {
+            return Ok(writer.clone());
+        }
+
+        let stream = self.stream.as_mut().ok_or_else(|| {
+            aerosocket_core::Error::Other("Connection not established".to_string())
+        })?;
+        let (read, write) = stream.take_split().ok_or_else(|| {
+            aerosocket_core::Error::Other("Transport does not support splitting".to_string())
+        })?;
+
+        let writer = ConnectionWriter {
+            inner: std::sync::Arc::new(tokio::sync::Mutex::new(write)),
+            shared: self.shared.clone(),
+            stats: self.stats.clone(),
+        };
+        self.stream = Some(Box::new(SplitStream {
+            read,
+            writer: writer.clone(),
+            remote_addr: self.remote_addr,
+            local_addr: self.local_addr,
+        }));
+        self.writer = Some(writer.clone());
+        Ok(writer)
+    }
+
+    /// Write any queued frames and flush the transport
+    pub async fn flush(&mut self) -> Result<()> {
+        if let Some(stream) = &mut self.stream {
+            self.outbound.write_down_to(stream, 0).await?;
+            stream.flush().await
+        } else {
+            Err(aerosocket_core::Error::Other(
+                "Connection not established".to_string(),
+            ))
+        }
+    }
+
     /// Send a text message
     pub async fn send_text(&mut self, text: impl AsRef<str>) -> Result<()> {
         self.send(Message::text(text.as_ref().to_string())).await
@@ -286,142 +967,483 @@ impl Connection {
     }
 
     /// Receive the next message
+    ///
+    /// A binary message sent as a single frame keeps the frame's payload
+    /// without a copy. With a buffer pool set, fragmented binary messages are
+    /// reassembled into recycled buffers and the reassembly buffer keeps its
+    /// allocation between messages, so steady-state traffic does not allocate
+    /// per message.
+    ///
+    /// Complete frames already buffered from an earlier read are consumed before
+    /// the stream is read again, so pipelined messages that arrived in a single
+    /// write are returned one per call without further socket reads.
+    ///
+    /// Returns `Ok(None)` once the stream ends after the peer's Close frame.
+    /// If it ends without one, as when the peer's socket is reset or dropped,
+    /// the closure is abnormal (RFC 6455 section 7.1.5) and `next()` fails
+    /// with [`Error::Closed`] carrying [`CloseCode::Abnormal`] (1006).
+    ///
+    /// With the `metrics` feature, the time between a message being returned
+    /// and the following call (or the handler finishing) is recorded as
+    /// `aerosocket_server_message_handle_duration_seconds`.
     pub async fn next(&mut self) -> Result<Option<Message>> {
+        #[cfg(feature = "metrics")]
+        self.record_handle_duration();
+
+        let message = match self.read_message().await {
+            Ok(Some(message @ (Message::Text(_) | Message::Binary(_)))) => {
+                self.spend_message_budget().await.map(|()| Some(message))
+            }
+            other => other,
+        };
+
+        #[cfg(feature = "metrics")]
+        if let Ok(Some(_)) = &message {
+            self.message_handed_out_at = Some(self.clock.now());
+        }
+
+        message
+    }
+
+    /// Spend one unit of the peer's message budget, closing with 1008 once
+    /// it is spent
+    async fn spend_message_budget(&mut self) -> Result<()> {
+        let Some(rate_limiter) = &self.rate_limiter else {
+            return Ok(());
+        };
+        let Err(err) = rate_limiter.check_message(self.remote_addr.ip()).await else {
+            return Ok(());
+        };
+        if let Some(stream) = &mut self.stream {
+            self.shared.record_close(CloseInitiator::Local, Some(1008));
+            send_close_frame(stream, &self.shared, 1008, "Rate limit exceeded").await;
+        }
+        self.state = ConnectionState::Closed;
+        Err(err)
+    }
+
+    /// Record how long the handler spent on the last message returned by `next()`
+    #[cfg(feature = "metrics")]
+    pub(crate) fn record_handle_duration(&mut self) {
+        if let Some(handed_out_at) = self.message_handed_out_at.take() {
+            let elapsed = self.clock.now().saturating_duration_since(handed_out_at);
+            metrics::histogram!("aerosocket_server_message_handle_duration_seconds")
+                .record(elapsed.as_secs_f64());
+        }
+    }
+
+    /// Read and reassemble the next message
+    ///
+    /// A failed read abandons any message being reassembled, so the
+    /// fragment state never outlives the error that interrupted it.
+    async fn read_message(&mut self) -> Result<Option<Message>> {
+        let result = self.assemble_message().await;
+        if result.is_err() {
+            self.reset_fragments();
+        }
+        result
+    }
+
+    /// Forget the fragmented message being reassembled, if any
+    fn reset_fragments(&mut self) {
+        self.fragment_opcode = None;
+        self.fragment_buffer.clear();
+        self.fragment_compressed = false;
+    }
+
+    async fn assemble_message(&mut self) -> Result<Option<Message>> {
+        // Stop reading while the peer is not taking our replies
+        if let Some(backpressure) = &self.backpressure {
+            if backpressure.strategy == BackpressureStrategy::FlowControl
+                && self.outbound.len() >= backpressure.high_water_mark
+            {
+                let low_water_mark = backpressure.low_water_mark;
+                self.drain_queue(low_water_mark).await?;
+            }
+        }
+        // Whatever `send` left queued goes out before we wait on the peer
+        if self.queues_without_waiting() {
+            self.drain_queue(0).await?;
+        }
+
         // Update activity timestamp before borrowing stream
         self.update_activity();
+        self.collect_writer_counts();
+        let inflates_messages = self.inflates_messages();
 
         if let Some(stream) = &mut self.stream {
-            let mut message_buffer = Vec::new();
             let mut final_frame = false;
-            let mut opcode = None;
+            // An unfragmented message keeps the frame's payload as is; only
+            // fragmented ones are copied into the reassembly buffer
+            let mut single_payload = None;
 
             // Keep reading frames until we get a complete message
             while !final_frame {
-                // Read frame data
-                let mut frame_buffer = BytesMut::new();
+                // Parse from already-buffered bytes, reading more only when needed
+                let frame = loop {
+                    let parsed = if inflates_messages {
+                        Frame::parse_compressed(&mut self.read_buffer, self.max_frame_size)
+                    } else {
+                        Frame::parse(
+                            &mut self.read_buffer,
+                            self.metadata.compression_negotiated,
+                            self.max_frame_size,
+                        )
+                    };
+                    match parsed {
+                        Ok(frame) => break frame,
+                        Err(Error::Frame(FrameError::InsufficientData { .. })) => {
+                            let now = self.clock.now();
+                            let idle_left = self.idle_timeout.map(|timeout| {
+                                timeout.saturating_sub(
+                                    now.saturating_duration_since(self.last_activity),
+                                )
+                            });
+                            let keepalive_left = self
+                                .keepalive
+                                .map(|keepalive| keepalive.remaining(now, self.last_activity));
+                            let limit = idle_left.into_iter().chain(keepalive_left).min();
 
-                // Read at least the frame header (2 bytes)
-                loop {
-                    let mut temp_buf = [0u8; 2];
-                    let n = stream.read(&mut temp_buf).await?;
-                    if n == 0 {
-                        self.state = ConnectionState::Closed;
-                        return Ok(None);
+                            let buffered = self.read_buffer.len();
+                            let read =
+                                stream.read_buf(&mut self.read_buffer, self.read_buffer_size);
+                            let n = match limit {
+                                Some(limit) => {
+                                    match clock::timeout(self.clock.as_ref(), limit, read).await {
+                                        Some(n) => n?,
+                                        None => {
+                                            // Drop the space reserved by the abandoned read
+                                            self.read_buffer.truncate(buffered);
+                                            let expired = match (idle_left, &mut self.keepalive) {
+                                                (Some(left), _) if left == limit => Some((
+                                                    "Idle timeout",
+                                                    TimeoutError::Idle {
+                                                        timeout: self
+                                                            .idle_timeout
+                                                            .unwrap_or_default(),
+                                                    },
+                                                )),
+                                                (_, Some(keepalive)) => keepalive
+                                                    .expire(self.clock.now())
+                                                    .map(|timeout| {
+                                                        (
+                                                            "Keepalive timeout",
+                                                            TimeoutError::Read { timeout },
+                                                        )
+                                                    }),
+                                                (_, None) => None,
+                                            };
+                                            match expired {
+                                                Some((reason, error)) => {
+                                                    self.shared.record_close(
+                                                        CloseInitiator::Local,
+                                                        Some(1001),
+                                                    );
+                                                    send_close_frame(
+                                                        stream,
+                                                        &self.shared,
+                                                        1001,
+                                                        reason,
+                                                    )
+                                                    .await;
+                                                    self.state = ConnectionState::Closed;
+                                                    return Err(Error::Timeout(error));
+                                                }
+                                                None => {
+                                                    stream
+                                                        .write_all(
+                                                            &Frame::ping(Bytes::new()).to_bytes(),
+                                                        )
+                                                        .await?;
+                                                    stream.flush().await?;
+                                                    continue;
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                                None => read.await?,
+                            };
+                            if n == 0 {
+                                self.state = ConnectionState::Closed;
+                                if self.shared.close_received() {
+                                    return Ok(None);
+                                }
+                                return Err(Error::Closed {
+                                    code: CloseCode::Abnormal,
+                                    reason: "connection closed without a close frame".to_string(),
+                                });
+                            }
+                        }
+                        Err(e @ Error::Frame(FrameError::TooLarge { .. })) => {
+                            self.shared.record_close(CloseInitiator::Local, Some(1009));
+                            send_close_frame(stream, &self.shared, 1009, "Frame too large").await;
+                            self.state = ConnectionState::Closed;
+                            return Err(e);
+                        }
+                        Err(e @ Error::Protocol(ProtocolError::ReservedOpcode(_))) => {
+                            self.shared.record_close(CloseInitiator::Local, Some(1002));
+                            send_close_frame(stream, &self.shared, 1002, "Reserved opcode").await;
+                            self.state = ConnectionState::Closed;
+                            return Err(e);
+                        }
+                        Err(e @ Error::Frame(FrameError::ControlFrameTooLarge { .. })) => {
+                            self.shared.record_close(CloseInitiator::Local, Some(1002));
+                            send_close_frame(stream, &self.shared, 1002, "Control frame too large")
+                                .await;
+                            self.state = ConnectionState::Closed;
+                            return Err(e);
+                        }
+                        Err(e) => {
+                            if let (true, Some(code)) = (self.strict_protocol, e.close_code()) {
+                                let code = code.code();
+                                self.shared.record_close(CloseInitiator::Local, Some(code));
+                                send_close_frame(
+                                    stream,
+                                    &self.shared,
+                                    code,
+                                    violation_reason(code),
+                                )
+                                .await;
+                                self.state = ConnectionState::Closed;
+                            }
+                            return Err(e);
+                        }
                     }
-                    frame_buffer.extend_from_slice(&temp_buf[..n]);
+                };
+
+                // Any frame, control frames included, counts as activity
+                self.last_activity = self.clock.now();
+                self.metadata.last_activity_at = self.last_activity;
+                if let Some(keepalive) = &mut self.keepalive {
+                    keepalive.ping_sent_at = None;
+                }
 
-                    if frame_buffer.len() >= 2 {
-                        break;
+                // RFC 6455 section 5.1: every client frame, continuations
+                // included, must be masked, unless explicitly relaxed
+                if !frame.masked && (self.strict_protocol || !self.allow_unmasked) {
+                    self.shared.record_close(CloseInitiator::Local, Some(1002));
+                    send_close_frame(stream, &self.shared, 1002, "Unmasked frame").await;
+                    self.state = ConnectionState::Closed;
+                    return Err(Error::Protocol(ProtocolError::MaskingRequired));
+                }
+
+                if self.reject_zero_mask && frame.mask == Some([0; 4]) {
+                    self.shared.record_close(CloseInitiator::Local, Some(1002));
+                    send_close_frame(stream, &self.shared, 1002, "Zero masking key").await;
+                    self.state = ConnectionState::Closed;
+                    return Err(Error::Protocol(ProtocolError::InvalidFrame(
+                        "all-zero masking key".to_string(),
+                    )));
+                }
+
+                if self.strict_protocol {
+                    if let Err(e) = frame.validate_strict() {
+                        let code = e.close_code().map_or(1002, |code| code.code());
+                        self.shared.record_close(CloseInitiator::Local, Some(code));
+                        send_close_frame(stream, &self.shared, code, violation_reason(code)).await;
+                        self.state = ConnectionState::Closed;
+                        return Err(e);
                     }
                 }
 
-                // Parse the frame to determine how much more data we need
-                match Frame::parse(&mut frame_buffer, self.metadata.compression_negotiated) {
-                    Ok(frame) => {
-                        // Handle control frames immediately
-                        match frame.opcode {
-                            Opcode::Ping => {
-                                let ping_data = frame.payload.to_vec();
-                                // Send pong response
-                                stream.write_all(&Frame::pong(ping_data).to_bytes()).await?;
-                                stream.flush().await?;
-                                continue;
-                            }
-                            Opcode::Pong => {
-                                // Handle pong response (update activity)
-                                // Note: We can't call update_activity here due to borrowing,
-                                // but activity is already updated at the start of next()
-                                continue;
-                            }
-                            Opcode::Close => {
-                                // Parse close frame
-                                let close_code = if frame.payload.len() >= 2 {
-                                    let code_bytes = &frame.payload[..2];
-                                    u16::from_be_bytes([code_bytes[0], code_bytes[1]])
-                                } else {
-                                    1000 // Normal closure
-                                };
-
-                                let close_reason = if frame.payload.len() > 2 {
-                                    String::from_utf8_lossy(&frame.payload[2..]).to_string()
-                                } else {
-                                    String::new()
-                                };
-
-                                self.state = ConnectionState::Closing;
-                                return Ok(Some(Message::close(
-                                    Some(close_code),
-                                    Some(close_reason),
-                                )));
-                            }
-                            Opcode::Continuation | Opcode::Text | Opcode::Binary => {
-                                // Handle data frames
-                                if opcode.is_none() {
-                                    opcode = Some(frame.opcode);
-                                }
+                // Handle control frames immediately; RFC 6455 section 5.4 lets
+                // them arrive between the fragments of a data message, which
+                // stay buffered in the meantime
+                match frame.opcode {
+                    Opcode::Ping => {
+                        // Send pong response, echoing the shared payload
+                        stream
+                            .write_all(&Frame::pong(frame.payload).to_bytes())
+                            .await?;
+                        stream.flush().await?;
+                        continue;
+                    }
+                    Opcode::Pong => {
+                        // Handle pong response (update activity)
+                        // Note: We can't call update_activity here due to borrowing,
+                        // but activity is already updated at the start of next()
+                        self.metadata.last_pong_at = Some(self.clock.now());
+                        self.shared
+                            .pongs_received
+                            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        continue;
+                    }
+                    Opcode::Close => {
+                        // Parse close frame; an empty payload carries no status
+                        let close_code = close_code_of(&frame.payload);
 
-                                message_buffer.extend_from_slice(&frame.payload);
-                                final_frame = frame.fin;
+                        let close_reason = if frame.payload.len() > 2 {
+                            String::from_utf8_lossy(&frame.payload[2..]).to_string()
+                        } else {
+                            String::new()
+                        };
 
-                                if !final_frame && frame.opcode != Opcode::Continuation {
-                                    return Err(aerosocket_core::Error::Other(
-                                        "Expected continuation frame".to_string(),
-                                    ));
-                                }
-                            }
-                            _ => {
-                                return Err(aerosocket_core::Error::Other(
-                                    "Unsupported opcode".to_string(),
-                                ));
+                        // A message still being fragmented will never complete
+                        self.fragment_opcode = None;
+                        self.fragment_buffer.clear();
+                        self.fragment_compressed = false;
+
+                        // Answer with a matching Close unless ours already went
+                        // out; either way the closing handshake is now complete
+                        let reply =
+                            send_close_frame(stream, &self.shared, close_code.unwrap_or(1000), "");
+                        match self.close_timeout {
+                            Some(limit) => {
+                                let _ = clock::timeout(self.clock.as_ref(), limit, reply).await;
                             }
+                            None => reply.await,
                         }
+                        self.state = ConnectionState::Closed;
+                        self.shared.mark_close_received();
+                        self.shared.record_close(CloseInitiator::Peer, close_code);
+                        return Ok(Some(Message::close(close_code, Some(close_reason))));
                     }
-                    Err(_e) => {
-                        // Need more data - read from stream
-                        let mut temp_buf = [0u8; 1024];
-                        match stream.read(&mut temp_buf).await {
-                            Ok(0) => {
+                    Opcode::Continuation | Opcode::Text | Opcode::Binary => {
+                        // Handle data frames: the first frame carries the
+                        // message opcode and any further ones are continuations
+                        match (self.fragment_opcode, frame.opcode) {
+                            (None, Opcode::Continuation)
+                            | (Some(_), Opcode::Text | Opcode::Binary) => {
+                                // RFC 6455 section 5.4: fail the connection with 1002
+                                self.shared.record_close(CloseInitiator::Local, Some(1002));
+                                send_close_frame(
+                                    stream,
+                                    &self.shared,
+                                    1002,
+                                    "Invalid continuation",
+                                )
+                                .await;
                                 self.state = ConnectionState::Closed;
-                                return Ok(None);
+                                return Err(Error::Protocol(ProtocolError::InvalidContinuation));
                             }
-                            Ok(n) => {
-                                frame_buffer.extend_from_slice(&temp_buf[..n]);
+                            (None, first) => {
+                                self.fragment_opcode = Some(first);
+                                self.fragment_compressed = frame.rsv[0];
+                                self.fragment_count = 0;
                             }
-                            Err(e) => return Err(e),
+                            _ => {}
                         }
-                        continue;
+
+                        self.fragment_count += 1;
+                        if self.fragment_count > self.max_fragments_per_message {
+                            self.shared.record_close(CloseInitiator::Local, Some(1009));
+                            send_close_frame(stream, &self.shared, 1009, "Too many fragments")
+                                .await;
+                            self.state = ConnectionState::Closed;
+                            return Err(Error::Message(MessageError::TooManyFragments {
+                                count: self.fragment_count,
+                                max: self.max_fragments_per_message,
+                            }));
+                        }
+
+                        let size = self.fragment_buffer.len() + frame.payload.len();
+                        if size > self.max_message_size {
+                            self.shared.record_close(CloseInitiator::Local, Some(1009));
+                            send_close_frame(stream, &self.shared, 1009, violation_reason(1009))
+                                .await;
+                            self.state = ConnectionState::Closed;
+                            return Err(Error::Message(MessageError::TooLarge {
+                                size,
+                                max: self.max_message_size,
+                            }));
+                        }
+
+                        if frame.fin && frame.opcode != Opcode::Continuation {
+                            single_payload = Some(frame.payload);
+                        } else {
+                            self.fragment_buffer.extend_from_slice(&frame.payload);
+                        }
+                        final_frame = frame.fin;
+                    }
+                    reserved => {
+                        // RFC 6455 section 5.2: fail the connection with 1002
+                        self.shared.record_close(CloseInitiator::Local, Some(1002));
+                        send_close_frame(stream, &self.shared, 1002, "Reserved opcode").await;
+                        self.state = ConnectionState::Closed;
+                        return Err(Error::Protocol(ProtocolError::ReservedOpcode(
+                            reserved.value(),
+                        )));
                     }
                 }
             }
 
-            // Convert the collected message based on opcode
-            let message = match opcode.unwrap_or(Opcode::Text) {
-                Opcode::Text => {
-                    let text = String::from_utf8_lossy(&message_buffer).to_string();
-                    Message::text(text)
-                }
-                Opcode::Binary => {
-                    let data = Bytes::from(message_buffer.clone());
-                    Message::binary(data)
+            // A compressed message is inflated whole, as its deflate stream
+            // runs across the frame boundaries
+            #[cfg(feature = "compression")]
+            if std::mem::take(&mut self.fragment_compressed) {
+                if let Some(deflate) = &mut self.deflate {
+                    let compressed = single_payload.as_deref().unwrap_or(&self.fragment_buffer);
+                    match deflate.decompress(compressed) {
+                        Ok(inflated) => single_payload = Some(Bytes::from(inflated)),
+                        Err(e) => {
+                            let code = e.close_code().map_or(1007, |code| code.code());
+                            self.shared.record_close(CloseInitiator::Local, Some(code));
+                            send_close_frame(stream, &self.shared, code, violation_reason(code))
+                                .await;
+                            self.state = ConnectionState::Closed;
+                            return Err(e);
+                        }
+                    }
                 }
-                _ => {
-                    return Err(aerosocket_core::Error::Other(
+            }
+
+            // Convert the collected message based on opcode
+            let payload = single_payload.as_deref().unwrap_or(&self.fragment_buffer);
+            let message_len = payload.len();
+            let message = match self.fragment_opcode.take().unwrap_or(Opcode::Text) {
+                Opcode::Text => match std::str::from_utf8(payload) {
+                    Ok(text) => Message::text(text),
+                    Err(_) if self.strict_protocol => {
+                        self.shared.record_close(CloseInitiator::Local, Some(1007));
+                        send_close_frame(stream, &self.shared, 1007, violation_reason(1007)).await;
+                        self.state = ConnectionState::Closed;
+                        return Err(Error::InvalidUtf8);
+                    }
+                    Err(_) => Message::text(String::from_utf8_lossy(payload)),
+                },
+                // A single frame's payload is passed on as is; only reassembled
+                // messages are built in a pooled buffer
+                Opcode::Binary => match (&single_payload, &self.buffer_pool) {
+                    (Some(frame_payload), _) => Message::binary(frame_payload.clone()),
+                    (None, Some(pool)) => Message::binary(pool.copy_from_slice(payload)),
+                    (None, None) => Message::binary(std::mem::take(&mut self.fragment_buffer)),
+                },
+                _ => {
+                    return Err(aerosocket_core::Error::Other(
                         "Invalid message opcode".to_string(),
                     ))
                 }
             };
+            match &self.buffer_pool {
+                // Keep the reassembly allocation unless a large message grew it
+                Some(pool) if self.fragment_buffer.capacity() <= pool.buffer_capacity() => {
+                    self.fragment_buffer.clear()
+                }
+                _ => self.fragment_buffer = Vec::new(),
+            }
 
             // Update metadata
             self.metadata.messages_received += 1;
-            self.metadata.bytes_received += message_buffer.len() as u64;
+            self.metadata.bytes_received += message_len as u64;
+            if let Some(stats) = &self.stats {
+                stats.record_received(1, message_len as u64);
+            }
 
             #[cfg(feature = "metrics")]
             {
                 metrics::counter!("aerosocket_server_messages_received_total").increment(1);
                 metrics::counter!("aerosocket_server_bytes_received_total")
-                    .increment(message_buffer.len() as u64);
+                    .increment(message_len as u64);
                 metrics::histogram!("aerosocket_server_message_size_bytes")
-                    .record(message_buffer.len() as f64);
+                    .record(message_len as f64);
+            }
+
+            if self.shared.spend(message_len as u64) {
+                self.shared.record_close(CloseInitiator::Local, Some(1008));
+                send_close_frame(stream, &self.shared, 1008, "Byte budget exceeded").await;
+                self.state = ConnectionState::Closed;
+                return Err(byte_budget_error());
             }
 
             Ok(Some(message))
@@ -433,10 +1455,190 @@ impl Connection {
     }
 
     /// Close the connection
+    ///
+    /// Sending the Close frame is bounded by the close timeout. If the transport
+    /// does not accept the frame in time it is dropped, the connection is marked
+    /// closed and a write timeout error is returned. Once both sides have sent
+    /// Close, as after the automatic reply to the peer's Close, this does
+    /// nothing, and so it does once a Close went out through the
+    /// [`ConnectionWriter`].
     pub async fn close(&mut self, code: Option<u16>, reason: Option<&str>) -> Result<()> {
+        if self.shared.close_sent() && self.shared.close_received() {
+            return Ok(());
+        }
+        self.ensure_sendable(true)?;
+        if self.shared.close_sent() {
+            return Ok(());
+        }
+        let message = Message::try_close(code, reason.map(|s| s.to_string()))?;
         self.state = ConnectionState::Closing;
-        self.send(Message::close(code, reason.map(|s| s.to_string())))
-            .await
+        self.shared.record_close(CloseInitiator::Local, code);
+
+        let Some(limit) = self.close_timeout else {
+            return self.send(message).await;
+        };
+
+        let clock = self.clock.clone();
+        match clock::timeout(clock.as_ref(), limit, self.send(message)).await {
+            Some(result) => result,
+            None => {
+                // The transport is wedged; force it down so close completes promptly
+                self.stream = None;
+                self.state = ConnectionState::Closed;
+                Err(Error::Timeout(TimeoutError::Write { timeout: limit }))
+            }
+        }
+    }
+
+    /// Send a Close frame but keep reading until the peer closes
+    ///
+    /// Moves the connection to [`ConnectionState::HalfClosed`]: any further
+    /// send fails with [`Error::Closed`], while [`next`](Self::next) keeps
+    /// returning the data messages the peer sent before it saw our Close, and
+    /// finally the peer's Close, after which the connection is closed. If the
+    /// peer has already closed, this simply answers its Close.
+    pub async fn send_close_only(&mut self, code: Option<u16>, reason: Option<&str>) -> Result<()> {
+        self.close(code, reason).await?;
+        if !self.shared.close_received() {
+            self.state = ConnectionState::HalfClosed;
+        }
+        Ok(())
+    }
+
+    /// Close the connection and wait for the peer to acknowledge
+    ///
+    /// Sends a Close frame, then reads until the peer's Close arrives. While
+    /// waiting, at most the configured drain limits of frames and bytes are read
+    /// and discarded, and the whole wait is bounded by the close timeout, so a
+    /// peer flooding data after our Close cannot delay teardown. The transport
+    /// is closed afterwards in every case.
+    pub async fn close_gracefully(
+        &mut self,
+        code: Option<u16>,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        self.close(code, reason).await?;
+
+        let clock = self.clock.clone();
+        if !self.shared.close_received() {
+            let limit = self.close_timeout;
+            let drain = self.drain_until_close();
+            match limit {
+                Some(limit) => {
+                    let _ = clock::timeout(clock.as_ref(), limit, drain).await;
+                }
+                None => {
+                    let _ = drain.await;
+                }
+            }
+        }
+
+        if let Some(mut stream) = self.stream.take() {
+            match self.close_timeout {
+                Some(limit) => {
+                    let _ = clock::timeout(clock.as_ref(), limit, stream.close()).await;
+                }
+                None => {
+                    let _ = stream.close().await;
+                }
+            }
+        }
+        self.state = ConnectionState::Closed;
+        Ok(())
+    }
+
+    /// Discard incoming frames until the peer's Close arrives or a drain limit is
+    /// hit; returns whether the Close was seen
+    async fn drain_until_close(&mut self) -> Result<bool> {
+        let inflates_messages = self.inflates_messages();
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(false);
+        };
+        let mut frames = 0usize;
+        let mut bytes_read = 0usize;
+
+        loop {
+            let parsed = if inflates_messages {
+                Frame::parse_compressed(&mut self.read_buffer, self.max_frame_size)
+            } else {
+                Frame::parse(
+                    &mut self.read_buffer,
+                    self.metadata.compression_negotiated,
+                    self.max_frame_size,
+                )
+            };
+            match parsed {
+                Ok(frame) if frame.opcode == Opcode::Close => {
+                    self.shared.mark_close_received();
+                    return Ok(true);
+                }
+                Ok(_) => {
+                    frames += 1;
+                    if frames >= self.close_drain_frames {
+                        return Ok(false);
+                    }
+                }
+                Err(Error::Frame(FrameError::InsufficientData { .. })) => {
+                    if bytes_read >= self.close_drain_bytes {
+                        return Ok(false);
+                    }
+                    let n = stream
+                        .read_buf(&mut self.read_buffer, self.read_buffer_size)
+                        .await?;
+                    if n == 0 {
+                        return Ok(false);
+                    }
+                    bytes_read += n;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Take ownership of the underlying transport stream
+    ///
+    /// Returns the stream together with any bytes that were read from it but not
+    /// yet parsed into frames, so the caller can take over framing (for example
+    /// to switch to a raw byte protocol). The connection is consumed.
+    pub fn into_inner(mut self) -> Result<(Box<dyn TransportStream>, Bytes)> {
+        self.take_stream()
+    }
+
+    /// Detach the transport stream and its buffered bytes, leaving the
+    /// connection closed
+    fn take_stream(&mut self) -> Result<(Box<dyn TransportStream>, Bytes)> {
+        let stream = self.stream.take().ok_or_else(|| {
+            aerosocket_core::Error::Other("Connection not established".to_string())
+        })?;
+        self.state = ConnectionState::Closed;
+        Ok((stream, self.read_buffer.split().freeze()))
+    }
+
+    /// Snapshot the parser and message assembler state for debugging
+    ///
+    /// Intended for inspecting stuck connections: the dump holds a copy of
+    /// the unparsed bytes and whether a fragmented message is half-received.
+    /// A failed read abandons the unfinished message, so after an error only
+    /// the unparsed bytes remain.
+    #[cfg(feature = "diagnostics")]
+    pub fn debug_dump(&self) -> ConnectionDump {
+        ConnectionDump {
+            state: self.state,
+            buffered: Bytes::copy_from_slice(&self.read_buffer),
+            assembler_opcode: self.fragment_opcode,
+            mid_fragmentation: self.fragment_opcode.is_some(),
+            pending_payload_len: self.fragment_buffer.len(),
+        }
+    }
+
+    /// Side that started the closing handshake, if it has started
+    pub fn close_initiator(&self) -> Option<CloseInitiator> {
+        self.shared.close_record().map(|(initiator, _)| initiator)
+    }
+
+    /// Status code carried by the first Close frame, if any
+    pub fn close_code(&self) -> Option<u16> {
+        self.shared.close_record().and_then(|(_, code)| code)
     }
 
     /// Check if the connection is established
@@ -451,12 +1653,470 @@ impl Connection {
 
     /// Get the connection age
     pub fn age(&self) -> std::time::Duration {
-        self.metadata.established_at.elapsed()
+        self.clock
+            .now()
+            .saturating_duration_since(self.metadata.established_at)
     }
 
     /// Get the time since last activity
     pub fn idle_time(&self) -> std::time::Duration {
-        self.metadata.last_activity_at.elapsed()
+        self.clock
+            .now()
+            .saturating_duration_since(self.metadata.last_activity_at)
+    }
+}
+
+/// Cloneable write half of a split [`Connection`]
+///
+/// Each frame is serialized up front and written with a single `write_all`
+/// while holding one mutex, so concurrent senders such as a handler and a
+/// broadcaster never interleave partial frames on the wire. The guarantee is
+/// frame-atomic: frames from different tasks are written whole, in the order
+/// the senders acquire the lock. Messages passed to [`send`](Self::send) are
+/// written as one frame and are therefore message-atomic as well; a
+/// fragmented message built from several [`send_raw`](Self::send_raw) calls
+/// may have other senders' frames land between its fragments.
+///
+/// The writer follows the connection's closing handshake: once either side
+/// has sent Close, only a Close may still go out, and only the first Close
+/// this side sends is written. What the writer sends counts against the
+/// connection's byte budget at once, and is added to the connection's
+/// metadata the next time the connection sends or reads.
+#[derive(Clone)]
+pub struct ConnectionWriter {
+    inner: std::sync::Arc<tokio::sync::Mutex<Box<dyn TransportWrite>>>,
+    shared: std::sync::Arc<Shared>,
+    stats: Option<StatsHandle>,
+}
+
+impl ConnectionWriter {
+    /// Send a message as a single frame
+    ///
+    /// The message is validated and refused while closing like
+    /// [`Connection::send`] does.
+    pub async fn send(&self, message: Message) -> Result<()> {
+        message.validate()?;
+        self.write_frame(message.into_frame(), 1).await
+    }
+
+    /// Send a text message
+    pub async fn send_text(&self, text: impl AsRef<str>) -> Result<()> {
+        self.send(Message::text(text.as_ref().to_string())).await
+    }
+
+    /// Send a binary message
+    pub async fn send_binary(&self, data: impl Into<Bytes>) -> Result<()> {
+        self.send(Message::binary(data)).await
+    }
+
+    /// Send a pre-built frame
+    ///
+    /// A data or control frame fails with [`Error::Closed`] once either side
+    /// has sent Close; a Close frame after this side's first is dropped.
+    pub async fn send_raw(&self, frame: Frame) -> Result<()> {
+        self.write_frame(frame, 0).await
+    }
+
+    /// Write one frame, counting it as `messages` sent messages
+    ///
+    /// The closing handshake is checked under the write lock, so a frame the
+    /// check lets through is on the wire before a Close that follows it.
+    async fn write_frame(&self, frame: Frame, messages: u64) -> Result<()> {
+        use std::sync::atomic::Ordering;
+
+        let bytes = frame.to_bytes();
+        let mut write = self.inner.lock().await;
+        if frame.opcode == Opcode::Close {
+            if !self.shared.mark_close_sent() {
+                return Ok(());
+            }
+            self.shared
+                .record_close(CloseInitiator::Local, close_code_of(&frame.payload));
+        } else {
+            self.shared.ensure_open()?;
+        }
+        write.write_all(&bytes).await?;
+        write.flush().await?;
+
+        let len = bytes.len() as u64;
+        let over_budget = self.shared.spend(len) && frame.opcode != Opcode::Close;
+        if over_budget && self.shared.mark_close_sent() {
+            self.shared.record_close(CloseInitiator::Local, Some(1008));
+            let close = Frame::close(Some(1008), Some("Byte budget exceeded")).to_bytes();
+            if write.write_all(&close).await.is_ok() {
+                let _ = write.flush().await;
+            }
+        }
+        drop(write);
+
+        self.shared
+            .writer_messages
+            .fetch_add(messages, Ordering::Relaxed);
+        self.shared.writer_bytes.fetch_add(len, Ordering::Relaxed);
+        if let Some(stats) = &self.stats {
+            stats.record_sent(messages, len);
+        }
+        if over_budget {
+            return Err(byte_budget_error());
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ConnectionWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionWriter").finish_non_exhaustive()
+    }
+}
+
+/// Encoded frames queued on a [`Connection`] that the transport has not taken yet
+#[derive(Debug, Default)]
+struct OutboundQueue {
+    /// Bytes not yet written to the transport
+    bytes: BytesMut,
+    /// Full length of each queued frame, oldest first, and whether it may be
+    /// dropped to make room
+    frames: VecDeque<(usize, bool)>,
+    /// Bytes of the oldest frame the transport has already taken
+    head_written: usize,
+}
+
+impl OutboundQueue {
+    /// Bytes the transport has not taken yet
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Append a frame, returning its encoded length
+    fn push(&mut self, frame: &Frame, droppable: bool) -> usize {
+        let queued = self.bytes.len();
+        frame.write_to(&mut self.bytes);
+        let frame_len = self.bytes.len() - queued;
+        self.frames.push_back((frame_len, droppable));
+        frame_len
+    }
+
+    /// Account for `written` bytes from the front taken by the transport
+    fn advance(&mut self, written: usize) {
+        self.bytes.advance(written);
+        self.head_written += written;
+        while let Some(&(len, _)) = self.frames.front() {
+            if self.head_written < len {
+                break;
+            }
+            self.head_written -= len;
+            self.frames.pop_front();
+        }
+    }
+
+    /// Remove the oldest droppable frame the transport has not started on
+    ///
+    /// Returns `false` if no queued frame may be dropped.
+    fn drop_oldest(&mut self) -> bool {
+        let started = usize::from(self.head_written > 0);
+        let Some(index) = self
+            .frames
+            .iter()
+            .skip(started)
+            .position(|&(_, droppable)| droppable)
+            .map(|index| index + started)
+        else {
+            return false;
+        };
+        let offset = self
+            .frames
+            .iter()
+            .take(index)
+            .map(|&(len, _)| len)
+            .sum::<usize>()
+            - self.head_written;
+        let (len, _) = self.frames.remove(index).unwrap_or_default();
+
+        let mut rest = self.bytes.split_off(offset);
+        rest.advance(len);
+        self.bytes.unsplit(rest);
+        true
+    }
+
+    /// Discard the queue, returning the rest of a frame the transport has
+    /// started on, which must still be written to keep the stream well-formed
+    fn clear(&mut self) -> BytesMut {
+        let unfinished = match self.frames.front() {
+            Some(&(len, _)) if self.head_written > 0 => {
+                self.bytes.split_to(len - self.head_written)
+            }
+            _ => BytesMut::new(),
+        };
+        *self = Self::default();
+        unfinished
+    }
+
+    /// Write the oldest frames until at most `target` bytes remain
+    async fn write_down_to(
+        &mut self,
+        stream: &mut Box<dyn TransportStream>,
+        target: usize,
+    ) -> Result<()> {
+        // Stop at a frame boundary, so what stays queued can still be dropped
+        let mut end = 0;
+        let mut started = self.head_written;
+        for &(len, _) in &self.frames {
+            if self.bytes.len() - end <= target {
+                break;
+            }
+            end += len - started;
+            started = 0;
+        }
+
+        while end > 0 {
+            let written = stream.write(&self.bytes[..end]).await?;
+            if written == 0 {
+                return Err(Error::Io(std::io::ErrorKind::WriteZero.into()));
+            }
+            self.advance(written);
+            end -= written;
+        }
+        Ok(())
+    }
+
+    /// Write whatever the transport takes without waiting
+    ///
+    /// Relies on the transport's `write` and `flush` being cancel-safe: a
+    /// pending call is dropped having written nothing.
+    fn write_ready(&mut self, stream: &mut Box<dyn TransportStream>) -> Result<()> {
+        while !self.bytes.is_empty() {
+            match stream.write(&self.bytes).now_or_never() {
+                Some(Ok(0)) => return Err(Error::Io(std::io::ErrorKind::WriteZero.into())),
+                Some(Ok(written)) => self.advance(written),
+                Some(Err(err)) => return Err(err),
+                None => return Ok(()),
+            }
+        }
+        stream.flush().now_or_never().unwrap_or(Ok(()))
+    }
+}
+
+/// Stream installed by [`Connection::split`]: reads from the read half and
+/// routes every write through the shared writer
+struct SplitStream {
+    read: Box<dyn TransportRead>,
+    writer: ConnectionWriter,
+    remote_addr: SocketAddr,
+    local_addr: SocketAddr,
+}
+
+#[async_trait::async_trait]
+impl TransportStream for SplitStream {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.read.read(buf).await
+    }
+
+    async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.writer.inner.lock().await.write_all(buf).await?;
+        Ok(buf.len())
+    }
+
+    async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        self.writer.inner.lock().await.write_all(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.writer.inner.lock().await.flush().await
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        self.writer.inner.lock().await.close().await
+    }
+
+    fn remote_addr(&self) -> Result<SocketAddr> {
+        Ok(self.remote_addr)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Ping schedule for a connection with keepalive enabled
+#[derive(Debug, Clone, Copy)]
+struct Keepalive {
+    /// Quiet time before a ping is sent
+    interval: Duration,
+    /// How long a ping may go unanswered before the connection is closed
+    timeout: Option<Duration>,
+    /// When the outstanding ping was sent
+    ping_sent_at: Option<std::time::Instant>,
+}
+
+impl Keepalive {
+    /// Time left before the next ping is due or the outstanding one expires
+    fn remaining(&self, now: std::time::Instant, last_activity: std::time::Instant) -> Duration {
+        let since = |instant| now.saturating_duration_since(instant);
+        match (self.ping_sent_at, self.timeout) {
+            (Some(sent), Some(timeout)) => timeout.saturating_sub(since(sent)),
+            (Some(sent), None) => self.interval.saturating_sub(since(sent)),
+            (None, _) => self.interval.saturating_sub(since(last_activity)),
+        }
+    }
+
+    /// Called once `remaining` has run out: returns the timeout if the
+    /// outstanding ping went unanswered, otherwise records a new ping as sent
+    fn expire(&mut self, now: std::time::Instant) -> Option<Duration> {
+        match (self.ping_sent_at, self.timeout) {
+            (Some(_), Some(timeout)) => Some(timeout),
+            _ => {
+                self.ping_sent_at = Some(now);
+                None
+            }
+        }
+    }
+}
+
+/// Close reason sent when strict mode fails a connection with `code`
+fn violation_reason(code: u16) -> &'static str {
+    match code {
+        1007 => "Invalid payload data",
+        1009 => "Message too big",
+        _ => "Protocol error",
+    }
+}
+
+/// Best-effort write of a Close frame, used when tearing down on a protocol error
+///
+/// Nothing is written if this side's Close already went out, through the
+/// connection or its writer.
+async fn send_close_frame(
+    stream: &mut Box<dyn TransportStream>,
+    shared: &Shared,
+    code: u16,
+    reason: &str,
+) {
+    if !shared.mark_close_sent() {
+        return;
+    }
+    let frame = Frame::close(Some(code), Some(reason)).to_bytes();
+    if stream.write_all(&frame).await.is_ok() {
+        let _ = stream.flush().await;
+    }
+}
+
+/// Status code carried by a Close frame's payload, if any
+fn close_code_of(payload: &[u8]) -> Option<u16> {
+    (payload.len() >= 2).then(|| u16::from_be_bytes([payload[0], payload[1]]))
+}
+
+fn byte_budget_error() -> Error {
+    Error::Security(SecurityError::PolicyViolation(
+        "connection byte budget exceeded".to_string(),
+    ))
+}
+
+/// Closing handshake bit set once this side's Close frame is on its way
+const CLOSE_SENT: u8 = 1;
+/// Closing handshake bit set once the peer's Close frame has been read
+const CLOSE_RECEIVED: u8 = 2;
+
+/// State a [`Connection`] shares with its [`ConnectionWriter`] and handles
+///
+/// Read and updated without the connection lock, so a Close sent through
+/// the writer counts as this side's Close, and a busy connection can still
+/// be seen to be closing.
+#[derive(Debug)]
+struct Shared {
+    /// `CLOSE_SENT` and `CLOSE_RECEIVED` bits of the closing handshake
+    close: std::sync::atomic::AtomicU8,
+    /// Side that sent the first Close frame and the status code it carried
+    close_record: std::sync::Mutex<Option<(CloseInitiator, Option<u16>)>>,
+    /// Pongs read so far
+    pongs_received: std::sync::atomic::AtomicU64,
+    /// Messages the writer sent that the metadata does not count yet
+    writer_messages: std::sync::atomic::AtomicU64,
+    /// Bytes the writer sent that the metadata does not count yet
+    writer_bytes: std::sync::atomic::AtomicU64,
+    /// Bytes sent and received, counted against the byte budget
+    traffic: std::sync::atomic::AtomicU64,
+    /// Byte budget, `u64::MAX` when unlimited
+    max_bytes: std::sync::atomic::AtomicU64,
+}
+
+impl Default for Shared {
+    fn default() -> Self {
+        Self {
+            close: Default::default(),
+            close_record: Default::default(),
+            pongs_received: Default::default(),
+            writer_messages: Default::default(),
+            writer_bytes: Default::default(),
+            traffic: Default::default(),
+            max_bytes: std::sync::atomic::AtomicU64::new(u64::MAX),
+        }
+    }
+}
+
+impl Shared {
+    /// Count `bytes` of traffic, returning whether the byte budget is exceeded
+    fn spend(&self, bytes: u64) -> bool {
+        use std::sync::atomic::Ordering;
+
+        let traffic = self.traffic.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        traffic > self.max_bytes.load(Ordering::Relaxed)
+    }
+
+    fn close_bits(&self) -> u8 {
+        self.close.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Whether this side has sent Close, including the automatic reply
+    fn close_sent(&self) -> bool {
+        self.close_bits() & CLOSE_SENT != 0
+    }
+
+    /// Whether the peer has sent Close
+    fn close_received(&self) -> bool {
+        self.close_bits() & CLOSE_RECEIVED != 0
+    }
+
+    /// Mark this side's Close as sent, returning whether it was not already
+    fn mark_close_sent(&self) -> bool {
+        self.close
+            .fetch_or(CLOSE_SENT, std::sync::atomic::Ordering::SeqCst)
+            & CLOSE_SENT
+            == 0
+    }
+
+    fn mark_close_received(&self) {
+        self.close
+            .fetch_or(CLOSE_RECEIVED, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Record the side that started the closing handshake, unless one is already
+    fn record_close(&self, initiator: CloseInitiator, code: Option<u16>) {
+        self.close_record
+            .lock()
+            .unwrap()
+            .get_or_insert((initiator, code));
+    }
+
+    fn close_record(&self) -> Option<(CloseInitiator, Option<u16>)> {
+        *self.close_record.lock().unwrap()
+    }
+
+    /// Refuse anything but a Close once either side has sent one
+    fn ensure_open(&self) -> Result<()> {
+        let reason = match self.close_bits() {
+            0 => return Ok(()),
+            bits if bits & CLOSE_SENT != 0 => "close frame already sent",
+            _ => "connection is closing",
+        };
+        let code = self.close_record().and_then(|(_, code)| code);
+        Err(Error::Closed {
+            code: CloseCode::from(code.unwrap_or(1005)),
+            reason: reason.to_string(),
+        })
     }
 }
 
@@ -467,14 +2127,21 @@ pub struct ConnectionHandle {
     id: u64,
     /// Connection reference
     connection: std::sync::Arc<tokio::sync::Mutex<Connection>>,
+    /// Write half, set once the connection is split through this handle
+    writer: std::sync::Arc<std::sync::OnceLock<ConnectionWriter>>,
+    /// Closing handshake and pong count, readable while the handler holds the lock
+    shared: std::sync::Arc<Shared>,
 }
 
 impl ConnectionHandle {
     /// Create a new connection handle
     pub fn new(id: u64, connection: Connection) -> Self {
+        let shared = connection.shared.clone();
         Self {
             id,
             connection: std::sync::Arc::new(tokio::sync::Mutex::new(connection)),
+            writer: Default::default(),
+            shared,
         }
     }
 
@@ -489,11 +2156,107 @@ impl ConnectionHandle {
             .try_lock()
             .map_err(|_| aerosocket_core::Error::Other("Failed to lock connection".to_string()))
     }
+
+    /// Send a message without waiting on a busy connection
+    ///
+    /// Goes through the connection's [`ConnectionWriter`] when it has been
+    /// split, so the send is not blocked by a handler waiting in `next()`.
+    /// Otherwise the connection is locked, failing if it is already in use.
+    pub async fn send(&self, message: Message) -> Result<()> {
+        if let Some(writer) = self.writer.get() {
+            return writer.send(message).await;
+        }
+        self.try_lock().await?.send(message).await
+    }
+
+    /// Close the connection without waiting on a busy connection
+    ///
+    /// Calls [`Connection::close`] when the connection is free. Otherwise the
+    /// Close goes through the [`ConnectionWriter`] if the connection has been
+    /// split, and counts as the connection's own Close, so the peer's reply is
+    /// not answered with a second one. A busy connection that was never split
+    /// fails to lock.
+    pub async fn close(&self, code: Option<u16>, reason: Option<&str>) -> Result<()> {
+        if let Ok(mut connection) = self.connection.try_lock() {
+            return connection.close(code, reason).await;
+        }
+        let close = Message::try_close(code, reason.map(str::to_string))?;
+        self.send(close).await
+    }
+
+    /// Whether either side has sent Close
+    ///
+    /// Does not lock the connection, like [`pongs_received`](Self::pongs_received).
+    pub fn is_closing(&self) -> bool {
+        self.shared.close_bits() != 0
+    }
+
+    /// Number of pongs the connection has read
+    ///
+    /// Does not lock the connection, so it can be polled while a handler is
+    /// waiting in `next()`.
+    pub fn pongs_received(&self) -> u64 {
+        self.shared
+            .pongs_received
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Split the connection and return its shared write half
+    ///
+    /// See [`Connection::split`]. Once split, [`send`](Self::send) on any
+    /// clone of this handle uses the writer.
+    pub async fn writer(&self) -> Result<ConnectionWriter> {
+        if let Some(writer) = self.writer.get() {
+            return Ok(writer.clone());
+        }
+        let writer = self.connection.lock().await.split()?;
+        Ok(self.writer.get_or_init(|| writer).clone())
+    }
+
+    /// Store a value in the connection context
+    ///
+    /// Waits for the connection lock, so it must not be called while the
+    /// same task holds a guard from [`try_lock`](Self::try_lock); use
+    /// [`Connection::insert`] on the guard instead.
+    pub async fn insert<T: Send + Sync + 'static>(&self, value: T) -> Option<T> {
+        self.connection.lock().await.insert(value)
+    }
+
+    /// Get a copy of the value of type `T` from the connection context
+    ///
+    /// Waits for the connection lock, like [`insert`](Self::insert).
+    pub async fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.connection.lock().await.get::<T>().cloned()
+    }
+
+    /// Get the subprotocol accepted in the handshake
+    ///
+    /// Waits for the connection lock, like [`insert`](Self::insert).
+    pub async fn subprotocol(&self) -> Option<String> {
+        self.connection.lock().await.metadata.subprotocol.clone()
+    }
+
+    /// Get the extensions accepted in the handshake
+    ///
+    /// Waits for the connection lock, like [`insert`](Self::insert).
+    pub async fn extensions(&self) -> Vec<String> {
+        self.connection.lock().await.metadata.extensions.clone()
+    }
+
+    /// Take ownership of the underlying transport stream
+    ///
+    /// See [`Connection::into_inner`]. Other handles to the same connection
+    /// observe it as closed afterwards.
+    pub async fn into_inner(self) -> Result<(Box<dyn TransportStream>, Bytes)> {
+        self.connection.lock().await.take_stream()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aerosocket_core::error::CloseError;
+    use aerosocket_core::transport::mock::ScriptedStream;
 
     #[test]
     fn test_connection_creation() {
@@ -508,6 +2271,154 @@ mod tests {
         assert!(!conn.is_closed());
     }
 
+    #[test]
+    fn test_outgoing_payload_is_not_copied() {
+        let data = Bytes::from(vec![0xAB; 4096]);
+        let frame = Message::binary(data.clone()).into_frame();
+        assert_eq!(frame.payload.as_ptr(), data.as_ptr());
+
+        let text = "x".repeat(4096);
+        let text_ptr = text.as_ptr();
+        let frame = Message::text(text).into_frame();
+        assert_eq!(frame.payload.as_ptr(), text_ptr);
+    }
+
+    #[test]
+    fn test_idle_timeout_on_mock_clock() {
+        let clock = crate::clock::MockClock::new();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::new(remote, local);
+        conn.set_clock(std::sync::Arc::new(clock.clone()));
+        conn.set_idle_timeout(Some(Duration::from_secs(60)));
+
+        clock.advance(Duration::from_secs(59));
+        assert!(!conn.is_timed_out());
+        assert_eq!(conn.time_until_timeout(), Some(Duration::from_secs(1)));
+
+        clock.advance(Duration::from_secs(2));
+        assert!(conn.is_timed_out());
+        assert_eq!(conn.time_until_timeout(), Some(Duration::ZERO));
+        assert_eq!(conn.idle_time(), Duration::from_secs(61));
+    }
+
+    #[tokio::test]
+    async fn test_eof_without_close_frame_is_abnormal() {
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+
+        let stream = ScriptedStream::new(vec![
+            client_frame(Frame::text("hi")),
+            client_frame(Frame::close(Some(1000), None)),
+        ]);
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        assert!(conn.next().await.unwrap().is_some());
+        assert!(matches!(conn.next().await, Ok(Some(Message::Close(_)))));
+        assert!(conn.next().await.unwrap().is_none());
+
+        let stream = ScriptedStream::new(vec![client_frame(Frame::text("hi"))]);
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        assert!(conn.next().await.unwrap().is_some());
+        assert!(matches!(
+            conn.next().await,
+            Err(Error::Closed {
+                code: CloseCode::Abnormal,
+                ..
+            })
+        ));
+        assert!(conn.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_silent_peer_hits_idle_timeout() {
+        // Keeps the read pending forever after a ping, like a peer gone quiet
+        let mut stream = ScriptedStream::new(vec![client_frame(Frame::ping("hi"))]);
+        stream.stall_when_empty = true;
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_idle_timeout(Some(Duration::from_millis(100)));
+
+        let started = std::time::Instant::now();
+        let result = tokio::time::timeout(Duration::from_secs(5), conn.next())
+            .await
+            .expect("next() ignored the idle timeout");
+        assert!(matches!(
+            result,
+            Err(Error::Timeout(TimeoutError::Idle { timeout })) if timeout == Duration::from_millis(100)
+        ));
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(conn.close_code(), Some(1001));
+        assert!(conn.is_closed());
+
+        // The ping was answered before the Close went out
+        let mut sent = BytesMut::from(&written.lock().unwrap()[..]);
+        let pong = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(pong.opcode, Opcode::Pong);
+        let close = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(&close.payload[..2], &1001u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_keepalive_ping_closes_with_1001() {
+        let mut stream = ScriptedStream::new(vec![client_frame(Frame::pong("hi"))]);
+        stream.stall_when_empty = true;
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_keepalive(
+            Some(Duration::from_millis(50)),
+            Some(Duration::from_millis(100)),
+        );
+        assert_eq!(conn.last_pong_at(), None);
+
+        let result = tokio::time::timeout(Duration::from_secs(5), conn.next())
+            .await
+            .expect("next() ignored the keepalive timeout");
+        assert!(matches!(
+            result,
+            Err(Error::Timeout(TimeoutError::Read { timeout })) if timeout == Duration::from_millis(100)
+        ));
+        assert!(conn.last_pong_at().is_some());
+        assert_eq!(conn.close_code(), Some(1001));
+
+        // One ping went out before the connection gave up on the peer
+        let mut sent = BytesMut::from(&written.lock().unwrap()[..]);
+        let ping = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(ping.opcode, Opcode::Ping);
+        let close = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(close.opcode, Opcode::Close);
+        assert_eq!(&close.payload[..2], &1001u16.to_be_bytes());
+        assert!(sent.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_close_timeout_on_mock_clock() {
+        let clock = crate::clock::MockClock::new();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(wedged_stream()));
+        conn.set_clock(std::sync::Arc::new(clock.clone()));
+        conn.set_close_timeout(Some(Duration::from_secs(3600)));
+
+        let advancer = tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            clock.advance(Duration::from_secs(3600));
+        });
+        let result = conn.close(Some(1000), None).await;
+        advancer.await.unwrap();
+
+        assert!(matches!(
+            result,
+            Err(Error::Timeout(TimeoutError::Write { .. }))
+        ));
+        assert!(conn.is_closed());
+    }
+
     #[tokio::test]
     async fn test_connection_handle() {
         let remote = "127.0.0.1:12345".parse().unwrap();
@@ -518,4 +2429,1615 @@ mod tests {
         assert_eq!(handle.id(), 1);
         assert!(handle.try_lock().await.is_ok());
     }
+
+    fn client_frame(frame: Frame) -> Vec<u8> {
+        frame.mask(true).to_bytes().to_vec()
+    }
+
+    /// Recorder keeping every histogram value, by metric name
+    #[cfg(feature = "metrics")]
+    #[derive(Default)]
+    struct HistogramRecorder {
+        values: std::sync::Arc<std::sync::Mutex<Vec<(String, f64)>>>,
+    }
+
+    #[cfg(feature = "metrics")]
+    struct RecordedHistogram {
+        name: String,
+        values: std::sync::Arc<std::sync::Mutex<Vec<(String, f64)>>>,
+    }
+
+    #[cfg(feature = "metrics")]
+    impl metrics::HistogramFn for RecordedHistogram {
+        fn record(&self, value: f64) {
+            self.values.lock().unwrap().push((self.name.clone(), value));
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    impl metrics::Recorder for HistogramRecorder {
+        fn describe_counter(
+            &self,
+            _: metrics::KeyName,
+            _: Option<metrics::Unit>,
+            _: metrics::SharedString,
+        ) {
+        }
+
+        fn describe_gauge(
+            &self,
+            _: metrics::KeyName,
+            _: Option<metrics::Unit>,
+            _: metrics::SharedString,
+        ) {
+        }
+
+        fn describe_histogram(
+            &self,
+            _: metrics::KeyName,
+            _: Option<metrics::Unit>,
+            _: metrics::SharedString,
+        ) {
+        }
+
+        fn register_counter(
+            &self,
+            _: &metrics::Key,
+            _: &metrics::Metadata<'_>,
+        ) -> metrics::Counter {
+            metrics::Counter::noop()
+        }
+
+        fn register_gauge(&self, _: &metrics::Key, _: &metrics::Metadata<'_>) -> metrics::Gauge {
+            metrics::Gauge::noop()
+        }
+
+        fn register_histogram(
+            &self,
+            key: &metrics::Key,
+            _: &metrics::Metadata<'_>,
+        ) -> metrics::Histogram {
+            metrics::Histogram::from_arc(std::sync::Arc::new(RecordedHistogram {
+                name: key.name().to_string(),
+                values: self.values.clone(),
+            }))
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_slow_handler_records_handle_duration() {
+        let recorder = HistogramRecorder::default();
+        let values = recorder.values.clone();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(async {
+                let stream = ScriptedStream::new(vec![
+                    client_frame(Frame::text("slow")),
+                    client_frame(Frame::text("fast")),
+                ]);
+                let remote = "127.0.0.1:12345".parse().unwrap();
+                let local = "127.0.0.1:8080".parse().unwrap();
+                let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+                conn.next().await.unwrap().unwrap();
+                // An artificially slow handler
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                conn.next().await.unwrap().unwrap();
+                conn.record_handle_duration();
+            })
+        });
+
+        let durations: Vec<f64> = values
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| name == "aerosocket_server_message_handle_duration_seconds")
+            .map(|(_, value)| *value)
+            .collect();
+        assert_eq!(durations.len(), 2);
+        assert!(durations[0] >= 0.05, "slow message took {}s", durations[0]);
+        assert!(durations[1] < durations[0]);
+    }
+
+    #[tokio::test]
+    async fn test_into_inner_returns_stream_and_buffered_bytes() {
+        // The peer sends a final WebSocket message and starts its raw protocol
+        // in the same write, then keeps writing raw bytes.
+        let mut first = client_frame(Frame::text("switch"));
+        first.extend_from_slice(b"RAW");
+        let stream = ScriptedStream::new(vec![first, b"MORE".to_vec()]);
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let msg = conn.next().await.unwrap().unwrap();
+        assert_eq!(msg.as_text(), Some("switch"));
+
+        let (mut stream, buffered) = conn.into_inner().unwrap();
+        assert_eq!(&buffered[..], b"RAW");
+
+        let mut buf = [0u8; 16];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"MORE");
+    }
+
+    #[tokio::test]
+    async fn test_handle_into_inner_closes_connection() {
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let conn = Connection::with_stream(remote, local, Box::new(ScriptedStream::new(vec![])));
+        let handle = ConnectionHandle::new(1, conn);
+        let other = handle.clone();
+
+        let (_stream, buffered) = handle.into_inner().await.unwrap();
+        assert!(buffered.is_empty());
+
+        let conn = other.try_lock().await.unwrap();
+        assert!(conn.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_feed_waits_for_flush() {
+        let stream = ScriptedStream::new(vec![]);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        conn.feed(Message::text("one")).await.unwrap();
+        conn.feed(Message::binary(&b"two"[..])).await.unwrap();
+        assert!(written.lock().unwrap().is_empty());
+        assert_eq!(conn.metadata().messages_sent, 2);
+
+        conn.flush().await.unwrap();
+        let mut expected = Frame::text("one").to_bytes().to_vec();
+        expected.extend_from_slice(&Frame::binary(&b"two"[..]).to_bytes());
+        assert_eq!(*written.lock().unwrap(), expected);
+
+        // Nothing is left to write on a second flush
+        conn.flush().await.unwrap();
+        assert_eq!(written.lock().unwrap().len(), expected.len());
+    }
+
+    #[tokio::test]
+    async fn test_send_buffered_flushes_at_threshold() {
+        let stream = ScriptedStream::new(vec![]);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        let frame_len = Frame::text("ping").to_bytes().len();
+        conn.set_flush_threshold(3 * frame_len);
+
+        conn.send_buffered(Message::text("ping")).await.unwrap();
+        conn.send_buffered(Message::text("ping")).await.unwrap();
+        assert!(written.lock().unwrap().is_empty());
+
+        // The third frame reaches the threshold and takes the others with it
+        conn.send_buffered(Message::text("ping")).await.unwrap();
+        assert_eq!(written.lock().unwrap().len(), 3 * frame_len);
+
+        conn.send_buffered(Message::text("ping")).await.unwrap();
+        assert_eq!(written.lock().unwrap().len(), 3 * frame_len);
+        conn.flush().await.unwrap();
+        assert_eq!(written.lock().unwrap().len(), 4 * frame_len);
+        assert_eq!(conn.metadata().messages_sent, 4);
+    }
+
+    /// Splittable stream that trickles writes out one byte at a time,
+    /// yielding in between so unsynchronized writers would interleave
+    fn trickle_stream() -> ScriptedStream {
+        let mut stream = ScriptedStream::new(vec![]);
+        stream.stall_when_empty = true;
+        stream.trickle_writes = true;
+        stream.splittable = true;
+        stream
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_split_writers_never_interleave_frames() {
+        let stream = trickle_stream();
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        let writer = conn.split().unwrap();
+
+        let mut tasks = Vec::new();
+        for fill in [b'a', b'b'] {
+            let writer = writer.clone();
+            tasks.push(tokio::spawn(async move {
+                for _ in 0..20 {
+                    writer
+                        .send_raw(Frame::binary(vec![fill; 64]))
+                        .await
+                        .unwrap();
+                }
+            }));
+        }
+        // The connection's own writes share the same lock
+        for _ in 0..20 {
+            conn.send_binary(vec![b'c'; 64]).await.unwrap();
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let mut buf = BytesMut::from(&written.lock().unwrap()[..]);
+        let mut counts = std::collections::HashMap::new();
+        while !buf.is_empty() {
+            let frame = Frame::parse(&mut buf, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+            assert_eq!(frame.payload.len(), 64);
+            let fill = frame.payload[0];
+            assert!(frame.payload.iter().all(|b| *b == fill));
+            *counts.entry(fill).or_insert(0) += 1;
+        }
+        assert_eq!(counts[&b'a'], 20);
+        assert_eq!(counts[&b'b'], 20);
+        assert_eq!(counts[&b'c'], 20);
+    }
+
+    #[tokio::test]
+    async fn test_split_requires_splittable_transport() {
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn =
+            Connection::with_stream(remote, local, Box::new(ScriptedStream::new(vec![])));
+        assert!(conn.split().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_writer_close_counts_as_the_connections_close() {
+        let mut stream = ScriptedStream::new(vec![client_frame(Frame::close(Some(1001), None))]);
+        stream.splittable = true;
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        let writer = conn.split().unwrap();
+
+        writer
+            .send(Message::close(Some(1001), Some("going away".to_string())))
+            .await
+            .unwrap();
+        assert!(matches!(
+            writer.send_text("late").await,
+            Err(Error::Closed { .. })
+        ));
+        assert!(matches!(
+            conn.send_text("late").await,
+            Err(Error::Closed { .. })
+        ));
+
+        // The peer's acknowledgement is not answered with a second Close
+        assert!(matches!(
+            conn.next().await.unwrap(),
+            Some(Message::Close(_))
+        ));
+        let sent = written_frames(&written.lock().unwrap());
+        assert_eq!(sent.len(), 1);
+        assert_eq!(&sent[0].payload[..2], &1001u16.to_be_bytes());
+        assert_eq!(conn.close_initiator(), Some(CloseInitiator::Local));
+        assert!(conn.close(Some(1000), None).await.is_ok());
+        assert_eq!(written_frames(&written.lock().unwrap()).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_writer_refuses_data_after_peer_close() {
+        let mut stream = ScriptedStream::new(vec![client_frame(Frame::close(Some(1000), None))]);
+        stream.splittable = true;
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        let writer = conn.split().unwrap();
+
+        writer.send_text("before").await.unwrap();
+        assert!(matches!(
+            conn.next().await.unwrap(),
+            Some(Message::Close(_))
+        ));
+        assert_eq!(conn.metadata.messages_sent, 1);
+
+        assert!(matches!(
+            writer.send_binary(vec![1u8, 2, 3]).await,
+            Err(Error::Closed { .. })
+        ));
+        // Our reply already completed the handshake
+        writer.send(Message::close(Some(1000), None)).await.unwrap();
+        let opcodes: Vec<_> = written_frames(&written.lock().unwrap())
+            .iter()
+            .map(|frame| frame.opcode)
+            .collect();
+        assert_eq!(opcodes, vec![Opcode::Text, Opcode::Close]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_send_uses_writer_while_connection_is_busy() {
+        let stream = trickle_stream();
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let handle =
+            ConnectionHandle::new(1, Connection::with_stream(remote, local, Box::new(stream)));
+
+        handle.writer().await.unwrap();
+        let _busy = handle.try_lock().await.unwrap();
+        handle.send(Message::text("hi")).await.unwrap();
+        assert_eq!(
+            *written.lock().unwrap(),
+            Frame::text("hi").to_bytes().to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_close_while_busy_is_the_connections_close() {
+        let mut stream = ScriptedStream::new(vec![client_frame(Frame::close(Some(1001), None))]);
+        stream.splittable = true;
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let handle =
+            ConnectionHandle::new(1, Connection::with_stream(remote, local, Box::new(stream)));
+
+        handle.writer().await.unwrap();
+        let mut busy = handle.try_lock().await.unwrap();
+        handle
+            .close(Some(1001), Some("Server shutdown"))
+            .await
+            .unwrap();
+
+        // The handler reads the peer's acknowledgement without replying
+        assert!(matches!(
+            busy.next().await.unwrap(),
+            Some(Message::Close(_))
+        ));
+        assert_eq!(busy.close_initiator(), Some(CloseInitiator::Local));
+        assert_eq!(written_frames(&written.lock().unwrap()).len(), 1);
+    }
+
+    /// Parse every frame the server wrote
+    fn written_frames(written: &[u8]) -> Vec<Frame> {
+        let mut buf = BytesMut::from(written);
+        let mut frames = Vec::new();
+        while !buf.is_empty() {
+            frames.push(Frame::parse(&mut buf, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap());
+        }
+        frames
+    }
+
+    /// Connection over a peer that reads nothing, so every frame the
+    /// connection writes stays in `written`
+    fn backpressured(
+        strategy: BackpressureStrategy,
+        reads: Vec<Vec<u8>>,
+    ) -> (Connection, std::sync::Arc<std::sync::Mutex<Vec<u8>>>) {
+        backpressured_over(ScriptedStream::new(reads), strategy)
+    }
+
+    fn backpressured_over(
+        stream: ScriptedStream,
+        strategy: BackpressureStrategy,
+    ) -> (Connection, std::sync::Arc<std::sync::Mutex<Vec<u8>>>) {
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        // 20-byte payloads make 22-byte frames
+        conn.set_backpressure(Some(BackpressureConfig {
+            strategy,
+            buffer_size: 100,
+            high_water_mark: 60,
+            low_water_mark: 30,
+            ..BackpressureConfig::default()
+        }));
+        (conn, written)
+    }
+
+    fn payloads(written: &[u8]) -> Vec<u8> {
+        written_frames(written)
+            .iter()
+            .map(|frame| frame.payload[0])
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_buffer_strategy_drains_to_low_water_mark() {
+        let (mut conn, written) = backpressured(BackpressureStrategy::Buffer, vec![]);
+
+        for i in 0..2 {
+            conn.feed(Message::binary(vec![i; 20])).await.unwrap();
+        }
+        assert!(written.lock().unwrap().is_empty());
+
+        // The third frame crosses the high water mark
+        conn.feed(Message::binary(vec![2; 20])).await.unwrap();
+        assert_eq!(payloads(&written.lock().unwrap()), vec![0, 1]);
+
+        conn.flush().await.unwrap();
+        assert_eq!(payloads(&written.lock().unwrap()), vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_strategy_discards_stale_messages() {
+        let (mut conn, written) = backpressured(BackpressureStrategy::DropOldest, vec![]);
+
+        for i in 0..6 {
+            conn.feed(Message::binary(vec![i; 20])).await.unwrap();
+        }
+        assert!(written.lock().unwrap().is_empty());
+
+        conn.flush().await.unwrap();
+        assert_eq!(payloads(&written.lock().unwrap()), vec![2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_reject_strategy_fails_once_full() {
+        let (mut conn, written) = backpressured(BackpressureStrategy::Reject, vec![]);
+
+        for i in 0..4 {
+            conn.feed(Message::binary(vec![i; 20])).await.unwrap();
+        }
+        let err = conn.feed(Message::binary(vec![4; 20])).await.unwrap_err();
+        assert!(matches!(err, Error::CapacityExceeded { size: 110 }));
+
+        // Queued messages are kept and the queue accepts more once flushed
+        conn.flush().await.unwrap();
+        conn.send(Message::binary(vec![5; 20])).await.unwrap();
+        assert_eq!(payloads(&written.lock().unwrap()), vec![0, 1, 2, 3, 5]);
+    }
+
+    /// Stream that takes `budget` bytes and then stops taking more
+    fn stalled_after(budget: usize, reads: Vec<Vec<u8>>) -> ScriptedStream {
+        let stream = ScriptedStream::new(reads);
+        stream
+            .write_budget
+            .store(budget, std::sync::atomic::Ordering::SeqCst);
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_reject_strategy_fails_once_transport_stalls() {
+        let stream = stalled_after(30, vec![]);
+        let budget = stream.write_budget.clone();
+        let (mut conn, written) = backpressured_over(stream, BackpressureStrategy::Reject);
+
+        // The transport takes the first frame and 8 bytes of the second; the
+        // 14 bytes it did not take and three more frames fill the queue
+        for i in 0..5 {
+            conn.send(Message::binary(vec![i; 20])).await.unwrap();
+        }
+        assert_eq!(written.lock().unwrap().len(), 30);
+        let err = conn.send(Message::binary(vec![5; 20])).await.unwrap_err();
+        assert!(matches!(err, Error::CapacityExceeded { size: 102 }));
+
+        budget.store(usize::MAX, std::sync::atomic::Ordering::SeqCst);
+        conn.flush().await.unwrap();
+        assert_eq!(payloads(&written.lock().unwrap()), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_strategy_keeps_frame_transport_started() {
+        let stream = stalled_after(30, vec![client_frame(Frame::text("hi"))]);
+        let budget = stream.write_budget.clone();
+        let (mut conn, written) = backpressured_over(stream, BackpressureStrategy::DropOldest);
+
+        for i in 0..7 {
+            conn.send(Message::binary(vec![i; 20])).await.unwrap();
+        }
+
+        // Frame 1 is partly written and must be finished; 2 and 3 made room
+        budget.store(usize::MAX, std::sync::atomic::Ordering::SeqCst);
+        let message = conn.next().await.unwrap().unwrap();
+        assert_eq!(message.as_text(), Some("hi"));
+        assert_eq!(payloads(&written.lock().unwrap()), vec![0, 1, 4, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_strategy_never_waits_on_wedged_transport() {
+        let (mut conn, written) =
+            backpressured_over(stalled_after(0, vec![]), BackpressureStrategy::DropOldest);
+
+        let sends = async {
+            for i in 0..10 {
+                conn.send(Message::binary(vec![i; 20])).await.unwrap();
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(1), sends)
+            .await
+            .expect("send waited on the transport");
+        assert!(written.lock().unwrap().is_empty());
+        assert_eq!(conn.outbound.len(), 88);
+    }
+
+    #[tokio::test]
+    async fn test_flow_control_strategy_drains_before_reading() {
+        let (mut conn, written) = backpressured(
+            BackpressureStrategy::FlowControl,
+            vec![client_frame(Frame::text("hi"))],
+        );
+
+        for i in 0..3 {
+            conn.feed(Message::binary(vec![i; 20])).await.unwrap();
+        }
+        assert!(written.lock().unwrap().is_empty());
+
+        let message = conn.next().await.unwrap().unwrap();
+        assert_eq!(message.as_text(), Some("hi"));
+        assert_eq!(payloads(&written.lock().unwrap()), vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_send_stream_fragments_reassemble() {
+        let stream = ScriptedStream::new(vec![]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_max_fragment_size(4);
+
+        let chunks = ["hello ", "", "wide", " world"].map(|chunk| Ok(Bytes::from(chunk)));
+        conn.send_stream(Opcode::Text, futures_util::stream::iter(chunks))
+            .await
+            .unwrap();
+
+        let frames = written_frames(&written.lock().unwrap());
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[0].opcode, Opcode::Text);
+        assert!(frames[1..].iter().all(|f| f.opcode == Opcode::Continuation));
+        assert!(frames.iter().all(|f| f.payload.len() <= 4));
+        assert_eq!(
+            frames.iter().map(|f| f.fin).collect::<Vec<_>>(),
+            [false, false, false, false, true]
+        );
+
+        let mut assembler = aerosocket_core::message::MessageAssembler::new();
+        let message = frames
+            .into_iter()
+            .filter_map(|frame| assembler.feed_frame(frame).unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(message.as_text(), Some("hello wide world"));
+        assert_eq!(conn.metadata().messages_sent, 1);
+
+        // An empty stream still sends a (single, empty) message
+        written.lock().unwrap().clear();
+        conn.send_stream(Opcode::Binary, futures_util::stream::empty())
+            .await
+            .unwrap();
+        let frames = written_frames(&written.lock().unwrap());
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].fin && frames[0].payload.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_send_stream_interleaves_writer_pings() {
+        let stream = trickle_stream();
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        let writer = conn.split().unwrap();
+
+        // Another task pings while the third chunk is being produced, after
+        // the first fragment has gone out
+        let chunks = futures_util::stream::unfold(0, |n| {
+            let writer = writer.clone();
+            async move {
+                match n {
+                    0 => Some((Ok(Bytes::from("first ")), 1)),
+                    1 => Some((Ok(Bytes::from("second ")), 2)),
+                    2 => {
+                        writer.send(Message::ping(None)).await.unwrap();
+                        Some((Ok(Bytes::from("third")), 3))
+                    }
+                    _ => None,
+                }
+            }
+        });
+        conn.send_stream(Opcode::Binary, chunks).await.unwrap();
+
+        let frames = written_frames(&written.lock().unwrap());
+        let opcodes: Vec<_> = frames.iter().map(|f| f.opcode).collect();
+        assert_eq!(
+            opcodes,
+            [
+                Opcode::Binary,
+                Opcode::Ping,
+                Opcode::Continuation,
+                Opcode::Continuation
+            ]
+        );
+
+        let mut assembler = aerosocket_core::message::MessageAssembler::new();
+        let messages: Vec<_> = frames
+            .into_iter()
+            .filter_map(|frame| assembler.feed_frame(frame).unwrap())
+            .collect();
+        assert!(matches!(messages[0], Message::Ping(_)));
+        assert_eq!(messages[1].as_bytes(), b"first second third");
+    }
+
+    #[tokio::test]
+    async fn test_context_is_shared_through_handle() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct UserId(u64);
+        #[derive(Debug, Clone, PartialEq)]
+        struct Session(&'static str);
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let handle = ConnectionHandle::new(1, Connection::new(remote, local));
+
+        assert_eq!(handle.insert(UserId(7)).await, None);
+        {
+            let mut connection = handle.try_lock().await.unwrap();
+            assert_eq!(connection.insert(Session("abc")), None);
+            assert_eq!(connection.insert(UserId(8)), Some(UserId(7)));
+            assert_eq!(connection.context().len(), 2);
+        }
+
+        // Other clones of the handle see the same values
+        let other = handle.clone();
+        assert_eq!(other.get::<UserId>().await, Some(UserId(8)));
+        assert_eq!(other.get::<Session>().await, Some(Session("abc")));
+        assert_eq!(other.get::<String>().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_send_after_close_is_refused() {
+        let stream = ScriptedStream::new(vec![]);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        conn.close(Some(1000), Some("bye")).await.unwrap();
+        let sent = written.lock().unwrap().len();
+
+        let err = conn.send_text("late").await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Closed {
+                code: CloseCode::Normal,
+                ..
+            }
+        ));
+        assert_eq!(written.lock().unwrap().len(), sent);
+    }
+
+    #[tokio::test]
+    async fn test_single_frame_payload_is_not_copied() {
+        let stream = ScriptedStream::new(vec![]);
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let data = vec![0x5a; 1024];
+        conn.read_buffer
+            .extend_from_slice(&client_frame(Frame::binary(data.clone())));
+        // 2 byte header, 2 byte extended length and the masking key
+        let payload_ptr = conn.read_buffer[8..].as_ptr();
+
+        let Message::Binary(binary) = conn.next().await.unwrap().unwrap() else {
+            panic!("expected a Binary message");
+        };
+        assert_eq!(binary.as_bytes().as_ptr(), payload_ptr);
+        assert_eq!(binary.as_bytes(), &data[..]);
+    }
+
+    #[tokio::test]
+    async fn test_buffer_pool_bounds_steady_state_allocations() {
+        const MESSAGES: u64 = 1000;
+        let reads = (0..MESSAGES)
+            .map(|i| {
+                let bytes = i.to_be_bytes();
+                let (head, tail) = bytes.split_at(4);
+                let mut read = client_frame(Frame::binary(head.to_vec()).fin(false));
+                read.extend(client_frame(Frame::new(
+                    Opcode::Continuation,
+                    tail.to_vec(),
+                )));
+                read
+            })
+            .chain([client_frame(Frame::binary(vec![1, 2, 3]))])
+            .collect();
+        let stream = ScriptedStream::new(reads);
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        let pool = BufferPool::new(4, 1024);
+        conn.set_buffer_pool(Some(pool.clone()));
+
+        // Each payload is dropped before the next arrives, so one buffer serves them all
+        let mut buffers = std::collections::HashSet::new();
+        for i in 0..MESSAGES {
+            let message = conn.next().await.unwrap().unwrap();
+            assert_eq!(message.as_bytes(), &i.to_be_bytes());
+            buffers.insert(message.as_bytes().as_ptr());
+        }
+        assert_eq!(buffers.len(), 1);
+
+        // A single-frame message keeps its payload and leaves the pool alone
+        let before = pool.stats();
+        let message = conn.next().await.unwrap().unwrap();
+        assert_eq!(message.as_bytes(), &[1, 2, 3]);
+        assert_eq!(pool.stats(), before);
+    }
+
+    #[tokio::test]
+    async fn test_bare_close_sends_empty_payload() {
+        let stream = ScriptedStream::new(vec![]);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        conn.close(None, None).await.unwrap();
+        assert_eq!(written.lock().unwrap().as_slice(), &[0x88, 0x00]);
+        assert_eq!(conn.close_code(), None);
+    }
+
+    #[tokio::test]
+    async fn test_bare_close_received_has_no_status() {
+        let stream = ScriptedStream::new(vec![client_frame(Frame::close(None, None))]);
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let Message::Close(close) = conn.next().await.unwrap().unwrap() else {
+            panic!("expected a Close message");
+        };
+        assert_eq!(close.code(), None);
+        assert_eq!(close.status(), CloseCode::NoStatus);
+        assert_eq!(conn.close_initiator(), Some(CloseInitiator::Peer));
+        assert_eq!(conn.close_code(), None);
+    }
+
+    #[tokio::test]
+    async fn test_close_reply_allowed_after_peer_close() {
+        let stream = ScriptedStream::new(vec![client_frame(Frame::close(Some(1001), None))]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let msg = conn.next().await.unwrap().unwrap();
+        assert!(matches!(msg, Message::Close(_)));
+
+        assert!(matches!(
+            conn.send_text("late").await,
+            Err(Error::Closed { .. })
+        ));
+        conn.send(Message::close(Some(1001), None)).await.unwrap();
+        conn.close(Some(1000), Some("bye")).await.unwrap();
+
+        // Only the automatic reply went out, mirroring the peer's code
+        let expected = Frame::close(Some(1001), Some("")).to_bytes();
+        assert_eq!(written.lock().unwrap().as_slice(), &expected[..]);
+    }
+
+    #[tokio::test]
+    async fn test_ping_between_fragments_is_answered() {
+        let stream = ScriptedStream::new(vec![
+            client_frame(Frame::new(Opcode::Text, "Hel").fin(false)),
+            client_frame(Frame::ping("are you there")),
+            client_frame(Frame::new(Opcode::Continuation, "lo").fin(true)),
+        ]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let message = conn.next().await.unwrap().unwrap();
+        assert_eq!(message.as_text(), Some("Hello"));
+        let expected = Frame::pong("are you there").to_bytes();
+        assert_eq!(written.lock().unwrap().as_slice(), &expected[..]);
+    }
+
+    #[tokio::test]
+    async fn test_close_between_fragments_drops_partial_message() {
+        let stream = ScriptedStream::new(vec![
+            client_frame(Frame::new(Opcode::Binary, "partial").fin(false)),
+            client_frame(Frame::close(Some(1001), None)),
+        ]);
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        assert!(matches!(
+            conn.next().await.unwrap(),
+            Some(Message::Close(_))
+        ));
+        assert!(conn.fragment_opcode.is_none());
+        assert!(conn.fragment_buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_read_drops_partial_message() {
+        let invalid_utf8 = vec![
+            client_frame(Frame::new(Opcode::Text, vec![0xF0, 0x9F]).fin(false)),
+            client_frame(Frame::new(Opcode::Continuation, vec![0x41]).fin(true)),
+        ];
+        let unmasked = vec![
+            client_frame(Frame::new(Opcode::Binary, "partial").fin(false)),
+            Frame::new(Opcode::Continuation, "rest").to_bytes().to_vec(),
+        ];
+
+        for reads in [invalid_utf8, unmasked] {
+            let stream = ScriptedStream::new(reads);
+            let remote = "127.0.0.1:12345".parse().unwrap();
+            let local = "127.0.0.1:8080".parse().unwrap();
+            let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+            conn.set_strict_protocol(true);
+
+            assert!(conn.next().await.is_err());
+            assert!(conn.fragment_opcode.is_none());
+            assert!(conn.fragment_buffer.is_empty());
+            assert!(!conn.fragment_compressed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_close_is_rejected_before_writing() {
+        let stream = ScriptedStream::new(vec![]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        assert!(matches!(
+            conn.close(Some(1006), None).await,
+            Err(Error::Close(CloseError::InvalidCode { code: 1006 }))
+        ));
+        assert!(matches!(
+            conn.send(Message::close(Some(1000), Some("x".repeat(124))))
+                .await,
+            Err(Error::Close(CloseError::ReasonTooLong { .. }))
+        ));
+        assert!(written.lock().unwrap().is_empty());
+        assert!(conn.is_connected());
+        assert_eq!(conn.close_initiator(), None);
+
+        conn.close(Some(4000), Some("bye")).await.unwrap();
+        assert_eq!(conn.close_code(), Some(4000));
+    }
+
+    #[tokio::test]
+    async fn test_peer_close_is_answered_and_completes_handshake() {
+        let stream = ScriptedStream::new(vec![client_frame(Frame::close(None, None))]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let msg = conn.next().await.unwrap().unwrap();
+        assert!(matches!(msg, Message::Close(_)));
+        assert_eq!(conn.state(), ConnectionState::Closed);
+        assert_eq!(conn.close_initiator(), Some(CloseInitiator::Peer));
+
+        // A bare Close is answered with 1000
+        let expected = Frame::close(Some(1000), Some("")).to_bytes();
+        assert_eq!(written.lock().unwrap().as_slice(), &expected[..]);
+    }
+
+    #[tokio::test]
+    async fn test_active_close_is_not_answered_twice() {
+        let stream = ScriptedStream::new(vec![client_frame(Frame::close(Some(1000), None))]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        conn.close(Some(1000), Some("done")).await.unwrap();
+        assert_eq!(conn.state(), ConnectionState::Closing);
+
+        let msg = conn.next().await.unwrap().unwrap();
+        assert!(matches!(msg, Message::Close(_)));
+        assert_eq!(conn.state(), ConnectionState::Closed);
+        assert_eq!(conn.close_initiator(), Some(CloseInitiator::Local));
+        conn.close(Some(1000), None).await.unwrap();
+
+        let expected = Frame::close(Some(1000), Some("done")).to_bytes();
+        assert_eq!(written.lock().unwrap().as_slice(), &expected[..]);
+    }
+
+    #[tokio::test]
+    async fn test_half_close_keeps_reading_until_peer_close() {
+        let stream = ScriptedStream::new(vec![
+            client_frame(Frame::text("in flight")),
+            client_frame(Frame::close(Some(1000), Some("done too"))),
+        ]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        conn.send_close_only(Some(1000), Some("done"))
+            .await
+            .unwrap();
+        assert_eq!(conn.state(), ConnectionState::HalfClosed);
+        assert!(matches!(
+            conn.send_text("more").await,
+            Err(Error::Closed { .. })
+        ));
+        assert!(matches!(
+            conn.close(Some(1000), None).await,
+            Err(Error::Closed { .. })
+        ));
+
+        // Data the peer sent before seeing our Close is still surfaced
+        let msg = conn.next().await.unwrap().unwrap();
+        assert_eq!(msg.as_text(), Some("in flight"));
+        assert_eq!(conn.state(), ConnectionState::HalfClosed);
+
+        let Message::Close(close) = conn.next().await.unwrap().unwrap() else {
+            panic!("expected a Close message");
+        };
+        assert_eq!(close.code(), Some(1000));
+        assert_eq!(conn.state(), ConnectionState::Closed);
+        assert_eq!(conn.close_initiator(), Some(CloseInitiator::Local));
+
+        // Only our own Close went out
+        let expected = Frame::close(Some(1000), Some("done")).to_bytes();
+        assert_eq!(written.lock().unwrap().as_slice(), &expected[..]);
+    }
+
+    #[tokio::test]
+    async fn test_masked_fragments_reassemble() {
+        let fragments = [
+            Frame::text("hello").fin(false),
+            Frame::continuation(" wor").fin(false),
+            Frame::continuation("ld!"),
+        ];
+        let reads = fragments.into_iter().map(client_frame).collect();
+        let stream = ScriptedStream::new(reads);
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let msg = conn.next().await.unwrap().unwrap();
+        assert_eq!(msg.as_text(), Some("hello world!"));
+    }
+
+    #[tokio::test]
+    async fn test_larger_read_buffer_takes_fewer_reads() {
+        let frame = client_frame(Frame::binary(vec![0x5A; 64 * 1024]));
+
+        let mut read_calls = Vec::new();
+        for size in [1024, 16 * 1024] {
+            let stream = ScriptedStream::new(vec![frame.clone()]);
+            let calls = stream.read_calls.clone();
+            let remote = "127.0.0.1:12345".parse().unwrap();
+            let local = "127.0.0.1:8080".parse().unwrap();
+            let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+            conn.set_read_buffer_size(size);
+
+            let msg = conn.next().await.unwrap().unwrap();
+            assert_eq!(msg.as_bytes().len(), 64 * 1024);
+            read_calls.push(calls.load(std::sync::atomic::Ordering::SeqCst));
+        }
+
+        // One read per buffer-sized chunk of the frame
+        assert_eq!(read_calls, vec![65, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_mixed_opcode_fragments_fail_with_1002() {
+        let stream = ScriptedStream::new(vec![
+            client_frame(Frame::text("hello").fin(false)),
+            client_frame(Frame::binary(&b"world"[..])),
+        ]);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        assert!(matches!(
+            conn.next().await,
+            Err(Error::Protocol(ProtocolError::InvalidContinuation))
+        ));
+        assert_eq!(conn.state(), ConnectionState::Closed);
+        assert_eq!(conn.close_code(), Some(1002));
+        assert_eq!(&written.lock().unwrap()[..4], &[0x88, 0x16, 0x03, 0xEA]);
+    }
+
+    #[tokio::test]
+    async fn test_strict_mode_rejects_what_lenient_tolerates() {
+        // (name, frame bytes, strict close code, accepted when lenient)
+        let cases: Vec<(&str, Vec<u8>, u16, bool)> = vec![
+            (
+                "unmasked frame",
+                Frame::text("hello").to_bytes().to_vec(),
+                1002,
+                true,
+            ),
+            (
+                "invalid close code",
+                client_frame(Frame::close(Some(999), None)),
+                1002,
+                true,
+            ),
+            (
+                "one-byte close payload",
+                client_frame(Frame::new(Opcode::Close, vec![3])),
+                1002,
+                true,
+            ),
+            (
+                "invalid UTF-8 text",
+                client_frame(Frame::text(vec![0xff, 0xfe])),
+                1007,
+                true,
+            ),
+            (
+                "oversized ping",
+                client_frame(Frame::ping(vec![0; 126])),
+                1002,
+                false,
+            ),
+            (
+                "invalid UTF-8 close reason",
+                client_frame(Frame::new(Opcode::Close, vec![0x03, 0xE8, 0xff])),
+                1007,
+                true,
+            ),
+            (
+                "reserved bit",
+                client_frame(Frame::text("hello").rsv(false, true, false)),
+                1002,
+                false,
+            ),
+            (
+                "fragmented ping",
+                client_frame(Frame::ping("hi").fin(false)),
+                1002,
+                false,
+            ),
+        ];
+
+        let remote: SocketAddr = "127.0.0.1:12345".parse().unwrap();
+        let local: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        for (name, bytes, code, lenient_ok) in cases {
+            let stream = ScriptedStream::new(vec![bytes.clone()]);
+            let written = stream.written.clone();
+            let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+            conn.set_allow_unmasked(true);
+            conn.set_strict_protocol(true);
+            assert!(conn.next().await.is_err(), "{name}: strict accepted");
+            assert_eq!(conn.close_code(), Some(code), "{name}");
+            assert_eq!(
+                &written.lock().unwrap()[2..4],
+                &code.to_be_bytes(),
+                "{name}"
+            );
+
+            // A tolerated frame is followed by a clean close, not a dropped socket
+            let close = client_frame(Frame::close(Some(1000), None));
+            let stream = ScriptedStream::new(vec![bytes, close]);
+            let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+            conn.set_allow_unmasked(true);
+            assert_eq!(conn.next().await.is_ok(), lenient_ok, "{name}: lenient");
+        }
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[tokio::test]
+    async fn test_debug_dump_shows_partial_frame() {
+        let partial = client_frame(Frame::continuation(" world"));
+        let stream = ScriptedStream::new(vec![
+            client_frame(Frame::text("hello").fin(false)),
+            partial[..3].to_vec(),
+        ]);
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let dump = conn.debug_dump();
+        assert!(dump.buffered.is_empty());
+        assert!(!dump.mid_fragmentation);
+
+        // The stream ends inside the continuation frame
+        assert!(matches!(
+            conn.next().await,
+            Err(Error::Closed {
+                code: CloseCode::Abnormal,
+                ..
+            })
+        ));
+
+        // The failed read abandoned the message but kept the bytes it read
+        let dump = conn.debug_dump();
+        assert_eq!(&dump.buffered[..], &partial[..3]);
+        assert_eq!(dump.assembler_opcode, None);
+        assert!(!dump.mid_fragmentation);
+        assert_eq!(dump.pending_payload_len, 0);
+        assert_eq!(dump.state, ConnectionState::Closed);
+        assert_eq!(
+            dump.buffered_hex(),
+            format!("{:02x} {:02x} {:02x}", partial[0], partial[1], partial[2])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_closes_with_1009() {
+        // Header declaring a 10GB payload with only the mask key present
+        let mut header = vec![0x82, 0x80 | 127];
+        header.extend_from_slice(&(10u64 << 30).to_be_bytes());
+        header.extend_from_slice(&[1, 2, 3, 4]);
+        let stream = ScriptedStream::new(vec![header, vec![0; 64]]);
+        let written = stream.written.clone();
+        let read_calls = stream.read_calls.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_max_frame_size(1024);
+
+        assert!(matches!(
+            conn.next().await,
+            Err(Error::Frame(FrameError::TooLarge { max: 1024, .. }))
+        ));
+        assert_eq!(conn.close_code(), Some(1009));
+        assert_eq!(read_calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(&written.lock().unwrap()[2..4], &1009u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_many_small_fragments_close_with_1009() {
+        // 64-byte fragments that never finish, well under the frame limit
+        let mut reads = vec![client_frame(
+            Frame::new(Opcode::Binary, vec![0u8; 64]).fin(false),
+        )];
+        reads.extend(
+            (0..20)
+                .map(|_| client_frame(Frame::new(Opcode::Continuation, vec![0u8; 64]).fin(false))),
+        );
+        let stream = ScriptedStream::new(reads);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_max_message_size(1024);
+
+        assert!(matches!(
+            conn.next().await,
+            Err(Error::Message(MessageError::TooLarge {
+                size: 1088,
+                max: 1024
+            }))
+        ));
+        assert!(conn.is_closed());
+        assert_eq!(conn.close_code(), Some(1009));
+        assert_eq!(&written.lock().unwrap()[2..4], &1009u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_too_many_fragments_close_with_1009() {
+        let max_fragments = 8;
+        let mut reads = vec![client_frame(Frame::new(Opcode::Text, "a").fin(false))];
+        reads.extend(
+            (0..max_fragments)
+                .map(|_| client_frame(Frame::new(Opcode::Continuation, "a").fin(false))),
+        );
+        let stream = ScriptedStream::new(reads);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_max_fragments_per_message(max_fragments);
+
+        assert!(matches!(
+            conn.next().await,
+            Err(Error::Message(MessageError::TooManyFragments {
+                count: 9,
+                max: 8
+            }))
+        ));
+        assert!(conn.is_closed());
+        assert_eq!(conn.close_code(), Some(1009));
+        assert_eq!(&written.lock().unwrap()[2..4], &1009u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_fragment_limit_applies_per_message() {
+        // Two messages of exactly the limit each
+        let mut reads = Vec::new();
+        for _ in 0..2 {
+            reads.push(client_frame(
+                Frame::new(Opcode::Binary, vec![1u8]).fin(false),
+            ));
+            reads.push(client_frame(
+                Frame::new(Opcode::Continuation, vec![2u8]).fin(false),
+            ));
+            reads.push(client_frame(Frame::new(Opcode::Continuation, vec![3u8])));
+        }
+        let stream = ScriptedStream::new(reads);
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_max_fragments_per_message(3);
+
+        for _ in 0..2 {
+            let message = conn.next().await.unwrap().unwrap();
+            assert_eq!(message.as_bytes(), &[1, 2, 3]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unmasked_text_frame_closes_with_1002() {
+        // FIN + text opcode, MASK bit clear, 5-byte payload
+        let mut unmasked = vec![0x81, 0x05];
+        unmasked.extend_from_slice(b"hello");
+        let stream = ScriptedStream::new(vec![unmasked]);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        assert!(matches!(
+            conn.next().await,
+            Err(Error::Protocol(ProtocolError::MaskingRequired))
+        ));
+        assert!(conn.is_closed());
+        assert_eq!(conn.close_code(), Some(1002));
+
+        let mut sent = BytesMut::from(&written.lock().unwrap()[..]);
+        let close = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(close.opcode, Opcode::Close);
+        assert_eq!(&close.payload[..2], &1002u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_unmasked_continuation_closes_with_protocol_error() {
+        let stream = ScriptedStream::new(vec![
+            client_frame(Frame::text("hello").fin(false)),
+            Frame::continuation(" world").to_bytes().to_vec(),
+        ]);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let err = conn.next().await.unwrap_err();
+        assert_eq!(err.close_code().map(|c| c.code()), Some(1002));
+        assert_eq!(conn.state(), ConnectionState::Closed);
+
+        let mut sent = BytesMut::from(&written.lock().unwrap()[..]);
+        let close = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(close.opcode, Opcode::Close);
+        assert_eq!(&close.payload[..2], &1002u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_reserved_opcode_closes_with_protocol_error() {
+        // Masked client frame with reserved opcode 0x3 and an empty payload
+        let stream = ScriptedStream::new(vec![vec![0x83, 0x80, 1, 2, 3, 4]]);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let err = conn.next().await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ReservedOpcode(0x3))
+        ));
+        assert!(conn.is_closed());
+
+        let mut sent = BytesMut::from(&written.lock().unwrap()[..]);
+        let close = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(close.opcode, Opcode::Close);
+        assert_eq!(&close.payload[..2], &1002u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_ping_closes_with_protocol_error() {
+        let stream = ScriptedStream::new(vec![client_frame(Frame::ping(vec![0; 126]))]);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let err = conn.next().await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Frame(FrameError::ControlFrameTooLarge { size: 126 })
+        ));
+        assert_eq!(conn.close_code(), Some(1002));
+
+        let sent = written_frames(&written.lock().unwrap());
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].opcode, Opcode::Close);
+        assert_eq!(&sent[0].payload[..2], &1002u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_largest_ping_is_answered() {
+        let stream = ScriptedStream::new(vec![
+            client_frame(Frame::ping(vec![7; 125])),
+            client_frame(Frame::close(Some(1000), None)),
+        ]);
+        let written = stream.written.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        assert!(conn.next().await.unwrap().is_some());
+        let sent = written_frames(&written.lock().unwrap());
+        assert_eq!(sent[0].opcode, Opcode::Pong);
+        assert_eq!(&sent[0].payload[..], &[7; 125][..]);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_ping_is_not_sent() {
+        let stream = ScriptedStream::new(vec![]);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        assert!(matches!(
+            conn.ping(Some(&[0; 126])).await,
+            Err(Error::Frame(FrameError::ControlFrameTooLarge { size: 126 }))
+        ));
+        assert!(conn.pong(Some(&[0; 200])).await.is_err());
+        assert!(written.lock().unwrap().is_empty());
+        assert!(!conn.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_frames_use_a_single_read() {
+        let mut pipelined = client_frame(Frame::text("one"));
+        pipelined.extend(client_frame(Frame::binary(&b"two"[..])));
+        pipelined.extend(client_frame(Frame::text("three")));
+        let stream = ScriptedStream::new(vec![pipelined]);
+        let read_calls = stream.read_calls.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        let first = conn.next().await.unwrap().unwrap();
+        assert_eq!(first.as_text(), Some("one"));
+        let second = conn.next().await.unwrap().unwrap();
+        assert_eq!(second.as_bytes(), b"two");
+        let third = conn.next().await.unwrap().unwrap();
+        assert_eq!(third.as_text(), Some("three"));
+
+        assert_eq!(read_calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_partial_frame_survives_between_calls() {
+        // One read carries two whole frames and the start of a third
+        let third = client_frame(Frame::text("three"));
+        let mut first_read = client_frame(Frame::text("one"));
+        first_read.extend(client_frame(Frame::binary(&b"two"[..])));
+        first_read.extend_from_slice(&third[..4]);
+        let stream = ScriptedStream::new(vec![first_read, third[4..].to_vec()]);
+        let read_calls = stream.read_calls.clone();
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        assert_eq!(conn.next().await.unwrap().unwrap().as_text(), Some("one"));
+        assert_eq!(conn.next().await.unwrap().unwrap().as_bytes(), b"two");
+        assert_eq!(read_calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        assert_eq!(conn.next().await.unwrap().unwrap().as_text(), Some("three"));
+        assert_eq!(read_calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reject_zero_mask() {
+        let mut frame = Frame::text("hi");
+        frame.masked = true;
+        frame.mask = Some([0; 4]);
+        let bytes = frame.to_bytes().to_vec();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+
+        // Accepted when the check is off
+        let stream = ScriptedStream::new(vec![bytes.clone()]);
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        let msg = conn.next().await.unwrap().unwrap();
+        assert_eq!(msg.as_text(), Some("hi"));
+
+        // Rejected with a 1002 close when the check is on
+        let stream = ScriptedStream::new(vec![bytes]);
+        let written = stream.written.clone();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_reject_zero_mask(true);
+        assert!(matches!(conn.next().await, Err(Error::Protocol(_))));
+        assert!(conn.is_closed());
+
+        let mut sent = BytesMut::from(&written.lock().unwrap()[..]);
+        let close = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(close.opcode, Opcode::Close);
+        assert_eq!(&close.payload[..2], &1002u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_max_connection_bytes_closes_with_policy_violation() {
+        let frames = vec![
+            client_frame(Frame::binary(vec![0u8; 40])),
+            client_frame(Frame::binary(vec![0u8; 40])),
+        ];
+        let stream = ScriptedStream::new(frames);
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_max_connection_bytes(Some(64));
+
+        // 40 bytes received so far: within budget
+        assert!(conn.next().await.unwrap().is_some());
+        // 80 bytes received: over budget
+        assert!(matches!(
+            conn.next().await,
+            Err(Error::Security(SecurityError::PolicyViolation(_)))
+        ));
+        assert!(conn.is_closed());
+
+        let mut sent = BytesMut::from(&written.lock().unwrap()[..]);
+        let close = Frame::parse(&mut sent, false, constants::DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(close.opcode, Opcode::Close);
+        assert_eq!(&close.payload[..2], &1008u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_max_connection_bytes_counts_sent_bytes() {
+        let stream = ScriptedStream::new(vec![]);
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_max_connection_bytes(Some(16));
+
+        conn.send_text("short").await.unwrap();
+        assert!(conn.send_text("this one goes over").await.is_err());
+        assert!(conn.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_max_connection_bytes_counts_raw_and_writer_sends() {
+        let mut stream = ScriptedStream::new(vec![]);
+        stream.splittable = true;
+        let written = stream.written.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_max_connection_bytes(Some(16));
+        let writer = conn.split().unwrap();
+
+        // 7 bytes, then 6: within budget
+        writer.send_text("short").await.unwrap();
+        conn.send_raw(Frame::text("more")).await.unwrap();
+        // 12 more bytes go over
+        assert!(matches!(
+            writer.send_binary(vec![0u8; 10]).await,
+            Err(Error::Security(SecurityError::PolicyViolation(_)))
+        ));
+        assert!(matches!(
+            conn.send_text("late").await,
+            Err(Error::Closed { .. })
+        ));
+
+        let sent = written_frames(&written.lock().unwrap());
+        let close = sent.last().unwrap();
+        assert_eq!(sent.len(), 4);
+        assert_eq!(close.opcode, Opcode::Close);
+        assert_eq!(&close.payload[..2], &1008u16.to_be_bytes());
+        assert_eq!(conn.close_code(), Some(1008));
+    }
+
+    /// Transport stream that keeps sending data frames and never a Close
+    fn flood_stream() -> ScriptedStream {
+        let mut stream = ScriptedStream::new(vec![]);
+        stream.repeat = Some(client_frame(Frame::binary(vec![7u8; 100])));
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_close_gracefully_caps_drain_frames() {
+        let stream = flood_stream();
+        let read_calls = stream.read_calls.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_close_timeout(None);
+        conn.set_close_drain_limits(10, usize::MAX);
+
+        conn.close_gracefully(Some(1000), None).await.unwrap();
+
+        assert!(conn.is_closed());
+        assert_eq!(read_calls.load(std::sync::atomic::Ordering::SeqCst), 10);
+    }
+
+    #[tokio::test]
+    async fn test_close_gracefully_caps_drain_bytes() {
+        let stream = flood_stream();
+        let read_calls = stream.read_calls.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+        conn.set_close_timeout(None);
+        conn.set_close_drain_limits(usize::MAX, 500);
+
+        conn.close_gracefully(Some(1000), None).await.unwrap();
+
+        assert!(conn.is_closed());
+        // Each read delivers a 106-byte frame; reading stops once 500 bytes are in
+        assert_eq!(read_calls.load(std::sync::atomic::Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_close_gracefully_stops_at_peer_close() {
+        let reads = vec![
+            client_frame(Frame::text("late data")),
+            client_frame(Frame::close(Some(1000), None)),
+            client_frame(Frame::text("never read")),
+        ];
+        let stream = ScriptedStream::new(reads);
+        let read_calls = stream.read_calls.clone();
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(stream));
+
+        conn.close_gracefully(Some(1000), None).await.unwrap();
+
+        assert!(conn.is_closed());
+        assert_eq!(read_calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    /// Transport stream whose reads, flush and close never complete
+    fn wedged_stream() -> ScriptedStream {
+        let mut stream = ScriptedStream::new(vec![]);
+        stream.stall_when_empty = true;
+        stream.wedged = true;
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_close_times_out_on_wedged_stream() {
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut conn = Connection::with_stream(remote, local, Box::new(wedged_stream()));
+        conn.set_close_timeout(Some(Duration::from_millis(50)));
+
+        let started = std::time::Instant::now();
+        let result = conn.close(Some(1000), Some("bye")).await;
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(matches!(
+            result,
+            Err(Error::Timeout(TimeoutError::Write { .. }))
+        ));
+        assert!(conn.is_closed());
+        assert!(conn.send_text("after close").await.is_err());
+    }
 }
@@ -0,0 +1,135 @@
+//! Per-connection handler state
+//!
+//! This module provides a type map for stashing state such as an
+//! authenticated user on a connection, so that code wrapping a handler can
+//! set it and the handler can read it back.
+
+use aerosocket_core::handshake::HandshakeRequest;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// Path and query of the handshake request
+///
+/// Stored in the context of every upgraded connection before the
+/// `authorize` hook runs, so handlers can read query parameters such as a
+/// token without parsing the request target themselves.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestTarget {
+    /// Request path, without the query string, still percent-encoded
+    pub path: String,
+    /// Percent-decoded query parameters; a repeated key keeps its first value
+    pub query: HashMap<String, String>,
+}
+
+impl RequestTarget {
+    /// Take the path and query of a handshake request
+    pub fn from_request(request: &HandshakeRequest) -> Self {
+        Self {
+            path: request.path().to_string(),
+            query: request.query_params(),
+        }
+    }
+
+    /// Get a query parameter
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.query.get(key).map(String::as_str)
+    }
+}
+
+/// Type map holding at most one value per type
+#[derive(Default)]
+pub struct ConnectionContext {
+    values: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl ConnectionContext {
+    /// Create an empty context
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a value, returning the previous value of the same type
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.values
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.downcast().ok())
+            .map(|previous| *previous)
+    }
+
+    /// Get the value of type `T`
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Get the value of type `T` mutably
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.values
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    /// Remove and return the value of type `T`
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.values
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    /// Check whether a value of type `T` is stored
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Number of stored values
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Check whether the context is empty
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Remove every value
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+impl std::fmt::Debug for ConnectionContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionContext")
+            .field("len", &self.values.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Session(u32);
+
+    #[test]
+    fn test_values_are_keyed_by_type() {
+        let mut context = ConnectionContext::new();
+        assert!(context.is_empty());
+
+        assert_eq!(context.insert(Session(1)), None);
+        assert_eq!(context.insert("name".to_string()), None);
+        assert_eq!(context.insert(Session(2)), Some(Session(1)));
+        assert_eq!(context.len(), 2);
+
+        context.get_mut::<Session>().unwrap().0 += 1;
+        assert_eq!(context.get::<Session>(), Some(&Session(3)));
+        assert_eq!(context.get::<String>().map(String::as_str), Some("name"));
+        assert!(!context.contains::<u64>());
+
+        assert_eq!(context.remove::<Session>(), Some(Session(3)));
+        assert!(!context.contains::<Session>());
+    }
+}
@@ -293,7 +293,25 @@ pub enum ManagerError {
 
 impl From<ServerError> for Error {
     fn from(err: ServerError) -> Self {
-        Error::Other(err.to_string())
+        match err {
+            ServerError::Protocol(err) => Error::Protocol(err.into()),
+            err => Error::Other(err.to_string()),
+        }
+    }
+}
+
+impl From<ProtocolError> for aerosocket_core::error::ProtocolError {
+    fn from(err: ProtocolError) -> Self {
+        use aerosocket_core::error::ProtocolError as Core;
+        match err {
+            ProtocolError::InvalidOpcode { opcode } => Core::ReservedOpcode(opcode),
+            ProtocolError::InvalidFrame { reason } => Core::InvalidFrame(reason),
+            ProtocolError::FragmentedControlFrame => Core::FragmentedControlFrame,
+            ProtocolError::InvalidContinuation => Core::InvalidContinuation,
+            ProtocolError::MaskingRequired => Core::MaskingRequired,
+            ProtocolError::ReservedBitsSet => Core::ReservedBitsSet,
+            err => Core::InvalidFrame(err.to_string()),
+        }
     }
 }
 
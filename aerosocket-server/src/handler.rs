@@ -18,6 +18,15 @@ pub trait Handler: Send + Sync + 'static {
         connection: crate::connection::ConnectionHandle,
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
 
+    /// Pick the handler for a connection upgraded on `path`
+    ///
+    /// Called during the handshake; `None` refuses the upgrade with 404.
+    /// Handlers serve every path by default, while a
+    /// [`Router`](crate::router::Router) dispatches by path.
+    fn route(&self, path: &str) -> Option<BoxedHandler> {
+        Some(self.clone_box())
+    }
+
     /// Clone the handler
     fn clone_box(&self) -> Box<dyn Handler>;
 }
@@ -360,4 +369,68 @@ mod tests {
         // Note: This test will fail until Connection::next and send are implemented
         // For now, we just test that the handler can be created
     }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct User {
+        name: String,
+    }
+
+    /// Authenticates the connection, then hands it to the inner handler
+    #[derive(Clone)]
+    struct AuthMiddleware {
+        inner: BoxedHandler,
+    }
+
+    impl Handler for AuthMiddleware {
+        fn handle<'a>(
+            &'a self,
+            connection: crate::connection::ConnectionHandle,
+        ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+            Box::pin(async move {
+                connection
+                    .insert(User {
+                        name: "alice".to_string(),
+                    })
+                    .await;
+                self.inner.handle(connection).await
+            })
+        }
+
+        fn clone_box(&self) -> Box<dyn Handler> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware_context_reaches_handler() {
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(None));
+        let handler_seen = seen.clone();
+        let handler = AuthMiddleware {
+            inner: Box::new(from_fn(
+                move |conn: crate::connection::ConnectionHandle| -> Pin<
+                    Box<dyn Future<Output = Result<()>> + Send>,
+                > {
+                    let seen = handler_seen.clone();
+                    Box::pin(async move {
+                        let conn = conn.try_lock().await?;
+                        *seen.lock().unwrap() = conn.get::<User>().cloned();
+                        Ok(())
+                    })
+                },
+            )),
+        };
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let connection = crate::connection::Connection::new(remote, local);
+        let handle = crate::connection::ConnectionHandle::new(1, connection);
+
+        handler.handle(handle.clone()).await.unwrap();
+
+        let expected = User {
+            name: "alice".to_string(),
+        };
+        assert_eq!(*seen.lock().unwrap(), Some(expected.clone()));
+        assert_eq!(handle.get::<User>().await, Some(expected));
+    }
 }
@@ -34,14 +34,20 @@
 #![doc(html_root_url = "https://docs.rs/aerosocket-server/")]
 
 // Public modules
+pub mod clock;
 pub mod config;
 pub mod connection;
+pub mod context;
 pub mod error;
 pub mod handler;
 pub mod logging;
 pub mod manager;
+pub mod middleware;
+pub mod pool;
 pub mod rate_limit;
+pub mod router;
 pub mod server;
+pub mod stats;
 pub mod tcp_transport;
 pub mod tls_transport;
 
@@ -49,12 +55,27 @@ pub mod tls_transport;
 pub mod prelude;
 
 // Re-export key types for convenience
-pub use config::{BackpressureConfig, CompressionConfig, ServerConfig, TlsConfig};
-pub use connection::{Connection, ConnectionHandle, ConnectionMetadata, ConnectionState};
+pub use config::{
+    AuthHook, BackpressureConfig, CompressionConfig, HandshakeHook, LoadShedHook, RawHandshakeHook,
+    ServerConfig, TcpTransportConfig, TlsConfig,
+};
+#[cfg(feature = "diagnostics")]
+pub use connection::ConnectionDump;
+pub use connection::{
+    CloseInitiator, Connection, ConnectionHandle, ConnectionMetadata, ConnectionState,
+    ConnectionWriter,
+};
+pub use context::{ConnectionContext, RequestTarget};
 pub use error::{
     ConfigError, ConnectionError, ContextError, ContextResult, ErrorContext, HandlerError,
     HandshakeError, ManagerError, ProtocolError, ServerError, TransportError,
 };
 pub use handler::{BoxedHandler, DefaultHandler, EchoHandler, Handler};
-pub use manager::{CloseReason, ConnectionHealth, ConnectionManager, ManagerStats};
-pub use server::{Server, ServerBuilder};
+pub use manager::{
+    CloseReason, ConnectionHealth, ConnectionManager, HealthCheckReport, ManagerStats,
+};
+pub use middleware::{LoggingMiddleware, Middleware, Next};
+pub use pool::{BufferPool, BufferPoolStats};
+pub use router::Router;
+pub use server::{Server, ServerBuilder, ShutdownSummary};
+pub use stats::{ServerStats, StatsHandle};
@@ -2,14 +2,15 @@
 //!
 //! This module provides connection management, monitoring, and cleanup functionality.
 
+use crate::clock::{self, SharedClock};
 use crate::config::ServerConfig;
-use crate::connection::{Connection, ConnectionHandle};
-use aerosocket_core::Result;
+use crate::connection::{CloseInitiator, Connection, ConnectionHandle};
+use crate::stats::StatsHandle;
+use aerosocket_core::{Error, Message, Result};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::{mpsc, Mutex};
-use tokio::time::interval;
+use tokio::sync::{mpsc, watch, Mutex, Notify};
 
 /// Connection manager statistics
 #[derive(Debug, Clone)]
@@ -24,10 +25,22 @@ pub struct ManagerStats {
     pub error_closures: u64,
     /// Number of connections closed normally
     pub normal_closures: u64,
-    /// Current memory usage in bytes
+    /// Number of closures started by this server (including idle timeouts)
+    pub local_closures: u64,
+    /// Number of closures started by the peer
+    pub peer_closures: u64,
+    /// Estimated memory held by active connections, in bytes
     pub memory_usage: u64,
     /// Peak number of concurrent connections
     pub peak_connections: usize,
+    /// Messages sent across all connections since server start
+    pub messages_sent: u64,
+    /// Messages received across all connections since server start
+    pub messages_received: u64,
+    /// Bytes sent across all connections since server start
+    pub bytes_sent: u64,
+    /// Payload bytes received across all connections since server start
+    pub bytes_received: u64,
 }
 
 impl Default for ManagerStats {
@@ -38,44 +51,172 @@ impl Default for ManagerStats {
             timeout_closures: 0,
             error_closures: 0,
             normal_closures: 0,
+            local_closures: 0,
+            peer_closures: 0,
             memory_usage: 0,
             peak_connections: 0,
+            messages_sent: 0,
+            messages_received: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+        }
+    }
+}
+
+/// Connection table and counters, shared with the cleanup task
+#[derive(Debug, Clone)]
+struct Registry {
+    /// Active connections by ID
+    connections: Arc<Mutex<HashMap<u64, ConnectionHandle>>>,
+    /// Connection statistics
+    stats: Arc<Mutex<ManagerStats>>,
+    /// Traffic counters shared with every added connection
+    traffic: StatsHandle,
+    /// Woken whenever a connection is removed
+    removed: Arc<Notify>,
+}
+
+impl Registry {
+    /// Remove a connection, attributing the closure to whichever side
+    /// started the closing handshake
+    async fn remove(&self, id: u64, reason: CloseReason) -> Option<ConnectionHandle> {
+        let mut connections_map = self.connections.lock().await;
+        let handle = connections_map.remove(&id)?;
+        let initiator = match reason {
+            CloseReason::Timeout => Some(CloseInitiator::Local),
+            _ => match handle.try_lock().await {
+                Ok(connection) => connection.close_initiator(),
+                Err(_) => None,
+            },
+        };
+
+        let mut stats = self.stats.lock().await;
+        stats.active_connections = connections_map.len();
+
+        match reason {
+            CloseReason::Timeout => stats.timeout_closures += 1,
+            CloseReason::Error => stats.error_closures += 1,
+            CloseReason::Normal => stats.normal_closures += 1,
+        }
+        match initiator {
+            Some(CloseInitiator::Local) => stats.local_closures += 1,
+            Some(CloseInitiator::Peer) => stats.peer_closures += 1,
+            None => {}
+        }
+        drop(stats);
+        drop(connections_map);
+
+        self.traffic.connection_closed();
+        self.removed.notify_waiters();
+        Some(handle)
+    }
+
+    /// Close and remove connections idle past their timeout
+    ///
+    /// Connections locked by their handler are skipped; a handler waiting in
+    /// `next()` hits the idle timeout itself.
+    async fn cleanup_idle(&self) {
+        let handles: Vec<_> = self.connections.lock().await.values().cloned().collect();
+
+        for handle in handles {
+            let Ok(mut connection) = handle.try_lock().await else {
+                continue;
+            };
+            if connection.is_timed_out() {
+                let _ = connection.close(Some(1001), Some("Idle timeout")).await;
+                drop(connection);
+                self.remove(handle.id(), CloseReason::Timeout).await;
+            }
         }
     }
+
+    /// Close and remove every connection
+    async fn close_all(&self) {
+        let connections = self.connections.lock().await;
+        let handles: Vec<_> = connections.values().cloned().collect();
+        drop(connections);
+
+        let mut local_closures = 0;
+        let mut peer_closures = 0;
+        for handle in &handles {
+            if let Ok(mut connection) = handle.try_lock().await {
+                let _ = connection.close(Some(1000), Some("Server shutdown")).await;
+                match connection.close_initiator() {
+                    Some(CloseInitiator::Local) => local_closures += 1,
+                    Some(CloseInitiator::Peer) => peer_closures += 1,
+                    None => {}
+                }
+            }
+        }
+
+        // Clear all connections and update stats
+        let mut connections_map = self.connections.lock().await;
+        let connection_count = connections_map.len();
+        connections_map.clear();
+        for _ in 0..connection_count {
+            self.traffic.connection_closed();
+        }
+
+        // Update statistics
+        let mut stats = self.stats.lock().await;
+        stats.active_connections = 0;
+        stats.normal_closures += connection_count as u64;
+        stats.local_closures += local_closures;
+        stats.peer_closures += peer_closures;
+        drop(stats);
+        drop(connections_map);
+
+        self.removed.notify_waiters();
+    }
 }
 
 /// Connection manager
+///
+/// Tracks the connections of a running server: the server adds each one
+/// once its handshake completes and removes it when its handler returns.
+/// [`Server::connection_manager`](crate::Server::connection_manager) gives
+/// access to a live server's manager.
 #[derive(Debug)]
 pub struct ConnectionManager {
     /// Server configuration
     config: ServerConfig,
-    /// Active connections by ID
-    connections: Arc<Mutex<HashMap<u64, ConnectionHandle>>>,
-    /// Connection statistics
-    stats: Arc<Mutex<ManagerStats>>,
+    /// Connection table and counters
+    registry: Registry,
     /// Next connection ID
     next_id: Arc<Mutex<u64>>,
     /// Cleanup interval
     cleanup_interval: Duration,
+    /// Time source handed to connections and used by the cleanup task
+    clock: SharedClock,
     /// Sender for cleanup notifications
     cleanup_tx: mpsc::Sender<u64>,
     /// Receiver for cleanup notifications
     cleanup_rx: Arc<Mutex<mpsc::Receiver<u64>>>,
+    /// Set once shutdown stops waiting, cancelling the remaining handlers;
+    /// dropping it stops the cleanup task
+    force_close: watch::Sender<bool>,
 }
 
 impl ConnectionManager {
     /// Create a new connection manager
     pub fn new(config: ServerConfig) -> Self {
         let (cleanup_tx, cleanup_rx) = mpsc::channel(1000);
+        let traffic = StatsHandle::new(Connection::footprint(config.read_buffer_size));
 
         Self {
             cleanup_interval: Duration::from_secs(30), // Default cleanup interval
             config,
-            connections: Arc::new(Mutex::new(HashMap::new())),
-            stats: Arc::new(Mutex::new(ManagerStats::default())),
+            registry: Registry {
+                connections: Arc::new(Mutex::new(HashMap::new())),
+                stats: Arc::new(Mutex::new(ManagerStats::default())),
+                traffic,
+                removed: Arc::new(Notify::new()),
+            },
             next_id: Arc::new(Mutex::new(1)),
+            clock: clock::default_clock(),
             cleanup_tx,
             cleanup_rx: Arc::new(Mutex::new(cleanup_rx)),
+            force_close: watch::Sender::new(false),
         }
     }
 
@@ -84,19 +225,30 @@ impl ConnectionManager {
         self.cleanup_interval = interval;
     }
 
+    /// Set the time source used for connections added from now on and for
+    /// health checks
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+
     /// Add a new connection
-    pub async fn add_connection(&self, connection: Connection) -> Result<ConnectionHandle> {
+    pub async fn add_connection(&self, mut connection: Connection) -> Result<ConnectionHandle> {
+        connection.set_clock(self.clock.clone());
+        connection.set_stats(Some(self.registry.traffic.clone()));
+
         let mut next_id = self.next_id.lock().await;
         let id = *next_id;
         *next_id += 1;
+        drop(next_id);
 
         let handle = ConnectionHandle::new(id, connection);
 
-        let mut connections = self.connections.lock().await;
+        let mut connections = self.registry.connections.lock().await;
         connections.insert(id, handle.clone());
+        self.registry.traffic.connection_opened();
 
         // Update statistics
-        let mut stats = self.stats.lock().await;
+        let mut stats = self.registry.stats.lock().await;
         stats.active_connections = connections.len();
         stats.total_connections += 1;
         stats.peak_connections = stats.peak_connections.max(stats.active_connections);
@@ -105,128 +257,166 @@ impl ConnectionManager {
     }
 
     /// Remove a connection
+    ///
+    /// The closure is also attributed to whichever side started the closing
+    /// handshake, as recorded on the connection.
     pub async fn remove_connection(&self, id: u64, reason: CloseReason) {
-        let mut connections = self.connections.lock().await;
-        if connections.remove(&id).is_some() {
-            // Update statistics
-            let mut stats = self.stats.lock().await;
-            stats.active_connections = connections.len();
-
-            match reason {
-                CloseReason::Timeout => stats.timeout_closures += 1,
-                CloseReason::Error => stats.error_closures += 1,
-                CloseReason::Normal => stats.normal_closures += 1,
-            }
-        }
+        self.registry.remove(id, reason).await;
     }
 
     /// Get connection by ID
     pub async fn get_connection(&self, id: u64) -> Option<ConnectionHandle> {
-        let connections = self.connections.lock().await;
+        let connections = self.registry.connections.lock().await;
         connections.get(&id).cloned()
     }
 
     /// Get all active connections
     pub async fn get_all_connections(&self) -> Vec<ConnectionHandle> {
-        let connections = self.connections.lock().await;
+        let connections = self.registry.connections.lock().await;
         connections.values().cloned().collect()
     }
 
+    /// Send a message to every connection that is not closing
+    ///
+    /// Each send goes through [`ConnectionHandle::send`], so a connection
+    /// whose handler is waiting in `next()` is only reached once it has been
+    /// split. The connections map is not locked while sending, and a failed
+    /// send does not stop the others; failures are returned by connection ID.
+    pub async fn broadcast(&self, message: Message) -> Vec<(u64, Error)> {
+        self.broadcast_skipping(message, None).await
+    }
+
+    /// [`broadcast`](Self::broadcast) to every connection but `except_id`
+    pub async fn broadcast_except(&self, message: Message, except_id: u64) -> Vec<(u64, Error)> {
+        self.broadcast_skipping(message, Some(except_id)).await
+    }
+
+    async fn broadcast_skipping(&self, message: Message, skip: Option<u64>) -> Vec<(u64, Error)> {
+        let handles: Vec<_> = self
+            .registry
+            .connections
+            .lock()
+            .await
+            .values()
+            .cloned()
+            .collect();
+
+        let mut failures = Vec::new();
+        for handle in handles {
+            if handle.is_closing() || skip == Some(handle.id()) {
+                continue;
+            }
+            if let Err(err) = handle.send(message.clone()).await {
+                failures.push((handle.id(), err));
+            }
+        }
+
+        failures
+    }
+
     /// Get current connection count
     pub async fn connection_count(&self) -> usize {
-        let connections = self.connections.lock().await;
+        let connections = self.registry.connections.lock().await;
         connections.len()
     }
 
     /// Get connection manager statistics
+    ///
+    /// Traffic totals come from counters the connections update as they
+    /// send and receive, so no connection lock is taken.
     pub async fn get_stats(&self) -> ManagerStats {
-        let stats = self.stats.lock().await;
+        let stats = self.registry.stats.lock().await;
+        let traffic = self.registry.traffic.snapshot();
         ManagerStats {
             active_connections: stats.active_connections,
             total_connections: stats.total_connections,
             timeout_closures: stats.timeout_closures,
             error_closures: stats.error_closures,
             normal_closures: stats.normal_closures,
-            memory_usage: stats.memory_usage,
+            local_closures: stats.local_closures,
+            peer_closures: stats.peer_closures,
+            memory_usage: self.registry.traffic.memory_for(stats.active_connections),
             peak_connections: stats.peak_connections,
+            messages_sent: traffic.messages_sent,
+            messages_received: traffic.messages_received,
+            bytes_sent: traffic.bytes_sent,
+            bytes_received: traffic.bytes_received,
         }
     }
 
+    /// Handle to the connection and traffic counters
+    ///
+    /// Reads without locking; see [`StatsHandle`].
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.registry.traffic.clone()
+    }
+
     /// Start the cleanup task
+    ///
+    /// Every cleanup interval, connections idle past their timeout are
+    /// closed with 1001 and removed. Sweeps keep to that schedule however
+    /// many removal requests arrive in between. The task ends when the
+    /// manager is dropped.
     pub async fn start_cleanup_task(&self) {
-        let connections = self.connections.clone();
-        let stats = self.stats.clone();
+        let registry = self.registry.clone();
         let cleanup_rx = self.cleanup_rx.clone();
         let cleanup_interval = self.cleanup_interval;
-        let idle_timeout = self.config.idle_timeout;
+        let mut dropped = self.force_close.subscribe();
 
         tokio::spawn(async move {
-            let mut cleanup_interval_timer = interval(cleanup_interval);
             let mut cleanup_receiver = cleanup_rx.lock().await;
+            let mut ticks = tokio::time::interval_at(
+                tokio::time::Instant::now() + cleanup_interval,
+                cleanup_interval,
+            );
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
             loop {
                 tokio::select! {
-                    _ = cleanup_interval_timer.tick() => {
+                    _ = ticks.tick() => {
                         // Periodic cleanup
-                        Self::cleanup_idle_connections(&connections, &stats, idle_timeout).await;
+                        registry.cleanup_idle().await;
                     }
                     Some(id) = cleanup_receiver.recv() => {
                         // Immediate cleanup for specific connection
-                        Self::remove_connection_internal(&connections, &stats, id, CloseReason::Timeout).await;
+                        registry.remove(id, CloseReason::Timeout).await;
                     }
+                    Err(_) = dropped.changed() => break,
                 }
             }
         });
     }
 
-    /// Cleanup idle connections
-    async fn cleanup_idle_connections(
-        connections: &Arc<Mutex<HashMap<u64, ConnectionHandle>>>,
-        stats: &Arc<Mutex<ManagerStats>>,
-        idle_timeout: Duration,
-    ) {
-        let mut connections_map = connections.lock().await;
-        let mut to_remove = Vec::new();
-
-        for (id, handle) in connections_map.iter() {
-            if let Ok(connection) = handle.try_lock().await {
-                if connection.is_timed_out() {
-                    to_remove.push(*id);
-                }
+    /// Wait until every connection is removed or `deadline` passes
+    ///
+    /// Returns whether the manager emptied in time.
+    pub(crate) async fn wait_until_empty(&self, deadline: tokio::time::Instant) -> bool {
+        loop {
+            // Created before the check so a removal in between is not missed
+            let removed = self.registry.removed.notified();
+            if self.connection_count().await == 0 {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, removed).await.is_err() {
+                return self.connection_count().await == 0;
             }
         }
+    }
 
-        for id in to_remove {
-            connections_map.remove(&id);
-            let mut stats = stats.lock().await;
-            stats.active_connections = connections_map.len();
-            stats.timeout_closures += 1;
-        }
+    /// Cancel the handlers of all remaining connections
+    pub(crate) fn force_close(&self) {
+        self.force_close.send_replace(true);
     }
 
-    /// Internal connection removal
-    async fn remove_connection_internal(
-        connections: &Arc<Mutex<HashMap<u64, ConnectionHandle>>>,
-        stats: &Arc<Mutex<ManagerStats>>,
-        id: u64,
-        reason: CloseReason,
-    ) {
-        let mut connections_map = connections.lock().await;
-        if connections_map.remove(&id).is_some() {
-            let mut stats = stats.lock().await;
-            stats.active_connections = connections_map.len();
-
-            match reason {
-                CloseReason::Timeout => stats.timeout_closures += 1,
-                CloseReason::Error => stats.error_closures += 1,
-                CloseReason::Normal => stats.normal_closures += 1,
-            }
-        }
+    /// Complete once shutdown forces the remaining connections closed
+    pub(crate) async fn forced(&self) {
+        let mut force_close = self.force_close.subscribe();
+        let _ = force_close.wait_for(|forced| *forced).await;
     }
 
     /// Monitor connection health
     pub async fn monitor_connections(&self) -> Result<Vec<ConnectionHealth>> {
-        let connections = self.connections.lock().await;
+        let connections = self.registry.connections.lock().await;
         let mut health_reports = Vec::new();
 
         for (id, handle) in connections.iter() {
@@ -235,8 +425,8 @@ impl ConnectionManager {
                     id: *id,
                     remote_addr: connection.remote_addr(),
                     state: connection.state(),
-                    uptime: connection.metadata().established_at.elapsed(),
-                    last_activity: connection.metadata().last_activity_at.elapsed(),
+                    uptime: connection.age(),
+                    last_activity: connection.idle_time(),
                     messages_sent: connection.metadata().messages_sent,
                     messages_received: connection.metadata().messages_received,
                     bytes_sent: connection.metadata().bytes_sent,
@@ -250,46 +440,144 @@ impl ConnectionManager {
         Ok(health_reports)
     }
 
-    /// Close all connections
-    pub async fn close_all_connections(&self) {
-        let connections = self.connections.lock().await;
-        let handles: Vec<_> = connections.values().cloned().collect();
-        let connection_count = connections.len();
-        drop(connections);
-
+    /// Close connections that have been open for longer than `max_age`
+    ///
+    /// Enforces a hard maximum lifetime on top of idle-timeout cleanup. Closed
+    /// connections are removed from the manager and counted as normal closures.
+    /// Connections currently locked by their handler are skipped. Returns the
+    /// number of connections closed.
+    pub async fn close_older_than(
+        &self,
+        max_age: Duration,
+        code: Option<u16>,
+        reason: Option<&str>,
+    ) -> usize {
+        let handles: Vec<_> = self
+            .registry
+            .connections
+            .lock()
+            .await
+            .values()
+            .cloned()
+            .collect();
+
+        let mut closed = Vec::new();
         for handle in handles {
             if let Ok(mut connection) = handle.try_lock().await {
-                let _ = connection.close(Some(1000), Some("Server shutdown")).await;
+                if connection.age() > max_age {
+                    let _ = connection.close(code, reason).await;
+                    closed.push(handle.id());
+                }
             }
         }
 
-        // Clear all connections and update stats
-        let mut connections_map = self.connections.lock().await;
-        connections_map.clear();
+        for id in &closed {
+            self.registry.remove(*id, CloseReason::Normal).await;
+        }
 
-        // Update statistics
-        let mut stats = self.stats.lock().await;
-        stats.active_connections = 0;
-        stats.normal_closures += connection_count as u64;
+        closed.len()
+    }
+
+    /// Ping every connection and close those that do not pong within `timeout`
+    ///
+    /// A server-wide liveness sweep, separate from per-connection keepalive.
+    /// Pongs are counted as the connection's handler reads from it, so a
+    /// connection counts as responsive if a pong arrived after the ping was
+    /// sent. Pings and the closing 1001 go through the connection's writer
+    /// when it has been split (see [`ConnectionHandle::writer`]), so a
+    /// handler waiting in `next()` does not hide its connection from the
+    /// check. Unresponsive connections are removed from the manager and
+    /// counted as timeout closures. Unsplit connections whose handler holds
+    /// the lock cannot be pinged and are reported as skipped.
+    pub async fn health_check(&self, timeout: Duration) -> HealthCheckReport {
+        let handles: Vec<_> = self
+            .registry
+            .connections
+            .lock()
+            .await
+            .values()
+            .cloned()
+            .collect();
+        let mut report = HealthCheckReport::default();
+
+        let mut pinged = Vec::new();
+        for handle in handles {
+            let pongs = handle.pongs_received();
+            match handle.send(Message::ping(None)).await {
+                Ok(()) => pinged.push((handle, pongs)),
+                Err(_) => report.skipped.push(handle.id()),
+            }
+        }
+
+        self.clock.sleep(timeout).await;
+
+        for (handle, pongs) in pinged {
+            if handle.pongs_received() > pongs {
+                report.responsive.push(handle.id());
+                continue;
+            }
+            let _ = handle.close(Some(1001), Some("Ping timeout")).await;
+            report.unresponsive.push(handle.id());
+        }
+
+        for id in &report.unresponsive {
+            self.registry.remove(*id, CloseReason::Timeout).await;
+        }
+
+        report
+    }
+
+    /// Close all connections
+    pub async fn close_all_connections(&self) {
+        self.registry.close_all().await;
+    }
+
+    /// Broadcast binary message to all connections
+    ///
+    /// Sends through [`broadcast`](Self::broadcast) and fails with the first
+    /// failed send once every connection has been tried.
+    pub async fn broadcast_binary_to_all(&self, data: &[u8]) -> Result<()> {
+        first_failure(self.broadcast(Message::binary(data.to_vec())).await)
+    }
+
+    /// Broadcast text message to all connections
+    ///
+    /// Fails like [`broadcast_binary_to_all`](Self::broadcast_binary_to_all).
+    pub async fn broadcast_text_to_all(&self, text: &str) -> Result<()> {
+        first_failure(self.broadcast(Message::text(text)).await)
+    }
+
+    /// Broadcast binary message to all connections except the specified one
+    ///
+    /// Fails like [`broadcast_binary_to_all`](Self::broadcast_binary_to_all).
+    pub async fn broadcast_binary_except(&self, data: &[u8], except_id: u64) -> Result<()> {
+        let message = Message::binary(data.to_vec());
+        first_failure(self.broadcast_except(message, except_id).await)
+    }
+
+    /// Broadcast text message to all connections except the specified one
+    ///
+    /// Fails like [`broadcast_binary_to_all`](Self::broadcast_binary_to_all).
+    pub async fn broadcast_text_except(&self, text: &str, except_id: u64) -> Result<()> {
+        first_failure(self.broadcast_except(Message::text(text), except_id).await)
+    }
+}
+
+/// Turn broadcast failures into the first error, if any
+fn first_failure(failures: Vec<(u64, Error)>) -> Result<()> {
+    match failures.into_iter().next() {
+        Some((_, err)) => Err(err),
+        None => Ok(()),
     }
 }
 
 impl Drop for ConnectionManager {
     fn drop(&mut self) {
         // Ensure all connections are closed when manager is dropped
-        let connections = self.connections.clone();
-        tokio::spawn(async move {
-            let manager = ConnectionManager {
-                config: ServerConfig::default(),
-                connections,
-                stats: Arc::new(Mutex::new(ManagerStats::default())),
-                next_id: Arc::new(Mutex::new(0)),
-                cleanup_interval: Duration::ZERO,
-                cleanup_tx: mpsc::channel(1).0,
-                cleanup_rx: Arc::new(Mutex::new(mpsc::channel(1).1)),
-            };
-            manager.close_all_connections().await;
-        });
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let registry = self.registry.clone();
+            runtime.spawn(async move { registry.close_all().await });
+        }
     }
 }
 
@@ -304,6 +592,17 @@ pub enum CloseReason {
     Normal,
 }
 
+/// Outcome of a [`ConnectionManager::health_check`] sweep
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthCheckReport {
+    /// Connections that answered the ping in time
+    pub responsive: Vec<u64>,
+    /// Connections that did not answer and were closed
+    pub unresponsive: Vec<u64>,
+    /// Connections that were busy and could not be checked
+    pub skipped: Vec<u64>,
+}
+
 /// Connection health information
 #[derive(Debug, Clone)]
 pub struct ConnectionHealth {
@@ -328,3 +627,371 @@ pub struct ConnectionHealth {
     /// Time until connection times out
     pub time_until_timeout: Option<Duration>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use aerosocket_core::protocol::Opcode;
+    use aerosocket_core::transport::mock::ScriptedStream;
+    use aerosocket_core::Frame;
+    use bytes::BytesMut;
+
+    fn connection_reading(reads: Vec<Vec<u8>>) -> Connection {
+        recorded_connection(reads, Default::default())
+    }
+
+    fn recorded_connection(
+        reads: Vec<Vec<u8>>,
+        written: Arc<std::sync::Mutex<Vec<u8>>>,
+    ) -> Connection {
+        let mut stream = ScriptedStream::new(reads);
+        stream.written = written;
+        stream.splittable = true;
+        Connection::with_stream(
+            "127.0.0.1:12345".parse().unwrap(),
+            "127.0.0.1:8080".parse().unwrap(),
+            Box::new(stream),
+        )
+    }
+
+    fn written_opcodes(written: &std::sync::Mutex<Vec<u8>>) -> Vec<Opcode> {
+        let mut buf = BytesMut::from(&written.lock().unwrap()[..]);
+        std::iter::from_fn(|| Frame::parse(&mut buf, false, usize::MAX).ok())
+            .map(|frame| frame.opcode)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_closures_are_attributed_to_initiator() {
+        let manager = ConnectionManager::new(ServerConfig::default());
+
+        let local = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+        let peer_close = Frame::close(Some(1001), Some("going away"))
+            .mask(true)
+            .to_bytes()
+            .to_vec();
+        let peer = manager
+            .add_connection(connection_reading(vec![peer_close]))
+            .await
+            .unwrap();
+
+        {
+            let mut connection = local.try_lock().await.unwrap();
+            connection.close(Some(1000), Some("bye")).await.unwrap();
+            assert_eq!(connection.close_initiator(), Some(CloseInitiator::Local));
+            assert_eq!(connection.close_code(), Some(1000));
+        }
+        {
+            let mut connection = peer.try_lock().await.unwrap();
+            let _ = connection.next().await;
+            assert_eq!(connection.close_initiator(), Some(CloseInitiator::Peer));
+            assert_eq!(connection.close_code(), Some(1001));
+        }
+
+        manager
+            .remove_connection(local.id(), CloseReason::Normal)
+            .await;
+        manager
+            .remove_connection(peer.id(), CloseReason::Normal)
+            .await;
+
+        let stats = manager.get_stats().await;
+        assert_eq!(stats.normal_closures, 2);
+        assert_eq!(stats.local_closures, 1);
+        assert_eq!(stats.peer_closures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stats_aggregate_connection_traffic() {
+        let manager = ConnectionManager::new(ServerConfig::default());
+        let frame = Frame::text("ping").mask(true).to_bytes().to_vec();
+        let handle = manager
+            .add_connection(connection_reading(vec![frame]))
+            .await
+            .unwrap();
+
+        {
+            let mut connection = handle.try_lock().await.unwrap();
+            connection.next().await.unwrap();
+            connection.send_text("pong").await.unwrap();
+        }
+
+        let stats = manager.get_stats().await;
+        assert_eq!(stats.messages_received, 1);
+        assert_eq!(stats.bytes_received, 4);
+        assert_eq!(stats.messages_sent, 1);
+        assert_eq!(stats.bytes_sent, 6);
+        assert!(stats.memory_usage > 0);
+
+        manager
+            .remove_connection(handle.id(), CloseReason::Normal)
+            .await;
+        let stats = manager.get_stats().await;
+        assert_eq!(stats.memory_usage, 0);
+        assert_eq!(stats.bytes_received, 4);
+    }
+
+    #[tokio::test]
+    async fn test_close_older_than_only_closes_old_connections() {
+        let clock = MockClock::new();
+        let mut manager = ConnectionManager::new(ServerConfig::default());
+        manager.set_clock(Arc::new(clock.clone()));
+
+        let oldest = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+        clock.advance(Duration::from_secs(60));
+        let older = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+        clock.advance(Duration::from_secs(30));
+        let young = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+        clock.advance(Duration::from_secs(5));
+
+        let closed = manager
+            .close_older_than(Duration::from_secs(20), Some(1001), Some("max lifetime"))
+            .await;
+        assert_eq!(closed, 2);
+        assert_eq!(manager.connection_count().await, 1);
+        assert!(manager.get_connection(young.id()).await.is_some());
+
+        for handle in [oldest, older] {
+            let connection = handle.try_lock().await.unwrap();
+            assert_eq!(connection.close_initiator(), Some(CloseInitiator::Local));
+            assert_eq!(connection.close_code(), Some(1001));
+        }
+        assert_eq!(young.try_lock().await.unwrap().close_initiator(), None);
+
+        let stats = manager.get_stats().await;
+        assert_eq!(stats.normal_closures, 2);
+        assert_eq!(stats.local_closures, 2);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_removes_idle_connections_on_mock_clock() {
+        let clock = MockClock::new();
+        let mut manager = ConnectionManager::new(ServerConfig::default());
+        manager.set_clock(Arc::new(clock.clone()));
+
+        let remote = "127.0.0.1:12345".parse().unwrap();
+        let local = "127.0.0.1:8080".parse().unwrap();
+        let mut connection = Connection::new(remote, local);
+        connection.set_idle_timeout(Some(Duration::from_secs(30)));
+        manager.add_connection(connection).await.unwrap();
+
+        clock.advance(Duration::from_secs(29));
+        manager.registry.cleanup_idle().await;
+        assert_eq!(manager.connection_count().await, 1);
+
+        clock.advance(Duration::from_secs(2));
+        manager.registry.cleanup_idle().await;
+        assert_eq!(manager.connection_count().await, 0);
+        assert_eq!(manager.get_stats().await.timeout_closures, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cleanup_task_closes_idle_connections() {
+        let mut manager = ConnectionManager::new(ServerConfig::default());
+        manager.set_cleanup_interval(Duration::from_secs(10));
+
+        let mut connection = connection_reading(vec![]);
+        connection.set_idle_timeout(Some(Duration::from_secs(30)));
+        let handle = manager.add_connection(connection).await.unwrap();
+        manager.start_cleanup_task().await;
+
+        // Let time pass until a sweep finds the connection idle
+        for _ in 0..60 {
+            if manager.connection_count().await == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        assert_eq!(manager.connection_count().await, 0);
+        assert_eq!(manager.stats_handle().snapshot().active_connections, 0);
+
+        let connection = handle.try_lock().await.unwrap();
+        assert_eq!(connection.close_code(), Some(1001));
+        assert_eq!(manager.get_stats().await.timeout_closures, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cleanup_sweeps_keep_schedule_between_removal_requests() {
+        let mut manager = ConnectionManager::new(ServerConfig::default());
+        manager.set_cleanup_interval(Duration::from_secs(10));
+
+        let mut connection = connection_reading(vec![]);
+        connection.set_idle_timeout(Some(Duration::from_secs(30)));
+        manager.add_connection(connection).await.unwrap();
+        manager.start_cleanup_task().await;
+
+        // Removal requests arrive more often than the sweep interval
+        for _ in 0..20 {
+            manager.cleanup_tx.send(u64::MAX).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(3)).await;
+        }
+        assert_eq!(manager.connection_count().await, 0);
+        assert_eq!(manager.get_stats().await.timeout_closures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_skips_closing_and_collects_failures() {
+        let manager = ConnectionManager::new(ServerConfig::default());
+        let ready = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+        let closed = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+        let busy = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+
+        let sent_before_close = {
+            let mut connection = closed.try_lock().await.unwrap();
+            let _ = connection.close(None, None).await;
+            connection.metadata().messages_sent
+        };
+        let guard = busy.try_lock().await.unwrap();
+
+        let failures = manager.broadcast(Message::text("hello")).await;
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, busy.id());
+        drop(guard);
+
+        let sent = |handle: ConnectionHandle| async move {
+            handle.try_lock().await.unwrap().metadata().messages_sent
+        };
+        assert_eq!(sent(ready).await, 1);
+        assert_eq!(sent(closed).await, sent_before_close);
+        assert_eq!(sent(busy).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_except_skips_busy_closing_connections() {
+        let manager = ConnectionManager::new(ServerConfig::default());
+        let written = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let closing = manager
+            .add_connection(recorded_connection(vec![], written.clone()))
+            .await
+            .unwrap();
+        let sender = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+        let busy = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+
+        // Closed through its writer while the handler holds the lock
+        closing.writer().await.unwrap();
+        let _closing_handler = closing.try_lock().await.unwrap();
+        closing.close(Some(1001), None).await.unwrap();
+        let _busy_handler = busy.try_lock().await.unwrap();
+
+        let result = manager.broadcast_text_except("hello", sender.id()).await;
+        assert!(matches!(result, Err(Error::Other(_))));
+        assert_eq!(written_opcodes(&written), [Opcode::Close]);
+        assert_eq!(sender.try_lock().await.unwrap().metadata().messages_sent, 0);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_closes_only_unresponsive_connections() {
+        let clock = MockClock::new();
+        let mut manager = ConnectionManager::new(ServerConfig::default());
+        manager.set_clock(Arc::new(clock.clone()));
+
+        let pong = Frame::pong(Vec::new()).mask(true).to_bytes().to_vec();
+        let responsive = manager
+            .add_connection(connection_reading(vec![pong]))
+            .await
+            .unwrap();
+        let unresponsive = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+
+        let timeout = Duration::from_secs(5);
+        let (report, ()) = tokio::join!(manager.health_check(timeout), async {
+            // The handler reads the pong while the sweep is waiting
+            let _ = responsive.try_lock().await.unwrap().next().await;
+            clock.advance(timeout);
+        });
+
+        assert_eq!(report.responsive, vec![responsive.id()]);
+        assert_eq!(report.unresponsive, vec![unresponsive.id()]);
+        assert!(report.skipped.is_empty());
+
+        assert_eq!(responsive.try_lock().await.unwrap().close_code(), None);
+        let connection = unresponsive.try_lock().await.unwrap();
+        assert_eq!(connection.close_initiator(), Some(CloseInitiator::Local));
+        assert_eq!(connection.close_code(), Some(1001));
+        drop(connection);
+
+        assert_eq!(manager.connection_count().await, 1);
+        assert!(manager.get_connection(responsive.id()).await.is_some());
+        assert_eq!(manager.get_stats().await.timeout_closures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_pings_split_connections_while_handler_runs() {
+        let clock = MockClock::new();
+        let mut manager = ConnectionManager::new(ServerConfig::default());
+        manager.set_clock(Arc::new(clock.clone()));
+
+        let pong = Frame::pong(Vec::new()).mask(true).to_bytes().to_vec();
+        let responsive = manager
+            .add_connection(connection_reading(vec![pong]))
+            .await
+            .unwrap();
+        let written = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let unresponsive = manager
+            .add_connection(recorded_connection(vec![], written.clone()))
+            .await
+            .unwrap();
+        let unsplit = manager
+            .add_connection(connection_reading(vec![]))
+            .await
+            .unwrap();
+        responsive.writer().await.unwrap();
+        unresponsive.writer().await.unwrap();
+
+        // Every handler holds its connection's lock for the whole sweep
+        let mut responsive_handler = responsive.try_lock().await.unwrap();
+        let unresponsive_handler = unresponsive.try_lock().await.unwrap();
+        let _unsplit_handler = unsplit.try_lock().await.unwrap();
+
+        let timeout = Duration::from_secs(5);
+        let (report, ()) = tokio::join!(manager.health_check(timeout), async {
+            let _ = responsive_handler.next().await;
+            clock.advance(timeout);
+        });
+
+        assert_eq!(report.responsive, vec![responsive.id()]);
+        assert_eq!(report.unresponsive, vec![unresponsive.id()]);
+        assert_eq!(report.skipped, vec![unsplit.id()]);
+        assert_eq!(written_opcodes(&written), [Opcode::Ping, Opcode::Close]);
+        // The handler sees the sweep's Close as its own
+        assert_eq!(
+            unresponsive_handler.close_initiator(),
+            Some(CloseInitiator::Local)
+        );
+        assert_eq!(unresponsive_handler.close_code(), Some(1001));
+
+        assert_eq!(manager.connection_count().await, 2);
+        assert!(manager.get_connection(unresponsive.id()).await.is_none());
+        assert_eq!(manager.get_stats().await.timeout_closures, 1);
+    }
+}
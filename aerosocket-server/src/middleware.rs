@@ -0,0 +1,295 @@
+//! Middleware wrapped around connection handlers
+//!
+//! A [`Middleware`] runs around the handler of every connection: it gets the
+//! connection and a [`Next`] that runs the rest of the chain, so it can act
+//! before and after the handler, skip it entirely, or inspect its result.
+//! Middleware is added with [`ServerBuilder::layer`](crate::ServerBuilder::layer)
+//! and runs in the order it was added, the first layer outermost.
+
+use crate::connection::ConnectionHandle;
+use crate::handler::{BoxedHandler, Handler};
+use aerosocket_core::Result;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Code run around the handler of every connection
+///
+/// An implementation calls [`Next::run`] to continue down the chain and
+/// returns its result, possibly after acting on it. Returning without
+/// calling it short-circuits the chain, so neither later middleware nor the
+/// handler see the connection.
+///
+/// ```rust
+/// # use aerosocket_server::prelude::*;
+/// # use std::future::Future;
+/// # use std::pin::Pin;
+/// struct HideAdmin;
+///
+/// impl Middleware for HideAdmin {
+///     fn around<'a>(
+///         &'a self,
+///         connection: ConnectionHandle,
+///         next: Next<'a>,
+///     ) -> Pin<Box<dyn Future<Output = aerosocket_core::Result<()>> + Send + 'a>> {
+///         Box::pin(async move {
+///             let path = connection.try_lock().await?.metadata().path.clone();
+///             if path.starts_with("/admin") {
+///                 return Ok(());
+///             }
+///             next.run(connection).await
+///         })
+///     }
+/// }
+/// ```
+pub trait Middleware: Send + Sync + 'static {
+    /// Run this layer for `connection`, continuing down the chain with `next`
+    fn around<'a>(
+        &'a self,
+        connection: ConnectionHandle,
+        next: Next<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+}
+
+/// The rest of a middleware chain, ending in the handler
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn Middleware>],
+    handler: &'a dyn Handler,
+}
+
+impl<'a> Next<'a> {
+    /// Run the remaining middleware and then the handler
+    pub fn run(
+        self,
+        connection: ConnectionHandle,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        match self.middleware.split_first() {
+            Some((layer, rest)) => layer.around(
+                connection,
+                Next {
+                    middleware: rest,
+                    handler: self.handler,
+                },
+            ),
+            None => self.handler.handle(connection),
+        }
+    }
+}
+
+/// Wrap `handler` in `middleware`, leaving it as it is without any
+pub(crate) fn layered(middleware: Vec<Arc<dyn Middleware>>, handler: BoxedHandler) -> BoxedHandler {
+    if middleware.is_empty() {
+        return handler;
+    }
+    Box::new(Layered {
+        middleware: middleware.into(),
+        inner: handler,
+    })
+}
+
+/// Handler running a middleware chain before its inner handler
+struct Layered {
+    middleware: Arc<[Arc<dyn Middleware>]>,
+    inner: BoxedHandler,
+}
+
+impl Handler for Layered {
+    fn handle<'a>(
+        &'a self,
+        connection: ConnectionHandle,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Next {
+            middleware: &self.middleware,
+            handler: self.inner.as_ref(),
+        }
+        .run(connection)
+    }
+
+    // Routing picks the inner handler, which keeps running behind the chain
+    fn route(&self, path: &str) -> Option<BoxedHandler> {
+        let inner = self.inner.route(path)?;
+        Some(Box::new(Layered {
+            middleware: self.middleware.clone(),
+            inner,
+        }))
+    }
+
+    fn clone_box(&self) -> Box<dyn Handler> {
+        Box::new(Layered {
+            middleware: self.middleware.clone(),
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+/// Middleware logging when each connection's handler starts and ends
+///
+/// The end is logged with the handler's running time, at error level when
+/// the handler failed. The result is passed on unchanged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoggingMiddleware;
+
+impl LoggingMiddleware {
+    /// Create the logging middleware
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Middleware for LoggingMiddleware {
+    fn around<'a>(
+        &'a self,
+        connection: ConnectionHandle,
+        next: Next<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            let id = connection.id();
+            let peer = match connection.try_lock().await {
+                Ok(conn) => format!("{} on {:?}", conn.remote_addr(), conn.metadata().path),
+                Err(_) => "a busy connection".to_string(),
+            };
+            crate::log_info!("Connection {} started: {}", id, peer);
+
+            let started = Instant::now();
+            let result = next.run(connection).await;
+            if let Err(e) = &result {
+                crate::log_error!(
+                    "Connection {} failed after {:?}: {}",
+                    id,
+                    started.elapsed(),
+                    e
+                );
+            } else {
+                crate::log_info!("Connection {} finished after {:?}", id, started.elapsed());
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Connection;
+    use aerosocket_core::Error;
+    use std::sync::Mutex;
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// Records entering and leaving, optionally without calling the rest
+    struct Record {
+        name: &'static str,
+        log: Log,
+        short_circuit: bool,
+    }
+
+    impl Middleware for Record {
+        fn around<'a>(
+            &'a self,
+            connection: ConnectionHandle,
+            next: Next<'a>,
+        ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+            Box::pin(async move {
+                self.log
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}-before", self.name));
+                if self.short_circuit {
+                    return Ok(());
+                }
+                let result = next.run(connection).await;
+                let outcome = if result.is_ok() { "after" } else { "saw-error" };
+                self.log
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}-{}", self.name, outcome));
+                result
+            })
+        }
+    }
+
+    fn record(name: &'static str, log: &Log) -> Arc<dyn Middleware> {
+        Arc::new(Record {
+            name,
+            log: log.clone(),
+            short_circuit: false,
+        })
+    }
+
+    fn handler(log: &Log, fail: bool) -> impl Handler {
+        let log = log.clone();
+        crate::handler::from_fn(move |_: ConnectionHandle| {
+            let log = log.clone();
+            Box::pin(async move {
+                log.lock().unwrap().push("handler".to_string());
+                if fail {
+                    return Err(Error::Other("handler failed".to_string()));
+                }
+                Ok(())
+            }) as Pin<Box<dyn Future<Output = Result<()>> + Send>>
+        })
+    }
+
+    fn connection() -> ConnectionHandle {
+        let addr = "127.0.0.1:8080".parse().unwrap();
+        ConnectionHandle::new(1, Connection::new(addr, addr))
+    }
+
+    #[tokio::test]
+    async fn test_layers_run_in_registration_order() {
+        let log = Log::default();
+        let chain = layered(
+            vec![record("a", &log), record("b", &log)],
+            Box::new(handler(&log, false)),
+        );
+
+        chain.handle(connection()).await.unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["a-before", "b-before", "handler", "b-after", "a-after"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_layer_can_short_circuit() {
+        let log = Log::default();
+        let gate = Arc::new(Record {
+            name: "gate",
+            log: log.clone(),
+            short_circuit: true,
+        });
+        let chain = layered(
+            vec![record("a", &log), gate, record("b", &log)],
+            Box::new(handler(&log, false)),
+        );
+
+        chain.handle(connection()).await.unwrap();
+        assert_eq!(*log.lock().unwrap(), ["a-before", "gate-before", "a-after"]);
+    }
+
+    #[tokio::test]
+    async fn test_layers_observe_handler_errors() {
+        let log = Log::default();
+        let chain = layered(
+            vec![Arc::new(LoggingMiddleware::new()), record("a", &log)],
+            Box::new(handler(&log, true)),
+        );
+
+        let err = chain.handle(connection()).await.unwrap_err();
+        assert!(matches!(err, Error::Other(_)));
+        assert_eq!(*log.lock().unwrap(), ["a-before", "handler", "a-saw-error"]);
+    }
+
+    #[tokio::test]
+    async fn test_routing_keeps_the_chain() {
+        let log = Log::default();
+        let router = crate::router::Router::new().route("/chat", handler(&log, false));
+        let chain = layered(vec![record("a", &log)], Box::new(router));
+
+        assert!(chain.route("/other").is_none());
+        let routed = chain.route("/chat").unwrap();
+        routed.handle(connection()).await.unwrap();
+        assert_eq!(*log.lock().unwrap(), ["a-before", "handler", "a-after"]);
+    }
+}
@@ -0,0 +1,171 @@
+//! Buffer pool for received message payloads
+//!
+//! This module provides a free-list of payload buffers shared by the
+//! connections of a server. A pooled payload is handed out as ordinary
+//! [`Bytes`]; its buffer goes back to the pool once the last clone of those
+//! bytes is dropped, so handlers never see the pool.
+
+use bytes::{Bytes, BytesMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+/// Buffer pool statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BufferPoolStats {
+    /// Buffers allocated because none was free, plus payloads too large to pool
+    pub allocations: u64,
+    /// Payloads placed in a recycled buffer
+    pub reuses: u64,
+    /// Buffers currently waiting in the free-list
+    pub available: usize,
+}
+
+/// Shared free-list of payload buffers
+///
+/// Clones share the same free-list. Payloads larger than the buffer
+/// capacity bypass the pool, and at most `max_buffers` idle buffers are
+/// kept, so the memory held by the pool is bounded by
+/// `max_buffers * buffer_capacity`.
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    free: Mutex<Vec<BytesMut>>,
+    max_buffers: usize,
+    buffer_capacity: usize,
+    allocations: AtomicU64,
+    reuses: AtomicU64,
+}
+
+impl BufferPool {
+    /// Create a pool keeping up to `max_buffers` idle buffers of `buffer_capacity` bytes
+    pub fn new(max_buffers: usize, buffer_capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                free: Mutex::new(Vec::with_capacity(max_buffers)),
+                max_buffers,
+                buffer_capacity,
+                allocations: AtomicU64::new(0),
+                reuses: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Capacity of each pooled buffer
+    pub fn buffer_capacity(&self) -> usize {
+        self.inner.buffer_capacity
+    }
+
+    /// Get the pool statistics
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            allocations: self.inner.allocations.load(Ordering::Relaxed),
+            reuses: self.inner.reuses.load(Ordering::Relaxed),
+            available: self.inner.free.lock().unwrap().len(),
+        }
+    }
+
+    /// Copy `data` into a pooled buffer
+    pub fn copy_from_slice(&self, data: &[u8]) -> Bytes {
+        if data.len() > self.inner.buffer_capacity {
+            self.inner.allocations.fetch_add(1, Ordering::Relaxed);
+            return Bytes::copy_from_slice(data);
+        }
+
+        let recycled = self.inner.free.lock().unwrap().pop();
+        let mut buf = match recycled {
+            Some(buf) => {
+                self.inner.reuses.fetch_add(1, Ordering::Relaxed);
+                buf
+            }
+            None => {
+                self.inner.allocations.fetch_add(1, Ordering::Relaxed);
+                BytesMut::with_capacity(self.inner.buffer_capacity)
+            }
+        };
+        buf.extend_from_slice(data);
+
+        Bytes::from_owner(PooledBuffer {
+            buf,
+            pool: Arc::downgrade(&self.inner),
+        })
+    }
+}
+
+impl std::fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("max_buffers", &self.inner.max_buffers)
+            .field("buffer_capacity", &self.inner.buffer_capacity)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Owner of a pooled buffer, returning it to the pool on drop
+struct PooledBuffer {
+    buf: BytesMut,
+    pool: Weak<PoolInner>,
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let Some(pool) = self.pool.upgrade() else {
+            return;
+        };
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        let mut free = pool.free.lock().unwrap();
+        if free.len() < pool.max_buffers && buf.capacity() >= pool.buffer_capacity {
+            free.push(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffers_return_when_payload_dropped() {
+        let pool = BufferPool::new(2, 64);
+
+        let payload = pool.copy_from_slice(b"hello");
+        let buffer = payload.as_ptr();
+        let clone = payload.clone();
+        assert_eq!(&clone[..], b"hello");
+        drop(payload);
+
+        // A live clone keeps the buffer out of the pool
+        let other = pool.copy_from_slice(b"other");
+        assert_ne!(other.as_ptr(), buffer);
+        drop(clone);
+
+        let payload = pool.copy_from_slice(b"again");
+        assert_eq!(&payload[..], b"again");
+        assert_eq!(payload.as_ptr(), buffer);
+    }
+
+    #[test]
+    fn test_pool_retention_is_bounded() {
+        let pool = BufferPool::new(2, 8);
+
+        let held: Vec<_> = (0..4).map(|_| pool.copy_from_slice(b"data")).collect();
+        drop(held);
+        assert_eq!(pool.stats().available, 2);
+
+        // Too large to pool, so it neither takes nor returns a buffer
+        let large = pool.copy_from_slice(&[0u8; 9]);
+        assert_eq!(pool.stats().available, 2);
+        drop(large);
+        assert_eq!(pool.stats().available, 2);
+    }
+}
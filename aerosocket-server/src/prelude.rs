@@ -5,11 +5,19 @@
 
 // Server types
 pub use crate::config::{
-    BackpressureConfig, BackpressureStrategy, CompressionConfig, ServerConfig, TlsConfig,
+    BackpressureConfig, BackpressureStrategy, CompressionConfig, HandshakeHook, OriginPolicy,
+    RawHandshakeHook, ServerConfig, TlsConfig,
 };
-pub use crate::connection::{Connection, ConnectionHandle, ConnectionMetadata, ConnectionState};
+pub use crate::connection::{
+    CloseInitiator, Connection, ConnectionHandle, ConnectionMetadata, ConnectionState,
+    ConnectionWriter,
+};
+pub use crate::context::ConnectionContext;
 pub use crate::handler::{from_fn, BoxedHandler, DefaultHandler, EchoHandler, Handler};
-pub use crate::server::{Server, ServerBuilder};
+pub use crate::middleware::{LoggingMiddleware, Middleware, Next};
+pub use crate::pool::BufferPool;
+pub use crate::router::Router;
+pub use crate::server::{Server, ServerBuilder, ShutdownSummary};
 
 // Re-export core types
 pub use aerosocket_core::prelude::*;
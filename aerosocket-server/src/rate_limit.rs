@@ -1,33 +1,61 @@
 //! Rate limiting and DoS protection for WebSocket server
 //!
 //! This module provides rate limiting capabilities to protect against DoS attacks.
+//! Budgets are counted per client network, either in fixed windows or with a
+//! token bucket (see [`RateLimitAlgorithm`]).
 
-use aerosocket_core::Result;
+use aerosocket_core::error::SecurityError;
+use aerosocket_core::{Error, Result};
 use std::collections::HashMap;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
+/// How request budgets are counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitAlgorithm {
+    /// Allow `max_requests` per fixed `window`; bursts can straddle a window boundary
+    #[default]
+    FixedWindow,
+    /// Allow bursts of up to `burst_capacity`, refilled at `refill_rate` per second
+    TokenBucket,
+}
+
 /// Rate limiter configuration
 #[derive(Debug, Clone)]
 pub struct RateLimitConfig {
+    /// Algorithm used for request and message budgets
+    pub algorithm: RateLimitAlgorithm,
     /// Maximum requests per window
     pub max_requests: usize,
     /// Time window for rate limiting
     pub window: Duration,
+    /// Tokens added to a bucket per second (token bucket only)
+    pub refill_rate: f64,
+    /// Tokens a full bucket holds (token bucket only)
+    pub burst_capacity: usize,
     /// Maximum concurrent connections per IP
     pub max_connections: usize,
     /// Connection timeout duration
     pub connection_timeout: Duration,
+    /// Prefix length used to group IPv4 clients (32 limits each address)
+    pub ipv4_prefix: u8,
+    /// Prefix length used to group IPv6 clients (64 limits each /64 network)
+    pub ipv6_prefix: u8,
 }
 
 impl Default for RateLimitConfig {
     fn default() -> Self {
         Self {
+            algorithm: RateLimitAlgorithm::FixedWindow,
             max_requests: 100,
             window: Duration::from_secs(60),
+            refill_rate: 10.0,
+            burst_capacity: 50,
             max_connections: 10,
             connection_timeout: Duration::from_secs(300),
+            ipv4_prefix: 32,
+            ipv6_prefix: 64,
         }
     }
 }
@@ -36,16 +64,88 @@ impl Default for RateLimitConfig {
 pub struct RateLimiter {
     config: RateLimitConfig,
     /// Request tracking per IP
-    request_counters: Mutex<HashMap<IpAddr, RequestCounter>>,
+    request_counters: Mutex<HashMap<IpAddr, Budget>>,
+    /// Message tracking per IP
+    message_counters: Mutex<HashMap<IpAddr, Budget>>,
     /// Connection tracking per IP
     connection_counters: Mutex<HashMap<IpAddr, usize>>,
 }
 
-/// Request counter for a specific IP
+/// Remaining budget for a specific IP
 #[derive(Debug, Clone)]
-struct RequestCounter {
-    count: usize,
-    window_start: Instant,
+enum Budget {
+    /// Requests counted in the window started at `window_start`
+    Window { count: usize, window_start: Instant },
+    /// Tokens left in the bucket as of `refilled_at`
+    Bucket { tokens: f64, refilled_at: Instant },
+}
+
+impl Budget {
+    /// Fresh budget under the configured algorithm
+    fn new(config: &RateLimitConfig, now: Instant) -> Self {
+        match config.algorithm {
+            RateLimitAlgorithm::FixedWindow => Budget::Window {
+                count: 0,
+                window_start: now,
+            },
+            RateLimitAlgorithm::TokenBucket => Budget::Bucket {
+                tokens: config.burst_capacity as f64,
+                refilled_at: now,
+            },
+        }
+    }
+
+    /// Spend one request, returning false when the budget is exhausted
+    fn try_take(&mut self, config: &RateLimitConfig, now: Instant) -> bool {
+        match self {
+            Budget::Window {
+                count,
+                window_start,
+            } => {
+                // Reset window if expired
+                if now.duration_since(*window_start) >= config.window {
+                    *count = 0;
+                    *window_start = now;
+                }
+                if *count >= config.max_requests {
+                    return false;
+                }
+                *count += 1;
+                true
+            }
+            Budget::Bucket {
+                tokens,
+                refilled_at,
+            } => {
+                *tokens = Self::refilled(*tokens, *refilled_at, config, now);
+                *refilled_at = now;
+                if *tokens < 1.0 {
+                    return false;
+                }
+                *tokens -= 1.0;
+                true
+            }
+        }
+    }
+
+    /// Whether dropping the entry would not change any future decision
+    fn is_expired(&self, config: &RateLimitConfig, now: Instant) -> bool {
+        match *self {
+            Budget::Window { window_start, .. } => {
+                now.duration_since(window_start) >= config.window * 2
+            }
+            Budget::Bucket {
+                tokens,
+                refilled_at,
+            } => Self::refilled(tokens, refilled_at, config, now) >= config.burst_capacity as f64,
+        }
+    }
+
+    /// Tokens in a bucket at `now`, capped at the burst capacity
+    fn refilled(tokens: f64, refilled_at: Instant, config: &RateLimitConfig, now: Instant) -> f64 {
+        let elapsed = now.duration_since(refilled_at).as_secs_f64();
+        (tokens + elapsed * config.refill_rate).min(config.burst_capacity as f64)
+    }
 }
 
 impl RateLimiter {
@@ -54,41 +154,63 @@ impl RateLimiter {
         Self {
             config,
             request_counters: Mutex::new(HashMap::new()),
+            message_counters: Mutex::new(HashMap::new()),
             connection_counters: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Map an address to the network prefix its limits are tracked under
+    fn bucket(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(v4) => {
+                let bits = u32::from(v4) & prefix_mask_u32(self.config.ipv4_prefix);
+                IpAddr::V4(Ipv4Addr::from(bits))
+            }
+            IpAddr::V6(v6) => {
+                let bits = u128::from(v6) & prefix_mask_u128(self.config.ipv6_prefix);
+                IpAddr::V6(Ipv6Addr::from(bits))
+            }
+        }
+    }
+
     /// Check if an IP is allowed to make a request
     pub async fn check_request_rate(&self, ip: IpAddr) -> Result<bool> {
-        let mut counters = self.request_counters.lock().await;
-        let now = Instant::now();
-
-        let counter = counters.entry(ip).or_insert_with(|| RequestCounter {
-            count: 0,
-            window_start: now,
-        });
+        Ok(self.take(&self.request_counters, ip).await)
+    }
 
-        // Reset window if expired
-        if now.duration_since(counter.window_start) >= self.config.window {
-            counter.count = 0;
-            counter.window_start = now;
+    /// Check if an IP may send another message
+    ///
+    /// Messages have a budget of their own, counted with the same algorithm
+    /// and limits as requests.
+    pub async fn check_message(&self, ip: IpAddr) -> Result<()> {
+        if self.take(&self.message_counters, ip).await {
+            Ok(())
+        } else {
+            Err(Error::Security(SecurityError::RateLimit))
         }
+    }
 
-        // Check rate limit
-        if counter.count >= self.config.max_requests {
-            return Ok(false);
-        }
+    /// Spend one unit of an IP's budget in `counters`
+    async fn take(&self, counters: &Mutex<HashMap<IpAddr, Budget>>, ip: IpAddr) -> bool {
+        let ip = self.bucket(ip);
+        let mut counters = counters.lock().await;
+        let now = Instant::now();
 
-        counter.count += 1;
-        Ok(true)
+        counters
+            .entry(ip)
+            .or_insert_with(|| Budget::new(&self.config, now))
+            .try_take(&self.config, now)
     }
 
     /// Check if an IP can establish a new connection
     pub async fn can_connect(&self, ip: IpAddr) -> Result<bool> {
+        let ip = self.bucket(ip);
         let mut conn_counters = self.connection_counters.lock().await;
         let current_count = conn_counters.entry(ip).or_insert(0);
 
         if *current_count >= self.config.max_connections {
+            #[cfg(feature = "metrics")]
+            metrics::counter!("aerosocket_server_connections_per_ip_rejected_total").increment(1);
             return Ok(false);
         }
 
@@ -98,6 +220,7 @@ impl RateLimiter {
 
     /// Remove a connection for an IP
     pub async fn remove_connection(&self, ip: IpAddr) {
+        let ip = self.bucket(ip);
         let mut conn_counters = self.connection_counters.lock().await;
         if let Some(count) = conn_counters.get_mut(&ip) {
             if *count > 0 {
@@ -113,12 +236,10 @@ impl RateLimiter {
     pub async fn cleanup(&self) {
         let now = Instant::now();
 
-        // Cleanup expired request counters
-        {
-            let mut counters = self.request_counters.lock().await;
-            counters.retain(|_, counter| {
-                now.duration_since(counter.window_start) < self.config.window * 2
-            });
+        // Cleanup expired request and message counters
+        for counters in [&self.request_counters, &self.message_counters] {
+            let mut counters = counters.lock().await;
+            counters.retain(|_, budget| !budget.is_expired(&self.config, now));
         }
 
         // Cleanup connection counters (they don't expire naturally)
@@ -141,6 +262,22 @@ impl RateLimiter {
     }
 }
 
+/// Netmask with the leading `prefix` bits set (prefixes above 32 are clamped)
+fn prefix_mask_u32(prefix: u8) -> u32 {
+    match prefix.min(32) {
+        0 => 0,
+        p => u32::MAX << (32 - p),
+    }
+}
+
+/// Netmask with the leading `prefix` bits set (prefixes above 128 are clamped)
+fn prefix_mask_u128(prefix: u8) -> u128 {
+    match prefix.min(128) {
+        0 => 0,
+        p => u128::MAX << (128 - p),
+    }
+}
+
 /// Rate limiting statistics
 #[derive(Debug, Clone)]
 pub struct RateLimitStats {
@@ -165,11 +302,17 @@ impl RateLimitMiddleware {
 
     /// Check if a connection is allowed
     pub async fn check_connection(&self, ip: IpAddr) -> Result<bool> {
-        // Check both request rate and connection limits
-        let request_ok = self.limiter.check_request_rate(ip).await?;
-        let connection_ok = self.limiter.can_connect(ip).await?;
+        // A connection refused for its request rate must not take a
+        // connection slot, as nothing would release it
+        Ok(self.limiter.check_request_rate(ip).await? && self.limiter.can_connect(ip).await?)
+    }
 
-        Ok(request_ok && connection_ok)
+    /// Check if a message from an IP is allowed
+    ///
+    /// Fails with [`SecurityError::RateLimit`] once the IP's message budget
+    /// is spent.
+    pub async fn check_message(&self, ip: IpAddr) -> Result<()> {
+        self.limiter.check_message(ip).await
     }
 
     /// Remove a connection from tracking
@@ -191,7 +334,6 @@ impl RateLimitMiddleware {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::net::{Ipv4Addr, Ipv6Addr};
 
     #[tokio::test]
     async fn test_rate_limiting() {
@@ -200,6 +342,7 @@ mod tests {
             window: Duration::from_secs(1),
             max_connections: 1,
             connection_timeout: Duration::from_secs(60),
+            ..Default::default()
         };
 
         let limiter = RateLimiter::new(config);
@@ -228,6 +371,7 @@ mod tests {
             window: Duration::from_secs(60),
             max_connections: 2,
             connection_timeout: Duration::from_secs(60),
+            ..Default::default()
         };
 
         let limiter = RateLimiter::new(config);
@@ -269,4 +413,110 @@ mod tests {
         let stats = middleware.stats().await;
         assert_eq!(stats.active_connections, 0);
     }
+
+    #[tokio::test]
+    async fn test_ipv6_prefix_aggregation() {
+        let config = RateLimitConfig {
+            max_requests: 2,
+            ..Default::default()
+        };
+        let limiter = RateLimiter::new(config);
+
+        let a: IpAddr = "2001:db8:1:1::1".parse().unwrap();
+        let b: IpAddr = "2001:db8:1:1:ffff::2".parse().unwrap();
+        let other: IpAddr = "2001:db8:1:2::1".parse().unwrap();
+
+        // a and b share a /64 and therefore a budget
+        assert!(limiter.check_request_rate(a).await.unwrap());
+        assert!(limiter.check_request_rate(b).await.unwrap());
+        assert!(!limiter.check_request_rate(a).await.unwrap());
+        assert!(!limiter.check_request_rate(b).await.unwrap());
+
+        // A different /64 has its own budget
+        assert!(limiter.check_request_rate(other).await.unwrap());
+        assert!(limiter.check_request_rate(other).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_ipv4_prefix_aggregation() {
+        let config = RateLimitConfig {
+            max_connections: 1,
+            ipv4_prefix: 24,
+            ..Default::default()
+        };
+        let limiter = RateLimiter::new(config);
+
+        let a = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 10));
+        let b = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 20));
+        let other = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 10));
+
+        assert!(limiter.can_connect(a).await.unwrap());
+        assert!(!limiter.can_connect(b).await.unwrap());
+        assert!(limiter.can_connect(other).await.unwrap());
+
+        // Releasing through a different address in the same prefix frees the slot
+        limiter.remove_connection(b).await;
+        assert!(limiter.can_connect(a).await.unwrap());
+    }
+
+    #[test]
+    fn test_token_bucket_accepts_steady_rate() {
+        let config = RateLimitConfig {
+            algorithm: RateLimitAlgorithm::TokenBucket,
+            refill_rate: 10.0,
+            burst_capacity: 2,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let mut budget = Budget::new(&config, start);
+
+        // One request per refill interval never runs the bucket dry
+        for step in 0..50 {
+            let now = start + Duration::from_millis(100 * step);
+            assert!(budget.try_take(&config, now), "rejected at step {step}");
+        }
+
+        // Twice the refill rate drains the burst and then every other request fails
+        let start = start + Duration::from_secs(10);
+        let accepted = (0..20)
+            .filter(|step| budget.try_take(&config, start + Duration::from_millis(50 * step)))
+            .count();
+        assert_eq!(accepted, 11);
+        assert!(!budget.is_expired(&config, start + Duration::from_millis(950)));
+        assert!(budget.is_expired(&config, start + Duration::from_secs(2)));
+    }
+
+    #[tokio::test]
+    async fn test_token_bucket_rejects_burst() {
+        let config = RateLimitConfig {
+            algorithm: RateLimitAlgorithm::TokenBucket,
+            refill_rate: 0.001,
+            burst_capacity: 3,
+            ..Default::default()
+        };
+        let middleware = RateLimitMiddleware::new(config);
+        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        for _ in 0..3 {
+            middleware.check_message(ip).await.unwrap();
+        }
+        assert!(matches!(
+            middleware.check_message(ip).await,
+            Err(Error::Security(SecurityError::RateLimit))
+        ));
+
+        // Connections and other clients keep budgets of their own
+        assert!(middleware.check_connection(ip).await.unwrap());
+        let other = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+        middleware.check_message(other).await.unwrap();
+    }
+
+    #[test]
+    fn test_prefix_masks() {
+        assert_eq!(prefix_mask_u32(32), u32::MAX);
+        assert_eq!(prefix_mask_u32(24), 0xffff_ff00);
+        assert_eq!(prefix_mask_u32(0), 0);
+        assert_eq!(prefix_mask_u128(64), u128::MAX << 64);
+        assert_eq!(prefix_mask_u128(200), u128::MAX);
+    }
 }
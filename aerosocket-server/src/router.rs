@@ -0,0 +1,150 @@
+//! Path-based routing of connections
+//!
+//! This module provides a [`Router`] that picks the handler for a connection
+//! from the path the client upgraded on. The route is resolved during the
+//! handshake, so a path without a route is answered with 404 before any
+//! WebSocket traffic is exchanged.
+
+use crate::connection::ConnectionHandle;
+use crate::handler::{BoxedHandler, Handler};
+use aerosocket_core::{Error, Result};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Handler dispatching connections by request path
+///
+/// A route matches its exact path and any path below it, so `/chat` serves
+/// `/chat` and `/chat/lobby` but not `/chatter`. When several routes match,
+/// the longest one wins. Paths no route matches go to the fallback handler,
+/// or are refused with 404 when there is none.
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: Vec<(String, BoxedHandler)>,
+    fallback: Option<BoxedHandler>,
+}
+
+impl Router {
+    /// Create a router without routes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve connections upgraded on `path`, or below it, with `handler`
+    ///
+    /// Adding a path again replaces its handler.
+    pub fn route<H: Handler>(mut self, path: impl Into<String>, handler: H) -> Self {
+        let path = path.into();
+        self.routes.retain(|(route, _)| *route != path);
+        self.routes.push((path, Box::new(handler)));
+        self
+    }
+
+    /// Serve connections no route matches with `handler` instead of refusing them
+    pub fn fallback<H: Handler>(mut self, handler: H) -> Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Check whether any routes were added
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Find the handler for a request path, ignoring any query string
+    pub fn resolve(&self, path: &str) -> Option<&BoxedHandler> {
+        self.matching_route(path)
+            .map(|(_, handler)| handler)
+            .or(self.fallback.as_ref())
+    }
+
+    /// Longest route matching a request path
+    fn matching_route(&self, path: &str) -> Option<&(String, BoxedHandler)> {
+        let path = request_path(path);
+        self.routes
+            .iter()
+            .filter(|(route, _)| route_matches(route, path))
+            .max_by_key(|(route, _)| route.len())
+    }
+}
+
+impl Handler for Router {
+    fn handle<'a>(
+        &'a self,
+        connection: ConnectionHandle,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            let path = connection.try_lock().await?.metadata.path.clone();
+            match self.resolve(&path) {
+                Some(handler) => handler.handle(connection).await,
+                None => Err(Error::Other(format!("No route for path {}", path))),
+            }
+        })
+    }
+
+    fn route(&self, path: &str) -> Option<BoxedHandler> {
+        self.resolve(path).and_then(|handler| handler.route(path))
+    }
+
+    fn clone_box(&self) -> Box<dyn Handler> {
+        Box::new(self.clone())
+    }
+}
+
+impl std::fmt::Debug for Router {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Router")
+            .field(
+                "routes",
+                &self
+                    .routes
+                    .iter()
+                    .map(|(route, _)| route)
+                    .collect::<Vec<_>>(),
+            )
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+/// Path of a request target, without its query string or fragment
+pub(crate) fn request_path(uri: &str) -> &str {
+    uri.split(['?', '#']).next().unwrap_or(uri)
+}
+
+/// Whether `route` is `path` itself or one of its parent segments
+fn route_matches(route: &str, path: &str) -> bool {
+    match path.strip_prefix(route) {
+        Some(rest) => rest.is_empty() || route.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::EchoHandler;
+
+    #[test]
+    fn test_exact_and_prefix_routes() {
+        let router = Router::new()
+            .route("/chat", EchoHandler::new())
+            .route("/chat/admin", EchoHandler::new())
+            .route("/stream/", EchoHandler::new());
+        let matched = |path| router.matching_route(path).map(|(route, _)| route.as_str());
+
+        assert_eq!(matched("/chat"), Some("/chat"));
+        assert_eq!(matched("/chat?room=1"), Some("/chat"));
+        assert_eq!(matched("/chat/lobby"), Some("/chat"));
+        assert_eq!(matched("/chat/admin"), Some("/chat/admin"));
+        assert_eq!(matched("/chat/admin/users"), Some("/chat/admin"));
+        assert_eq!(matched("/stream/video"), Some("/stream/"));
+
+        assert!(router.resolve("/chatter").is_none());
+        assert!(router.resolve("/stream").is_none());
+        assert!(router.resolve("/").is_none());
+        assert!(router
+            .fallback(EchoHandler::new())
+            .resolve("/chatter")
+            .is_some());
+    }
+}
@@ -5,132 +5,63 @@
 use crate::{
     config::ServerConfig,
     connection::{Connection, ConnectionHandle},
+    context::{ConnectionContext, RequestTarget},
+    error::HandshakeError,
     handler::{BoxedHandler, Handler},
-    rate_limit::RateLimitMiddleware,
+    manager::CloseReason,
+    middleware::Middleware,
+    rate_limit::{RateLimitAlgorithm, RateLimitMiddleware},
+    stats::{ServerStats, StatsHandle},
 };
 use aerosocket_core::error::ConfigError;
+use aerosocket_core::error::ProtocolError;
+use aerosocket_core::error::SecurityError;
 use aerosocket_core::handshake::{
-    create_server_handshake, parse_client_handshake, response_to_string, validate_client_handshake,
-    HandshakeConfig,
+    create_server_handshake_with_headers, header_has_token, parse_client_handshake,
+    response_to_string, validate_client_handshake, DeflateParams, HandshakeConfig,
+    HandshakeDecision, HandshakeRequest, HandshakeResponse,
 };
+use aerosocket_core::protocol::constants::{
+    HEADER_SEC_WEBSOCKET_EXTENSIONS, HEADER_SEC_WEBSOCKET_PROTOCOL, HEADER_SEC_WEBSOCKET_VERSION,
+    WEBSOCKET_VERSION,
+};
+use aerosocket_core::protocol::http_header::ORIGIN;
+use aerosocket_core::protocol::http_status::{
+    BAD_REQUEST, FORBIDDEN, NOT_FOUND, SERVICE_UNAVAILABLE, TOO_MANY_REQUESTS, UNAUTHORIZED,
+    UPGRADE_REQUIRED,
+};
+use aerosocket_core::protocol::http_value;
+use aerosocket_core::protocol::{extensions, ExtensionOffer};
 use aerosocket_core::transport::TransportStream;
-use aerosocket_core::{Error, Result, Transport};
-use std::collections::HashMap;
+#[cfg(feature = "compression")]
+use aerosocket_core::DeflateContext;
+use aerosocket_core::{Error, Message, Result, Transport};
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::Semaphore;
 use tokio::time::{timeout, Duration};
 
+pub use crate::manager::ConnectionManager;
+
 /// WebSocket server
 pub struct Server {
     config: ServerConfig,
     handler: BoxedHandler,
+    layers: Vec<Arc<dyn Middleware>>,
     rate_limiter: Option<Arc<RateLimitMiddleware>>,
+    handler_limit: Option<Arc<Semaphore>>,
     manager: Arc<ConnectionManager>,
 }
 
-/// Connection manager for tracking active connections
-#[derive(Debug)]
-pub struct ConnectionManager {
-    connections: Arc<Mutex<HashMap<u64, ConnectionHandle>>>,
-    next_id: Arc<Mutex<u64>>,
-}
-
-impl ConnectionManager {
-    /// Create a new connection manager
-    pub fn new() -> Self {
-        Self {
-            connections: Arc::new(Mutex::new(HashMap::new())),
-            next_id: Arc::new(Mutex::new(1)),
-        }
-    }
-
-    /// Add a new connection
-    pub async fn add_connection(&self, connection: Connection) -> u64 {
-        let mut next_id = self.next_id.lock().await;
-        let id = *next_id;
-        *next_id += 1;
-
-        let handle = ConnectionHandle::new(id, connection);
-        let mut connections = self.connections.lock().await;
-        connections.insert(id, handle);
-        id
-    }
-
-    /// Remove a connection
-    pub async fn remove_connection(&self, id: u64) -> Option<ConnectionHandle> {
-        let mut connections = self.connections.lock().await;
-        connections.remove(&id)
-    }
-
-    /// Get a connection by ID
-    pub async fn get_connection(&self, id: u64) -> Option<ConnectionHandle> {
-        let connections = self.connections.lock().await;
-        connections.get(&id).cloned()
-    }
-
-    /// Get all active connections
-    pub async fn get_all_connections(&self) -> Vec<ConnectionHandle> {
-        let connections = self.connections.lock().await;
-        connections.values().cloned().collect()
-    }
-
-    /// Get the number of active connections
-    pub async fn connection_count(&self) -> usize {
-        let connections = self.connections.lock().await;
-        connections.len()
-    }
-
-    /// Broadcast binary message to all connections
-    pub async fn broadcast_binary_to_all(&self, data: &[u8]) -> Result<()> {
-        let data = data.to_vec();
-        let connections = self.get_all_connections().await;
-        for handle in connections {
-            if let Ok(mut conn) = handle.try_lock().await {
-                let _ = conn.send_binary(data.clone()).await;
-            }
-        }
-        Ok(())
-    }
-
-    /// Broadcast text message to all connections
-    pub async fn broadcast_text_to_all(&self, text: &str) -> Result<()> {
-        let connections = self.get_all_connections().await;
-        for handle in connections {
-            if let Ok(mut conn) = handle.try_lock().await {
-                let _ = conn.send_text(text).await;
-            }
-        }
-        Ok(())
-    }
-
-    /// Broadcast binary message to all connections except the specified one
-    pub async fn broadcast_binary_except(&self, data: &[u8], except_id: u64) -> Result<()> {
-        let data = data.to_vec();
-        let connections = self.get_all_connections().await;
-        for handle in connections {
-            if handle.id() != except_id {
-                if let Ok(mut conn) = handle.try_lock().await {
-                    let _ = conn.send_binary(data.clone()).await;
-                }
-            }
-        }
-        Ok(())
-    }
-
-    /// Broadcast text message to all connections except the specified one
-    pub async fn broadcast_text_except(&self, text: &str, except_id: u64) -> Result<()> {
-        let connections = self.get_all_connections().await;
-        for handle in connections {
-            if handle.id() != except_id {
-                if let Ok(mut conn) = handle.try_lock().await {
-                    let _ = conn.send_text(text).await;
-                }
-            }
-        }
-        Ok(())
-    }
+/// How the connections open at shutdown ended
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// Connections whose handlers finished within the shutdown timeout
+    pub closed_cleanly: usize,
+    /// Connections still open at the timeout, whose handlers were cancelled
+    pub forced: usize,
 }
 
 impl std::fmt::Debug for Server {
@@ -138,6 +69,7 @@ impl std::fmt::Debug for Server {
         f.debug_struct("Server")
             .field("config", &self.config)
             .field("handler", &"<handler>")
+            .field("layers", &self.layers.len())
             .field("manager", &self.manager)
             .finish()
     }
@@ -146,40 +78,145 @@ impl std::fmt::Debug for Server {
 impl Server {
     /// Create a new server with the given config and handler
     pub fn new(config: ServerConfig, handler: BoxedHandler) -> Self {
-        let rate_limiter = if config.backpressure.enabled {
+        let rate_limiter = if config.backpressure.enabled || config.max_connections_per_ip.is_some()
+        {
             Some(Arc::new(RateLimitMiddleware::new(
                 crate::rate_limit::RateLimitConfig {
-                    max_requests: config.backpressure.max_requests_per_minute,
+                    max_requests: if config.backpressure.enabled {
+                        config.backpressure.max_requests_per_minute
+                    } else {
+                        usize::MAX
+                    },
                     window: Duration::from_secs(60),
-                    max_connections: config.max_connections / 10, // 10% of max connections per IP
+                    algorithm: config.backpressure.rate_limit_algorithm,
+                    refill_rate: config.backpressure.refill_rate,
+                    burst_capacity: config.backpressure.burst_capacity,
+                    // 10% of max connections per IP unless configured
+                    max_connections: config
+                        .max_connections_per_ip
+                        .unwrap_or(config.max_connections / 10),
                     connection_timeout: config.idle_timeout,
+                    ..Default::default()
                 },
             )))
         } else {
             None
         };
 
+        let handler_limit = config
+            .max_concurrent_handlers
+            .map(|permits| Arc::new(Semaphore::new(permits)));
+        let manager = Arc::new(ConnectionManager::new(config.clone()));
+
         Self {
             config,
             handler,
+            layers: Vec::new(),
             rate_limiter,
-            manager: Arc::new(ConnectionManager::new()),
+            handler_limit,
+            manager,
         }
     }
 
+    /// Snapshot the server's connection and traffic counters
+    ///
+    /// This works without the `metrics` feature and only reads atomics, so
+    /// it is cheap enough to call on every request to a `/metrics` endpoint.
+    pub fn stats(&self) -> ServerStats {
+        self.manager.stats_handle().snapshot()
+    }
+
+    /// Get a handle that keeps reporting after [`serve`](Self::serve)
+    /// consumes the server
+    ///
+    /// ```rust,no_run
+    /// # async fn example(server: aerosocket_server::Server) {
+    /// let stats = server.stats_handle();
+    /// tokio::spawn(server.serve());
+    /// println!("{:?}", stats.snapshot());
+    /// # }
+    /// ```
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.manager.stats_handle()
+    }
+
+    /// Get the manager tracking this server's connections
+    ///
+    /// Like [`stats_handle`](Self::stats_handle), it stays usable after
+    /// [`serve`](Self::serve) consumes the server, for instance to call
+    /// [`monitor_connections`](ConnectionManager::monitor_connections) on
+    /// the live server.
+    pub fn connection_manager(&self) -> Arc<ConnectionManager> {
+        self.manager.clone()
+    }
+
     /// Create a server builder
     pub fn builder() -> ServerBuilder {
         ServerBuilder::new()
     }
 
+    fn with_layers(mut self, layers: Vec<Arc<dyn Middleware>>) -> Self {
+        self.layers = layers;
+        self
+    }
+
     /// Start serving connections
     pub async fn serve(self) -> Result<()> {
         let manager = self.manager.clone();
         self.serve_with_connection_manager(manager).await
     }
 
+    /// Start serving connections with [`EchoHandler`](crate::handler::EchoHandler)
+    pub async fn serve_echo(mut self) -> Result<()> {
+        self.handler = Box::new(crate::handler::EchoHandler::new());
+        self.serve().await
+    }
+
+    /// Start serving connections with an async closure as the handler
+    ///
+    /// The closure is called once per connection with its [`ConnectionHandle`]:
+    ///
+    /// ```rust,no_run
+    /// # use aerosocket_server::prelude::*;
+    /// # async fn run(server: Server) -> aerosocket_core::Result<()> {
+    /// server
+    ///     .serve_fn(|handle| async move {
+    ///         let mut conn = handle.try_lock().await?;
+    ///         while let Some(msg) = conn.next().await? {
+    ///             if let Message::Text(text) = msg {
+    ///                 conn.send_text(text.as_str()).await?;
+    ///             }
+    ///         }
+    ///         Ok(())
+    ///     })
+    ///     .await
+    /// # }
+    /// ```
+    pub async fn serve_fn<F, Fut>(mut self, f: F) -> Result<()>
+    where
+        F: Fn(ConnectionHandle) -> Fut + Send + Sync + Clone + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.handler = Box::new(crate::handler::FnHandler::new(
+            move |connection: ConnectionHandle| {
+                Box::pin(f(connection))
+                    as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+            },
+        ));
+        self.serve().await
+    }
+
     /// Start serving with graceful shutdown
-    pub async fn serve_with_graceful_shutdown<F>(self, shutdown_signal: F) -> Result<()>
+    ///
+    /// Once `shutdown_signal` completes the server stops accepting and sends
+    /// every tracked connection a 1001 Close. It then waits up to the
+    /// configured shutdown timeout for their handlers to finish, cancels the
+    /// handlers still running, and returns how many connections ended each
+    /// way.
+    pub async fn serve_with_graceful_shutdown<F>(
+        self,
+        shutdown_signal: F,
+    ) -> Result<ShutdownSummary>
     where
         F: std::future::Future<Output = ()> + Send + 'static,
     {
@@ -194,32 +231,34 @@ impl Server {
         self,
         connection_manager: Arc<ConnectionManager>,
     ) -> Result<()> {
-        let (_shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
-        let shutdown_rx = Box::pin(async move {
-            let _ = shutdown_rx.recv().await;
-        });
-
-        self.serve_with_connection_manager_and_shutdown(connection_manager, shutdown_rx)
-            .await
+        self.serve_with_connection_manager_and_shutdown(
+            connection_manager,
+            Box::pin(std::future::pending()),
+        )
+        .await
+        .map(|_| ())
     }
 
     /// Internal serve method with shutdown signal
     async fn serve_with_connection_manager_and_shutdown<F>(
         self,
-        _connection_manager: Arc<ConnectionManager>,
-        _shutdown_signal: F,
-    ) -> Result<()>
+        connection_manager: Arc<ConnectionManager>,
+        shutdown_signal: F,
+    ) -> Result<ShutdownSummary>
     where
         F: std::future::Future<Output = ()> + Send + Unpin + 'static,
     {
+        connection_manager.start_cleanup_task().await;
+
         // Create transport based on configuration
         #[cfg(feature = "tcp-transport")]
         {
             if self.config.transport_type == crate::config::TransportType::Tcp {
-                let transport =
-                    crate::tcp_transport::TcpTransport::bind(self.config.bind_address).await?;
+                let transport = crate::tcp_transport::TcpTransport::bind(self.config.bind_address)
+                    .await?
+                    .with_config(self.config.tcp.clone());
                 return self
-                    .serve_with_tcp_transport(transport, _connection_manager, _shutdown_signal)
+                    .serve_with_tcp_transport(transport, connection_manager, shutdown_signal)
                     .await;
             }
         }
@@ -236,10 +275,11 @@ impl Server {
                     self.config.bind_address,
                     server_config,
                 )
-                .await?;
+                .await?
+                .with_tcp_config(self.config.tcp.clone());
 
                 return self
-                    .serve_with_tls_transport(transport, _connection_manager, _shutdown_signal)
+                    .serve_with_tls_transport(transport, connection_manager, shutdown_signal)
                     .await;
             }
         }
@@ -248,16 +288,17 @@ impl Server {
         if self.config.transport_type == crate::config::TransportType::Tcp {
             #[cfg(feature = "tcp-transport")]
             {
-                let transport =
-                    crate::tcp_transport::TcpTransport::bind(self.config.bind_address).await?;
+                let transport = crate::tcp_transport::TcpTransport::bind(self.config.bind_address)
+                    .await?
+                    .with_config(self.config.tcp.clone());
                 return self
-                    .serve_with_tcp_transport(transport, _connection_manager, _shutdown_signal)
+                    .serve_with_tcp_transport(transport, connection_manager, shutdown_signal)
                     .await;
             }
         }
-        Err(Error::Config(ConfigError::Validation(
-            "No transport available".to_string(),
-        )))
+        Err(Error::MissingTransport {
+            requested: self.config.transport_type,
+        })
     }
 
     /// Serve with TCP transport
@@ -266,16 +307,18 @@ impl Server {
         self,
         transport: crate::tcp_transport::TcpTransport,
         connection_manager: Arc<ConnectionManager>,
-        _shutdown_signal: F,
-    ) -> Result<()>
+        mut shutdown_signal: F,
+    ) -> Result<ShutdownSummary>
     where
-        F: std::future::Future<Output = ()> + Send + 'static,
+        F: std::future::Future<Output = ()> + Send + Unpin + 'static,
     {
         // Spawn connection handling task
-        let handler = self.handler;
+        let handler = crate::middleware::layered(self.layers, self.handler);
         let config = self.config.clone();
         let manager = connection_manager.clone();
         let rate_limiter = self.rate_limiter.clone();
+        let handler_limit = self.handler_limit.clone();
+        let shutdown_timeout = self.config.shutdown_timeout;
 
         let server_task = tokio::spawn(async move {
             let mut connection_counter = 0u64;
@@ -286,6 +329,12 @@ impl Server {
                     result = transport.accept() => {
                         match result {
                             Ok(mut stream) => {
+                                if config.load_shed.as_ref().is_some_and(|hook| hook.should_shed()) {
+                                    crate::log_warn!("Shedding load, refusing connection");
+                                    tokio::spawn(Self::shed_connection(stream, config.handshake_timeout));
+                                    continue;
+                                }
+
                                 // Get remote address for rate limiting
                                 let remote_addr = match stream.remote_addr() {
                                     Ok(addr) => addr.ip(),
@@ -296,12 +345,17 @@ impl Server {
                                     }
                                 };
 
-                                // Check rate limiting if enabled
+                                // Check rate limiting if enabled; with a handshake hook the
+                                // decision waits until the hook has had a chance to exempt it
+                                let mut rate_limited = false;
                                 if let Some(ref rate_limiter) = rate_limiter {
                                     if !rate_limiter.check_connection(remote_addr).await.unwrap_or(true) {
                                         crate::log_warn!("Rate limit exceeded for IP: {}", remote_addr);
-                                        let _ = stream.close().await;
-                                        continue;
+                                        if config.on_handshake.is_none() {
+                                            let _ = stream.close().await;
+                                            continue;
+                                        }
+                                        rate_limited = true;
                                     }
                                 }
 
@@ -319,6 +373,7 @@ impl Server {
                                 let handler = handler.clone();
                                 let config = config.clone();
                                 let rate_limiter = rate_limiter.clone();
+                                let handler_limit = handler_limit.clone();
 
                                 // Spawn connection handler
                                 tokio::spawn(async move {
@@ -328,6 +383,8 @@ impl Server {
                                         config,
                                         manager,
                                         rate_limiter,
+                                        handler_limit,
+                                        rate_limited,
                                     ).await {
                                         crate::log_error!("Connection handling error: {:?}", e);
                                     }
@@ -339,6 +396,9 @@ impl Server {
                             }
                         }
                     }
+                    _ = &mut shutdown_signal => {
+                        break;
+                    }
                     _ = tokio::signal::ctrl_c() => {
                         break;
                     }
@@ -347,10 +407,10 @@ impl Server {
         });
 
         // Wait for server task completion
-        match server_task.await {
-            Ok(()) => Ok(()),
-            Err(e) => Err(Error::Other(format!("Server task panicked: {}", e))),
+        if let Err(e) = server_task.await {
+            return Err(Error::Other(format!("Server task panicked: {}", e)));
         }
+        Ok(Self::graceful_shutdown(connection_manager, shutdown_timeout).await)
     }
 
     /// Serve with TLS transport
@@ -359,15 +419,17 @@ impl Server {
         self,
         transport: crate::tls_transport::TlsTransport,
         connection_manager: Arc<ConnectionManager>,
-        _shutdown_signal: F,
-    ) -> Result<()>
+        mut shutdown_signal: F,
+    ) -> Result<ShutdownSummary>
     where
         F: std::future::Future<Output = ()> + Send + Unpin + 'static,
     {
-        let handler = self.handler;
+        let handler = crate::middleware::layered(self.layers, self.handler);
         let config = self.config.clone();
         let manager = connection_manager.clone();
         let rate_limiter = self.rate_limiter.clone();
+        let handler_limit = self.handler_limit.clone();
+        let shutdown_timeout = self.config.shutdown_timeout;
 
         let server_task = tokio::spawn(async move {
             let mut connection_counter = 0u64;
@@ -377,6 +439,12 @@ impl Server {
                     result = transport.accept() => {
                         match result {
                             Ok(mut stream) => {
+                                if config.load_shed.as_ref().is_some_and(|hook| hook.should_shed()) {
+                                    crate::log_warn!("Shedding load, refusing TLS connection");
+                                    tokio::spawn(Self::shed_connection(stream, config.handshake_timeout));
+                                    continue;
+                                }
+
                                 let remote_ip = match stream.remote_addr() {
                                     Ok(addr) => addr.ip(),
                                     Err(e) => {
@@ -386,11 +454,15 @@ impl Server {
                                     }
                                 };
 
+                                let mut rate_limited = false;
                                 if let Some(ref rate_limiter) = rate_limiter {
                                     if !rate_limiter.check_connection(remote_ip).await.unwrap_or(true) {
                                         crate::log_warn!("Rate limit exceeded for IP: {}", remote_ip);
-                                        let _ = stream.close().await;
-                                        continue;
+                                        if config.on_handshake.is_none() {
+                                            let _ = stream.close().await;
+                                            continue;
+                                        }
+                                        rate_limited = true;
                                     }
                                 }
 
@@ -411,6 +483,7 @@ impl Server {
                                 let handler = handler.clone();
                                 let config = config.clone();
                                 let rate_limiter = rate_limiter.clone();
+                                let handler_limit = handler_limit.clone();
 
                                 tokio::spawn(async move {
                                     if let Err(e) = Self::handle_tls_connection(
@@ -419,6 +492,8 @@ impl Server {
                                         config,
                                         manager,
                                         rate_limiter,
+                                        handler_limit,
+                                        rate_limited,
                                     )
                                     .await
                                     {
@@ -431,6 +506,9 @@ impl Server {
                             }
                         }
                     }
+                    _ = &mut shutdown_signal => {
+                        break;
+                    }
                     _ = tokio::signal::ctrl_c() => {
                         break;
                     }
@@ -438,10 +516,10 @@ impl Server {
             }
         });
 
-        match server_task.await {
-            Ok(()) => Ok(()),
-            Err(e) => Err(Error::Other(format!("Server task panicked: {}", e))),
+        if let Err(e) = server_task.await {
+            return Err(Error::Other(format!("Server task panicked: {}", e)));
         }
+        Ok(Self::graceful_shutdown(connection_manager, shutdown_timeout).await)
     }
 
     /// Handle a single TLS connection
@@ -452,20 +530,25 @@ impl Server {
         config: ServerConfig,
         connection_manager: Arc<ConnectionManager>,
         rate_limiter: Option<Arc<RateLimitMiddleware>>,
+        handler_limit: Option<Arc<Semaphore>>,
+        rate_limited: bool,
     ) -> Result<()> {
-        let (remote_addr, local_addr, endpoint, negotiated_extensions) =
-            Self::perform_tls_handshake(&mut stream, &config).await?;
+        let upgrade =
+            Self::perform_tls_handshake(&mut stream, &config, rate_limited, &handler).await?;
+        let (remote_addr, handler) = (upgrade.remote_addr, upgrade.handler.clone());
 
+        let sni = stream.sni().map(str::to_string);
         let boxed_stream: Box<dyn TransportStream> = Box::new(stream);
 
         // Create connection with stream
-        let mut connection = Connection::with_stream(remote_addr, local_addr, boxed_stream);
-        connection.metadata.compression_negotiated = negotiated_extensions
-            .iter()
-            .any(|e| e.contains("permessage-deflate"));
-        connection.metadata.extensions = negotiated_extensions;
+        let mut connection = Self::upgraded_connection(upgrade, boxed_stream, &config);
+        if config.backpressure.limit_messages {
+            connection.set_rate_limiter(rate_limiter.clone());
+        }
+        connection.metadata.sni = sni;
 
-        let connection_id = connection_manager.add_connection(connection).await;
+        let connection_handle = connection_manager.add_connection(connection).await?;
+        let connection_id = connection_handle.id();
 
         #[cfg(feature = "prometheus")]
         {
@@ -475,19 +558,30 @@ impl Server {
             metrics::counter!("aerosocket_server_endpoint_connections_opened_total").increment(1);
         }
 
-        let connection_handle = connection_manager
-            .get_connection(connection_id)
-            .await
-            .ok_or_else(|| Error::Other("Failed to get connection handle".to_string()))?;
-
-        if let Err(e) = handler.handle(connection_handle).await {
-            crate::log_error!("Handler error: {:?}", e);
-        }
+        let result = Self::run_handler_until_forced(
+            &handler,
+            connection_handle,
+            handler_limit,
+            &connection_manager,
+        )
+        .await;
+        let reason = match result {
+            Ok(()) => CloseReason::Normal,
+            Err(e) => {
+                crate::log_error!("Handler error: {:?}", e);
+                CloseReason::Error
+            }
+        };
 
-        connection_manager.remove_connection(connection_id).await;
+        connection_manager
+            .remove_connection(connection_id, reason)
+            .await;
 
+        // Exempted connections over the limit were never counted
         if let Some(ref rate_limiter) = rate_limiter {
-            rate_limiter.connection_closed(remote_addr.ip()).await;
+            if !rate_limited {
+                rate_limiter.connection_closed(remote_addr.ip()).await;
+            }
         }
 
         #[cfg(feature = "prometheus")]
@@ -500,6 +594,109 @@ impl Server {
         Ok(())
     }
 
+    /// Run the handler for a connection until it finishes or shutdown gives
+    /// up waiting for it
+    async fn run_handler_until_forced(
+        handler: &BoxedHandler,
+        connection: ConnectionHandle,
+        handler_limit: Option<Arc<Semaphore>>,
+        connection_manager: &ConnectionManager,
+    ) -> Result<()> {
+        tokio::select! {
+            result = Self::run_handler(handler, connection, handler_limit) => result,
+            _ = connection_manager.forced() => {
+                Err(Error::Other("Handler cancelled by server shutdown".to_string()))
+            }
+        }
+    }
+
+    /// Run the handler for a connection, first waiting for a free handler slot
+    /// when `max_concurrent_handlers` is set
+    async fn run_handler(
+        handler: &BoxedHandler,
+        connection: ConnectionHandle,
+        handler_limit: Option<Arc<Semaphore>>,
+    ) -> Result<()> {
+        let _permit = match handler_limit {
+            Some(limit) => Some(
+                limit
+                    .acquire_owned()
+                    .await
+                    .map_err(|_| Error::Other("Handler limit closed".to_string()))?,
+            ),
+            None => None,
+        };
+
+        #[cfg(feature = "metrics")]
+        {
+            let result = handler.handle(connection.clone()).await;
+            // The handler is done with the last message it received
+            if let Ok(mut conn) = connection.try_lock().await {
+                conn.record_handle_duration();
+            }
+            result
+        }
+        #[cfg(not(feature = "metrics"))]
+        handler.handle(connection).await
+    }
+
+    /// Create a connection for an upgraded stream, applying per-connection settings
+    fn new_connection(
+        remote_addr: SocketAddr,
+        local_addr: SocketAddr,
+        stream: Box<dyn TransportStream>,
+        config: &ServerConfig,
+    ) -> Connection {
+        let mut connection = Connection::with_stream(remote_addr, local_addr, stream);
+        connection.set_idle_timeout(Some(config.idle_timeout));
+        connection.set_keepalive(config.keepalive_interval, config.keepalive_timeout);
+        connection.set_close_timeout(Some(config.close_timeout));
+        connection.set_close_drain_limits(config.close_drain_frames, config.close_drain_bytes);
+        connection.set_read_buffer_size(config.read_buffer_size);
+        connection.set_max_frame_size(config.max_frame_size);
+        connection.set_max_message_size(config.max_message_size);
+        connection.set_max_fragments_per_message(config.max_fragments_per_message);
+        connection.set_reject_zero_mask(config.reject_zero_mask);
+        connection.set_allow_unmasked(config.allow_unmasked_clients);
+        connection.set_strict_protocol(config.strict_protocol);
+        connection.set_max_connection_bytes(config.max_connection_bytes);
+        connection.set_buffer_pool(config.buffer_pool.clone());
+        connection.set_backpressure(
+            config
+                .backpressure
+                .enabled
+                .then(|| config.backpressure.clone()),
+        );
+        connection
+    }
+
+    /// Create the connection for a completed handshake
+    fn upgraded_connection(
+        upgrade: Upgrade,
+        stream: Box<dyn TransportStream>,
+        config: &ServerConfig,
+    ) -> Connection {
+        let mut connection =
+            Self::new_connection(upgrade.remote_addr, upgrade.local_addr, stream, config);
+        connection.metadata.compression_negotiated = upgrade
+            .extensions
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(extensions::PERMESSAGE_DEFLATE));
+        connection.metadata.extensions = upgrade.extensions;
+        #[cfg(feature = "compression")]
+        if let Some(params) = upgrade.deflate {
+            connection.set_deflate(Some(
+                DeflateContext::for_server(&params, u32::from(config.compression.level))
+                    .with_dictionary(config.compression.dictionary.clone())
+                    .with_max_message_size(config.max_message_size),
+            ));
+        }
+        connection.metadata.subprotocol = upgrade.subprotocol;
+        connection.metadata.path = crate::router::request_path(&upgrade.endpoint).to_string();
+        *connection.context_mut() = upgrade.context;
+        connection
+    }
+
     /// Handle a single connection
     async fn handle_connection(
         mut stream: crate::tcp_transport::TcpStream,
@@ -507,23 +704,25 @@ impl Server {
         config: ServerConfig,
         connection_manager: Arc<ConnectionManager>,
         rate_limiter: Option<Arc<RateLimitMiddleware>>,
+        handler_limit: Option<Arc<Semaphore>>,
+        rate_limited: bool,
     ) -> Result<()> {
         // Perform WebSocket handshake
-        let (remote_addr, local_addr, endpoint, negotiated_extensions) =
-            Self::perform_handshake(&mut stream, &config).await?;
+        let upgrade = Self::perform_handshake(&mut stream, &config, rate_limited, &handler).await?;
+        let (remote_addr, handler) = (upgrade.remote_addr, upgrade.handler.clone());
 
         // Convert to boxed transport stream
         let boxed_stream: Box<dyn TransportStream> = Box::new(stream);
 
         // Create connection with stream
-        let mut connection = Connection::with_stream(remote_addr, local_addr, boxed_stream);
-        connection.metadata.compression_negotiated = negotiated_extensions
-            .iter()
-            .any(|e| e.contains("permessage-deflate"));
-        connection.metadata.extensions = negotiated_extensions;
+        let mut connection = Self::upgraded_connection(upgrade, boxed_stream, &config);
+        if config.backpressure.limit_messages {
+            connection.set_rate_limiter(rate_limiter.clone());
+        }
 
         // Add to connection manager
-        let connection_id = connection_manager.add_connection(connection).await;
+        let connection_handle = connection_manager.add_connection(connection).await?;
+        let connection_id = connection_handle.id();
 
         #[cfg(feature = "prometheus")]
         {
@@ -533,23 +732,33 @@ impl Server {
             metrics::counter!("aerosocket_server_endpoint_connections_opened_total").increment(1);
         }
 
-        // Get connection handle
-        let connection_handle = connection_manager
-            .get_connection(connection_id)
-            .await
-            .ok_or_else(|| Error::Other("Failed to get connection handle".to_string()))?;
-
         // Call handler
-        if let Err(e) = handler.handle(connection_handle).await {
-            crate::log_error!("Handler error: {:?}", e);
-        }
+        let result = Self::run_handler_until_forced(
+            &handler,
+            connection_handle,
+            handler_limit,
+            &connection_manager,
+        )
+        .await;
+        let reason = match result {
+            Ok(()) => CloseReason::Normal,
+            Err(e) => {
+                crate::log_error!("Handler error: {:?}", e);
+                CloseReason::Error
+            }
+        };
 
         // Remove connection from manager
-        connection_manager.remove_connection(connection_id).await;
+        connection_manager
+            .remove_connection(connection_id, reason)
+            .await;
 
         // Clean up rate limiting
+        // Exempted connections over the limit were never counted
         if let Some(ref rate_limiter) = rate_limiter {
-            rate_limiter.connection_closed(remote_addr.ip()).await;
+            if !rate_limited {
+                rate_limiter.connection_closed(remote_addr.ip()).await;
+            }
         }
 
         #[cfg(feature = "prometheus")]
@@ -562,28 +771,14 @@ impl Server {
         Ok(())
     }
 
-    /// Perform WebSocket handshake over TLS
-    #[cfg(feature = "tls-transport")]
-    #[cfg_attr(feature = "logging", tracing::instrument(skip(stream, config)))]
-    async fn perform_tls_handshake(
-        stream: &mut crate::tls_transport::TlsStreamWrapper,
-        config: &ServerConfig,
-    ) -> Result<(SocketAddr, SocketAddr, String, Vec<String>)> {
-        let start = Instant::now();
-        // Read HTTP request over TLS
-        let request_data =
-            Self::read_tls_handshake_request(stream, config.handshake_timeout).await?;
-        let request_str = String::from_utf8_lossy(&request_data);
-
-        // Parse handshake request
-        let request = parse_client_handshake(&request_str)?;
-
-        // Create handshake config
-        let handshake_config = HandshakeConfig {
+    /// Build the handshake configuration from the server configuration
+    fn handshake_config(config: &ServerConfig) -> HandshakeConfig {
+        HandshakeConfig {
             protocols: config.supported_protocols.clone(),
             extensions: config.supported_extensions.clone(),
             origin: None,
             allowed_origins: config.allowed_origins.clone(),
+            origin_policy: config.origin_policy,
             host: None,
             auth: None,
             compression: aerosocket_core::handshake::CompressionConfig {
@@ -591,44 +786,239 @@ impl Server {
                 client_max_window_bits: config.compression.client_max_window_bits,
                 server_max_window_bits: config.compression.server_max_window_bits,
                 compression_level: Some(config.compression.level as u32),
+                server_no_context_takeover: !config.compression.server_context_takeover,
+                client_no_context_takeover: !config.compression.client_context_takeover,
             },
             extra_headers: config.extra_headers.clone(),
+            protocol_selector: config.protocol_selector.clone(),
+        }
+    }
+
+    /// Names of the extensions accepted in a handshake response
+    fn negotiated_extensions(response: &HandshakeResponse) -> Result<Vec<String>> {
+        match response.header(HEADER_SEC_WEBSOCKET_EXTENSIONS) {
+            Some(ext_header) => Ok(ExtensionOffer::parse_header(ext_header)?
+                .into_iter()
+                .map(|offer| offer.name)
+                .collect()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Run the `on_handshake` hook, answering a refusal with its HTTP status
+    ///
+    /// Returns the per-connection response headers of an accepting decision.
+    /// A client over its rate limit is refused with 429 unless the hook
+    /// exempts it.
+    async fn decide_handshake(
+        stream: &mut dyn TransportStream,
+        request: &HandshakeRequest,
+        config: &ServerConfig,
+        rate_limited: bool,
+    ) -> Result<Vec<(String, String)>> {
+        let decision = match &config.on_handshake {
+            Some(hook) => hook.decide(request),
+            None => HandshakeDecision::accept(),
+        };
+
+        match decision {
+            HandshakeDecision::AcceptExempt(headers) => Ok(headers),
+            HandshakeDecision::Accept(_) if rate_limited => {
+                Self::refuse_handshake(stream, TOO_MANY_REQUESTS, "Too Many Requests").await?;
+                Err(Error::Security(SecurityError::RateLimit))
+            }
+            HandshakeDecision::Accept(headers) => Ok(headers),
+            HandshakeDecision::Reject { status, reason } => {
+                Self::refuse_handshake(stream, status, &reason).await?;
+                Err(Error::Security(SecurityError::Blocked { reason }))
+            }
+        }
+    }
+
+    /// Run the `authorize` hook, answering a refusal with 401 or 403
+    ///
+    /// Returns the context the hook filled in, which becomes the connection's.
+    /// It starts out holding the request's [`RequestTarget`].
+    async fn authorize_handshake(
+        stream: &mut dyn TransportStream,
+        request: &HandshakeRequest,
+        config: &ServerConfig,
+    ) -> Result<ConnectionContext> {
+        let mut context = ConnectionContext::new();
+        context.insert(RequestTarget::from_request(request));
+        let Some(hook) = &config.authorize else {
+            return Ok(context);
+        };
+
+        match hook.authorize(request, &mut context).await {
+            Ok(()) => Ok(context),
+            Err(HandshakeError::AuthenticationFailed { reason }) => {
+                Self::refuse_handshake(stream, UNAUTHORIZED, "Unauthorized").await?;
+                Err(Error::Security(SecurityError::Authentication(reason)))
+            }
+            Err(e) => {
+                Self::refuse_handshake(stream, FORBIDDEN, "Forbidden").await?;
+                Err(Error::Security(SecurityError::Authorization(e.to_string())))
+            }
+        }
+    }
+
+    /// Answer a connection refused by the load-shed hook with 503 and close it
+    ///
+    /// The request head is read first (bounded by `limit`), so the response
+    /// is not lost to a reset caused by unread request bytes.
+    async fn shed_connection(mut stream: impl TransportStream, limit: Duration) {
+        let _ = timeout(limit, async {
+            let mut request = Vec::new();
+            let mut chunk = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() <= 8192 {
+                match stream.read(&mut chunk).await {
+                    Ok(n) if n > 0 => request.extend_from_slice(&chunk[..n]),
+                    _ => break,
+                }
+            }
+        })
+        .await;
+        let _ =
+            Self::refuse_handshake(&mut stream, SERVICE_UNAVAILABLE, "Service Unavailable").await;
+        let _ = stream.close().await;
+    }
+
+    /// Answer a handshake request with an empty HTTP error response
+    async fn refuse_handshake(
+        stream: &mut dyn TransportStream,
+        status: u16,
+        reason: &str,
+    ) -> Result<()> {
+        Self::refuse_handshake_with_headers(stream, status, reason, &[]).await
+    }
+
+    /// Answer a handshake request with an empty HTTP error response carrying `headers`
+    async fn refuse_handshake_with_headers(
+        stream: &mut dyn TransportStream,
+        status: u16,
+        reason: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<()> {
+        let mut response = format!("HTTP/1.1 {} {}\r\n", status, reason);
+        for (name, value) in headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str("Content-Length: 0\r\nConnection: close\r\n\r\n");
+        stream.write_all(response.as_bytes()).await?;
+        stream.flush().await
+    }
+
+    /// Answer a request that failed handshake validation with a matching HTTP error
+    ///
+    /// A missing or unsupported `Sec-WebSocket-Version` gets 426 naming the
+    /// supported version (RFC 6455 section 4.4), an origin the server does not
+    /// allow gets 403, and any other malformed request 400.
+    async fn refuse_invalid_handshake(stream: &mut dyn TransportStream, error: &Error) {
+        let (status, reason) = Self::handshake_error_status(error);
+        let headers: &[(&str, &str)] = if status == UPGRADE_REQUIRED {
+            &[("Sec-WebSocket-Version", WEBSOCKET_VERSION)]
+        } else {
+            &[]
+        };
+        // The validation error is what the caller reports, so a failed write is ignored
+        let _ = Self::refuse_handshake_with_headers(stream, status, reason, headers).await;
+    }
+
+    /// HTTP status for a handshake request that failed parsing or validation
+    fn handshake_error_status(error: &Error) -> (u16, &'static str) {
+        match error {
+            Error::Protocol(
+                ProtocolError::MissingHeader(header)
+                | ProtocolError::InvalidHeaderValue { header, .. },
+            ) if header.eq_ignore_ascii_case(HEADER_SEC_WEBSOCKET_VERSION) => {
+                (UPGRADE_REQUIRED, "Upgrade Required")
+            }
+            Error::Protocol(ProtocolError::MissingHeader(header))
+                if header.eq_ignore_ascii_case(ORIGIN) =>
+            {
+                (FORBIDDEN, "Forbidden")
+            }
+            Error::Protocol(ProtocolError::InvalidOrigin { .. }) => (FORBIDDEN, "Forbidden"),
+            _ => (BAD_REQUEST, "Bad Request"),
+        }
+    }
+
+    /// Perform WebSocket handshake over TLS
+    #[cfg(feature = "tls-transport")]
+    #[cfg_attr(
+        feature = "logging",
+        tracing::instrument(skip(stream, config, handler))
+    )]
+    async fn perform_tls_handshake(
+        stream: &mut crate::tls_transport::TlsStreamWrapper,
+        config: &ServerConfig,
+        rate_limited: bool,
+        handler: &BoxedHandler,
+    ) -> Result<Upgrade> {
+        let start = Instant::now();
+        // Read HTTP request over TLS
+        let request_data =
+            Self::read_tls_handshake_request(stream, config.handshake_timeout).await?;
+        let request_str = String::from_utf8_lossy(&request_data);
+
+        // Create handshake config
+        let handshake_config = Self::handshake_config(config);
+
+        // Parse and validate the request, answering a bad one with an HTTP error
+        let validated = parse_client_handshake(&request_str).and_then(|request| {
+            validate_client_handshake(&request, &handshake_config).map(|()| request)
+        });
+        let request = match validated {
+            Ok(request) => request,
+            Err(e) => {
+                Self::refuse_invalid_handshake(stream, &e).await;
+                return Err(e);
+            }
+        };
+
+        // Pick the handler serving the requested path
+        let Some(handler) = handler.route(&request.uri) else {
+            Self::refuse_handshake(stream, NOT_FOUND, "Not Found").await?;
+            return Err(Error::Other(format!("No route for path {}", request.uri)));
         };
 
-        // Validate request
-        validate_client_handshake(&request, &handshake_config)?;
+        // Let the application accept or refuse, and add its own headers
+        let response_headers =
+            Self::decide_handshake(stream, &request, config, rate_limited).await?;
+        let context = Self::authorize_handshake(stream, &request, config).await?;
 
         // Create response
-        let response = create_server_handshake(&request, &handshake_config)?;
+        let response =
+            create_server_handshake_with_headers(&request, &handshake_config, &response_headers)?;
         let response_str = response_to_string(&response);
 
         // Send response over TLS
         stream.write_all(response_str.as_bytes()).await?;
         stream.flush().await?;
 
+        if let Some(hook) = &config.on_raw_handshake {
+            hook.observe(&request_data, response_str.as_bytes());
+        }
+
         #[cfg(feature = "prometheus")]
         {
             let elapsed = start.elapsed().as_secs_f64();
             metrics::histogram!("aerosocket_server_handshake_duration_seconds").record(elapsed);
         }
 
-        // Get addresses
-        let remote_addr = stream.remote_addr()?;
-        let local_addr = stream.local_addr()?;
-        let endpoint = request.uri.clone();
-
-        // Extract negotiated extensions from response headers
-        let negotiated_extensions =
-            if let Some(ext_header) = response.headers.get("Sec-WebSocket-Extensions") {
-                ext_header
-                    .split(',')
-                    .map(|s| s.trim().split(';').next().unwrap_or(s.trim()).to_string())
-                    .collect()
-            } else {
-                vec![]
-            };
-
-        Ok((remote_addr, local_addr, endpoint, negotiated_extensions))
+        Ok(Upgrade {
+            remote_addr: stream.remote_addr()?,
+            local_addr: stream.local_addr()?,
+            endpoint: request.uri.clone(),
+            extensions: Self::negotiated_extensions(&response)?,
+            deflate: response.deflate_params()?,
+            subprotocol: response
+                .header(HEADER_SEC_WEBSOCKET_PROTOCOL)
+                .map(str::to_string),
+            handler,
+            context,
+        })
     }
 
     /// Read handshake request from TLS stream
@@ -674,76 +1064,110 @@ impl Server {
     }
 
     /// Perform WebSocket handshake
-    #[cfg_attr(feature = "logging", tracing::instrument(skip(stream, config)))]
+    #[cfg_attr(
+        feature = "logging",
+        tracing::instrument(skip(stream, config, handler))
+    )]
     async fn perform_handshake(
         stream: &mut crate::tcp_transport::TcpStream,
         config: &ServerConfig,
-    ) -> Result<(SocketAddr, SocketAddr, String, Vec<String>)> {
+        rate_limited: bool,
+        handler: &BoxedHandler,
+    ) -> Result<Upgrade> {
         let start = Instant::now();
         // Read HTTP request
         let request_data = Self::read_handshake_request(stream, config.handshake_timeout).await?;
         let request_str = String::from_utf8_lossy(&request_data);
 
-        // Check if it's a WebSocket upgrade request
-        if !request_str.contains("Upgrade: websocket") {
+        if !Self::is_upgrade_request(&request_str) {
+            if rate_limited {
+                return Err(Error::Security(SecurityError::RateLimit));
+            }
             // Handle as HTTP request
-            return Self::handle_http_request(stream, &request_str, config).await;
+            return Self::handle_http_request(stream, &request_str, config)
+                .await
+                .map(|(remote_addr, local_addr, endpoint, extensions)| Upgrade {
+                    remote_addr,
+                    local_addr,
+                    endpoint,
+                    extensions,
+                    deflate: None,
+                    subprotocol: None,
+                    handler: handler.clone(),
+                    context: ConnectionContext::new(),
+                });
         }
 
-        // Parse handshake request
-        let request = parse_client_handshake(&request_str)?;
-
         // Create handshake config
-        let handshake_config = HandshakeConfig {
-            protocols: config.supported_protocols.clone(),
-            extensions: config.supported_extensions.clone(),
-            origin: None,
-            allowed_origins: config.allowed_origins.clone(),
-            host: None,
-            auth: None,
-            compression: aerosocket_core::handshake::CompressionConfig {
-                enabled: config.compression.enabled,
-                client_max_window_bits: config.compression.client_max_window_bits,
-                server_max_window_bits: config.compression.server_max_window_bits,
-                compression_level: Some(config.compression.level as u32),
-            },
-            extra_headers: config.extra_headers.clone(),
+        let handshake_config = Self::handshake_config(config);
+
+        // Parse and validate the request, answering a bad one with an HTTP error
+        let validated = parse_client_handshake(&request_str).and_then(|request| {
+            validate_client_handshake(&request, &handshake_config).map(|()| request)
+        });
+        let request = match validated {
+            Ok(request) => request,
+            Err(e) => {
+                Self::refuse_invalid_handshake(stream, &e).await;
+                return Err(e);
+            }
+        };
+
+        // Pick the handler serving the requested path
+        let Some(handler) = handler.route(&request.uri) else {
+            Self::refuse_handshake(stream, NOT_FOUND, "Not Found").await?;
+            return Err(Error::Other(format!("No route for path {}", request.uri)));
         };
 
-        // Validate request
-        validate_client_handshake(&request, &handshake_config)?;
+        // Let the application accept or refuse, and add its own headers
+        let response_headers =
+            Self::decide_handshake(stream, &request, config, rate_limited).await?;
+        let context = Self::authorize_handshake(stream, &request, config).await?;
 
         // Create response
-        let response = create_server_handshake(&request, &handshake_config)?;
+        let response =
+            create_server_handshake_with_headers(&request, &handshake_config, &response_headers)?;
         let response_str = response_to_string(&response);
 
         // Send response
         stream.write_all(response_str.as_bytes()).await?;
         stream.flush().await?;
 
+        if let Some(hook) = &config.on_raw_handshake {
+            hook.observe(&request_data, response_str.as_bytes());
+        }
+
         #[cfg(feature = "prometheus")]
         {
             let elapsed = start.elapsed().as_secs_f64();
             metrics::histogram!("aerosocket_server_handshake_duration_seconds").record(elapsed);
         }
 
-        // Get addresses
-        let remote_addr = stream.remote_addr()?;
-        let local_addr = stream.local_addr()?;
-        let endpoint = request.uri.clone();
-
-        // Extract negotiated extensions from response headers
-        let negotiated_extensions =
-            if let Some(ext_header) = response.headers.get("Sec-WebSocket-Extensions") {
-                ext_header
-                    .split(',')
-                    .map(|s| s.trim().split(';').next().unwrap_or(s.trim()).to_string())
-                    .collect()
-            } else {
-                vec![]
-            };
+        Ok(Upgrade {
+            remote_addr: stream.remote_addr()?,
+            local_addr: stream.local_addr()?,
+            endpoint: request.uri.clone(),
+            extensions: Self::negotiated_extensions(&response)?,
+            deflate: response.deflate_params()?,
+            subprotocol: response
+                .header(HEADER_SEC_WEBSOCKET_PROTOCOL)
+                .map(str::to_string),
+            handler,
+            context,
+        })
+    }
 
-        Ok((remote_addr, local_addr, endpoint, negotiated_extensions))
+    /// Check whether a raw request asks to upgrade to WebSocket
+    ///
+    /// Header names and the `websocket` token are case-insensitive; our own
+    /// client, for one, sends its header names in lowercase.
+    fn is_upgrade_request(request: &str) -> bool {
+        request.lines().any(|line| {
+            line.split_once(':').is_some_and(|(name, value)| {
+                name.trim().eq_ignore_ascii_case("upgrade")
+                    && header_has_token(value, http_value::WEBSOCKET)
+            })
+        })
     }
 
     /// Read handshake request from stream
@@ -787,19 +1211,51 @@ impl Server {
         }
     }
 
-    /// Perform graceful shutdown
-    async fn graceful_shutdown(&self, connection_manager: Arc<ConnectionManager>) -> Result<()> {
-        // Get all connections
+    /// Close every tracked connection with 1001, waiting at most `drain_timeout`
+    /// for their handlers to finish before cancelling the rest
+    ///
+    /// A connection whose handler holds the lock is reached through its split
+    /// writer, if it has one; otherwise it is left to its handler.
+    async fn graceful_shutdown(
+        connection_manager: Arc<ConnectionManager>,
+        drain_timeout: Duration,
+    ) -> ShutdownSummary {
+        let deadline = tokio::time::Instant::now() + drain_timeout;
         let connections = connection_manager.get_all_connections().await;
+        let open: Vec<u64> = connections.iter().map(ConnectionHandle::id).collect();
 
-        // Send close frames to all connections
+        let mut closing = tokio::task::JoinSet::new();
         for handle in connections {
-            if let Ok(mut connection) = handle.try_lock().await {
-                let _ = connection.close(Some(1000), Some("Server shutdown")).await;
-            }
+            closing.spawn(async move {
+                let _ = handle.close(Some(1001), Some("Server shutdown")).await;
+            });
         }
 
-        Ok(())
+        if connection_manager.wait_until_empty(deadline).await {
+            return ShutdownSummary {
+                closed_cleanly: open.len(),
+                forced: 0,
+            };
+        }
+
+        // Includes connections that finished their handshake after shutdown began
+        let remaining: HashSet<u64> = connection_manager
+            .get_all_connections()
+            .await
+            .iter()
+            .map(ConnectionHandle::id)
+            .collect();
+        crate::log_warn!(
+            "Shutdown timed out, forcing {} connections closed",
+            remaining.len()
+        );
+        closing.abort_all();
+        connection_manager.force_close();
+
+        ShutdownSummary {
+            closed_cleanly: open.iter().filter(|id| !remaining.contains(id)).count(),
+            forced: remaining.len(),
+        }
     }
 
     /// Handle HTTP request (for /health, /metrics)
@@ -862,30 +1318,69 @@ impl Server {
     }
 
     /// Broadcast binary message to all connections
+    ///
+    /// Connections that are closing are skipped. Fails with the first failed
+    /// send once every other connection has been tried.
     pub async fn broadcast_binary_to_all(&self, data: &[u8]) -> Result<()> {
         self.manager.broadcast_binary_to_all(data).await
     }
 
     /// Broadcast text message to all connections
+    ///
+    /// Fails like [`broadcast_binary_to_all`](Self::broadcast_binary_to_all).
     pub async fn broadcast_text_to_all(&self, text: &str) -> Result<()> {
         self.manager.broadcast_text_to_all(text).await
     }
 
     /// Broadcast binary message to all connections except the specified one
+    ///
+    /// Fails like [`broadcast_binary_to_all`](Self::broadcast_binary_to_all).
     pub async fn broadcast_binary_except(&self, data: &[u8], except_id: u64) -> Result<()> {
         self.manager.broadcast_binary_except(data, except_id).await
     }
 
     /// Broadcast text message to all connections except the specified one
+    ///
+    /// Fails like [`broadcast_binary_to_all`](Self::broadcast_binary_to_all).
     pub async fn broadcast_text_except(&self, text: &str, except_id: u64) -> Result<()> {
         self.manager.broadcast_text_except(text, except_id).await
     }
 }
 
+/// Outcome of a completed WebSocket handshake
+struct Upgrade {
+    remote_addr: SocketAddr,
+    local_addr: SocketAddr,
+    /// Request target the client upgraded on
+    endpoint: String,
+    /// Names of the accepted extensions
+    extensions: Vec<String>,
+    /// Accepted `permessage-deflate` parameters
+    deflate: Option<DeflateParams>,
+    /// Subprotocol accepted in the response
+    subprotocol: Option<String>,
+    /// Handler serving the endpoint
+    handler: BoxedHandler,
+    /// Context filled in by the authorization hook
+    context: ConnectionContext,
+}
+
 /// Server builder
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct ServerBuilder {
     config: ServerConfig,
+    router: crate::router::Router,
+    layers: Vec<Arc<dyn Middleware>>,
+}
+
+impl std::fmt::Debug for ServerBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerBuilder")
+            .field("config", &self.config)
+            .field("router", &self.router)
+            .field("layers", &self.layers.len())
+            .finish()
+    }
 }
 
 impl ServerBuilder {
@@ -893,6 +1388,8 @@ impl ServerBuilder {
     pub fn new() -> Self {
         Self {
             config: ServerConfig::default(),
+            router: crate::router::Router::new(),
+            layers: Vec::new(),
         }
     }
 
@@ -910,6 +1407,15 @@ impl ServerBuilder {
         self
     }
 
+    /// Set maximum concurrent connections from one client IP
+    ///
+    /// Connections beyond the limit are closed right after accept, counted by
+    /// the `aerosocket_server_connections_per_ip_rejected_total` metric.
+    pub fn max_connections_per_ip(mut self, max: usize) -> Self {
+        self.config.max_connections_per_ip = Some(max);
+        self
+    }
+
     /// Set maximum frame size
     pub fn max_frame_size(mut self, size: usize) -> Self {
         self.config.max_frame_size = size;
@@ -922,19 +1428,60 @@ impl ServerBuilder {
         self
     }
 
+    /// Set the maximum number of frames a message may be fragmented into
+    pub fn max_fragments_per_message(mut self, max: usize) -> Self {
+        self.config.max_fragments_per_message = max;
+        self
+    }
+
     /// Set handshake timeout
     pub fn handshake_timeout(mut self, timeout: std::time::Duration) -> Self {
         self.config.handshake_timeout = timeout;
         self
     }
 
+    /// Limit how many connection handlers may run at the same time
+    pub fn max_concurrent_handlers(mut self, max: usize) -> Self {
+        self.config.max_concurrent_handlers = Some(max);
+        self
+    }
+
     /// Set idle timeout
     pub fn idle_timeout(mut self, timeout: std::time::Duration) -> Self {
         self.config.idle_timeout = timeout;
         self
     }
 
+    /// Set close timeout
+    pub fn close_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.config.close_timeout = timeout;
+        self
+    }
+
+    /// Set how long a graceful shutdown waits for connections to close
+    ///
+    /// Handlers still running when it runs out are cancelled.
+    pub fn shutdown_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.config.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Ping connections that have been quiet for `interval`
+    pub fn keepalive_interval(mut self, interval: std::time::Duration) -> Self {
+        self.config.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Close connections that send nothing within `timeout` of a keepalive ping
+    pub fn keepalive_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.config.keepalive_timeout = Some(timeout);
+        self
+    }
+
     /// Enable/disable compression
+    ///
+    /// With compression disabled, clients offering `permessage-deflate` are
+    /// still accepted; the offer is declined and frames are sent uncompressed.
     pub fn compression(mut self, enabled: bool) -> Self {
         self.config.compression.enabled = enabled;
         self
@@ -946,6 +1493,55 @@ impl ServerBuilder {
         self
     }
 
+    /// Set the size of each connection's outbound queue and its water marks
+    ///
+    /// All three are in bytes; see [`BackpressureStrategy`](crate::config::BackpressureStrategy)
+    /// for how they apply.
+    pub fn backpressure_limits(
+        mut self,
+        buffer_size: usize,
+        high_water_mark: usize,
+        low_water_mark: usize,
+    ) -> Self {
+        self.config.backpressure.buffer_size = buffer_size;
+        self.config.backpressure.high_water_mark = high_water_mark;
+        self.config.backpressure.low_water_mark = low_water_mark;
+        self
+    }
+
+    /// Set how many connection attempts a client address may make per minute
+    ///
+    /// Clients over the limit are dropped before the handshake, unless an
+    /// `on_handshake` hook is set, in which case they are answered with
+    /// `429 Too Many Requests` unless the hook exempts them with
+    /// [`HandshakeDecision::exempt_from_rate_limit`].
+    pub fn max_requests_per_minute(mut self, max: usize) -> Self {
+        self.config.backpressure.max_requests_per_minute = max;
+        self
+    }
+
+    /// Count client budgets with a token bucket instead of per-minute windows
+    ///
+    /// Each client may burst up to `burst_capacity` requests (or messages,
+    /// see [`limit_messages`](Self::limit_messages)), refilled at
+    /// `refill_rate` per second; `max_requests_per_minute` no longer applies.
+    /// Both must be greater than 0.
+    pub fn token_bucket(mut self, refill_rate: f64, burst_capacity: usize) -> Self {
+        self.config.backpressure.rate_limit_algorithm = RateLimitAlgorithm::TokenBucket;
+        self.config.backpressure.refill_rate = refill_rate;
+        self.config.backpressure.burst_capacity = burst_capacity;
+        self
+    }
+
+    /// Limit the data messages each client may send
+    ///
+    /// Messages get a budget of their own with the same algorithm and limits
+    /// as connection attempts. A client that spends it is closed with 1008.
+    pub fn limit_messages(mut self, enabled: bool) -> Self {
+        self.config.backpressure.limit_messages = enabled;
+        self
+    }
+
     /// Configure TLS using certificate and key files (requires `tls-transport` feature)
     #[cfg(feature = "tls-transport")]
     pub fn tls(mut self, cert_file: impl Into<String>, key_file: impl Into<String>) -> Self {
@@ -956,6 +1552,14 @@ impl ServerBuilder {
         self
     }
 
+    /// Configure TLS from a full [`TlsConfig`](crate::config::TlsConfig),
+    /// e.g. to require client certificates (requires `tls-transport` feature)
+    #[cfg(feature = "tls-transport")]
+    pub fn tls_config(mut self, tls: crate::config::TlsConfig) -> Self {
+        self.config.tls = Some(tls);
+        self
+    }
+
     /// Use TLS transport instead of TCP (requires `tls-transport` feature)
     #[cfg(feature = "tls-transport")]
     pub fn transport_tls(mut self) -> Self {
@@ -963,24 +1567,233 @@ impl ServerBuilder {
         self
     }
 
+    /// Set the socket options applied to accepted connections
+    pub fn tcp_config(mut self, config: crate::config::TcpTransportConfig) -> Self {
+        self.config.tcp = config;
+        self
+    }
+
+    /// Set whether Nagle's algorithm is disabled on accepted connections (on by default)
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.config.tcp.nodelay = nodelay;
+        self
+    }
+
+    /// Add a subprotocol the server speaks
+    ///
+    /// Once any is added, clients must offer one of them, and the first added
+    /// that the client offers is accepted. Handlers read the choice through
+    /// [`ConnectionHandle::subprotocol`].
+    pub fn protocol(mut self, protocol: impl Into<String>) -> Self {
+        self.config.supported_protocols.push(protocol.into());
+        self
+    }
+
     /// Add an allowed origin for CORS (empty list means allow all)
+    ///
+    /// Takes an exact origin, a subdomain wildcard such as
+    /// `https://*.example.com`, or `*` for any origin but `null`.
     pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
         self.config.allowed_origins.push(origin.into());
         self
     }
 
+    /// Set how many bytes each connection requests from the transport per read
+    pub fn read_buffer_size(mut self, size: usize) -> Self {
+        self.config.read_buffer_size = size;
+        self
+    }
+
+    /// Limit the total bytes a single connection may transfer
+    pub fn max_connection_bytes(mut self, max: u64) -> Self {
+        self.config.max_connection_bytes = Some(max);
+        self
+    }
+
+    /// Reject client frames masked with an all-zero key
+    pub fn reject_zero_mask(mut self, reject: bool) -> Self {
+        self.config.reject_zero_mask = reject;
+        self
+    }
+
+    /// Accept unmasked client frames, for trusted server-to-server links only
+    pub fn allow_unmasked_clients(mut self, allow: bool) -> Self {
+        self.config.allow_unmasked_clients = allow;
+        self
+    }
+
+    /// Enforce every RFC 6455 MUST on client frames (see [`ServerConfig::strict_protocol`])
+    pub fn strict_protocol(mut self, strict: bool) -> Self {
+        self.config.strict_protocol = strict;
+        self
+    }
+
+    /// Set the policy for requests with a missing or unlisted `Origin` header
+    pub fn origin_policy(mut self, policy: crate::config::OriginPolicy) -> Self {
+        self.config.origin_policy = policy;
+        self
+    }
+
+    /// Decide per request whether to accept a handshake and which headers to add
+    ///
+    /// ```rust,no_run
+    /// # use aerosocket_server::prelude::*;
+    /// # use aerosocket_core::HandshakeDecision;
+    /// let builder = ServerBuilder::new().on_handshake(|request| {
+    ///     match request.uri.split_once("session=") {
+    ///         Some((_, session)) => HandshakeDecision::accept()
+    ///             .with_header("Set-Cookie", format!("session={}", session)),
+    ///         None => HandshakeDecision::reject(401, "Unauthorized"),
+    ///     }
+    /// });
+    /// ```
+    pub fn on_handshake<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&HandshakeRequest) -> HandshakeDecision + Send + Sync + 'static,
+    {
+        self.config.on_handshake = Some(crate::config::HandshakeHook::new(f));
+        self
+    }
+
+    /// Authorize each handshake request asynchronously, e.g. against a session store
+    ///
+    /// See [`AuthHook`](crate::config::AuthHook) for when it runs and how a
+    /// refusal is answered. Values stored in the context are available to
+    /// the handler through the connection's context, which also holds the
+    /// request's [`RequestTarget`] with its decoded query parameters.
+    ///
+    /// ```rust,no_run
+    /// # use aerosocket_server::prelude::*;
+    /// # use aerosocket_server::HandshakeError;
+    /// struct Token(String);
+    ///
+    /// let builder = ServerBuilder::new().authorize(|request, context| {
+    ///     Box::pin(async move {
+    ///         let token = request
+    ///             .headers
+    ///             .get("authorization")
+    ///             .and_then(|value| value.strip_prefix("Bearer "))
+    ///             .ok_or_else(|| HandshakeError::AuthenticationFailed {
+    ///                 reason: "missing bearer token".to_string(),
+    ///             })?;
+    ///         context.insert(Token(token.to_string()));
+    ///         Ok(())
+    ///     })
+    /// });
+    /// ```
+    pub fn authorize<F>(mut self, f: F) -> Self
+    where
+        F: for<'a> Fn(
+                &'a HandshakeRequest,
+                &'a mut ConnectionContext,
+            ) -> crate::config::AuthFuture<'a>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.config.authorize = Some(crate::config::AuthHook::new(f));
+        self
+    }
+
+    /// Choose the subprotocol from the client's offers, in the order it sent them
+    ///
+    /// Returning `None` accepts the connection without a subprotocol, and so
+    /// does returning a subprotocol the client did not offer. The choice is
+    /// available to handlers as `ConnectionMetadata::subprotocol`.
+    ///
+    /// ```rust,no_run
+    /// # use aerosocket_server::prelude::*;
+    /// // Speak the newest protocol version the client supports
+    /// let builder = ServerBuilder::new().select_protocol(|offered| {
+    ///     offered
+    ///         .iter()
+    ///         .filter(|protocol| protocol.starts_with("chat.v"))
+    ///         .max()
+    ///         .cloned()
+    /// });
+    /// ```
+    pub fn select_protocol<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&[String]) -> Option<String> + Send + 'static,
+    {
+        self.config.protocol_selector = Some(crate::config::ProtocolSelector::new(f));
+        self
+    }
+
+    /// Observe the raw bytes of every completed handshake, e.g. for audit logs
+    ///
+    /// The callback receives the request exactly as read from the client and
+    /// the 101 response exactly as written back.
+    pub fn on_raw_handshake<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&[u8], &[u8]) + Send + Sync + 'static,
+    {
+        self.config.on_raw_handshake = Some(crate::config::RawHandshakeHook::new(f));
+        self
+    }
+
+    /// Shed load by refusing new connections while `f` returns `true`
+    ///
+    /// The predicate runs as each connection is accepted, before rate
+    /// limiting and the handshake, so it should be cheap, e.g. comparing a
+    /// memory high-water mark. Refused clients get `503 Service Unavailable`.
+    pub fn load_shed<F>(mut self, f: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.config.load_shed = Some(crate::config::LoadShedHook::new(f));
+        self
+    }
+
+    /// Recycle received binary payload buffers through `pool`
+    ///
+    /// The pool is shared by every connection of the server; keep a clone to
+    /// read its [`stats`](crate::pool::BufferPool::stats).
+    pub fn buffer_pool(mut self, pool: crate::pool::BufferPool) -> Self {
+        self.config.buffer_pool = Some(pool);
+        self
+    }
+
+    /// Serve connections upgraded on `path`, or below it, with `handler`
+    ///
+    /// Once a route is added, upgrades on paths no route matches are refused
+    /// with 404, unless a handler passed to
+    /// [`build_with_handler`](Self::build_with_handler) takes them. See
+    /// [`Router`](crate::router::Router) for the matching rules.
+    pub fn route<H: Handler>(mut self, path: impl Into<String>, handler: H) -> Self {
+        self.router = self.router.route(path, handler);
+        self
+    }
+
+    /// Run `middleware` around the handler of every connection
+    ///
+    /// Layers run in the order they are added, the first one outermost, and
+    /// wrap whichever handler the server ends up with, routes included. See
+    /// [`Middleware`].
+    pub fn layer<M: Middleware>(mut self, middleware: M) -> Self {
+        self.layers.push(Arc::new(middleware));
+        self
+    }
+
     /// Build the server
     pub fn build(self) -> Result<Server> {
         // Validate configuration
         self.config.validate()?;
 
+        if !self.router.is_empty() {
+            let server = Server::new(self.config, Box::new(self.router));
+            return Ok(server.with_layers(self.layers));
+        }
+
         // Create default handler
         let handler = Box::new(crate::handler::DefaultHandler::new());
 
-        Ok(Server::new(self.config, handler))
+        Ok(Server::new(self.config, handler).with_layers(self.layers))
     }
 
     /// Build the server with a custom handler
+    ///
+    /// With routes added, the handler serves the paths no route matches.
     pub fn build_with_handler<H>(self, handler: H) -> Result<Server>
     where
         H: Handler + Send + Sync + 'static,
@@ -988,7 +1801,13 @@ impl ServerBuilder {
         // Validate configuration
         self.config.validate()?;
 
-        Ok(Server::new(self.config, Box::new(handler)))
+        if !self.router.is_empty() {
+            let router = self.router.fallback(handler);
+            let server = Server::new(self.config, Box::new(router));
+            return Ok(server.with_layers(self.layers));
+        }
+
+        Ok(Server::new(self.config, Box::new(handler)).with_layers(self.layers))
     }
 
     /// Build the server with a WASM-based handler loaded from a .wasm file
@@ -1021,6 +1840,1392 @@ mod tests {
             .max_frame_size(1024 * 1024)
             .compression(true);
 
-        assert!(builder.build().is_ok());
+        // Enabling compression only validates when the feature is compiled in
+        assert_eq!(builder.build().is_ok(), cfg!(feature = "compression"));
+    }
+
+    #[test]
+    fn test_upgrade_detection_ignores_case() {
+        let request = |upgrade: &str| format!("GET / HTTP/1.1\r\nHost: x\r\n{}\r\n\r\n", upgrade);
+        for upgrade in [
+            "Upgrade: websocket",
+            "upgrade: websocket",
+            "UPGRADE:WebSocket",
+            "Upgrade: h2c, websocket",
+        ] {
+            assert!(Server::is_upgrade_request(&request(upgrade)), "{}", upgrade);
+        }
+        for upgrade in [
+            "Upgrade: h2c",
+            "X-Upgrade: websocket",
+            "Connection: websocket",
+        ] {
+            assert!(
+                !Server::is_upgrade_request(&request(upgrade)),
+                "{}",
+                upgrade
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_handlers_serializes_handlers() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let handler: BoxedHandler = {
+            let events = events.clone();
+            Box::new(crate::handler::from_fn(move |conn: ConnectionHandle| {
+                let events = events.clone();
+                Box::pin(async move {
+                    events.lock().unwrap().push(("start", conn.id()));
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    events.lock().unwrap().push(("end", conn.id()));
+                    Ok(())
+                })
+                    as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+            }))
+        };
+        let limit = Some(Arc::new(Semaphore::new(1)));
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let first = ConnectionHandle::new(1, Connection::new(addr, addr));
+        let second = ConnectionHandle::new(2, Connection::new(addr, addr));
+
+        let (a, b) = tokio::join!(
+            Server::run_handler(&handler, first, limit.clone()),
+            Server::run_handler(&handler, second, limit.clone()),
+        );
+        a.unwrap();
+        b.unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![("start", 1), ("end", 1), ("start", 2), ("end", 2)]
+        );
+    }
+
+    /// Pick a free local port for a server under test
+    #[cfg(feature = "tcp-transport")]
+    fn free_local_addr() -> SocketAddr {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    /// Send a raw upgrade request for `path` and read the response head
+    ///
+    /// Returns the stream, the response head and any bytes read past it.
+    #[cfg(feature = "tcp-transport")]
+    async fn raw_upgrade(
+        addr: SocketAddr,
+        path: &str,
+    ) -> (tokio::net::TcpStream, String, bytes::BytesMut) {
+        raw_upgrade_with(addr, path, "").await
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    async fn raw_upgrade_with(
+        addr: SocketAddr,
+        path: &str,
+        extra_headers: &str,
+    ) -> (tokio::net::TcpStream, String, bytes::BytesMut) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = loop {
+            match tokio::net::TcpStream::connect(addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n{}\r\n",
+            path, addr, extra_headers
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut buf = bytes::BytesMut::new();
+        let header_end = loop {
+            let mut chunk = [0u8; 1024];
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "server closed during handshake");
+            buf.extend_from_slice(&chunk[..n]);
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+        };
+        let head = String::from_utf8(buf.split_to(header_end).to_vec()).unwrap();
+        (stream, head, buf)
+    }
+
+    /// Read the next frame the server sends, or `None` once it hangs up
+    ///
+    /// RSV1 is accepted and kept, so a compressed reply comes back still
+    /// deflated.
+    #[cfg(feature = "tcp-transport")]
+    async fn read_server_frame(
+        stream: &mut tokio::net::TcpStream,
+        buf: &mut bytes::BytesMut,
+    ) -> Option<aerosocket_core::frame::Frame> {
+        use tokio::io::AsyncReadExt;
+
+        loop {
+            match aerosocket_core::frame::Frame::parse_compressed(
+                buf,
+                aerosocket_core::protocol::constants::DEFAULT_MAX_FRAME_SIZE,
+            ) {
+                Ok(frame) => return Some(frame),
+                Err(_) => {
+                    let mut chunk = [0u8; 1024];
+                    match stream.read(&mut chunk).await {
+                        Ok(0) | Err(_) => return None,
+                        Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_serve_fn_echoes_end_to_end() {
+        use aerosocket_core::frame::Frame;
+        use tokio::io::AsyncWriteExt;
+
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_fn(|handle| async move {
+            let mut conn = handle.try_lock().await?;
+            while let Some(msg) = conn.next().await? {
+                if let Message::Text(text) = msg {
+                    conn.send_text(text.as_str()).await?;
+                }
+            }
+            Ok(())
+        }));
+
+        let (mut stream, head, mut buf) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+
+        let frame = Frame::text("hello").mask(true).to_bytes();
+        stream.write_all(&frame).await.unwrap();
+
+        let reply = read_server_frame(&mut stream, &mut buf)
+            .await
+            .expect("server closed before replying");
+        assert_eq!(&reply.payload[..], b"hello");
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_stats_count_round_trip_traffic() {
+        use aerosocket_core::frame::Frame;
+        use tokio::io::AsyncWriteExt;
+
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(server.stats(), ServerStats::default());
+        let stats = server.stats_handle();
+        tokio::spawn(server.serve_fn(|handle| async move {
+            let mut conn = handle.try_lock().await?;
+            while let Some(msg) = conn.next().await? {
+                if let Message::Text(text) = msg {
+                    conn.send_text(text.as_str()).await?;
+                }
+            }
+            Ok(())
+        }));
+
+        let (mut stream, head, mut buf) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+
+        stream
+            .write_all(&Frame::text("hello").mask(true).to_bytes())
+            .await
+            .unwrap();
+        read_server_frame(&mut stream, &mut buf)
+            .await
+            .expect("server closed before replying");
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.active_connections, 1);
+        assert_eq!(snapshot.total_connections, 1);
+        assert_eq!(snapshot.messages_received, 1);
+        assert_eq!(snapshot.bytes_received, 5);
+        assert_eq!(snapshot.messages_sent, 1);
+        // Two header bytes plus the unmasked payload
+        assert_eq!(snapshot.bytes_sent, 7);
+        assert!(snapshot.memory_usage > 0);
+
+        drop(stream);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stats.snapshot().active_connections > 0 {
+            assert!(Instant::now() < deadline, "connection was never removed");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(stats.snapshot().memory_usage, 0);
+        assert_eq!(stats.snapshot().messages_received, 1);
+    }
+
+    #[cfg(all(feature = "tcp-transport", feature = "compression"))]
+    #[tokio::test]
+    async fn test_deflate_context_carries_over_between_messages() {
+        use aerosocket_core::frame::Frame;
+        use tokio::io::AsyncWriteExt;
+
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .compression(true)
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_fn(|handle| async move {
+            let mut conn = handle.try_lock().await?;
+            while let Some(msg) = conn.next().await? {
+                if let Message::Text(text) = msg {
+                    conn.send_text(text.as_str()).await?;
+                }
+            }
+            Ok(())
+        }));
+
+        let (mut stream, head, mut buf) = raw_upgrade_with(
+            addr,
+            "/",
+            "Sec-WebSocket-Extensions: permessage-deflate\r\n",
+        )
+        .await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+        assert!(head.contains("permessage-deflate"));
+
+        let message = r#"{"event":"tick","symbol":"BTC","price":64000}"#.repeat(50);
+        let mut client = DeflateContext::new(6, true, true);
+        let mut sizes = Vec::new();
+        for _ in 0..2 {
+            let frame = Frame::text(message.clone())
+                .compress_with(&mut client)
+                .mask(true);
+            stream.write_all(&frame.to_bytes()).await.unwrap();
+
+            let reply = read_server_frame(&mut stream, &mut buf)
+                .await
+                .expect("server closed before replying");
+            assert!(reply.rsv[0], "reply was not compressed");
+            sizes.push(reply.payload.len());
+            assert_eq!(
+                client.decompress(&reply.payload).unwrap(),
+                message.as_bytes()
+            );
+        }
+        assert!(sizes[1] < sizes[0], "second reply not smaller: {sizes:?}");
+    }
+
+    #[cfg(all(feature = "tcp-transport", feature = "compression"))]
+    #[tokio::test]
+    async fn test_rsv1_rejected_when_deflate_not_negotiated() {
+        use aerosocket_core::frame::Frame;
+        use tokio::io::AsyncWriteExt;
+
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .compression(true)
+            .build()
+            .unwrap();
+        let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+        let result_tx = Arc::new(std::sync::Mutex::new(Some(result_tx)));
+        tokio::spawn(server.serve_fn(move |handle| {
+            let result_tx = result_tx.clone();
+            async move {
+                let mut conn = handle.try_lock().await?;
+                let result = conn.next().await.map(|_| ());
+                if let Some(tx) = result_tx.lock().unwrap().take() {
+                    let _ = tx.send((result, conn.metadata().compression_negotiated));
+                }
+                Ok(())
+            }
+        }));
+
+        // Compression is enabled on the server, but this client never offers it
+        let (mut stream, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+        assert!(!head.contains("permessage-deflate"));
+
+        let frame = Frame::text("hello").rsv(true, false, false).mask(true);
+        stream.write_all(&frame.to_bytes()).await.unwrap();
+
+        let (result, negotiated) = tokio::time::timeout(Duration::from_secs(5), result_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!negotiated);
+        assert!(matches!(
+            result,
+            Err(Error::Frame(
+                aerosocket_core::error::FrameError::ReservedBitsSet
+            ))
+        ));
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_on_handshake_sets_per_connection_headers() {
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .on_handshake(|request| match request.uri.split_once("?session=") {
+                Some((_, session)) => HandshakeDecision::accept()
+                    .with_header("Set-Cookie", format!("session={}; HttpOnly", session)),
+                None => HandshakeDecision::reject(401, "Unauthorized"),
+            })
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_echo());
+
+        let (_stream, head, _) = raw_upgrade(addr, "/chat?session=abc123").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+        assert!(head.contains("Set-Cookie: session=abc123; HttpOnly\r\n"));
+
+        let (_stream, head, _) = raw_upgrade(addr, "/chat").await;
+        assert!(head.starts_with("HTTP/1.1 401 Unauthorized"));
+        assert!(!head.contains("Set-Cookie"));
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_query_params_reach_the_handler() {
+        let handler = crate::handler::from_fn(|handle: ConnectionHandle| {
+            Box::pin(async move {
+                let target = handle
+                    .get::<RequestTarget>()
+                    .await
+                    .expect("request target stored at handshake");
+                let reply = format!(
+                    "{} {} {}",
+                    target.path,
+                    target.param("token").unwrap_or("-"),
+                    target.param("room").unwrap_or("-")
+                );
+                handle.send(Message::text(reply)).await
+            })
+                as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+        });
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .build_with_handler(handler)
+            .unwrap();
+        tokio::spawn(server.serve());
+
+        let (mut stream, head, mut buf) =
+            raw_upgrade(addr, "/live?token=a%20b&room=42&room=7").await;
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+        let frame = read_server_frame(&mut stream, &mut buf)
+            .await
+            .expect("server closed before replying");
+        assert_eq!(&frame.payload[..], b"/live a b 42");
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_layers_wrap_routed_handlers() {
+        use crate::middleware::Next;
+
+        struct Greeter;
+
+        impl Middleware for Greeter {
+            fn around<'a>(
+                &'a self,
+                connection: ConnectionHandle,
+                next: Next<'a>,
+            ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>>
+            {
+                Box::pin(async move {
+                    connection.send(Message::text("layer")).await?;
+                    next.run(connection).await
+                })
+            }
+        }
+
+        let handler = crate::handler::from_fn(|handle: ConnectionHandle| {
+            Box::pin(async move { handle.send(Message::text("handler")).await })
+                as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+        });
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .route("/chat", handler)
+            .layer(Greeter)
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve());
+
+        let (mut stream, head, mut buf) = raw_upgrade(addr, "/chat").await;
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+        for expected in ["layer", "handler"] {
+            let frame = read_server_frame(&mut stream, &mut buf).await.unwrap();
+            assert_eq!(&frame.payload[..], expected.as_bytes());
+        }
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_authorize_requires_bearer_token() {
+        #[derive(Clone)]
+        struct User(String);
+
+        let handler = crate::handler::from_fn(|handle: ConnectionHandle| {
+            Box::pin(async move {
+                let user = handle.get::<User>().await.expect("user set by authorize");
+                handle
+                    .send(Message::text(format!("hello {}", user.0)))
+                    .await
+            })
+                as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+        });
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .authorize(|request, context| {
+                Box::pin(async move {
+                    let token = request
+                        .headers
+                        .get("authorization")
+                        .and_then(|value| value.strip_prefix("Bearer "))
+                        .ok_or_else(|| HandshakeError::AuthenticationFailed {
+                            reason: "missing bearer token".to_string(),
+                        })?;
+                    // Stand-in for an asynchronous token lookup
+                    tokio::task::yield_now().await;
+                    match token {
+                        "secret-alice" => {
+                            context.insert(User("alice".to_string()));
+                            Ok(())
+                        }
+                        _ => Err(HandshakeError::InvalidHeaderValue {
+                            header: "authorization".to_string(),
+                            value: "unknown token".to_string(),
+                        }),
+                    }
+                })
+            })
+            .build_with_handler(handler)
+            .unwrap();
+        tokio::spawn(server.serve());
+
+        let (_stream, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 401 Unauthorized"), "{}", head);
+
+        let (_stream, head, _) =
+            raw_upgrade_with(addr, "/", "Authorization: Bearer secret-bob\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 403 Forbidden"), "{}", head);
+
+        let (mut stream, head, mut buf) =
+            raw_upgrade_with(addr, "/", "Authorization: Bearer secret-alice\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+        let frame = read_server_frame(&mut stream, &mut buf)
+            .await
+            .expect("server closed before greeting");
+        assert_eq!(&frame.payload[..], b"hello alice");
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_invalid_handshake_gets_http_error() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .allow_origin("https://example.com")
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve());
+
+        let valid = "Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n";
+        for (headers, status) in [
+            (
+                format!(
+                    "{}Sec-WebSocket-Version: 8\r\nOrigin: https://example.com\r\n",
+                    valid
+                ),
+                "426",
+            ),
+            (
+                format!(
+                    "{}Sec-WebSocket-Version: 13\r\nOrigin: https://evil.example\r\n",
+                    valid
+                ),
+                "403",
+            ),
+            (
+                "Sec-WebSocket-Version: 13\r\nOrigin: https://example.com\r\n".to_string(),
+                "400",
+            ),
+        ] {
+            let mut stream = loop {
+                match tokio::net::TcpStream::connect(addr).await {
+                    Ok(stream) => break stream,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            };
+            let request = format!(
+                "GET / HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n{}\r\n",
+                addr, headers
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await.unwrap();
+            let response = String::from_utf8(response).unwrap();
+            assert!(
+                response.starts_with(&format!("HTTP/1.1 {} ", status)),
+                "expected {}: {}",
+                status,
+                response
+            );
+            assert_eq!(
+                response.contains("Sec-WebSocket-Version: 13\r\n"),
+                status == "426",
+                "{}",
+                response
+            );
+        }
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_handler_sees_negotiated_subprotocol() {
+        let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+        let result_tx = Arc::new(std::sync::Mutex::new(Some(result_tx)));
+        let handler = crate::handler::from_fn(move |handle: ConnectionHandle| {
+            let result_tx = result_tx.clone();
+            Box::pin(async move {
+                let negotiated = (handle.subprotocol().await, handle.extensions().await);
+                if let Some(tx) = result_tx.lock().unwrap().take() {
+                    let _ = tx.send(negotiated);
+                }
+                Ok(())
+            })
+                as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+        });
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .protocol("chat")
+            .protocol("chat.v2")
+            .build_with_handler(handler)
+            .unwrap();
+        tokio::spawn(server.serve());
+
+        let (_stream, head, _) =
+            raw_upgrade_with(addr, "/", "Sec-WebSocket-Protocol: superchat, chat\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+        assert!(
+            head.to_ascii_lowercase()
+                .contains("sec-websocket-protocol: chat\r\n"),
+            "{}",
+            head
+        );
+
+        let (subprotocol, extensions) = tokio::time::timeout(Duration::from_secs(5), result_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(subprotocol.as_deref(), Some("chat"));
+        assert!(extensions.is_empty());
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_protocol_selector_sets_subprotocol() {
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .select_protocol(|offered| offered.iter().find(|p| *p == "chat.v2").cloned())
+            .build_with_handler(crate::handler::from_fn(|handle: ConnectionHandle| {
+                Box::pin(async move {
+                    let mut conn = handle.try_lock().await?;
+                    let subprotocol = conn.metadata.subprotocol.clone();
+                    conn.send_text(subprotocol.unwrap_or_else(|| "none".to_string()))
+                        .await
+                })
+                    as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+            }))
+            .unwrap();
+        tokio::spawn(server.serve());
+
+        for (offered, expected) in [("chat.v1, chat.v2", "chat.v2"), ("chat.v1", "none")] {
+            let (mut stream, head, mut buf) = raw_upgrade_with(
+                addr,
+                "/",
+                &format!("Sec-WebSocket-Protocol: {}\r\n", offered),
+            )
+            .await;
+            assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+            let header = head
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.eq_ignore_ascii_case("sec-websocket-protocol"))
+                .map(|(_, value)| value.trim());
+            assert_eq!(header, (expected != "none").then_some(expected));
+
+            let greeting = read_server_frame(&mut stream, &mut buf)
+                .await
+                .expect("server closed before greeting");
+            assert_eq!(&greeting.payload[..], expected.as_bytes());
+        }
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_routes_dispatch_by_path() {
+        // Each handler greets with its own name and the path it was given
+        let greeter = |name: &'static str| {
+            crate::handler::from_fn(move |handle: ConnectionHandle| {
+                Box::pin(async move {
+                    let mut conn = handle.try_lock().await?;
+                    let greeting = format!("{} {}", name, conn.metadata.path);
+                    conn.send_text(greeting).await
+                })
+                    as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+            })
+        };
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .route("/chat", greeter("chat"))
+            .route("/chat/admin", greeter("admin"))
+            .route("/stream", greeter("stream"))
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve());
+
+        for (path, expected) in [
+            ("/chat?room=1", "chat /chat"),
+            ("/chat/lobby", "chat /chat/lobby"),
+            ("/chat/admin", "admin /chat/admin"),
+            ("/stream", "stream /stream"),
+        ] {
+            let (mut stream, head, mut buf) = raw_upgrade(addr, path).await;
+            assert!(head.starts_with("HTTP/1.1 101"), "{}: {}", path, head);
+            let greeting = read_server_frame(&mut stream, &mut buf)
+                .await
+                .unwrap_or_else(|| panic!("{}: server closed before greeting", path));
+            assert_eq!(&greeting.payload[..], expected.as_bytes());
+        }
+
+        for path in ["/", "/chatter", "/streams"] {
+            let (_, head, _) = raw_upgrade(addr, path).await;
+            assert!(head.starts_with("HTTP/1.1 404"), "{}: {}", path, head);
+        }
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_connections_per_ip_are_limited() {
+        use tokio::io::AsyncReadExt;
+
+        // Hold every connection open until the client goes away
+        let handler = crate::handler::from_fn(|handle: ConnectionHandle| {
+            Box::pin(async move {
+                let mut conn = handle.try_lock().await?;
+                while let Ok(Some(_)) = conn.next().await {}
+                Ok(())
+            })
+                as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+        });
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .max_connections_per_ip(2)
+            .build_with_handler(handler)
+            .unwrap();
+        tokio::spawn(server.serve());
+
+        let (first, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+        let (_second, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+
+        // The third connection from the same IP is closed without a handshake
+        let mut third = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut buf = [0u8; 64];
+        let read = tokio::time::timeout(Duration::from_secs(5), third.read(&mut buf)).await;
+        assert!(matches!(read, Ok(Ok(0)) | Ok(Err(_))), "{:?}", read);
+
+        // Closing a connection frees its slot
+        drop(first);
+        let head = loop {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let request = format!(
+                "GET / HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                 Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+                addr
+            );
+            tokio::io::AsyncWriteExt::write_all(&mut stream, request.as_bytes())
+                .await
+                .unwrap();
+            let mut response = [0u8; 1024];
+            if let Ok(n @ 1..) = stream.read(&mut response).await {
+                break String::from_utf8_lossy(&response[..n]).to_string();
+            }
+        };
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_keepalive_pings_idle_handler() {
+        use aerosocket_core::frame::Frame;
+        use aerosocket_core::protocol::Opcode;
+        use tokio::io::AsyncWriteExt;
+
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .keepalive_interval(Duration::from_millis(50))
+            .keepalive_timeout(Duration::from_secs(5))
+            .build_with_handler(crate::handler::from_fn(|handle: ConnectionHandle| {
+                Box::pin(async move {
+                    let mut conn = handle.try_lock().await?;
+                    while conn.next().await?.is_some() {}
+                    Ok(())
+                })
+                    as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+            }))
+            .unwrap();
+        let serving = tokio::spawn(server.serve());
+
+        let (mut stream, head, mut buf) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+
+        // Answer the first ping; the server keeps pinging while the handler waits
+        for round in 0..2 {
+            let frame = tokio::time::timeout(
+                Duration::from_secs(5),
+                read_server_frame(&mut stream, &mut buf),
+            )
+            .await
+            .expect("server sent no keepalive ping")
+            .expect("server closed the idle connection");
+            assert_eq!(frame.opcode, Opcode::Ping, "round {}", round);
+            stream
+                .write_all(&Frame::pong(frame.payload).mask(true).to_bytes())
+                .await
+                .unwrap();
+        }
+
+        serving.abort();
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_graceful_shutdown_signal_stops_server() {
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .shutdown_timeout(Duration::from_secs(1))
+            .build_with_handler(crate::handler::from_fn(|handle: ConnectionHandle| {
+                Box::pin(async move {
+                    // Split so shutdown can reach the connection while
+                    // this handler waits in next()
+                    handle.writer().await?.send_text("ready").await?;
+                    let mut conn = handle.try_lock().await?;
+                    while conn.next().await?.is_some() {}
+                    Ok(())
+                })
+                    as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+            }))
+            .unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let mut shutdown_tx = Some(shutdown_tx);
+        let serving = tokio::spawn(server.serve_with_graceful_shutdown(async move {
+            let _ = shutdown_rx.await;
+        }));
+
+        let (mut stream, head, mut buf) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+        let mut frames = Vec::new();
+        while frames.len() < 2 {
+            let frame = read_server_frame(&mut stream, &mut buf)
+                .await
+                .expect("server closed without a Close frame");
+            frames.push(frame);
+            if frames.len() == 1 {
+                drop(shutdown_tx.take());
+            }
+        }
+
+        assert_eq!(&frames[0].payload[..], b"ready");
+        assert_eq!(frames[1].opcode, aerosocket_core::protocol::Opcode::Close);
+        assert_eq!(&frames[1].payload[..2], &1001u16.to_be_bytes());
+        let summary = tokio::time::timeout(Duration::from_secs(5), serving)
+            .await
+            .expect("serve_with_graceful_shutdown did not return")
+            .unwrap()
+            .unwrap();
+        // The client never answered the Close
+        assert_eq!(summary.forced, 1);
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_live_server_reports_through_its_manager() {
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .build()
+            .unwrap();
+        let manager = server.connection_manager();
+        tokio::spawn(server.serve_echo());
+
+        let active = |expected: usize| {
+            let manager = manager.clone();
+            async move {
+                tokio::time::timeout(Duration::from_secs(5), async {
+                    while manager.get_stats().await.active_connections != expected {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                })
+                .await
+                .is_ok()
+            }
+        };
+
+        let (stream, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+        assert!(active(1).await);
+        assert_eq!(manager.stats_handle().snapshot().active_connections, 1);
+        assert_eq!(manager.get_stats().await.total_connections, 1);
+
+        drop(stream);
+        assert!(active(0).await);
+        assert_eq!(manager.connection_count().await, 0);
+        assert_eq!(manager.stats_handle().snapshot().active_connections, 0);
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_dropped_peer_socket_is_abnormal_closure() {
+        use aerosocket_core::error::CloseCode;
+        use aerosocket_core::frame::Frame;
+        use tokio::io::AsyncWriteExt;
+
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .build()
+            .unwrap();
+        let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+        let result_tx = Arc::new(std::sync::Mutex::new(Some(result_tx)));
+        tokio::spawn(server.serve_fn(move |handle| {
+            let result_tx = result_tx.clone();
+            async move {
+                let mut conn = handle.try_lock().await?;
+                let first = conn.next().await?;
+                let second = conn.next().await;
+                if let Some(tx) = result_tx.lock().unwrap().take() {
+                    let _ = tx.send((first, second));
+                }
+                Ok(())
+            }
+        }));
+
+        let (mut stream, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+        let frame = Frame::text("last words").mask(true).to_bytes();
+        stream.write_all(&frame).await.unwrap();
+        // Gone without a Close frame
+        drop(stream);
+
+        let (first, second) = tokio::time::timeout(Duration::from_secs(5), result_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.unwrap().as_text(), Some("last words"));
+        assert!(matches!(
+            second,
+            Err(Error::Closed {
+                code: CloseCode::Abnormal,
+                ..
+            })
+        ));
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_graceful_shutdown_forces_stuck_connections() {
+        use aerosocket_core::frame::Frame;
+        use aerosocket_core::protocol::Opcode;
+        use tokio::io::AsyncWriteExt;
+
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .shutdown_timeout(Duration::from_millis(500))
+            .build_with_handler(crate::handler::from_fn(|handle: ConnectionHandle| {
+                Box::pin(async move {
+                    handle.writer().await?.send_text("ready").await?;
+                    let mut conn = handle.try_lock().await?;
+                    while conn.next().await?.is_some() {}
+                    Ok(())
+                })
+                    as std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>>
+            }))
+            .unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve_with_graceful_shutdown(async move {
+            let _ = shutdown_rx.await;
+        }));
+
+        let (mut fast, head, mut fast_buf) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+        let (mut stuck, head, mut stuck_buf) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+        for (stream, buf) in [(&mut fast, &mut fast_buf), (&mut stuck, &mut stuck_buf)] {
+            let ready = read_server_frame(stream, buf).await.unwrap();
+            assert_eq!(&ready.payload[..], b"ready");
+        }
+        shutdown_tx.send(()).unwrap();
+
+        // The fast peer answers the Close; the stuck one never does
+        let close = read_server_frame(&mut fast, &mut fast_buf).await.unwrap();
+        assert_eq!(close.opcode, Opcode::Close);
+        assert_eq!(&close.payload[..2], &1001u16.to_be_bytes());
+        let reply = Frame::close(Some(1001), None).mask(true).to_bytes();
+        fast.write_all(&reply).await.unwrap();
+        drop(fast);
+
+        let close = read_server_frame(&mut stuck, &mut stuck_buf).await.unwrap();
+        assert_eq!(close.opcode, Opcode::Close);
+
+        let summary = tokio::time::timeout(Duration::from_secs(5), serving)
+            .await
+            .expect("serve_with_graceful_shutdown did not return")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            summary,
+            ShutdownSummary {
+                closed_cleanly: 1,
+                forced: 1,
+            }
+        );
+
+        // The cancelled handler drops the stuck connection
+        let hung_up = tokio::time::timeout(
+            Duration::from_secs(5),
+            read_server_frame(&mut stuck, &mut stuck_buf),
+        )
+        .await
+        .expect("stuck connection was not dropped");
+        assert!(hung_up.is_none());
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_load_shed_refuses_with_503() {
+        let accepted = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = accepted.clone();
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .load_shed(move || counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) >= 2)
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_echo());
+
+        let (_first, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+        let (_second, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+
+        let (_third, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 503 Service Unavailable"));
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_exempt_handshake_bypasses_rate_limit() {
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .max_requests_per_minute(1)
+            .on_handshake(|request| {
+                let trusted = request
+                    .headers
+                    .get("authorization")
+                    .is_some_and(|value| value == "Bearer secret");
+                if trusted {
+                    HandshakeDecision::accept().exempt_from_rate_limit()
+                } else {
+                    HandshakeDecision::accept()
+                }
+            })
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_echo());
+
+        let (_first, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+
+        let (_second, head, _) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 429 Too Many Requests"));
+
+        let (_trusted, head, _) =
+            raw_upgrade_with(addr, "/", "Authorization: Bearer secret\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_message_budget_closes_connection_with_1008() {
+        use aerosocket_core::frame::Frame;
+        use tokio::io::AsyncWriteExt;
+
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .token_bucket(0.001, 2)
+            .limit_messages(true)
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_echo());
+
+        let (mut stream, head, mut buf) = raw_upgrade(addr, "/").await;
+        assert!(head.starts_with("HTTP/1.1 101"));
+        for text in ["one", "two", "three"] {
+            let frame = Frame::text(text).mask(true).to_bytes();
+            stream.write_all(&frame).await.unwrap();
+        }
+
+        for expected in ["one", "two"] {
+            let echo = read_server_frame(&mut stream, &mut buf).await.unwrap();
+            assert_eq!(&echo.payload[..], format!("Echo: {}", expected).as_bytes());
+        }
+        let close = read_server_frame(&mut stream, &mut buf).await.unwrap();
+        assert_eq!(close.opcode, aerosocket_core::protocol::Opcode::Close);
+        assert_eq!(&close.payload[..2], &1008u16.to_be_bytes());
+    }
+
+    #[cfg(feature = "tcp-transport")]
+    #[tokio::test]
+    async fn test_on_raw_handshake_sees_exact_bytes() {
+        let captured = Arc::new(std::sync::Mutex::new(None));
+        let sink = captured.clone();
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .on_raw_handshake(move |request, response| {
+                *sink.lock().unwrap() = Some((request.to_vec(), response.to_vec()));
+            })
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_echo());
+
+        let (_stream, head, _) = raw_upgrade(addr, "/audit").await;
+        // The hook runs right after the response is flushed
+        let (request, response) = loop {
+            if let Some(raw) = captured.lock().unwrap().take() {
+                break raw;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        };
+        let request = String::from_utf8(request).unwrap();
+        assert!(request.starts_with("GET /audit HTTP/1.1\r\n"));
+        assert!(request.contains("Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n"));
+        assert_eq!(String::from_utf8(response).unwrap(), head);
+    }
+
+    #[cfg(feature = "tls-transport")]
+    #[tokio::test]
+    async fn test_handler_sees_client_sni() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let cert = rcgen::generate_simple_self_signed(vec!["app.example.com".to_string()]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+        let seen = Arc::new(std::sync::Mutex::new(None));
+        let sink = seen.clone();
+        let addr = free_local_addr();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .tls(cert_path.to_str().unwrap(), key_path.to_str().unwrap())
+            .transport_tls()
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_fn(move |handle| {
+            let sink = sink.clone();
+            async move {
+                let sni = handle.try_lock().await?.metadata().sni.clone();
+                *sink.lock().unwrap() = Some(sni);
+                Ok(())
+            }
+        }));
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(cert.serialize_der().unwrap()))
+            .unwrap();
+        let client_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+        let tcp = loop {
+            match tokio::net::TcpStream::connect(addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        let server_name = rustls::ServerName::try_from("app.example.com").unwrap();
+        let mut stream = connector.connect(server_name, tcp).await.unwrap();
+        stream
+            .write_all(
+                b"GET / HTTP/1.1\r\nHost: app.example.com\r\nUpgrade: websocket\r\n\
+                  Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = [0u8; 12];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response, b"HTTP/1.1 101");
+
+        let sni = loop {
+            if let Some(sni) = seen.lock().unwrap().take() {
+                break sni;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        };
+        assert_eq!(sni.as_deref(), Some("app.example.com"));
+    }
+
+    /// Start a TLS server requiring client certificates signed by `ca`
+    ///
+    /// Returns its address and whether the handler ran.
+    #[cfg(feature = "tls-transport")]
+    fn mtls_server(
+        ca: &rcgen::Certificate,
+        dir: &std::path::Path,
+    ) -> (SocketAddr, Arc<std::sync::atomic::AtomicBool>) {
+        let server_cert =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = dir.join("server.pem");
+        let key_path = dir.join("server.key");
+        let ca_path = dir.join("ca.pem");
+        std::fs::write(
+            &cert_path,
+            server_cert.serialize_pem_with_signer(ca).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(&key_path, server_cert.serialize_private_key_pem()).unwrap();
+        std::fs::write(&ca_path, ca.serialize_pem().unwrap()).unwrap();
+
+        let handled = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = handled.clone();
+        let addr = free_local_addr();
+        let tls = crate::config::TlsConfig::new(
+            cert_path.to_str().unwrap().to_string(),
+            key_path.to_str().unwrap().to_string(),
+        )
+        .client_auth(true)
+        .ca_file(ca_path.to_str().unwrap().to_string());
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .tls_config(tls)
+            .transport_tls()
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_fn(move |_| {
+            let flag = flag.clone();
+            async move {
+                flag.store(true, std::sync::atomic::Ordering::SeqCst);
+                Ok(())
+            }
+        }));
+        (addr, handled)
+    }
+
+    /// Open a TLS connection and send an upgrade request,
+    /// returning the first bytes of the response
+    #[cfg(feature = "tls-transport")]
+    async fn mtls_upgrade(
+        addr: SocketAddr,
+        client_config: rustls::ClientConfig,
+    ) -> std::io::Result<[u8; 12]> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+        let tcp = loop {
+            match tokio::net::TcpStream::connect(addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        let server_name = rustls::ServerName::try_from("localhost").unwrap();
+        let mut stream = connector.connect(server_name, tcp).await?;
+        stream
+            .write_all(
+                b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                  Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .await?;
+        let mut response = [0u8; 12];
+        stream.read_exact(&mut response).await?;
+        Ok(response)
+    }
+
+    /// Client config trusting only `ca`, presenting `client_cert` signed by
+    /// `signer` if given
+    #[cfg(feature = "tls-transport")]
+    fn ca_client(
+        ca: &rcgen::Certificate,
+        client_cert: Option<&rcgen::Certificate>,
+        signer: &rcgen::Certificate,
+    ) -> rustls::ClientConfig {
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+        let builder = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        match client_cert {
+            Some(cert) => builder
+                .with_client_auth_cert(
+                    vec![rustls::Certificate(
+                        cert.serialize_der_with_signer(signer).unwrap(),
+                    )],
+                    rustls::PrivateKey(cert.serialize_private_key_der()),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        }
+    }
+
+    #[cfg(feature = "tls-transport")]
+    fn test_ca() -> rcgen::Certificate {
+        let mut params = rcgen::CertificateParams::new(Vec::new());
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        rcgen::Certificate::from_params(params).unwrap()
+    }
+
+    #[cfg(feature = "tls-transport")]
+    #[tokio::test]
+    async fn test_mtls_accepts_client_signed_by_ca() {
+        let dir = tempfile::tempdir().unwrap();
+        let ca = test_ca();
+        let (addr, handled) = mtls_server(&ca, dir.path());
+
+        let client = rcgen::generate_simple_self_signed(vec!["client".to_string()]).unwrap();
+        let response = mtls_upgrade(addr, ca_client(&ca, Some(&client), &ca))
+            .await
+            .unwrap();
+        assert_eq!(&response, b"HTTP/1.1 101");
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !handled.load(std::sync::atomic::Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[cfg(feature = "tls-transport")]
+    #[tokio::test]
+    async fn test_mtls_rejects_client_without_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let ca = test_ca();
+        let (addr, handled) = mtls_server(&ca, dir.path());
+
+        assert!(mtls_upgrade(addr, ca_client(&ca, None, &ca)).await.is_err());
+
+        // A certificate from another CA is no better
+        let stranger_ca = test_ca();
+        let client = rcgen::generate_simple_self_signed(vec!["client".to_string()]).unwrap();
+        assert!(
+            mtls_upgrade(addr, ca_client(&ca, Some(&client), &stranger_ca))
+                .await
+                .is_err()
+        );
+        assert!(!handled.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[cfg(feature = "tls-transport")]
+    #[test]
+    fn test_client_auth_requires_ca_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+        let tls = crate::config::TlsConfig::new(
+            cert_path.to_str().unwrap().to_string(),
+            key_path.to_str().unwrap().to_string(),
+        )
+        .client_auth(true);
+        assert!(matches!(
+            crate::config::build_rustls_server_config(&tls),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[cfg(not(feature = "tls-transport"))]
+    #[tokio::test]
+    async fn test_serve_reports_missing_tls_transport() {
+        let config = ServerConfig {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            transport_type: crate::config::TransportType::Tls,
+            ..Default::default()
+        };
+        let server = Server::new(config, Box::new(crate::handler::DefaultHandler::new()));
+
+        match server.serve().await {
+            Err(Error::MissingTransport { requested }) => {
+                assert_eq!(requested, crate::config::TransportType::Tls);
+            }
+            other => panic!("expected MissingTransport error, got {:?}", other),
+        }
     }
 }
@@ -0,0 +1,158 @@
+//! Live server statistics
+//!
+//! Connections report their traffic into a shared [`StatsHandle`] as they
+//! update their own [`ConnectionMetadata`](crate::connection::ConnectionMetadata),
+//! so a snapshot is a handful of atomic loads and never waits on a
+//! connection lock. This works without the `metrics` feature and is cheap
+//! enough to serve from a `/metrics` endpoint on every scrape.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Point-in-time copy of a server's counters
+///
+/// Traffic totals include connections that have since closed, so they only
+/// ever grow while the server runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Connections currently open
+    pub active_connections: usize,
+    /// Connections accepted since the server started
+    pub total_connections: u64,
+    /// Largest number of connections open at once
+    pub peak_connections: usize,
+    /// Messages sent across all connections
+    pub messages_sent: u64,
+    /// Messages received across all connections
+    pub messages_received: u64,
+    /// Bytes sent across all connections, frame headers included
+    pub bytes_sent: u64,
+    /// Payload bytes received across all connections
+    pub bytes_received: u64,
+    /// Estimated memory held by open connections, in bytes
+    ///
+    /// Each connection counts its own state plus one read buffer of the
+    /// configured size.
+    pub memory_usage: u64,
+}
+
+/// Cloneable handle to a server's live counters
+///
+/// Clones share the same counters, so a handle taken before
+/// [`Server::serve`](crate::Server::serve) consumes the server keeps
+/// reporting while it runs.
+#[derive(Debug, Clone, Default)]
+pub struct StatsHandle {
+    inner: Arc<StatsInner>,
+}
+
+#[derive(Debug, Default)]
+struct StatsInner {
+    connection_footprint: u64,
+    active_connections: AtomicUsize,
+    total_connections: AtomicU64,
+    peak_connections: AtomicUsize,
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl StatsHandle {
+    /// Create counters for connections holding about `connection_footprint`
+    /// bytes each
+    pub fn new(connection_footprint: u64) -> Self {
+        Self {
+            inner: Arc::new(StatsInner {
+                connection_footprint,
+                ..StatsInner::default()
+            }),
+        }
+    }
+
+    /// Take a snapshot of the counters
+    pub fn snapshot(&self) -> ServerStats {
+        let inner = &self.inner;
+        let active_connections = inner.active_connections.load(Ordering::Relaxed);
+        ServerStats {
+            active_connections,
+            total_connections: inner.total_connections.load(Ordering::Relaxed),
+            peak_connections: inner.peak_connections.load(Ordering::Relaxed),
+            messages_sent: inner.messages_sent.load(Ordering::Relaxed),
+            messages_received: inner.messages_received.load(Ordering::Relaxed),
+            bytes_sent: inner.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: inner.bytes_received.load(Ordering::Relaxed),
+            memory_usage: active_connections as u64 * inner.connection_footprint,
+        }
+    }
+
+    /// Estimated memory held by `connections` open connections
+    pub(crate) fn memory_for(&self, connections: usize) -> u64 {
+        connections as u64 * self.inner.connection_footprint
+    }
+
+    pub(crate) fn connection_opened(&self) {
+        let active = self
+            .inner
+            .active_connections
+            .fetch_add(1, Ordering::Relaxed)
+            + 1;
+        self.inner.total_connections.fetch_add(1, Ordering::Relaxed);
+        self.inner
+            .peak_connections
+            .fetch_max(active, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_closed(&self) {
+        self.inner
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_sent(&self, messages: u64, bytes: u64) {
+        self.inner
+            .messages_sent
+            .fetch_add(messages, Ordering::Relaxed);
+        self.inner.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_received(&self, messages: u64, bytes: u64) {
+        self.inner
+            .messages_received
+            .fetch_add(messages, Ordering::Relaxed);
+        self.inner
+            .bytes_received
+            .fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_tracks_connections_and_traffic() {
+        let stats = StatsHandle::new(100);
+        let shared = stats.clone();
+
+        stats.connection_opened();
+        stats.connection_opened();
+        stats.connection_closed();
+        shared.record_sent(1, 10);
+        shared.record_received(2, 5);
+
+        assert_eq!(
+            stats.snapshot(),
+            ServerStats {
+                active_connections: 1,
+                total_connections: 2,
+                peak_connections: 2,
+                messages_sent: 1,
+                messages_received: 2,
+                bytes_sent: 10,
+                bytes_received: 5,
+                memory_usage: 100,
+            }
+        );
+    }
+}
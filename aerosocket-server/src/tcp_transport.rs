@@ -3,11 +3,14 @@
 //! This module provides TCP transport functionality.
 
 use aerosocket_core::{
-    transport::{Transport, TransportStream},
+    transport::{
+        SplitHalves, TcpTransportConfig, Transport, TransportRead, TransportStream, TransportWrite,
+    },
     Result,
 };
 use async_trait::async_trait;
 use std::net::SocketAddr;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::{TcpListener, TcpStream as TokioTcpStream};
 
 /// TCP transport implementation
@@ -15,6 +18,7 @@ use tokio::net::{TcpListener, TcpStream as TokioTcpStream};
 pub struct TcpTransport {
     listener: Option<TcpListener>,
     local_addr: SocketAddr,
+    config: TcpTransportConfig,
 }
 
 impl TcpTransport {
@@ -29,6 +33,7 @@ impl TcpTransport {
         Ok(Self {
             listener: Some(listener),
             local_addr,
+            config: TcpTransportConfig::default(),
         })
     }
 
@@ -37,8 +42,15 @@ impl TcpTransport {
         Self {
             listener: None,
             local_addr: "0.0.0.0:0".parse().unwrap(),
+            config: TcpTransportConfig::default(),
         }
     }
+
+    /// Set the socket options applied to accepted streams
+    pub fn with_config(mut self, config: TcpTransportConfig) -> Self {
+        self.config = config;
+        self
+    }
 }
 
 impl Default for TcpTransport {
@@ -58,6 +70,9 @@ impl Transport for TcpTransport {
                     .accept()
                     .await
                     .map_err(aerosocket_core::Error::Io)?;
+                self.config
+                    .apply(&stream)
+                    .map_err(aerosocket_core::Error::Io)?;
                 Ok(TcpStream::from_tokio(stream))
             }
             None => Err(aerosocket_core::Error::Other(
@@ -194,4 +209,79 @@ impl TransportStream for TcpStream {
             )),
         }
     }
+
+    fn take_split(&mut self) -> Option<SplitHalves> {
+        let (read, write) = self.stream.take()?.into_split();
+        Some((Box::new(TcpReadHalf(read)), Box::new(TcpWriteHalf(write))))
+    }
+}
+
+/// Read half of a split TCP stream
+#[derive(Debug)]
+pub struct TcpReadHalf(OwnedReadHalf);
+
+#[async_trait]
+impl TransportRead for TcpReadHalf {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        use tokio::io::AsyncReadExt;
+        self.0.read(buf).await.map_err(aerosocket_core::Error::Io)
+    }
+}
+
+/// Write half of a split TCP stream
+#[derive(Debug)]
+pub struct TcpWriteHalf(OwnedWriteHalf);
+
+#[async_trait]
+impl TransportWrite for TcpWriteHalf {
+    async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        self.0
+            .write_all(buf)
+            .await
+            .map_err(aerosocket_core::Error::Io)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        self.0.flush().await.map_err(aerosocket_core::Error::Io)
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        self.0.shutdown().await.map_err(aerosocket_core::Error::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_accepted_stream_has_nodelay() {
+        let transport = TcpTransport::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let addr = transport.local_addr().unwrap();
+
+        let client = TokioTcpStream::connect(addr);
+        let (accepted, _client) = tokio::join!(transport.accept(), client);
+        let accepted = accepted.unwrap();
+        assert!(accepted.stream.as_ref().unwrap().nodelay().unwrap());
+
+        let transport = transport.with_config(TcpTransportConfig {
+            nodelay: false,
+            recv_buffer: Some(64 * 1024),
+            ..TcpTransportConfig::default()
+        });
+        let client = TokioTcpStream::connect(addr);
+        let (accepted, _client) = tokio::join!(transport.accept(), client);
+        assert!(!accepted
+            .unwrap()
+            .stream
+            .as_ref()
+            .unwrap()
+            .nodelay()
+            .unwrap());
+    }
 }
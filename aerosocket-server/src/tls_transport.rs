@@ -4,7 +4,7 @@
 //! Note: TLS functionality requires the "tls-transport" feature and proper certificate setup.
 
 #[cfg(feature = "tls-transport")]
-use aerosocket_core::transport::TransportStream;
+use aerosocket_core::transport::{SplitHalves, TransportRead, TransportStream, TransportWrite};
 #[cfg(feature = "tls-transport")]
 use aerosocket_core::{Error, Result, Transport};
 #[cfg(feature = "tls-transport")]
@@ -28,12 +28,17 @@ pub struct TlsTransport {
     acceptor: TlsAcceptor,
     /// Local address
     local_addr: SocketAddr,
+    /// Socket options applied to accepted connections
+    tcp_config: aerosocket_core::transport::TcpTransportConfig,
 }
 
 #[cfg(feature = "tls-transport")]
 /// TLS stream wrapper
 pub struct TlsStreamWrapper {
-    inner: TlsStream<TokioTcpStream>,
+    /// TLS stream, taken out when the wrapper is split
+    inner: Option<TlsStream<TokioTcpStream>>,
+    /// Server name the client requested through SNI
+    sni: Option<String>,
 }
 
 #[cfg(feature = "tls-transport")]
@@ -43,6 +48,7 @@ impl Transport for TlsTransport {
 
     async fn accept(&self) -> Result<Self::Stream> {
         let tcp_stream = self.listener.accept().await.map_err(|e| Error::Io(e))?.0;
+        self.tcp_config.apply(&tcp_stream).map_err(Error::Io)?;
 
         let tls_stream = self
             .acceptor
@@ -50,7 +56,12 @@ impl Transport for TlsTransport {
             .await
             .map_err(|e| Error::Other(format!("Failed to accept TLS connection: {}", e)))?;
 
-        Ok(TlsStreamWrapper { inner: tls_stream })
+        let sni = tls_stream.get_ref().1.server_name().map(str::to_string);
+
+        Ok(TlsStreamWrapper {
+            inner: Some(tls_stream),
+            sni,
+        })
     }
 
     fn local_addr(&self) -> Result<SocketAddr> {
@@ -63,45 +74,113 @@ impl Transport for TlsTransport {
     }
 }
 
+#[cfg(feature = "tls-transport")]
+impl TlsStreamWrapper {
+    /// Server name the client sent in the TLS handshake (SNI), if any
+    pub fn sni(&self) -> Option<&str> {
+        self.sni.as_deref()
+    }
+
+    fn stream(&mut self) -> Result<&mut TlsStream<TokioTcpStream>> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| Error::Other("Stream has been split".to_string()))
+    }
+
+    fn stream_ref(&self) -> Result<&TlsStream<TokioTcpStream>> {
+        self.inner
+            .as_ref()
+            .ok_or_else(|| Error::Other("Stream has been split".to_string()))
+    }
+}
+
 #[cfg(feature = "tls-transport")]
 #[async_trait]
 impl TransportStream for TlsStreamWrapper {
     async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         use tokio::io::AsyncReadExt;
-        self.inner.read(buf).await.map_err(|e| Error::Io(e))
+        self.stream()?.read(buf).await.map_err(|e| Error::Io(e))
     }
 
     async fn write(&mut self, buf: &[u8]) -> Result<usize> {
         use tokio::io::AsyncWriteExt;
-        self.inner.write(buf).await.map_err(|e| Error::Io(e))
+        self.stream()?.write(buf).await.map_err(|e| Error::Io(e))
     }
 
     async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
         use tokio::io::AsyncWriteExt;
-        self.inner.write_all(buf).await.map_err(|e| Error::Io(e))
+        self.stream()?
+            .write_all(buf)
+            .await
+            .map_err(|e| Error::Io(e))
     }
 
     async fn flush(&mut self) -> Result<()> {
         use tokio::io::AsyncWriteExt;
-        self.inner.flush().await.map_err(|e| Error::Io(e))
+        self.stream()?.flush().await.map_err(|e| Error::Io(e))
     }
 
     async fn close(&mut self) -> Result<()> {
         use tokio::io::AsyncWriteExt;
-        self.inner.shutdown().await.map_err(|e| Error::Io(e))
+        self.stream()?.shutdown().await.map_err(|e| Error::Io(e))
     }
 
     fn remote_addr(&self) -> Result<SocketAddr> {
-        self.inner.get_ref().0.peer_addr().map_err(|e| Error::Io(e))
+        self.stream_ref()?
+            .get_ref()
+            .0
+            .peer_addr()
+            .map_err(|e| Error::Io(e))
     }
 
     fn local_addr(&self) -> Result<SocketAddr> {
-        self.inner
+        self.stream_ref()?
             .get_ref()
             .0
             .local_addr()
             .map_err(|e| Error::Io(e))
     }
+
+    fn take_split(&mut self) -> Option<SplitHalves> {
+        let (read, write) = tokio::io::split(self.inner.take()?);
+        Some((Box::new(TlsReadHalf(read)), Box::new(TlsWriteHalf(write))))
+    }
+}
+
+#[cfg(feature = "tls-transport")]
+/// Read half of a split TLS stream
+pub struct TlsReadHalf(tokio::io::ReadHalf<TlsStream<TokioTcpStream>>);
+
+#[cfg(feature = "tls-transport")]
+#[async_trait]
+impl TransportRead for TlsReadHalf {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        use tokio::io::AsyncReadExt;
+        self.0.read(buf).await.map_err(Error::Io)
+    }
+}
+
+#[cfg(feature = "tls-transport")]
+/// Write half of a split TLS stream
+pub struct TlsWriteHalf(tokio::io::WriteHalf<TlsStream<TokioTcpStream>>);
+
+#[cfg(feature = "tls-transport")]
+#[async_trait]
+impl TransportWrite for TlsWriteHalf {
+    async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        self.0.write_all(buf).await.map_err(Error::Io)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        self.0.flush().await.map_err(Error::Io)
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        self.0.shutdown().await.map_err(Error::Io)
+    }
 }
 
 #[cfg(feature = "tls-transport")]
@@ -118,9 +197,19 @@ impl TlsTransport {
             listener,
             acceptor,
             local_addr,
+            tcp_config: Default::default(),
         })
     }
 
+    /// Set the socket options applied to accepted connections
+    pub fn with_tcp_config(
+        mut self,
+        config: aerosocket_core::transport::TcpTransportConfig,
+    ) -> Self {
+        self.tcp_config = config;
+        self
+    }
+
     /// Create a new TLS transport with default configuration
     pub async fn bind_with_default_config(addr: SocketAddr) -> Result<Self> {
         let config = create_default_tls_config()?;
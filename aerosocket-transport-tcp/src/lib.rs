@@ -9,10 +9,11 @@
 pub mod tcp;
 
 // Re-export TCP transport types
+pub use aerosocket_core::transport::TcpTransportConfig;
 pub use tcp::{TcpStream, TcpTransport};
 
 /// Prelude module
 pub mod prelude {
     pub use crate::tcp::{TcpStream, TcpTransport};
-    pub use aerosocket_core::transport::{Transport, TransportStream};
+    pub use aerosocket_core::transport::{TcpTransportConfig, Transport, TransportStream};
 }
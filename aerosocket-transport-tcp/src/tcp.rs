@@ -3,7 +3,7 @@
 //! This module provides TCP-based transport implementation for WebSocket connections.
 
 use aerosocket_core::{
-    transport::{Transport, TransportStream},
+    transport::{TcpTransportConfig, Transport, TransportStream},
     Result,
 };
 use async_trait::async_trait;
@@ -15,6 +15,7 @@ use tokio::net::{TcpListener, TcpStream as TokioTcpStream};
 pub struct TcpTransport {
     listener: Option<TcpListener>,
     local_addr: SocketAddr,
+    config: TcpTransportConfig,
 }
 
 impl TcpTransport {
@@ -29,6 +30,7 @@ impl TcpTransport {
         Ok(Self {
             listener: Some(listener),
             local_addr,
+            config: TcpTransportConfig::default(),
         })
     }
 
@@ -37,8 +39,15 @@ impl TcpTransport {
         Self {
             listener: None,
             local_addr: "0.0.0.0:0".parse().unwrap(),
+            config: TcpTransportConfig::default(),
         }
     }
+
+    /// Set the socket options applied to accepted streams
+    pub fn with_config(mut self, config: TcpTransportConfig) -> Self {
+        self.config = config;
+        self
+    }
 }
 
 impl Default for TcpTransport {
@@ -58,6 +67,9 @@ impl Transport for TcpTransport {
                     .accept()
                     .await
                     .map_err(aerosocket_core::Error::Io)?;
+                self.config
+                    .apply(&stream)
+                    .map_err(aerosocket_core::Error::Io)?;
                 Ok(TcpStream::from_tokio(stream))
             }
             None => Err(aerosocket_core::Error::Other(
@@ -106,9 +118,18 @@ impl TcpStream {
 
     /// Connect to a remote address
     pub async fn connect(addr: SocketAddr) -> Result<Self> {
+        Self::connect_with_config(addr, &TcpTransportConfig::default()).await
+    }
+
+    /// Connect to a remote address and apply the given socket options
+    pub async fn connect_with_config(
+        addr: SocketAddr,
+        config: &TcpTransportConfig,
+    ) -> Result<Self> {
         let stream = tokio::net::TcpStream::connect(addr)
             .await
             .map_err(aerosocket_core::Error::Io)?;
+        config.apply(&stream).map_err(aerosocket_core::Error::Io)?;
 
         Ok(Self::from_tokio(stream))
     }
@@ -220,4 +241,20 @@ mod tests {
         let _stream = TcpStream::new();
         // Basic creation test
     }
+
+    #[tokio::test]
+    async fn test_connect_applies_socket_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let config = TcpTransportConfig {
+            nodelay: false,
+            ..TcpTransportConfig::default()
+        };
+        let stream = TcpStream::connect_with_config(addr, &config).await.unwrap();
+        assert!(!stream.stream.as_ref().unwrap().nodelay().unwrap());
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        assert!(stream.stream.as_ref().unwrap().nodelay().unwrap());
+    }
 }
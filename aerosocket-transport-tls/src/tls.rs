@@ -215,6 +215,15 @@ impl TlsStream {
             .await
             .map_err(aerosocket_core::Error::Io)?;
 
+        Self::connect_stream(tcp_stream, config, server_name).await
+    }
+
+    /// Create a new TLS stream over an already connected TCP stream
+    pub async fn connect_stream(
+        tcp_stream: TokioTcpStream,
+        config: Arc<ClientConfig>,
+        server_name: &str,
+    ) -> Result<Self> {
         let connector = TlsConnector::from(config);
         let domain = rustls::ServerName::try_from(server_name)
             .map_err(|e| aerosocket_core::Error::Other(format!("Invalid domain name: {}", e)))?;
@@ -19,6 +19,12 @@ pub struct WebSocketClient {
     ws: Option<web_sys::WebSocket>,
     #[cfg(feature = "wasm-bindgen")]
     url: String,
+    /// Messages and state reported by the browser's event handlers
+    #[cfg(feature = "wasm-bindgen")]
+    inbox: std::rc::Rc<std::cell::RefCell<wasm_impl::Inbox>>,
+    /// Event handlers installed on the socket, dropped with the client
+    #[cfg(feature = "wasm-bindgen")]
+    handlers: Vec<wasm_bindgen::closure::Closure<dyn FnMut(wasm_bindgen::JsValue)>>,
     #[cfg(not(feature = "wasm-bindgen"))]
     _private: (),
 }
@@ -41,10 +47,7 @@ impl WebSocketClient {
         }
         #[cfg(feature = "wasm-bindgen")]
         {
-            Self {
-                ws: None,
-                url: _url,
-            }
+            Self::new_wasm(_url)
         }
     }
 }
@@ -69,35 +72,192 @@ impl WebSocketConfig {
 #[cfg(feature = "wasm-bindgen")]
 mod wasm_impl {
     use super::*;
-    use aerosocket_core::Error as CoreError;
-    use js_sys::Uint8Array;
+    use aerosocket_core::{Error as CoreError, Message};
+    use futures_util::Stream;
+    use js_sys::{ArrayBuffer, Uint8Array};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::task::{Context, Poll, Waker};
     use wasm_bindgen::prelude::*;
-    use web_sys::{MessageEvent, WebSocket};
+    use web_sys::{BinaryType, CloseEvent, MessageEvent, WebSocket};
 
     // Helper function to convert errors
     fn error_to_js(error: CoreError) -> JsValue {
         JsValue::from_str(&error.to_string())
     }
 
+    /// State shared between the client and the socket's event handlers
+    #[derive(Default)]
+    pub(crate) struct Inbox {
+        /// Received messages not yet taken by a reader
+        messages: VecDeque<Message>,
+        /// Whether the socket has opened
+        open: bool,
+        /// Whether the socket failed or closed; no more messages will arrive
+        ended: bool,
+        /// Why the socket failed, if it did
+        error: Option<String>,
+        /// Task waiting for the next event
+        waker: Option<Waker>,
+    }
+
+    impl Inbox {
+        fn update(inbox: &Rc<RefCell<Inbox>>, f: impl FnOnce(&mut Inbox)) {
+            let waker = {
+                let mut inbox = inbox.borrow_mut();
+                f(&mut inbox);
+                inbox.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Stream of the messages received by a [`WebSocketClient`]
+    ///
+    /// Text and binary messages arrive as they are received. When the server
+    /// closes the connection its Close message is the last item; a socket
+    /// error ends the stream without one.
+    pub struct MessageStream {
+        inbox: Rc<RefCell<Inbox>>,
+    }
+
+    impl Stream for MessageStream {
+        type Item = Message;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+            let mut inbox = self.inbox.borrow_mut();
+            match inbox.messages.pop_front() {
+                Some(message) => Poll::Ready(Some(message)),
+                None if inbox.ended => Poll::Ready(None),
+                None => {
+                    inbox.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    /// Convert a browser message event into a message
+    ///
+    /// The socket delivers binary data as an `ArrayBuffer`, so anything that
+    /// is neither a string nor a buffer is dropped.
+    fn message_from_event(event: &MessageEvent) -> Option<Message> {
+        let data = event.data();
+        if let Some(text) = data.as_string() {
+            return Some(Message::text(text));
+        }
+        data.dyn_into::<ArrayBuffer>()
+            .ok()
+            .map(|buffer| Message::binary(Uint8Array::new(&buffer).to_vec()))
+    }
+
     impl WebSocketClient {
         pub fn new_wasm(url: String) -> Self {
-            Self { ws: None, url }
+            Self {
+                ws: None,
+                url,
+                inbox: Rc::default(),
+                handlers: Vec::new(),
+            }
         }
 
+        /// Open the connection, waiting until the socket is open
+        ///
+        /// Messages received from then on are available through
+        /// [`next`](Self::next) or [`messages`](Self::messages).
         pub async fn connect(&mut self) -> Result<(), JsValue> {
             let ws = WebSocket::new(&self.url)
                 .map_err(|e| JsValue::from_str(&e.as_string().unwrap_or_default()))?;
+            ws.set_binary_type(BinaryType::Arraybuffer);
+
+            let inbox = Rc::new(RefCell::new(Inbox::default()));
+
+            let state = inbox.clone();
+            let onopen = Closure::wrap(Box::new(move |_event: JsValue| {
+                Inbox::update(&state, |inbox| inbox.open = true);
+            }) as Box<dyn FnMut(JsValue)>);
+
+            let state = inbox.clone();
+            let onmessage = Closure::wrap(Box::new(move |event: JsValue| {
+                if let Some(message) = message_from_event(event.unchecked_ref()) {
+                    Inbox::update(&state, |inbox| inbox.messages.push_back(message));
+                }
+            }) as Box<dyn FnMut(JsValue)>);
+
+            let state = inbox.clone();
+            let onerror = Closure::wrap(Box::new(move |_event: JsValue| {
+                Inbox::update(&state, |inbox| {
+                    inbox.error = Some("WebSocket error".to_string());
+                    inbox.ended = true;
+                });
+            }) as Box<dyn FnMut(JsValue)>);
 
-            let _ws_clone = ws.clone();
-            let onopen_closure = Closure::wrap(Box::new(move |_event: MessageEvent| {
-                web_sys::console::log_1(&"WebSocket connected".into());
-            }) as Box<dyn Fn(MessageEvent)>);
+            let state = inbox.clone();
+            let onclose = Closure::wrap(Box::new(move |event: JsValue| {
+                let event: &CloseEvent = event.unchecked_ref();
+                let message = Message::close(Some(event.code()), Some(event.reason()));
+                Inbox::update(&state, |inbox| {
+                    if !inbox.ended {
+                        inbox.messages.push_back(message);
+                        inbox.ended = true;
+                    }
+                });
+            }) as Box<dyn FnMut(JsValue)>);
 
-            ws.set_onopen(Some(onopen_closure.as_ref().unchecked_ref()));
-            onopen_closure.forget();
+            ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+            ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+            ws.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+            ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
 
+            self.detach();
+            self.handlers = vec![onopen, onmessage, onerror, onclose];
+            self.inbox = inbox;
             self.ws = Some(ws);
-            Ok(())
+
+            let inbox = self.inbox.clone();
+            std::future::poll_fn(move |cx| {
+                let mut inbox = inbox.borrow_mut();
+                if inbox.open {
+                    Poll::Ready(Ok(()))
+                } else if inbox.ended {
+                    let error = inbox.error.as_deref().unwrap_or("WebSocket closed");
+                    Poll::Ready(Err(JsValue::from_str(error)))
+                } else {
+                    inbox.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+
+        /// Receive the next message, or `None` once the connection has ended
+        pub async fn next(&mut self) -> Option<Message> {
+            futures_util::StreamExt::next(&mut self.messages()).await
+        }
+
+        /// Stream of received messages
+        ///
+        /// Every stream taken from a client reads from the same queue, so a
+        /// message goes to whichever reader polls first.
+        pub fn messages(&self) -> MessageStream {
+            MessageStream {
+                inbox: self.inbox.clone(),
+            }
+        }
+
+        /// Remove the event handlers from the current socket, if any
+        fn detach(&mut self) {
+            if let Some(ws) = &self.ws {
+                ws.set_onopen(None);
+                ws.set_onmessage(None);
+                ws.set_onerror(None);
+                ws.set_onclose(None);
+            }
+            self.handlers.clear();
         }
 
         pub fn send_text(&self, text: &str) -> Result<(), JsValue> {
@@ -130,6 +290,17 @@ mod wasm_impl {
         }
     }
 
+    impl Drop for WebSocketClient {
+        fn drop(&mut self) {
+            // The handlers are freed with the client, so the browser must
+            // stop calling them first
+            if let Some(ws) = &self.ws {
+                let _ = ws.close();
+            }
+            self.detach();
+        }
+    }
+
     impl WebSocketConfig {
         pub fn new_wasm() -> Self {
             Self {
@@ -144,8 +315,13 @@ mod wasm_impl {
     }
 }
 
+#[cfg(feature = "wasm-bindgen")]
+pub use wasm_impl::MessageStream;
+
 /// Prelude module
 pub mod prelude {
+    #[cfg(feature = "wasm-bindgen")]
+    pub use crate::MessageStream;
     pub use crate::{WebSocketClient, WebSocketConfig};
     pub use aerosocket_core::prelude::*;
 }
@@ -0,0 +1,40 @@
+//! Browser round-trip tests for the WebAssembly client
+//!
+//! These need a browser and an echo server listening on
+//! `ws://127.0.0.1:8080`, such as `examples/echo_server.rs`; run them with
+//! `wasm-pack test --headless --firefox aerosocket-wasm`. Set
+//! `AEROSOCKET_ECHO_URL` at build time to test against another server.
+
+#![cfg(target_arch = "wasm32")]
+
+use aerosocket_core::Message;
+use aerosocket_wasm::WebSocketClient;
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+fn echo_url() -> String {
+    option_env!("AEROSOCKET_ECHO_URL")
+        .unwrap_or("ws://127.0.0.1:8080")
+        .to_string()
+}
+
+#[wasm_bindgen_test]
+async fn test_echo_round_trip() {
+    let mut client = WebSocketClient::new(echo_url());
+    client.connect().await.unwrap();
+
+    client.send_text("hello from the browser").unwrap();
+    let message = client.next().await.unwrap();
+    assert_eq!(message.as_text(), Some("hello from the browser"));
+
+    client.send_binary(&[1, 2, 3]).unwrap();
+    let message = client.next().await.unwrap();
+    assert!(matches!(message, Message::Binary(_)));
+    assert_eq!(message.as_bytes(), &[1, 2, 3]);
+
+    client.close().unwrap();
+    let message = client.next().await.unwrap();
+    assert!(matches!(message, Message::Close(_)));
+    assert!(client.next().await.is_none());
+}
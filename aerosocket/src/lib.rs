@@ -18,7 +18,17 @@
 //!         .max_connections(10_000)
 //!         .build()?;
 //!
-//!     server.serve().await?;
+//!     server
+//!         .serve_fn(|handle| async move {
+//!             let mut conn = handle.try_lock().await?;
+//!             while let Some(msg) = conn.next().await? {
+//!                 if let Message::Text(text) = msg {
+//!                     conn.send_text(text.as_str()).await?;
+//!                 }
+//!             }
+//!             Ok(())
+//!         })
+//!         .await?;
 //!
 //!     Ok(())
 //! }
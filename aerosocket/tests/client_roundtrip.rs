@@ -1,5 +1,3 @@
-use std::io::Result;
-
 #[cfg(all(
     feature = "server",
     feature = "client",
@@ -7,7 +5,7 @@ use std::io::Result;
     feature = "tokio-runtime"
 ))]
 mod tests {
-    use super::*;
+    use std::io::Result;
 
     #[tokio::test]
     #[ignore]
@@ -17,13 +15,140 @@ mod tests {
     }
 }
 
+#[cfg(all(
+    feature = "server",
+    feature = "client",
+    feature = "transport-tcp",
+    feature = "tokio-runtime"
+))]
+mod masking_tests {
+    use aerosocket::client::{Client, ClientConfig, ClientConnection};
+    use aerosocket::server::ServerBuilder;
+    use aerosocket_core::Message;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    /// Start an echo server and return its address
+    fn echo_server(allow_unmasked_clients: bool) -> SocketAddr {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .allow_unmasked_clients(allow_unmasked_clients)
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_echo());
+        addr
+    }
+
+    async fn connect(addr: SocketAddr, config: ClientConfig) -> ClientConnection {
+        loop {
+            match Client::new(addr)
+                .with_config(config.clone())
+                .connect()
+                .await
+            {
+                Ok(connection) => return connection,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn unmasked_link_communicates_when_both_ends_opt_in() {
+        let addr = echo_server(true);
+        let mut conn = connect(addr, ClientConfig::default().mask_frames(false)).await;
+
+        conn.send_text("no mask").await.unwrap();
+        let reply = conn.next().await.unwrap().unwrap();
+        assert_eq!(reply.as_text(), Some("Echo: no mask"));
+    }
+
+    #[tokio::test]
+    async fn client_frames_on_the_wire_are_masked() {
+        use aerosocket_core::handshake::{
+            create_server_handshake, parse_client_handshake, response_to_string, HandshakeConfig,
+        };
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A bare listener that answers the handshake and hands back the raw
+        // bytes of the frames that follow
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let head_end = loop {
+                if let Some(pos) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break pos + 4;
+                }
+                let mut chunk = [0u8; 1024];
+                let n = stream.read(&mut chunk).await.unwrap();
+                received.extend_from_slice(&chunk[..n]);
+            };
+            let request = std::str::from_utf8(&received[..head_end]).unwrap();
+            let parsed = parse_client_handshake(request).unwrap();
+            let response = create_server_handshake(&parsed, &HandshakeConfig::default()).unwrap();
+            stream
+                .write_all(response_to_string(&response).as_bytes())
+                .await
+                .unwrap();
+
+            // Two frames of "hello" (6-byte header with mask key + 5 bytes)
+            let mut frames = received.split_off(head_end);
+            while frames.len() < 22 {
+                let mut chunk = [0u8; 1024];
+                let n = stream.read(&mut chunk).await.unwrap();
+                assert!(n > 0, "client closed before sending both frames");
+                frames.extend_from_slice(&chunk[..n]);
+            }
+            frames
+        });
+
+        let mut conn = connect(addr, ClientConfig::default()).await;
+        conn.send_text("hello").await.unwrap();
+        conn.send_text("hello").await.unwrap();
+        let wire = server.await.unwrap();
+
+        let mut keys = Vec::new();
+        for frame in wire[..22].chunks(11) {
+            assert_eq!(frame[0], 0x81);
+            assert_eq!(frame[1], 0x80 | 5, "MASK bit must be set");
+            let key = &frame[2..6];
+            let unmasked: Vec<u8> = frame[6..]
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ key[i % 4])
+                .collect();
+            assert_eq!(unmasked, b"hello");
+            keys.push(key.to_vec());
+        }
+        assert_ne!(keys[0], keys[1], "each frame needs a fresh mask key");
+    }
+
+    #[tokio::test]
+    async fn server_rejects_unmasked_frames_by_default() {
+        let addr = echo_server(false);
+        let mut conn = connect(addr, ClientConfig::default().mask_frames(false)).await;
+
+        conn.send_text("no mask").await.unwrap();
+        match conn.next().await.unwrap() {
+            Some(Message::Close(close)) => assert_eq!(close.code(), Some(1002)),
+            other => panic!("expected a 1002 close, got {:?}", other),
+        }
+    }
+}
+
 #[cfg(all(
     feature = "client",
     feature = "transport-tls",
     feature = "tokio-runtime"
 ))]
 mod tls_tests {
-    use super::*;
+    use std::io::Result;
 
     #[tokio::test]
     #[ignore]
@@ -32,3 +157,87 @@ mod tls_tests {
         Ok(())
     }
 }
+
+#[cfg(all(
+    feature = "server",
+    feature = "client",
+    feature = "transport-tcp",
+    feature = "tokio-runtime",
+    feature = "compression"
+))]
+mod compression_tests {
+    use aerosocket::client::{Client, ClientConfig, ClientConnection};
+    use aerosocket::server::ServerBuilder;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    async fn connect(addr: SocketAddr, config: ClientConfig) -> ClientConnection {
+        loop {
+            match Client::new(addr)
+                .with_config(config.clone())
+                .connect()
+                .await
+            {
+                Ok(connection) => return connection,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn server_with_compression_disabled_declines_deflate_offer() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = ServerBuilder::new()
+            .bind(addr.to_string())
+            .unwrap()
+            .compression(false)
+            .build()
+            .unwrap();
+        tokio::spawn(server.serve_echo());
+
+        let mut config = ClientConfig::default();
+        config.compression.enabled = true;
+        let mut conn = connect(addr, config).await;
+        assert!(!conn.metadata().compression_negotiated);
+        assert!(conn.metadata().extensions.is_empty());
+
+        conn.send_text("plain").await.unwrap();
+        let reply = conn.next().await.unwrap().unwrap();
+        assert_eq!(reply.as_text(), Some("Echo: plain"));
+    }
+
+    #[tokio::test]
+    async fn compressed_messages_round_trip_with_and_without_takeover() {
+        let message = r#"{"event":"tick","symbol":"BTC","price":64000}"#.repeat(100);
+
+        for takeover in [true, false] {
+            let addr = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap();
+            let server = ServerBuilder::new()
+                .bind(addr.to_string())
+                .unwrap()
+                .compression(true)
+                .build()
+                .unwrap();
+            tokio::spawn(server.serve_echo());
+
+            let mut config = ClientConfig::default();
+            config.compression.enabled = true;
+            config.compression.client_context_takeover = takeover;
+            config.compression.server_context_takeover = takeover;
+            let mut conn = connect(addr, config).await;
+            assert!(conn.metadata().compression_negotiated);
+
+            for _ in 0..3 {
+                conn.send_text(&message).await.unwrap();
+                let reply = conn.next().await.unwrap().unwrap();
+                assert_eq!(reply.as_text(), Some(format!("Echo: {message}").as_str()));
+            }
+        }
+    }
+}
@@ -3,6 +3,7 @@
 //! This example demonstrates a WebSocket client that connects to an echo server
 //! and sends messages to test the connection.
 
+#[cfg(feature = "client")]
 use aerosocket::prelude::*;
 
 #[cfg(feature = "client")]